@@ -15,6 +15,8 @@ CREATE TABLE IF NOT EXISTS users (
     public_key TEXT NOT NULL,
     encrypted_private_key TEXT NOT NULL,
     is_verified BOOLEAN DEFAULT FALSE,
+    role VARCHAR(20) NOT NULL DEFAULT 'user' CHECK (role IN ('user', 'admin')),
+    deleted_at TIMESTAMP WITH TIME ZONE,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
 );
@@ -25,6 +27,10 @@ CREATE TABLE IF NOT EXISTS wallets (
     user_id UUID REFERENCES users(id) ON DELETE SET NULL,
     balance DECIMAL(20, 8) DEFAULT 0,
     last_zakat_date TIMESTAMP WITH TIME ZONE,
+    status VARCHAR(16) NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'frozen')),
+    zakat_exempt BOOLEAN NOT NULL DEFAULT FALSE,
+    zakat_rate DECIMAL(7, 4) CHECK (zakat_rate >= 0 AND zakat_rate <= 100),
+    public_key TEXT,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
 );
@@ -37,6 +43,11 @@ CREATE TABLE IF NOT EXISTS blocks (
     hash VARCHAR(64) UNIQUE NOT NULL,
     nonce BIGINT NOT NULL,
     merkle_root VARCHAR(64),
+    hash_attempts BIGINT,
+    mining_seconds DOUBLE PRECISION,
+    coinbase_hash VARCHAR(64),
+    version INTEGER NOT NULL DEFAULT 1,
+    burned_fees DECIMAL(20, 8) NOT NULL DEFAULT 0 CHECK (burned_fees >= 0),
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
 );
 
@@ -49,6 +60,7 @@ CREATE TABLE IF NOT EXISTS pending_transactions (
     amount DECIMAL(20, 8) NOT NULL CHECK (amount > 0),
     fee DECIMAL(20, 8) NOT NULL DEFAULT 0.1,
     note TEXT,
+    note_encrypted BOOLEAN NOT NULL DEFAULT FALSE,
     signature TEXT NOT NULL,
     timestamp BIGINT NOT NULL,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
@@ -62,9 +74,11 @@ CREATE TABLE IF NOT EXISTS utxos (
     transaction_hash VARCHAR(64) NOT NULL,
     output_index INTEGER NOT NULL,
     is_spent BOOLEAN DEFAULT FALSE,
+    is_coinbase BOOLEAN NOT NULL DEFAULT FALSE,
     reserved_by UUID REFERENCES pending_transactions(id) ON DELETE SET NULL,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     spent_at TIMESTAMP WITH TIME ZONE,
+    spent_by_transaction VARCHAR(64),
     UNIQUE(transaction_hash, output_index)
 );
 
@@ -75,9 +89,12 @@ CREATE TABLE IF NOT EXISTS transactions (
     sender_wallet_id VARCHAR(64) REFERENCES wallets(wallet_id),
     receiver_wallet_id VARCHAR(64) REFERENCES wallets(wallet_id),
     amount DECIMAL(20, 8) NOT NULL CHECK (amount > 0),
+    fee DECIMAL(20, 8),
     note TEXT,
+    note_encrypted BOOLEAN NOT NULL DEFAULT FALSE,
     signature TEXT NOT NULL,
     block_index BIGINT REFERENCES blocks(index),
+    ordinal INTEGER,
     transaction_type VARCHAR(50) DEFAULT 'transfer',
     timestamp BIGINT NOT NULL,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
@@ -99,6 +116,9 @@ CREATE TABLE IF NOT EXISTS zakat_records (
     amount DECIMAL(20, 8) NOT NULL CHECK (amount > 0),
     transaction_hash VARCHAR(64),
     deduction_date TIMESTAMP WITH TIME ZONE NOT NULL,
+    record_type VARCHAR(20) NOT NULL DEFAULT 'deduction',
+    balance_at_deduction DECIMAL(20, 8),
+    rate DECIMAL(7, 4),
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
 );
 
@@ -137,6 +157,79 @@ CREATE TABLE IF NOT EXISTS email_otps (
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
 );
 
+-- Webhooks table
+CREATE TABLE IF NOT EXISTS webhooks (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    wallet_id VARCHAR(64) REFERENCES wallets(wallet_id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- Idempotency keys table (replays of POST /api/transaction/create)
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    sender_wallet_id VARCHAR(64) NOT NULL,
+    idempotency_key VARCHAR(255) NOT NULL,
+    request_hash VARCHAR(64) NOT NULL,
+    transaction_hash VARCHAR(64),
+    response JSONB,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    PRIMARY KEY (sender_wallet_id, idempotency_key)
+);
+
+-- Payment requests table (invoices shared as blockwallet: URIs)
+CREATE TABLE IF NOT EXISTS payment_requests (
+    id UUID PRIMARY KEY,
+    wallet_id VARCHAR(64) NOT NULL REFERENCES wallets(wallet_id) ON DELETE CASCADE,
+    amount DECIMAL(20, 8) NOT NULL CHECK (amount > 0),
+    note TEXT,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    fulfilled_by VARCHAR(64),
+    fulfilled_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- Webhook deliveries table (signed payloads queued for the delivery worker)
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    webhook_id UUID NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    status VARCHAR(10) NOT NULL DEFAULT 'queued' CHECK (status IN ('queued', 'delivered', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    delivered_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Notification preferences table (which wallet events email the user)
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    payment_received BOOLEAN NOT NULL DEFAULT TRUE,
+    zakat_deducted BOOLEAN NOT NULL DEFAULT FALSE,
+    low_balance_threshold DECIMAL(20, 8) CHECK (low_balance_threshold > 0),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- Email outbox table (notification emails queued for SMTP delivery)
+CREATE TABLE IF NOT EXISTS email_outbox (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    recipient VARCHAR(255) NOT NULL,
+    event VARCHAR(30) NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    status VARCHAR(10) NOT NULL DEFAULT 'queued' CHECK (status IN ('queued', 'sent', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    sent_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Sender of coinbase (mining reward) transactions; frozen so it can never receive transfers
+INSERT INTO wallets (wallet_id, status, zakat_exempt)
+VALUES ('COINBASE', 'frozen', TRUE)
+ON CONFLICT (wallet_id) DO NOTHING;
+
 -- ============================================
 -- INDEXES FOR PERFORMANCE
 -- ============================================
@@ -144,6 +237,9 @@ CREATE TABLE IF NOT EXISTS email_otps (
 -- Users indexes
 CREATE INDEX IF NOT EXISTS idx_users_email ON users(email);
 CREATE INDEX IF NOT EXISTS idx_users_wallet_id ON users(wallet_id);
+CREATE INDEX IF NOT EXISTS idx_users_deleted_at ON users(deleted_at) WHERE deleted_at IS NOT NULL;
+-- Dashed and undashed forms of a CNIC count as the same
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_cnic_normalized ON users((regexp_replace(cnic, '[-\s]', '', 'g')));
 
 -- Wallets indexes
 CREATE INDEX IF NOT EXISTS idx_wallets_user_id ON wallets(user_id);
@@ -153,15 +249,19 @@ CREATE INDEX IF NOT EXISTS idx_utxos_wallet_id ON utxos(wallet_id);
 CREATE INDEX IF NOT EXISTS idx_utxos_is_spent ON utxos(is_spent);
 CREATE INDEX IF NOT EXISTS idx_utxos_transaction_hash ON utxos(transaction_hash);
 CREATE INDEX IF NOT EXISTS idx_utxos_reserved_by ON utxos(reserved_by);
+CREATE INDEX IF NOT EXISTS idx_utxos_coinbase_amount ON utxos(amount) WHERE is_coinbase;
+CREATE INDEX IF NOT EXISTS idx_utxos_spent ON utxos(wallet_id, spent_at DESC) WHERE is_spent = true;
 
 -- Blocks indexes
 CREATE INDEX IF NOT EXISTS idx_blocks_hash ON blocks(hash);
+CREATE INDEX IF NOT EXISTS idx_blocks_created_at ON blocks(created_at);
 
 -- Transactions indexes
 CREATE INDEX IF NOT EXISTS idx_transactions_hash ON transactions(transaction_hash);
 CREATE INDEX IF NOT EXISTS idx_transactions_sender ON transactions(sender_wallet_id);
 CREATE INDEX IF NOT EXISTS idx_transactions_receiver ON transactions(receiver_wallet_id);
 CREATE INDEX IF NOT EXISTS idx_transactions_block ON transactions(block_index);
+CREATE INDEX IF NOT EXISTS idx_transactions_created_id ON transactions(created_at DESC, id DESC);
 
 -- Pending transactions indexes
 CREATE INDEX IF NOT EXISTS idx_pending_tx_hash ON pending_transactions(transaction_hash);
@@ -181,6 +281,19 @@ CREATE INDEX IF NOT EXISTS idx_zakat_records_wallet ON zakat_records(wallet_id);
 -- Beneficiaries indexes
 CREATE INDEX IF NOT EXISTS idx_beneficiaries_user ON beneficiaries(user_id);
 
+-- Webhooks indexes
+CREATE INDEX IF NOT EXISTS idx_webhooks_wallet ON webhooks(wallet_id);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_queued ON webhook_deliveries(next_attempt_at) WHERE status = 'queued';
+
+-- Idempotency keys indexes
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created ON idempotency_keys(created_at);
+
+-- Payment requests indexes
+CREATE INDEX IF NOT EXISTS idx_payment_requests_wallet ON payment_requests(wallet_id);
+
+-- Email outbox indexes
+CREATE INDEX IF NOT EXISTS idx_email_outbox_queued ON email_outbox(created_at) WHERE status = 'queued';
+
 -- Email OTPs indexes
 CREATE INDEX IF NOT EXISTS idx_email_otps_email ON email_otps(email);
 CREATE INDEX IF NOT EXISTS idx_email_otps_expires ON email_otps(expires_at);
@@ -216,7 +329,32 @@ COMMENT ON TABLE blocks IS 'Blockchain blocks with proof of work';
 COMMENT ON TABLE transactions IS 'Mined transactions included in blocks';
 COMMENT ON TABLE pending_transactions IS 'Transactions waiting to be mined';
 COMMENT ON TABLE zakat_records IS 'Monthly zakat deduction records';
+COMMENT ON COLUMN users.role IS 'user or admin; admins may trigger zakat and read analytics and system logs';
+COMMENT ON COLUMN zakat_records.record_type IS 'deduction (paid into the pool) or distribution (paid out of the pool)';
+COMMENT ON COLUMN zakat_records.balance_at_deduction IS 'Wallet balance the deduction was computed against; NULL for distributions and older records';
+COMMENT ON COLUMN zakat_records.rate IS 'Zakat percentage applied (e.g. 2.5); NULL for distributions and older records';
 COMMENT ON TABLE transaction_logs IS 'User transaction activity logs';
 COMMENT ON TABLE system_logs IS 'System-wide activity and error logs';
 COMMENT ON TABLE email_otps IS 'Stores OTP codes for email verification';
+COMMENT ON TABLE webhooks IS 'Callback URLs notified when a wallet transaction is mined';
+COMMENT ON TABLE idempotency_keys IS 'Idempotency-Key headers seen on transaction creation and the pending transaction they produced';
+COMMENT ON COLUMN idempotency_keys.response IS 'Pending transaction returned to the first request, NULL while it is still being processed';
+COMMENT ON TABLE payment_requests IS 'Invoices a wallet shares for payment; each can be paid once before it expires';
+COMMENT ON COLUMN payment_requests.fulfilled_by IS 'Hash of the transaction that paid the request, NULL while unpaid';
+COMMENT ON COLUMN transactions.note_encrypted IS 'note holds base64 RSA-OAEP ciphertext only the receiver can decrypt';
+COMMENT ON COLUMN wallets.status IS 'active or frozen; frozen wallets are skipped by transfers, mining and zakat';
+COMMENT ON COLUMN wallets.zakat_exempt IS 'Never charged zakat (system wallets, manually managed accounts)';
+COMMENT ON COLUMN wallets.zakat_rate IS 'Zakat percentage charged instead of ZAKAT_PERCENTAGE; NULL uses the global rate';
+COMMENT ON COLUMN blocks.version IS '1: hash needs MINING_DIFFICULTY / 4 leading zero hex digits; 2: hash below 2^(256 - MINING_DIFFICULTY)';
+COMMENT ON COLUMN transactions.ordinal IS 'Position within its block, matching the order hashed into the merkle root; NULL for older transactions';
+COMMENT ON TABLE notification_preferences IS 'Which wallet events email the user; users without a row get the column defaults';
+COMMENT ON COLUMN notification_preferences.low_balance_threshold IS 'Email when a mined send leaves the balance below this; NULL disables';
+COMMENT ON TABLE email_outbox IS 'Notification emails waiting for (or done with) SMTP delivery';
+COMMENT ON COLUMN blocks.burned_fees IS 'Fees destroyed instead of paid to the miner, under FEE_BURN_PERCENTAGE';
+COMMENT ON COLUMN wallets.public_key IS 'Signing key of a wallet imported without an owner; owned wallets use users.public_key';
+COMMENT ON TABLE webhook_deliveries IS 'Signed webhook payloads waiting for (or done with) delivery';
+COMMENT ON COLUMN webhook_deliveries.next_attempt_at IS 'Earliest time the worker tries again; pushed back exponentially after each failure';
+COMMENT ON COLUMN transactions.transaction_type IS 'transfer, or coinbase for a block''s mining reward (sent from the COINBASE wallet, not part of the merkle root)';
+COMMENT ON COLUMN users.deleted_at IS 'When the account was deleted; email, name and CNIC are anonymized and its tokens refused';
+COMMENT ON COLUMN utxos.spent_by_transaction IS 'Hash of the transaction that spent this UTXO, NULL while unspent or if spent before V18';
 COMMENT ON COLUMN utxos.reserved_by IS 'UUID of pending transaction that has reserved this UTXO, NULL if not reserved';
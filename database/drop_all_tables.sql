@@ -4,6 +4,12 @@
 
 -- Drop tables in reverse order of dependencies to avoid foreign key conflicts
 
+DROP TABLE IF EXISTS schema_migrations CASCADE;
+DROP TABLE IF EXISTS email_outbox CASCADE;
+DROP TABLE IF EXISTS notification_preferences CASCADE;
+DROP TABLE IF EXISTS payment_requests CASCADE;
+DROP TABLE IF EXISTS idempotency_keys CASCADE;
+DROP TABLE IF EXISTS webhooks CASCADE;
 DROP TABLE IF EXISTS system_logs CASCADE;
 DROP TABLE IF EXISTS transaction_logs CASCADE;
 DROP TABLE IF EXISTS zakat_records CASCADE;
@@ -0,0 +1,6 @@
+-- V10: admins can freeze a wallet; frozen wallets cannot send, receive or pay zakat
+
+ALTER TABLE wallets ADD COLUMN IF NOT EXISTS status VARCHAR(16) NOT NULL DEFAULT 'active'
+    CHECK (status IN ('active', 'frozen'));
+
+COMMENT ON COLUMN wallets.status IS 'active or frozen; frozen wallets are skipped by transfers, mining and zakat';
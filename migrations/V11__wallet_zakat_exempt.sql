@@ -0,0 +1,5 @@
+-- V11: wallets the owner or an admin exempted from zakat are left out of deduction runs
+
+ALTER TABLE wallets ADD COLUMN IF NOT EXISTS zakat_exempt BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMENT ON COLUMN wallets.zakat_exempt IS 'Never charged zakat (system wallets, manually managed accounts)';
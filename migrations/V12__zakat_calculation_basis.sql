@@ -0,0 +1,7 @@
+-- V12: deductions record the balance and rate they were computed from
+
+ALTER TABLE zakat_records ADD COLUMN IF NOT EXISTS balance_at_deduction DECIMAL(20, 8);
+ALTER TABLE zakat_records ADD COLUMN IF NOT EXISTS rate DECIMAL(7, 4);
+
+COMMENT ON COLUMN zakat_records.balance_at_deduction IS 'Wallet balance the deduction was computed against; NULL for distributions and older records';
+COMMENT ON COLUMN zakat_records.rate IS 'Zakat percentage applied (e.g. 2.5); NULL for distributions and older records';
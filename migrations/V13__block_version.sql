@@ -0,0 +1,6 @@
+-- V13: blocks record the proof-of-work rule they were mined under.
+-- Existing blocks were checked against leading zero hex digits (version 1).
+
+ALTER TABLE blocks ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1;
+
+COMMENT ON COLUMN blocks.version IS '1: hash needs MINING_DIFFICULTY / 4 leading zero hex digits; 2: hash below 2^(256 - MINING_DIFFICULTY)';
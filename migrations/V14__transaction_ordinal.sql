@@ -0,0 +1,7 @@
+-- V14: a transaction's position within its block, so blocks load their transactions
+-- in the order the merkle root was computed. NULL for transactions mined before this
+-- migration; those fall back to insertion time.
+
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS ordinal INTEGER;
+
+COMMENT ON COLUMN transactions.ordinal IS 'Position within its block, matching the order hashed into the merkle root; NULL for older transactions';
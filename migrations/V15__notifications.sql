@@ -0,0 +1,28 @@
+-- V15: per-user email notification preferences and the outbox notification emails are queued in
+
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    payment_received BOOLEAN NOT NULL DEFAULT TRUE,
+    zakat_deducted BOOLEAN NOT NULL DEFAULT FALSE,
+    low_balance_threshold DECIMAL(20, 8) CHECK (low_balance_threshold > 0),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS email_outbox (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    recipient VARCHAR(255) NOT NULL,
+    event VARCHAR(30) NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    status VARCHAR(10) NOT NULL DEFAULT 'queued' CHECK (status IN ('queued', 'sent', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    sent_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_email_outbox_queued ON email_outbox(created_at) WHERE status = 'queued';
+
+COMMENT ON TABLE notification_preferences IS 'Which wallet events email the user; users without a row get the column defaults';
+COMMENT ON COLUMN notification_preferences.low_balance_threshold IS 'Email when a mined send leaves the balance below this; NULL disables';
+COMMENT ON TABLE email_outbox IS 'Notification emails waiting for (or done with) SMTP delivery';
@@ -0,0 +1,18 @@
+-- V16: mining rewards get a row in transactions (type 'coinbase', sent from the COINBASE
+-- system wallet) so they show up in the miner's history. Block loads skip these rows;
+-- the merkle root only covers transfers.
+
+-- Frozen so it can never receive transfers; it only exists to satisfy the sender foreign key
+INSERT INTO wallets (wallet_id, status, zakat_exempt)
+VALUES ('COINBASE', 'frozen', TRUE)
+ON CONFLICT (wallet_id) DO NOTHING;
+
+-- Backfill rewards already paid by mined blocks, ordered after the block's transfers
+INSERT INTO transactions (transaction_hash, sender_wallet_id, receiver_wallet_id, amount, fee, signature,
+                          block_index, transaction_type, timestamp, ordinal)
+SELECT u.transaction_hash, 'COINBASE', u.wallet_id, u.amount, 0, '', b.index, 'coinbase', b.timestamp,
+       (SELECT COUNT(*) FROM transactions t WHERE t.block_index = b.index)::int
+FROM blocks b
+JOIN utxos u ON u.transaction_hash = b.coinbase_hash AND u.output_index = 0
+WHERE b.index > 0
+ON CONFLICT (transaction_hash) DO NOTHING;
@@ -0,0 +1,8 @@
+-- V17: deleted accounts keep their row (and wallet, for the chain's history) with PII replaced;
+-- deleted_at marks them so their tokens are refused
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX IF NOT EXISTS idx_users_deleted_at ON users(deleted_at) WHERE deleted_at IS NOT NULL;
+
+COMMENT ON COLUMN users.deleted_at IS 'When the account was deleted; email, name and CNIC are anonymized and its tokens refused';
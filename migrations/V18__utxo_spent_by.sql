@@ -0,0 +1,8 @@
+-- V18: record which transaction consumed each output so spent UTXOs can be audited;
+-- outputs spent before this migration keep a NULL spender
+
+ALTER TABLE utxos ADD COLUMN IF NOT EXISTS spent_by_transaction VARCHAR(64);
+
+CREATE INDEX IF NOT EXISTS idx_utxos_spent ON utxos(wallet_id, spent_at DESC) WHERE is_spent = true;
+
+COMMENT ON COLUMN utxos.spent_by_transaction IS 'Hash of the transaction that spent this UTXO, NULL while unspent or if spent before V18';
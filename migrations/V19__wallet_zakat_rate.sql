@@ -0,0 +1,6 @@
+-- V19: admins can charge a wallet a different zakat percentage (tiers, business wallets);
+-- NULL keeps the global ZAKAT_PERCENTAGE
+
+ALTER TABLE wallets ADD COLUMN IF NOT EXISTS zakat_rate DECIMAL(7, 4) CHECK (zakat_rate >= 0 AND zakat_rate <= 100);
+
+COMMENT ON COLUMN wallets.zakat_rate IS 'Zakat percentage charged instead of ZAKAT_PERCENTAGE; NULL uses the global rate';
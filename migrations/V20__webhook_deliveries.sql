@@ -0,0 +1,19 @@
+-- V20: webhook notifications are queued here and retried by a background worker,
+-- so a restart doesn't drop them
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    webhook_id UUID NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    status VARCHAR(10) NOT NULL DEFAULT 'queued' CHECK (status IN ('queued', 'delivered', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    delivered_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_queued ON webhook_deliveries(next_attempt_at) WHERE status = 'queued';
+
+COMMENT ON TABLE webhook_deliveries IS 'Signed webhook payloads waiting for (or done with) delivery';
+COMMENT ON COLUMN webhook_deliveries.next_attempt_at IS 'Earliest time the worker tries again; pushed back exponentially after each failure';
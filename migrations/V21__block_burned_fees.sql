@@ -0,0 +1,12 @@
+-- V21: the fees each block burned under FEE_BURN_PERCENTAGE, so the supply total sums a column
+-- instead of log metadata
+
+ALTER TABLE blocks ADD COLUMN IF NOT EXISTS burned_fees DECIMAL(20, 8) NOT NULL DEFAULT 0 CHECK (burned_fees >= 0);
+
+-- Carry over what earlier blocks recorded in their fee_burn logs
+UPDATE blocks b SET burned_fees = (l.metadata->>'burned_units')::int8 * 0.00000001
+FROM system_logs l
+WHERE l.log_type = 'fee_burn'
+  AND (l.metadata->>'block_index')::int8 = b."index";
+
+COMMENT ON COLUMN blocks.burned_fees IS 'Fees destroyed instead of paid to the miner, under FEE_BURN_PERCENTAGE';
@@ -0,0 +1,6 @@
+-- V22: imported wallets have no owner, so the key their transfers were signed with is kept on
+-- the wallet for signature checks and later exports
+
+ALTER TABLE wallets ADD COLUMN IF NOT EXISTS public_key TEXT;
+
+COMMENT ON COLUMN wallets.public_key IS 'Signing key of a wallet imported without an owner; owned wallets use users.public_key';
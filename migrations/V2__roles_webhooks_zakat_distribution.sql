@@ -0,0 +1,23 @@
+-- V2: admin roles, webhooks, zakat distribution records and cursor/analytics indexes
+
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS role VARCHAR(20) NOT NULL DEFAULT 'user' CHECK (role IN ('user', 'admin'));
+
+ALTER TABLE zakat_records
+    ADD COLUMN IF NOT EXISTS record_type VARCHAR(20) NOT NULL DEFAULT 'deduction';
+
+CREATE TABLE IF NOT EXISTS webhooks (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    wallet_id VARCHAR(64) REFERENCES wallets(wallet_id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_blocks_created_at ON blocks(created_at);
+CREATE INDEX IF NOT EXISTS idx_transactions_created_id ON transactions(created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_webhooks_wallet ON webhooks(wallet_id);
+
+COMMENT ON COLUMN users.role IS 'user or admin; admins may trigger zakat and read analytics and system logs';
+COMMENT ON COLUMN zakat_records.record_type IS 'deduction (paid into the pool) or distribution (paid out of the pool)';
+COMMENT ON TABLE webhooks IS 'Callback URLs notified when a wallet transaction is mined';
@@ -0,0 +1,5 @@
+-- V3: one account per CNIC regardless of formatting ("35202-1234567-1" and "3520212345671" collide).
+-- Fails if existing users already share a normalized CNIC; resolve those rows before upgrading.
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_cnic_normalized
+    ON users ((regexp_replace(cnic, '[-\s]', '', 'g')));
@@ -0,0 +1,16 @@
+-- V4: Idempotency-Key support for POST /api/transaction/create
+
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    sender_wallet_id VARCHAR(64) NOT NULL,
+    idempotency_key VARCHAR(255) NOT NULL,
+    request_hash VARCHAR(64) NOT NULL,
+    transaction_hash VARCHAR(64),
+    response JSONB,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    PRIMARY KEY (sender_wallet_id, idempotency_key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created ON idempotency_keys(created_at);
+
+COMMENT ON TABLE idempotency_keys IS 'Idempotency-Key headers seen on transaction creation and the pending transaction they produced';
+COMMENT ON COLUMN idempotency_keys.response IS 'Pending transaction returned to the first request, NULL while it is still being processed';
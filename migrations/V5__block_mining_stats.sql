@@ -0,0 +1,5 @@
+-- V5: proof-of-work effort per block, used for the hashrate estimate in mining stats.
+-- NULL for blocks mined before this migration.
+
+ALTER TABLE blocks ADD COLUMN IF NOT EXISTS hash_attempts BIGINT;
+ALTER TABLE blocks ADD COLUMN IF NOT EXISTS mining_seconds DOUBLE PRECISION;
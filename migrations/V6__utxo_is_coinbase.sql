@@ -0,0 +1,18 @@
+-- V6: flag coinbase outputs so mined-supply totals can use an index instead of
+-- scanning every UTXO's hash.
+
+ALTER TABLE utxos ADD COLUMN IF NOT EXISTS is_coinbase BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Older rows: seeded rewards used a literal `coinbase_` prefix, mined rewards the
+-- sha256 of `coinbase_<block index>_<miner wallet>`
+UPDATE utxos SET is_coinbase = TRUE
+WHERE NOT is_coinbase AND transaction_hash LIKE 'coinbase\_%';
+
+UPDATE utxos u SET is_coinbase = TRUE
+FROM blocks b
+WHERE NOT u.is_coinbase
+  AND u.output_index = 0
+  AND u.transaction_hash = encode(sha256(convert_to('coinbase_' || b.index || '_' || u.wallet_id, 'UTF8')), 'hex');
+
+-- Covers SUM(amount) over coinbase outputs
+CREATE INDEX IF NOT EXISTS idx_utxos_coinbase_amount ON utxos(amount) WHERE is_coinbase;
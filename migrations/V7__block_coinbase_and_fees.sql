@@ -0,0 +1,13 @@
+-- V7: record what validation needs to check a block's coinbase against the reward schedule.
+-- transactions.fee is NULL for transactions confirmed before this migration (fee unknown).
+
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS fee DECIMAL(20, 8);
+ALTER TABLE blocks ADD COLUMN IF NOT EXISTS coinbase_hash VARCHAR(64);
+
+-- Link existing mined rewards (sha256 of `coinbase_<block index>_<miner wallet>`) to their blocks
+UPDATE blocks b SET coinbase_hash = u.transaction_hash
+FROM utxos u
+WHERE b.coinbase_hash IS NULL
+  AND u.is_coinbase
+  AND u.output_index = 0
+  AND u.transaction_hash = encode(sha256(convert_to('coinbase_' || b.index || '_' || u.wallet_id, 'UTF8')), 'hex');
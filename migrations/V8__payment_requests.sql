@@ -0,0 +1,17 @@
+-- V8: payment requests shared as signed blockwallet: URIs and paid via POST /api/transaction/create
+
+CREATE TABLE IF NOT EXISTS payment_requests (
+    id UUID PRIMARY KEY,
+    wallet_id VARCHAR(64) NOT NULL REFERENCES wallets(wallet_id) ON DELETE CASCADE,
+    amount DECIMAL(20, 8) NOT NULL CHECK (amount > 0),
+    note TEXT,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    fulfilled_by VARCHAR(64),
+    fulfilled_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_payment_requests_wallet ON payment_requests(wallet_id);
+
+COMMENT ON TABLE payment_requests IS 'Invoices a wallet shares for payment; each can be paid once before it expires';
+COMMENT ON COLUMN payment_requests.fulfilled_by IS 'Hash of the transaction that paid the request, NULL while unpaid';
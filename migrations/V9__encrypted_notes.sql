@@ -0,0 +1,6 @@
+-- V9: notes a sender marked private are stored encrypted to the receiver's public key
+
+ALTER TABLE pending_transactions ADD COLUMN IF NOT EXISTS note_encrypted BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS note_encrypted BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMENT ON COLUMN transactions.note_encrypted IS 'note holds base64 RSA-OAEP ciphertext only the receiver can decrypt';
@@ -1,24 +1,25 @@
-use crate::models::{Block, Transaction, PendingTransaction};
-use crate::crypto::sha256_hash;
-use crate::database::DbPool;
+use crate::models::{Block, ChainValidationReport, Transaction, PendingTransaction, MiningStats, SupplyAudit, SupplyInfo, UTXO, WalletStatus};
+use crate::config::{ChainConfig, GenesisConfig};
+use crate::crypto::{create_transaction_payload, import_public_key_pem, sha256_hash, verify_signature};
+use crate::database::{AdvisoryLock, DbPool};
+use crate::shutdown::Shutdown;
+use crate::utils::{from_units, to_units};
 use chrono::Utc;
-use std::env;
+use deadpool_postgres::GenericClient;
+use rsa::RsaPublicKey;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
 use uuid::Uuid;
 
+/// Sender recorded on the `coinbase` transaction that pays a block's mining reward
+pub const COINBASE_SENDER: &str = "COINBASE";
+
 /// Calculate the block reward based on block height (halving mechanism)
-pub fn calculate_block_reward(block_height: i32) -> f64 {
-    let initial_reward = env::var("BLOCK_REWARD")
-        .unwrap_or_else(|_| "50.0".to_string())
-        .parse::<f64>()
-        .unwrap_or(50.0);
-    
-    let halving_interval = env::var("HALVING_INTERVAL")
-        .unwrap_or_else(|_| "210".to_string())
-        .parse::<i32>()
-        .unwrap_or(210);
+pub fn calculate_block_reward(block_height: i32, chain: &ChainConfig) -> f64 {
+    let initial_reward = chain.block_reward;
     
     // Calculate number of halvings that have occurred
-    let halvings = block_height / halving_interval;
+    let halvings = block_height / chain.halving_interval;
     
     // Reward = initial_reward / (2 ^ halvings)
     // Using bit shift for efficiency: dividing by 2^n is same as right shift by n
@@ -30,12 +31,82 @@ pub fn calculate_block_reward(block_height: i32) -> f64 {
     initial_reward / (2_u64.pow(halvings as u32) as f64)
 }
 
+/// Subsidy for a block at `height`: the halving reward, capped at what's left of the max supply
+pub fn capped_block_subsidy(height: i64, mined_before: f64, chain: &ChainConfig) -> f64 {
+    let block_reward = calculate_block_reward(height as i32, chain);
+    block_reward.min((chain.max_coin_supply - mined_before).max(0.0))
+}
+
+/// Units of `fees` destroyed under `FEE_BURN_PERCENTAGE`, rounded to the nearest unit
+pub fn burned_fees(fees: i64, chain: &ChainConfig) -> i64 {
+    (fees as f64 * chain.fee_burn_percentage / 100.0).round() as i64
+}
+
+/// Coinbase units a block must pay: its capped subsidy plus the fees of its transactions less
+/// `burned`, the share recorded as burned when it was mined (`FEE_BURN_PERCENTAGE` may have changed since)
+pub fn expected_coinbase_reward(height: i64, fees: i64, burned: i64, mined_before: f64, chain: &ChainConfig) -> i64 {
+    to_units(capped_block_subsidy(height, mined_before, chain)) + fees - burned
+}
+
+/// Change left after spending `inputs` on `amount` plus `fee`, or `None` if the inputs fall short.
+/// All in smallest units, so an exact spend leaves exactly zero rather than a rounding remainder.
+pub fn change_after_spend(inputs: i64, amount: i64, fee: i64) -> Option<i64> {
+    let change = inputs - amount - fee;
+    (change >= 0).then_some(change)
+}
+
+/// Mining statistics at `height`, derived from the same `chain` parameters mining uses
+pub fn build_mining_stats(
+    chain: &ChainConfig,
+    height: i64,
+    total_mined: f64,
+    last_block_mining_seconds: Option<f64>,
+    estimated_hashrate: Option<f64>,
+) -> MiningStats {
+    let halving_interval = chain.halving_interval as i64;
+    let next_halving_block = ((height / halving_interval) + 1) * halving_interval;
+
+    MiningStats {
+        current_block_height: height,
+        current_block_reward: calculate_block_reward(height as i32, chain),
+        next_halving_block,
+        blocks_until_halving: next_halving_block - height,
+        total_mined_coins: total_mined,
+        max_coin_supply: chain.max_coin_supply,
+        remaining_coins: (chain.max_coin_supply - total_mined).max(0.0),
+        halving_interval: chain.halving_interval,
+        percentage_mined: (total_mined / chain.max_coin_supply * 100.0).min(100.0),
+        last_block_mining_seconds,
+        estimated_hashrate,
+    }
+}
+
+/// Supply breakdown; coins held by the zakat pool and rewards that haven't matured yet don't
+/// count as circulating
+pub fn build_supply_info(
+    chain: &ChainConfig,
+    total_mined: f64,
+    zakat_pool_balance: f64,
+    immature: f64,
+    total_burned: f64,
+) -> SupplyInfo {
+    SupplyInfo {
+        max_supply: chain.max_coin_supply,
+        total_mined,
+        total_burned,
+        zakat_pool_balance,
+        immature,
+        circulating: (total_mined - zakat_pool_balance - immature).max(0.0),
+        percentage_mined: (total_mined / chain.max_coin_supply * 100.0).min(100.0),
+    }
+}
+
 /// Get total coins mined so far (sum of all coinbase rewards)
-pub async fn get_total_mined_coins(client: &deadpool_postgres::Client) -> Result<f64, anyhow::Error> {
+pub async fn get_total_mined_coins(client: &impl GenericClient) -> Result<f64, anyhow::Error> {
     let row = client.query_one(
         "SELECT COALESCE(SUM(amount)::float8, 0) 
          FROM utxos 
-         WHERE transaction_hash LIKE 'coinbase_%'",
+         WHERE is_coinbase",
         &[],
     ).await?;
     
@@ -43,10 +114,14 @@ pub async fn get_total_mined_coins(client: &deadpool_postgres::Client) -> Result
     Ok(total)
 }
 
+/// Merkle root of a block without transactions: the SHA-256 of the bytes `empty`.
+/// Every empty block's hash commits to it, so it can never change.
+pub const EMPTY_MERKLE_ROOT: &str = "2e1cfa82b035c26cbbbdae632cea070514eb8b773f616aaeaf668e2f0be8f10d";
+
 /// Calculate merkle root from transactions
 pub fn calculate_merkle_root(transactions: &[Transaction]) -> String {
     if transactions.is_empty() {
-        return sha256_hash(b"empty");
+        return EMPTY_MERKLE_ROOT.to_string();
     }
 
     let mut hashes: Vec<String> = transactions
@@ -98,9 +173,75 @@ pub fn calculate_block_hash(block: &Block) -> String {
     sha256_hash(data.as_bytes())
 }
 
+/// Hashes a block at any nonce without re-serializing it: the SHA-256 state after the fields
+/// before the nonce is computed once, and each attempt only feeds in the nonce and merkle root.
+/// Must stay in step with [`calculate_block_hash`].
+#[derive(Clone)]
+pub struct BlockHasher {
+    prefix: Sha256,
+    suffix: String,
+}
+
+impl BlockHasher {
+    pub fn new(block: &Block) -> Self {
+        let tx_hashes: Vec<&String> = block.transactions.iter().map(|tx| &tx.transaction_hash).collect();
+        let transactions_data = serde_json::to_string(&tx_hashes).unwrap_or_default();
+        let mut prefix = Sha256::new();
+        prefix.update(format!("{}{}{}{}", block.index, block.timestamp, transactions_data, block.previous_hash));
+        BlockHasher {
+            prefix,
+            suffix: block.merkle_root.clone().unwrap_or_default(),
+        }
+    }
+
+    pub fn hash(&self, nonce: i64) -> String {
+        let mut hasher = self.prefix.clone();
+        hasher.update(nonce.to_string());
+        hasher.update(&self.suffix);
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// A transaction as the legacy block hash serialized it: the fields `Transaction` had when those
+/// blocks were mined, in that order. Fields added since (`note_encrypted`, `fee`) must never be added here.
+#[derive(serde::Serialize)]
+struct LegacyHashTransaction<'a> {
+    id: Uuid,
+    transaction_hash: &'a str,
+    sender_wallet_id: &'a str,
+    receiver_wallet_id: &'a str,
+    #[serde(with = "crate::utils::coin_amount")]
+    amount: i64,
+    note: &'a Option<String>,
+    signature: &'a str,
+    block_index: Option<i64>,
+    transaction_type: &'a str,
+    timestamp: i64,
+    created_at: chrono::DateTime<Utc>,
+}
+
+impl<'a> From<&'a Transaction> for LegacyHashTransaction<'a> {
+    fn from(tx: &'a Transaction) -> Self {
+        LegacyHashTransaction {
+            id: tx.id,
+            transaction_hash: &tx.transaction_hash,
+            sender_wallet_id: &tx.sender_wallet_id,
+            receiver_wallet_id: &tx.receiver_wallet_id,
+            amount: tx.amount,
+            note: &tx.note,
+            signature: &tx.signature,
+            block_index: tx.block_index,
+            transaction_type: &tx.transaction_type,
+            timestamp: tx.timestamp,
+            created_at: tx.created_at,
+        }
+    }
+}
+
 /// Legacy hash calculation for backward compatibility with old blocks
 fn calculate_block_hash_legacy(block: &Block) -> String {
-    let transactions_json = serde_json::to_string(&block.transactions).unwrap_or_default();
+    let transactions: Vec<LegacyHashTransaction> = block.transactions.iter().map(LegacyHashTransaction::from).collect();
+    let transactions_json = serde_json::to_string(&transactions).unwrap_or_default();
     let data = format!(
         "{}{}{}{}{}{}",
         block.index,
@@ -113,40 +254,122 @@ fn calculate_block_hash_legacy(block: &Block) -> String {
     sha256_hash(data.as_bytes())
 }
 
+/// Result of a successful proof-of-work search
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MiningWork {
+    pub nonce: i64,
+    /// Hashes computed across all threads
+    pub attempts: i64,
+    pub elapsed: std::time::Duration,
+}
+
+impl MiningWork {
+    /// Hashes per second
+    pub fn hashrate(&self) -> f64 {
+        self.attempts as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Why a proof-of-work search ended without a nonce
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProofOfWorkError {
+    /// The cancel flag was set
+    Cancelled,
+    /// A worker thread panicked; holds its panic message
+    WorkerPanicked(String),
+}
+
+impl std::fmt::Display for ProofOfWorkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ProofOfWorkError::Cancelled => write!(f, "Mining aborted: server is shutting down"),
+            ProofOfWorkError::WorkerPanicked(msg) => write!(f, "Mining worker thread panicked: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ProofOfWorkError {}
+
+/// Blocks mined when difficulty counted whole hex digits; their hash needs `difficulty / 4` leading zeros
+pub const LEGACY_BLOCK_VERSION: i32 = 1;
+/// Blocks whose hash, read as a big-endian integer, must fall below `2^(256 - difficulty)`
+pub const BLOCK_VERSION: i32 = 2;
+
+/// Whether `hash` read as a big-endian 256-bit integer is below the target `2^(256 - difficulty)`,
+/// i.e. starts with at least `difficulty` zero bits. Each step of difficulty doubles the expected work.
+pub fn meets_difficulty(hash: &str, difficulty: usize) -> bool {
+    let mut zero_bits = 0;
+    for c in hash.chars() {
+        match c.to_digit(16) {
+            Some(0) => zero_bits += 4,
+            Some(digit) => {
+                zero_bits += digit.leading_zeros() as usize - 28;
+                break;
+            }
+            None => return false,
+        }
+    }
+    zero_bits >= difficulty
+}
+
+/// Whether `block.hash` meets `difficulty` under the rule of the block's version
+pub fn block_meets_difficulty(block: &Block, difficulty: usize) -> bool {
+    if block.version == LEGACY_BLOCK_VERSION {
+        block.hash.starts_with(&"0".repeat(difficulty / 4))
+    } else {
+        meets_difficulty(&block.hash, difficulty)
+    }
+}
+
 /// Proof of Work: Find nonce that produces hash with required difficulty (Multi-threaded)
-pub fn proof_of_work(block: &mut Block, difficulty: usize) -> i64 {
-    use std::sync::{Arc, atomic::{AtomicBool, AtomicI64, Ordering}};
+pub fn proof_of_work(block: &mut Block, difficulty: usize) -> Result<MiningWork, ProofOfWorkError> {
+    proof_of_work_until(block, difficulty, Arc::new(AtomicBool::new(false)))
+}
+
+/// Proof of Work that gives up once `cancel` is set, leaving `block` untouched.
+/// A panicking worker stops the others and is reported as an error instead of unwinding into the caller.
+pub fn proof_of_work_until(
+    block: &mut Block,
+    difficulty: usize,
+    cancel: Arc<AtomicBool>,
+) -> Result<MiningWork, ProofOfWorkError> {
+    use std::sync::atomic::AtomicI64;
     use std::thread;
     
-    let target = "0".repeat(difficulty);
+    let started = std::time::Instant::now();
     let num_threads = num_cpus::get();
     let found = Arc::new(AtomicBool::new(false));
+    let failed = Arc::new(AtomicBool::new(false));
     let found_nonce = Arc::new(AtomicI64::new(0));
-    let block_clone = Arc::new(block.clone());
+    let attempts = Arc::new(AtomicI64::new(0));
+    let hasher = BlockHasher::new(block);
     
     log::info!("Starting mining with {} threads", num_threads);
     
     let handles: Vec<_> = (0..num_threads)
         .map(|thread_id| {
             let found = Arc::clone(&found);
+            let failed = Arc::clone(&failed);
             let found_nonce = Arc::clone(&found_nonce);
-            let target = target.clone();
-            let block = Arc::clone(&block_clone);
+            let attempts = Arc::clone(&attempts);
+            let hasher = hasher.clone();
+            let cancel = Arc::clone(&cancel);
             
             thread::spawn(move || {
+                let _stop_on_panic = StopOnPanic(failed.clone());
                 let mut nonce = thread_id as i64;
                 let step = num_threads as i64;
+                let mut tried = 0i64;
                 
                 loop {
-                    if found.load(Ordering::Relaxed) {
+                    if found.load(Ordering::Relaxed) || failed.load(Ordering::Relaxed) || cancel.load(Ordering::Relaxed) {
                         break;
                     }
                     
-                    let mut test_block = (*block).clone();
-                    test_block.nonce = nonce;
-                    let hash = calculate_block_hash(&test_block);
+                    let hash = hasher.hash(nonce);
+                    tried += 1;
                     
-                    if hash.starts_with(&target) {
+                    if meets_difficulty(&hash, difficulty) {
                         found.store(true, Ordering::Relaxed);
                         found_nonce.store(nonce, Ordering::Relaxed);
                         log::info!("✅ Block mined! Thread {} found nonce: {}", thread_id, nonce);
@@ -160,43 +383,89 @@ pub fn proof_of_work(block: &mut Block, difficulty: usize) -> i64 {
                         log::info!("Thread {} mining... nonce: {}", thread_id, nonce);
                     }
                 }
+
+                attempts.fetch_add(tried, Ordering::Relaxed);
             })
         })
         .collect();
     
-    // Wait for all threads to finish
-    for handle in handles {
-        handle.join().unwrap();
+    join_workers(handles)?;
+    
+    if !found.load(Ordering::Relaxed) {
+        log::warn!("⛔ Mining cancelled before a nonce was found");
+        return Err(ProofOfWorkError::Cancelled);
     }
     
     let nonce = found_nonce.load(Ordering::Relaxed);
     block.nonce = nonce;
     block.hash = calculate_block_hash(block);
     
-    nonce
+    Ok(MiningWork {
+        nonce,
+        attempts: attempts.load(Ordering::Relaxed),
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Sets its flag when dropped during a panic, so the other workers stop searching
+struct StopOnPanic(Arc<AtomicBool>);
+
+impl Drop for StopOnPanic {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Wait for every worker, reporting the first panic
+fn join_workers(handles: Vec<std::thread::JoinHandle<()>>) -> Result<(), ProofOfWorkError> {
+    let mut result = Ok(());
+    for handle in handles {
+        if let Err(payload) = handle.join() {
+            let msg = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            log::error!("❌ Mining worker panicked: {}", msg);
+            if result.is_ok() {
+                result = Err(ProofOfWorkError::WorkerPanicked(msg));
+            }
+        }
+    }
+    result
 }
 
 /// Validate a single block
-pub fn validate_block(block: &Block, previous_block: Option<&Block>) -> bool {
+pub fn validate_block(block: &Block, previous_block: Option<&Block>, chain: &ChainConfig) -> bool {
+    match check_block(block, previous_block, chain) {
+        Ok(()) => true,
+        Err(reason) => {
+            log::error!("Block {} is invalid: {}", block.index, reason);
+            false
+        }
+    }
+}
+
+/// Validate a single block, explaining the first check it fails
+pub fn check_block(block: &Block, previous_block: Option<&Block>, chain: &ChainConfig) -> Result<(), String> {
     // Check if hash is correct - try both old and new hash calculation methods
     let calculated_hash_new = calculate_block_hash(block);
     let calculated_hash_old = calculate_block_hash_legacy(block);
     
     if calculated_hash_new != block.hash && calculated_hash_old != block.hash {
-        log::error!("Invalid block hash (tried both new and legacy methods)");
-        return false;
+        return Err("Invalid block hash (tried both new and legacy methods)".to_string());
     }
 
     // Check previous hash
     if let Some(prev) = previous_block {
         if block.previous_hash != prev.hash {
-            log::error!("Invalid previous hash");
-            return false;
+            return Err(format!("Invalid previous hash: expected {}", prev.hash));
         }
         
         if block.index != prev.index + 1 {
-            log::error!("Invalid block index");
-            return false;
+            return Err(format!("Invalid block index: expected {}", prev.index + 1));
         }
     }
 
@@ -204,83 +473,327 @@ pub fn validate_block(block: &Block, previous_block: Option<&Block>) -> bool {
     let calculated_merkle = calculate_merkle_root(&block.transactions);
     if let Some(merkle) = &block.merkle_root {
         if merkle != &calculated_merkle {
-            log::error!("Invalid merkle root");
-            return false;
+            return Err("Invalid merkle root".to_string());
         }
     }
 
     // Check difficulty
-    let difficulty = env::var("MINING_DIFFICULTY")
-        .unwrap_or_else(|_| "5".to_string())
-        .parse::<usize>()
-        .unwrap_or(5);
-    
-    let target = "0".repeat(difficulty);
-    if !block.hash.starts_with(&target) {
-        log::error!("Hash doesn't meet difficulty requirement");
-        return false;
+    if !block_meets_difficulty(block, chain.mining_difficulty) {
+        return Err("Hash doesn't meet difficulty requirement".to_string());
+    }
+
+    Ok(())
+}
+
+/// Re-verify each of `block`'s transaction signatures against the payload rebuilt from its fields,
+/// returning the first failure. Sender public keys are looked up in the database (see
+/// [`check_transaction_signatures`]); `keys` caches them across blocks.
+pub async fn check_block_signatures(
+    client: &deadpool_postgres::Client,
+    block: &Block,
+    chain: &ChainConfig,
+    keys: &mut std::collections::HashMap<String, Option<RsaPublicKey>>,
+) -> Result<Option<String>, tokio_postgres::Error> {
+    let mut missing: Vec<String> = block
+        .transactions
+        .iter()
+        .filter(|tx| tx.transaction_type != "coinbase" && !keys.contains_key(&tx.sender_wallet_id))
+        .map(|tx| tx.sender_wallet_id.clone())
+        .collect();
+    missing.sort();
+    missing.dedup();
+    if !missing.is_empty() {
+        let pems = crate::database::queries::get_wallet_public_keys(client, &missing).await?;
+        for wallet_id in missing {
+            let key = pems.get(&wallet_id).and_then(|pem| import_public_key_pem(pem).ok());
+            keys.insert(wallet_id, key);
+        }
+    }
+
+    Ok(check_transaction_signatures(block, chain, keys))
+}
+
+/// [`check_block_signatures`] against keys already known. Transfers need a valid RSA signature from
+/// the sender's key in `keys`; zakat transactions carry a system signature instead and must move
+/// coins into or out of the zakat pool.
+pub fn check_transaction_signatures(
+    block: &Block,
+    chain: &ChainConfig,
+    keys: &std::collections::HashMap<String, Option<RsaPublicKey>>,
+) -> Option<String> {
+    use crate::services::zakat_service;
+
+    let zakat_pool_wallet_id = &chain.zakat_pool_wallet_id;
+
+    for tx in block.transactions.iter().filter(|tx| tx.transaction_type != "coinbase") {
+        let payload = create_transaction_payload(
+            &tx.sender_wallet_id,
+            &tx.receiver_wallet_id,
+            from_units(tx.amount),
+            tx.timestamp,
+            &tx.note,
+        );
+
+        if let Some(tag) = zakat_service::system_signature_tag(&payload, &tx.signature) {
+            let pool_side = if tag == zakat_service::DEDUCTION_SIGNATURE_TAG { &tx.receiver_wallet_id } else { &tx.sender_wallet_id };
+            if pool_side != zakat_pool_wallet_id {
+                return Some(format!("System-signed transaction {} doesn't involve the zakat pool", tx.transaction_hash));
+            }
+            continue;
+        }
+
+        let Some(public_key) = keys.get(&tx.sender_wallet_id).and_then(Option::as_ref) else {
+            return Some(format!("Sender {} of transaction {} has no signing key", tx.sender_wallet_id, tx.transaction_hash));
+        };
+        // A signature that can't even be decoded is as wrong as one that doesn't match
+        if !matches!(verify_signature(public_key, &payload, &tx.signature), Ok(true)) {
+            return Some(format!("Invalid signature on transaction {}", tx.transaction_hash));
+        }
     }
 
-    true
+    None
 }
 
-/// Validate entire blockchain
-pub async fn validate_blockchain(pool: &DbPool) -> Result<bool, Box<dyn std::error::Error>> {
+/// Blocks reachable from the chosen tip, and those left over
+#[derive(Debug, Clone)]
+pub struct CanonicalChain {
+    /// Genesis first, ending at the tip with the most work
+    pub blocks: Vec<Block>,
+    /// Blocks off the canonical chain: the losing side of a fork, or blocks whose parent is unknown
+    pub orphans: Vec<Block>,
+}
+
+/// Expected hashes behind a block: `2^difficulty` for the bit target, or four bits per hex digit for legacy blocks
+fn block_work(block: &Block, difficulty: usize) -> f64 {
+    let bits = if block.version == LEGACY_BLOCK_VERSION { difficulty / 4 * 4 } else { difficulty };
+    2f64.powi(bits as i32)
+}
+
+/// Groups of two or more blocks extending the same parent, i.e. competing at the same height
+pub fn find_competing_blocks(blocks: &[Block]) -> Vec<Vec<&Block>> {
+    let mut by_parent: std::collections::BTreeMap<&str, Vec<&Block>> = std::collections::BTreeMap::new();
+    for block in blocks.iter().filter(|b| b.index > 0) {
+        by_parent.entry(block.previous_hash.as_str()).or_default().push(block);
+    }
+    by_parent.into_values().filter(|siblings| siblings.len() > 1).collect()
+}
+
+/// Follow `previous_hash` links back from the tip with the most cumulative work. Only tips that
+/// reach a genesis block count; ties go to the earlier block, so the first one seen keeps its place.
+pub fn find_canonical_chain(blocks: Vec<Block>, chain: &ChainConfig) -> CanonicalChain {
+    use std::collections::HashMap;
+
+    let by_hash: HashMap<&str, usize> = blocks.iter().enumerate().map(|(i, b)| (b.hash.as_str(), i)).collect();
+
+    // Cumulative work from genesis, or `None` for blocks that don't link back to one
+    let mut work: Vec<Option<Option<f64>>> = vec![None; blocks.len()];
+    let mut on_path = vec![false; blocks.len()];
+    for start in 0..blocks.len() {
+        // Walk back until a block with known work, a genesis block, or a missing parent
+        let mut path = Vec::new();
+        let mut current = Some(start);
+        let mut base = None;
+        while let Some(i) = current {
+            if let Some(known) = work[i] {
+                base = known;
+                break;
+            }
+            if on_path[i] {
+                break;
+            }
+            on_path[i] = true;
+            path.push(i);
+            if blocks[i].index == 0 {
+                base = Some(0.0);
+                break;
+            }
+            current = by_hash.get(blocks[i].previous_hash.as_str()).copied();
+        }
+        for &i in path.iter().rev() {
+            on_path[i] = false;
+            base = base.map(|w| w + block_work(&blocks[i], chain.mining_difficulty));
+            work[i] = Some(base);
+        }
+    }
+
+    let tip = (0..blocks.len())
+        .filter_map(|i| work[i].flatten().map(|w| (i, w)))
+        .max_by(|&(a, work_a), &(b, work_b)| {
+            work_a
+                .total_cmp(&work_b)
+                .then_with(|| (blocks[b].timestamp, blocks[b].index).cmp(&(blocks[a].timestamp, blocks[a].index)))
+        });
+
+    let mut path = Vec::new();
+    let mut current = tip.map(|(i, _)| i);
+    while let Some(i) = current {
+        path.push(i);
+        current = if blocks[i].index == 0 { None } else { by_hash.get(blocks[i].previous_hash.as_str()).copied() };
+    }
+
+    let mut slots: Vec<Option<Block>> = blocks.into_iter().map(Some).collect();
+    let canonical = path.iter().rev().filter_map(|&i| slots[i].take()).collect();
+    let mut orphans: Vec<Block> = slots.into_iter().flatten().collect();
+    orphans.sort_by_key(|b| b.index);
+    CanonicalChain { blocks: canonical, orphans }
+}
+
+/// Validate entire blockchain along the canonical chain; orphaned blocks are logged but not validated.
+/// Stops at the first invalid block unless `full` is set, in which case every failure is collected.
+/// `deep` also re-verifies every transaction signature (see `check_block_signatures`).
+pub async fn validate_blockchain(
+    pool: &DbPool,
+    chain: &ChainConfig,
+    full: bool,
+    deep: bool,
+) -> Result<ChainValidationReport, Box<dyn std::error::Error>> {
     let client = pool.get().await?;
     
     // Get all blocks
     let rows = client
         .query("SELECT index FROM blocks ORDER BY index ASC", &[])
         .await?;
-    
-    let mut previous_block: Option<Block> = None;
-    
+    let mut blocks = Vec::with_capacity(rows.len());
     for row in rows {
         let index: i64 = row.get(0);
-        let block = crate::database::queries::get_block_by_index(&client, index)
-            .await?
-            .ok_or("Block not found")?;
-        
-        if !validate_block(&block, previous_block.as_ref()) {
-            log::error!("Blockchain validation failed at block {}", index);
-            return Ok(false);
+        blocks.push(
+            crate::database::queries::get_block_by_index(&client, index)
+                .await?
+                .ok_or("Block not found")?,
+        );
+    }
+
+    for siblings in find_competing_blocks(&blocks) {
+        let hashes: Vec<&str> = siblings.iter().map(|b| b.hash.as_str()).collect();
+        log::warn!("Competing blocks extend {}: {:?}", siblings[0].previous_hash, hashes);
+    }
+    let total_blocks = blocks.len() as i64;
+    let canonical = find_canonical_chain(blocks, chain);
+    for orphan in &canonical.orphans {
+        log::warn!("Block {} ({}) is not on the canonical chain", orphan.index, orphan.hash);
+    }
+    let mut report = ChainValidationReport {
+        valid: true,
+        total_blocks,
+        first_invalid_index: None,
+        reason: None,
+        failures: Vec::new(),
+    };
+    if total_blocks > 0 && canonical.blocks.is_empty() {
+        log::error!("No block links back to a genesis block");
+        report.valid = false;
+        report.reason = Some("No block links back to a genesis block".to_string());
+        return Ok(report);
+    }
+    
+    let mut previous_block: Option<&Block> = None;
+    // Coinbase units paid by the blocks validated so far, for the max-supply cap
+    let mut mined_before: i64 = 0;
+    let mut sender_keys = std::collections::HashMap::new();
+    
+    for block in &canonical.blocks {
+        let index = block.index;
+        let mut failure = check_block(block, previous_block, chain).err();
+        if deep && failure.is_none() {
+            failure = check_block_signatures(&client, block, chain, &mut sender_keys).await?;
+        }
+
+        // Genesis allocations aren't rewards, but count towards the supply cap
+        if index == 0 {
+            mined_before += crate::database::queries::get_block_coinbase_amount(&client, index).await?;
+        } else {
+            let coinbase = crate::database::queries::get_block_coinbase_amount(&client, index).await?;
+            match crate::database::queries::get_block_fees(&client, index).await? {
+                Some(fees) => {
+                    let burned = crate::database::queries::get_block_burned_fees(&client, index).await?;
+                    let expected = expected_coinbase_reward(index, fees, burned, from_units(mined_before), chain);
+                    if !(0..=fees).contains(&burned) && failure.is_none() {
+                        failure = Some(format!("Invalid burned fees: {} of {} in fees",
+                            from_units(burned), from_units(fees)));
+                    }
+                    if coinbase != expected && failure.is_none() {
+                        failure = Some(format!("Invalid coinbase: paid {}, expected {}",
+                            from_units(coinbase), from_units(expected)));
+                    }
+                }
+                None => log::warn!("Block {} has transactions without recorded fees; coinbase not checked", index),
+            }
+            mined_before += coinbase;
+        }
+
+        if let Some(reason) = failure {
+            log::error!("Blockchain validation failed at block {}: {}", index, reason);
+            report.record_failure(index, reason);
+            if !full {
+                return Ok(report);
+            }
         }
         
         previous_block = Some(block);
     }
     
-    log::info!("✅ Blockchain validation successful");
-    Ok(true)
+    if report.valid {
+        log::info!("✅ Blockchain validation successful");
+    }
+    Ok(report)
 }
 
-/// Create genesis block
-pub fn create_genesis_block() -> Block {
+/// Create genesis block. With `chain.genesis` set, the block depends only on that config:
+/// its timestamp, a `previous_hash` committing to the message and allocations, and the lowest valid nonce.
+pub fn create_genesis_block(chain: &ChainConfig) -> Result<Block, ProofOfWorkError> {
     let transactions = vec![];
     let merkle_root = calculate_merkle_root(&transactions);
     
+    let (timestamp, previous_hash) = match &chain.genesis {
+        Some(genesis) => (genesis.timestamp, genesis_commitment(genesis)),
+        None => (Utc::now().timestamp(), "0".to_string()),
+    };
     let mut block = Block {
         index: 0,
-        timestamp: Utc::now().timestamp(),
+        timestamp,
         transactions,
-        previous_hash: "0".to_string(),
+        previous_hash,
         hash: String::new(),
         nonce: 0,
         merkle_root: Some(merkle_root),
+        version: BLOCK_VERSION,
     };
 
     // Mine genesis block
-    let difficulty = env::var("MINING_DIFFICULTY")
-        .unwrap_or_else(|_| "5".to_string())
-        .parse::<usize>()
-        .unwrap_or(5);
-    
-    proof_of_work(&mut block, difficulty);
+    if chain.genesis.is_some() {
+        proof_of_work_lowest_nonce(&mut block, chain.mining_difficulty);
+    } else {
+        proof_of_work(&mut block, chain.mining_difficulty)?;
+    }
     
-    block
+    Ok(block)
+}
+
+/// Hash of the genesis message and allocations, stored as the genesis block's `previous_hash`
+pub fn genesis_commitment(genesis: &GenesisConfig) -> String {
+    let allocations: Vec<String> = genesis
+        .allocations
+        .iter()
+        .map(|a| format!("{}={}", a.wallet_id, to_units(a.amount)))
+        .collect();
+    let data = format!("genesis:{}:{}", genesis.message.as_deref().unwrap_or(""), allocations.join(","));
+    sha256_hash(data.as_bytes())
+}
+
+/// Single-threaded search from nonce 0, so the result doesn't depend on which thread wins
+fn proof_of_work_lowest_nonce(block: &mut Block, difficulty: usize) {
+    block.nonce = 0;
+    loop {
+        block.hash = calculate_block_hash(block);
+        if meets_difficulty(&block.hash, difficulty) {
+            return;
+        }
+        block.nonce += 1;
+    }
 }
 
 /// Initialize blockchain (create genesis block if needed)
-pub async fn initialize_blockchain(pool: DbPool) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn initialize_blockchain(pool: DbPool, chain: &ChainConfig) -> Result<(), Box<dyn std::error::Error>> {
     let client = pool.get().await?;
     
     // Check if genesis block exists
@@ -289,10 +802,31 @@ pub async fn initialize_blockchain(pool: DbPool) -> Result<(), Box<dyn std::erro
         .await?;
     
     if result.is_none() {
+        chain.check_premine()?;
         log::info!("Creating genesis block...");
-        let genesis = create_genesis_block();
+        let genesis = create_genesis_block(chain)?;
         
         crate::database::queries::create_block(&client, &genesis).await?;
+
+        // Premine: one coinbase output per allocation, sharing the genesis coinbase hash
+        let allocations = chain.genesis.as_ref().map_or(&[][..], |g| &g.allocations[..]);
+        if !allocations.is_empty() {
+            let coinbase_hash = sha256_hash(format!("coinbase_0_{}", genesis.hash).as_bytes());
+            for (output_index, allocation) in allocations.iter().enumerate() {
+                crate::database::queries::ensure_wallet(&client, &allocation.wallet_id).await?;
+                crate::database::queries::create_coinbase_utxo(
+                    &client,
+                    &allocation.wallet_id,
+                    to_units(allocation.amount),
+                    &coinbase_hash,
+                    output_index as i32,
+                )
+                .await?;
+                crate::database::queries::refresh_wallet_balance(&client, &allocation.wallet_id).await?;
+            }
+            crate::database::queries::set_block_coinbase(&client, 0, &coinbase_hash).await?;
+            log::info!("💰 Genesis allocated coins to {} wallet(s)", allocations.len());
+        }
         
         log::info!("✅ Genesis block created: {}", genesis.hash);
     } else {
@@ -302,22 +836,169 @@ pub async fn initialize_blockchain(pool: DbPool) -> Result<(), Box<dyn std::erro
     Ok(())
 }
 
-/// Mine pending transactions into a new block with coinbase reward
-pub async fn mine_block(pool: &DbPool, miner_wallet_id: &str) -> Result<Block, Box<dyn std::error::Error>> {
-    let client = pool.get().await?;
-    
+/// Arbitrary key for the advisory lock that lets only one `mine_block` run at a time
+const MINING_LOCK_KEY: i64 = 0x6d69_6e69_6e67;
+
+/// Returned by `mine_block` when another mining run holds the lock
+#[derive(Debug)]
+pub struct MiningInProgress;
+
+impl std::fmt::Display for MiningInProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Mining already in progress")
+    }
+}
+
+impl std::error::Error for MiningInProgress {}
+
+/// A block saved by `mine_block` and what its miner was paid, in smallest units
+#[derive(Debug, Clone)]
+pub struct MinedBlock {
+    pub block: Block,
+    /// Subsidy for the block's height, after the max-supply cap
+    pub block_reward: i64,
+    /// Fees of the block's transfers, including any burned share
+    pub total_fees: i64,
+    /// Coinbase paid to the miner: `block_reward` plus fees less the burned share
+    pub total_reward: i64,
+}
+
+/// Mine pending transactions into a new block with coinbase reward.
+/// Shutdown aborts proof of work before anything is written; once a nonce is found the block is saved in full.
+/// Runs are serialized across all nodes sharing the database: a second caller gets `MiningInProgress`
+/// instead of mining the same block from the same pending set.
+pub async fn mine_block(
+    pool: &DbPool,
+    miner_wallet_id: &str,
+    chain: &ChainConfig,
+    shutdown: &Shutdown,
+) -> Result<MinedBlock, Box<dyn std::error::Error>> {
+    let mut lock = AdvisoryLock::try_acquire(pool, MINING_LOCK_KEY).await?.ok_or(MiningInProgress)?;
+
+    let result = mine_block_locked(lock.client_mut(), miner_wallet_id, chain, shutdown).await;
+
+    lock.unlock().await?;
+
+    result
+}
+
+async fn mine_block_locked(
+    client: &mut deadpool_postgres::Client,
+    miner_wallet_id: &str,
+    chain: &ChainConfig,
+    shutdown: &Shutdown,
+) -> Result<MinedBlock, Box<dyn std::error::Error>> {
+    loop {
+        let mut plan = plan_block(client, miner_wallet_id, chain).await?;
+
+        // Proof of Work
+        let difficulty = chain.mining_difficulty;
+        log::info!("Starting Proof of Work with difficulty {}...", difficulty);
+        let work = proof_of_work_until(&mut plan.block, difficulty, shutdown.flag())?;
+        log::info!("✅ Block mined! Hash: {} ({} hashes in {:.2}s, {:.0} H/s)",
+            plan.block.hash, work.attempts, work.elapsed.as_secs_f64(), work.hashrate());
+
+        match write_block(client, plan, &work, miner_wallet_id, chain).await? {
+            Some(mined) => return Ok(mined),
+            None => log::warn!("A receiver was frozen or removed while the block was mined; planning it again"),
+        }
+    }
+}
+
+/// A block's transfers, checked and applied in memory before the block is built, so the
+/// merkle root only ever covers transfers that are written with it
+struct BlockPlan {
+    /// Not yet mined: `hash` and `nonce` are set by proof of work
+    block: Block,
+    /// Transfers in the block, in block order
+    included: Vec<PendingTransaction>,
+    spends: BlockSpends,
+    total_fees: i64,
+}
+
+/// Pick the pending transfers that can go into the next block and build it from them.
+/// Transfers that fail (spent inputs, a receiver that can't receive) stay out and have their UTXOs released.
+async fn plan_block(
+    client: &deadpool_postgres::Client,
+    miner_wallet_id: &str,
+    chain: &ChainConfig,
+) -> Result<BlockPlan, Box<dyn std::error::Error>> {
     // Get latest block
-    let latest_block = crate::database::queries::get_latest_block(&client)
+    let latest_block = crate::database::queries::get_latest_block(client)
         .await?
         .ok_or("No blocks found")?;
     
-    // Get pending transactions
-    let pending_transactions = crate::database::queries::get_pending_transactions(&client).await?;
+    // A frozen miner can't receive the coinbase
+    if let Some(miner) = crate::database::queries::get_wallet(client, miner_wallet_id).await? {
+        if miner.status == WalletStatus::Frozen {
+            return Err(format!("Miner wallet {} is frozen", miner_wallet_id).into());
+        }
+    }
+    
+    // Get pending transactions; those touching a frozen wallet stay pending until it is unfrozen
+    let mut pending_transactions = crate::database::queries::get_pending_transactions(client).await?;
+    let involved: Vec<String> = pending_transactions
+        .iter()
+        .flat_map(|pt| [pt.sender_wallet_id.clone(), pt.receiver_wallet_id.clone()])
+        .collect();
+    let frozen = crate::database::queries::get_frozen_wallet_ids(client, &involved).await?;
+    if !frozen.is_empty() {
+        let before = pending_transactions.len();
+        pending_transactions.retain(|pt| {
+            !frozen.contains(&pt.sender_wallet_id) && !frozen.contains(&pt.receiver_wallet_id)
+        });
+        log::warn!("Holding back {} pending transaction(s) involving frozen wallets",
+            before - pending_transactions.len());
+    }
     
     log::info!("Mining block with {} pending transactions", pending_transactions.len());
     
-    // Convert pending transactions to transactions
-    let transactions: Vec<Transaction> = pending_transactions
+    // Work out every transaction's spends in memory, so the block can be written in a few
+    // batched statements rather than several round-trips per transaction
+    let hashes: Vec<String> = pending_transactions.iter().map(|tx| tx.transaction_hash.clone()).collect();
+    let already_confirmed = crate::database::queries::get_confirmed_hashes(client, &hashes).await?;
+    let mut senders: Vec<String> = pending_transactions.iter().map(|tx| tx.sender_wallet_id.clone()).collect();
+    senders.sort();
+    senders.dedup();
+    let mut spends = BlockSpends::default();
+    let receivers: Vec<String> = pending_transactions.iter().map(|tx| tx.receiver_wallet_id.clone()).collect();
+    spends.receivable = crate::database::queries::get_receivable_wallet_ids(client, &receivers).await?;
+    // Coinbase outputs without enough blocks on top can't be spent yet
+    let immature = crate::database::queries::get_immature_coinbase_ids(client, &senders, chain.coinbase_maturity).await?;
+    for utxo in crate::database::queries::get_unspent_utxos_for_wallets(client, &senders).await? {
+        if immature.contains(&utxo.id) {
+            continue;
+        }
+        spends.spendable.entry(utxo.wallet_id.clone()).or_default().push(utxo);
+    }
+
+    let mut total_fees: i64 = 0;
+    let mut included = Vec::with_capacity(pending_transactions.len());
+    
+    for pending_tx in pending_transactions {
+        let applied = if already_confirmed.contains(&pending_tx.transaction_hash) {
+            Err("already confirmed in an earlier block".to_string())
+        } else {
+            spends.apply(&pending_tx)
+        };
+        match applied {
+            Ok(fee) => {
+                total_fees += fee;
+                log::info!("✅ Collected fee: {} for transaction {}", fee, pending_tx.transaction_hash);
+                included.push(pending_tx);
+            },
+            Err(e) => {
+                log::error!("❌ Skipping transaction {}: {}", pending_tx.transaction_hash, e);
+                // Release reserved UTXOs on failure
+                if let Err(release_err) = release_reserved_utxos_internal(client, pending_tx.id, &pending_tx.sender_wallet_id).await {
+                    log::error!("Failed to release UTXOs for failed transaction {}: {}", pending_tx.id, release_err);
+                }
+            }
+        }
+    }
+
+    // Convert the included transfers to transactions
+    let transactions: Vec<Transaction> = included
         .iter()
         .map(|pt| Transaction {
             id: pt.id,
@@ -326,6 +1007,8 @@ pub async fn mine_block(pool: &DbPool, miner_wallet_id: &str) -> Result<Block, B
             receiver_wallet_id: pt.receiver_wallet_id.clone(),
             amount: pt.amount,
             note: pt.note.clone(),
+            note_encrypted: pt.note_encrypted,
+            fee: Some(pt.fee),
             signature: pt.signature.clone(),
             block_index: Some(latest_block.index + 1),
             transaction_type: "transfer".to_string(),
@@ -337,201 +1020,285 @@ pub async fn mine_block(pool: &DbPool, miner_wallet_id: &str) -> Result<Block, B
     // Create new block
     let merkle_root = calculate_merkle_root(&transactions);
     
-    let mut new_block = Block {
+    let block = Block {
         index: latest_block.index + 1,
         timestamp: Utc::now().timestamp(),
-        transactions: transactions.clone(),
+        transactions,
         previous_hash: latest_block.hash.clone(),
         hash: String::new(),
         nonce: 0,
         merkle_root: Some(merkle_root),
+        version: BLOCK_VERSION,
     };
-    
-    // Proof of Work
-    let difficulty = env::var("MINING_DIFFICULTY")
-        .unwrap_or_else(|_| "5".to_string())
-        .parse::<usize>()
-        .unwrap_or(5);
-    
-    log::info!("Starting Proof of Work with difficulty {}...", difficulty);
-    proof_of_work(&mut new_block, difficulty);
-    log::info!("✅ Block mined! Hash: {}", new_block.hash);
+
+    Ok(BlockPlan { block, included, spends, total_fees })
+}
+
+/// Write a mined block with its transactions, UTXO changes and coinbase in one database transaction.
+/// `None`, with nothing written, if a receiver was frozen or removed after the block was planned.
+async fn write_block(
+    client: &mut deadpool_postgres::Client,
+    plan: BlockPlan,
+    work: &MiningWork,
+    miner_wallet_id: &str,
+    chain: &ChainConfig,
+) -> Result<Option<MinedBlock>, Box<dyn std::error::Error>> {
+    let BlockPlan { block: new_block, included, spends, total_fees } = plan;
+    // Any error drops `transaction` and rolls back, so a block is never saved without its transactions
+    let transaction = client.transaction().await?;
+
+    // Receivers are checked again under a share lock: one frozen while the block was mined would
+    // otherwise get the transfer, and a freeze issued now waits until the block is written
+    let receivers: Vec<String> = included.iter().map(|tx| tx.receiver_wallet_id.clone()).collect();
+    let receivable = crate::database::queries::get_receivable_wallet_ids(&transaction, &receivers).await?;
+    if let Some(tx) = included.iter().find(|tx| !receivable.contains(&tx.receiver_wallet_id)) {
+        log::warn!("Receiver wallet {} of transaction {} no longer exists or is frozen",
+            tx.receiver_wallet_id, tx.transaction_hash);
+        return Ok(None);
+    }
     
     // Save block to database
     log::info!("Saving block to database: index={}, timestamp={}, hash={}", 
         new_block.index, new_block.timestamp, new_block.hash);
     
-    match crate::database::queries::create_block(&client, &new_block).await {
+    match crate::database::queries::create_block(&transaction, &new_block).await {
         Ok(_) => log::info!("✅ Block saved to database"),
         Err(e) => {
             log::error!("❌ Failed to save block: {:?}", e);
             return Err(Box::new(e));
         }
     }
-    
-    // Process each pending transaction and collect fees
-    let mut total_fees = 0.0;
-    
-    for pending_tx in &pending_transactions {
-        // Move to transactions table
-        match crate::database::queries::create_transaction(
-            &client,
-            pending_tx,
-            new_block.index,
-            "transfer",
-        )
-        .await {
-            Ok(_) => {},
-            Err(e) => {
-                log::error!("❌ Failed to create transaction for {}: {:?}", pending_tx.transaction_hash, e);
-                // Release reserved UTXOs on failure
-                if let Err(release_err) = release_reserved_utxos_internal(&client, pending_tx.id, &pending_tx.sender_wallet_id).await {
-                    log::error!("Failed to release UTXOs for failed transaction {}: {}", pending_tx.id, release_err);
-                }
-                continue; // Skip this transaction but continue with others
-            }
-        }
-        
-        // Update UTXOs: mark spent and create new ones, collect fee
-        match update_utxos_for_transaction(&client, pending_tx).await {
-            Ok(fee) => {
-                total_fees += fee;
-                log::info!("✅ Collected fee: {} for transaction {}", fee, pending_tx.transaction_hash);
-            },
-            Err(e) => {
-                log::error!("❌ Failed to update UTXOs for {}: {:?}", pending_tx.transaction_hash, e);
-                // Release reserved UTXOs on failure
-                if let Err(release_err) = release_reserved_utxos_internal(&client, pending_tx.id, &pending_tx.sender_wallet_id).await {
-                    log::error!("Failed to release UTXOs for failed transaction {}: {}", pending_tx.id, release_err);
-                }
-                continue;
-            }
-        }
-        
-        // Delete from pending only after successful processing
-        crate::database::queries::delete_pending_transaction(&client, pending_tx.id).await?;
-    }
+    crate::database::queries::record_block_mining_work(&transaction, new_block.index, work).await?;
+
+    // Ordinals follow the order the merkle root was computed in
+    let mined: Vec<&PendingTransaction> = included.iter().collect();
+    let confirmed: Vec<(&PendingTransaction, i32)> = mined.iter().copied().zip(0..).collect();
+    crate::database::queries::create_transactions(&transaction, &confirmed, new_block.index, "transfer").await?;
+    // Outputs created and spent within this block must exist before they are marked
+    crate::database::queries::create_utxos(&transaction, &spends.created).await?;
+    crate::database::queries::mark_utxos_spent(&transaction, &spends.spent).await?;
+    let mined_ids: Vec<Uuid> = mined.iter().map(|tx| tx.id).collect();
+    crate::database::queries::delete_pending_transactions(&transaction, &mined_ids).await?;
+
+    // Refresh cached balances only now that the spends are no longer counted as pending
+    let mut touched: Vec<String> = mined
+        .iter()
+        .flat_map(|tx| [tx.sender_wallet_id.clone(), tx.receiver_wallet_id.clone()])
+        .collect();
+    touched.sort();
+    touched.dedup();
+    crate::database::queries::refresh_wallet_balances(&transaction, &touched).await?;
     
     // Calculate block reward with halving mechanism
-    let block_reward = calculate_block_reward(new_block.index as i32);
+    let block_reward = calculate_block_reward(new_block.index as i32, chain);
     
     // Check if we've reached max supply
-    let max_supply = env::var("MAX_COIN_SUPPLY")
-        .unwrap_or_else(|_| "21000000.0".to_string())
-        .parse::<f64>()
-        .unwrap_or(21000000.0);
-    
-    let total_mined = get_total_mined_coins(&client).await?;
-    
-    let actual_reward = if total_mined + block_reward > max_supply {
-        // If adding full reward would exceed max supply, only give remaining amount
-        let remaining = max_supply - total_mined;
-        if remaining > 0.0 {
-            log::warn!("⚠️ Approaching max supply! Reward reduced from {} to {}", block_reward, remaining);
-            remaining
+    let max_supply = chain.max_coin_supply;
+    
+    let total_mined = get_total_mined_coins(&transaction).await?;
+    
+    // If adding full reward would exceed max supply, only give remaining amount
+    let actual_reward = capped_block_subsidy(new_block.index, total_mined, chain);
+    if actual_reward < block_reward {
+        if actual_reward > 0.0 {
+            log::warn!("⚠️ Approaching max supply! Reward reduced from {} to {}", block_reward, actual_reward);
         } else {
             log::warn!("⚠️ Max coin supply reached! No mining reward for block {}", new_block.index);
-            0.0
         }
-    } else {
-        block_reward
-    };
+    }
     
-    // Add transaction fees to block reward
-    let total_reward = actual_reward + total_fees;
+    // Add transaction fees to block reward, less the share that is burned
+    let burned = burned_fees(total_fees, chain);
+    let total_reward = to_units(actual_reward) + total_fees - burned;
+    if burned > 0 {
+        crate::database::queries::set_block_burned_fees(&transaction, new_block.index, burned).await?;
+        crate::database::queries::create_system_log(
+            &transaction,
+            "fee_burn",
+            None,
+            &format!("Burned {} of {} in fees from block {}", from_units(burned), from_units(total_fees), new_block.index),
+            None,
+            Some(serde_json::json!({
+                "block_index": new_block.index,
+                "fees_units": total_fees,
+                "burned_units": burned,
+            })),
+        )
+        .await?;
+    }
     
     // Only create coinbase UTXO if there's a reward to give
-    if total_reward > 0.0 {
+    if total_reward > 0 {
         let coinbase_hash = sha256_hash(format!("coinbase_{}_{}", new_block.index, miner_wallet_id).as_bytes());
         
         // Create UTXO for mining reward + fees
-        crate::database::queries::create_utxo(
-            &client,
+        crate::database::queries::create_coinbase_utxo(&transaction, miner_wallet_id, total_reward, &coinbase_hash, 0).await?;
+        crate::database::queries::set_block_coinbase(&transaction, new_block.index, &coinbase_hash).await?;
+        // Ordered after every transfer
+        crate::database::queries::create_coinbase_transaction(
+            &transaction,
+            &coinbase_hash,
             miner_wallet_id,
             total_reward,
-            &coinbase_hash,
-            0,
+            new_block.index,
+            mined.len() as i32,
+            new_block.timestamp,
         )
         .await?;
         
-        log::info!("✅ Block {} mined! Reward: {} coins (Block reward: {}, Fees: {}, Block height: {}, Total mined: {}/{})", 
-            new_block.index, total_reward, actual_reward, total_fees, new_block.index, total_mined + actual_reward, max_supply);
+        log::info!("✅ Block {} mined! Reward: {} coins (Block reward: {}, Fees: {}, Burned: {}, Block height: {}, Total mined: {}/{})", 
+            new_block.index, from_units(total_reward), actual_reward, from_units(total_fees), from_units(burned), new_block.index, total_mined + actual_reward, max_supply);
     } else {
         log::info!("✅ Block {} mined! No reward (max supply reached)", new_block.index);
     }
     
     // Update miner's wallet balance
-    let miner_balance = calculate_wallet_balance(&client, miner_wallet_id).await?;
-    crate::database::queries::update_wallet_balance(&client, miner_wallet_id, miner_balance).await?;
+    crate::database::queries::refresh_wallet_balance(&transaction, miner_wallet_id).await?;
+
+    // Every wallet touched by this block must still cover its pending sends
+    let mut affected: Vec<String> = touched
+        .into_iter()
+        .chain(std::iter::once(miner_wallet_id.to_string()))
+        .collect();
+    affected.sort();
+    affected.dedup();
+    check_balance_invariant(&transaction, &affected).await?;
+    transaction.commit().await?;
+
+    // The block is final; a notification that can't be queued is only logged
+    if let Err(e) = crate::services::notification_service::notify_block_mined(client, new_block.index, &mined).await {
+        log::error!("Failed to queue notifications for block {}: {}", new_block.index, e);
+    }
+    if let Err(e) = crate::services::webhook_service::queue_block_deliveries(client, &new_block).await {
+        log::error!("Failed to queue webhooks for block {}: {}", new_block.index, e);
+    }
     
-    Ok(new_block)
+    Ok(Some(MinedBlock {
+        block: new_block,
+        block_reward: to_units(actual_reward),
+        total_fees,
+        total_reward,
+    }))
 }
 
-/// Update UTXOs for a transaction and return the transaction fee
-async fn update_utxos_for_transaction(
-    client: &deadpool_postgres::Client,
-    transaction: &PendingTransaction,
-) -> Result<f64, anyhow::Error> {
-    // Get sender's unspent UTXOs
-    let sender_utxos = crate::database::queries::get_unspent_utxos(client, &transaction.sender_wallet_id).await?;
-    
-    // Select UTXOs to cover the transaction amount + fee
-    let total_required = transaction.amount + transaction.fee;
-    let mut total = 0.0;
-    let mut utxos_to_spend = Vec::new();
-    
-    for utxo in sender_utxos {
-        if total >= total_required {
-            break;
-        }
-        total += utxo.amount;
-        utxos_to_spend.push(utxo);
+/// Fails if any of `wallet_ids` has a negative available balance (unspent UTXOs minus pending sends)
+pub async fn check_balance_invariant(
+    client: &impl GenericClient,
+    wallet_ids: &[String],
+) -> Result<(), anyhow::Error> {
+    let negative = crate::database::queries::get_negative_balances(client, Some(wallet_ids)).await?;
+    if negative.is_empty() {
+        return Ok(());
     }
-    
-    if total < total_required {
-        return Err(anyhow::anyhow!("Insufficient UTXOs to cover transaction amount + fee"));
+
+    for wallet in &negative {
+        log::error!("❌ Balance invariant violated: wallet {} has available balance {}", wallet.wallet_id, wallet.available);
     }
-    
-    // Mark selected UTXOs as spent
-    for utxo in &utxos_to_spend {
-        crate::database::queries::mark_utxo_spent(client, utxo.id).await?;
+    Err(anyhow::anyhow!(
+        "Balance invariant violated for {} wallet(s): {}",
+        negative.len(),
+        negative.iter().map(|w| w.wallet_id.as_str()).collect::<Vec<_>>().join(", ")
+    ))
+}
+
+/// Supply-wide consistency check: coinbase rewards and unspent outputs must stay within `max_supply`,
+/// and no wallet may have a negative available balance
+pub async fn audit_supply(client: &deadpool_postgres::Client, max_supply: f64) -> Result<SupplyAudit, anyhow::Error> {
+    let unspent_supply = crate::database::queries::get_unspent_supply(client).await?;
+    let total_mined_coins = get_total_mined_coins(client).await?;
+    let negative_balances = crate::database::queries::get_negative_balances(client, None).await?;
+
+    Ok(SupplyAudit {
+        unspent_supply,
+        total_mined_coins,
+        max_coin_supply: max_supply,
+        within_max_supply: total_mined_coins <= max_supply + 1e-8 && unspent_supply <= max_supply + 1e-8,
+        negative_balances,
+    })
+}
+
+/// UTXO changes a block makes, worked out in memory so they can be written in a few statements
+#[derive(Default)]
+struct BlockSpends {
+    /// Unspent outputs per sender, including outputs created earlier in the same block
+    spendable: std::collections::HashMap<String, Vec<UTXO>>,
+    /// Outputs spent by the block, with the hash of the spending transaction
+    spent: Vec<(Uuid, String)>,
+    /// Outputs the block creates, inserted unspent before `spent` is applied
+    created: Vec<UTXO>,
+    /// Receivers that exist and aren't frozen; transfers to any other wallet are rejected
+    receivable: std::collections::HashSet<String>,
+}
+
+impl BlockSpends {
+    /// Spend inputs for `transaction` and add its outputs, returning its fee. Nothing changes on error.
+    fn apply(&mut self, transaction: &PendingTransaction) -> Result<i64, String> {
+        if !self.receivable.contains(&transaction.receiver_wallet_id) {
+            return Err(format!("Receiver wallet {} no longer exists or is frozen", transaction.receiver_wallet_id));
+        }
+        let available = self.spendable.get(&transaction.sender_wallet_id).map(Vec::as_slice).unwrap_or_default();
+
+        // Outputs reserved for this transaction (e.g. dust consolidation) are spent as-is;
+        // otherwise select from unreserved UTXOs (strategy set by COIN_SELECTION)
+        let total_required = transaction
+            .amount
+            .checked_add(transaction.fee)
+            .ok_or_else(|| "Transaction amount + fee overflows".to_string())?;
+        let reserved: Vec<UTXO> = available
+            .iter()
+            .filter(|u| u.reserved_by == Some(transaction.id))
+            .cloned()
+            .collect();
+        let utxos_to_spend = if reserved.is_empty() {
+            let unreserved: Vec<UTXO> = available.iter().filter(|u| u.reserved_by.is_none()).cloned().collect();
+            crate::coin_selection::select_utxos(&unreserved, total_required)
+        } else {
+            reserved
+        };
+        let total: i64 = utxos_to_spend.iter().map(|u| u.amount).sum();
+
+        let change = match change_after_spend(total, transaction.amount, transaction.fee) {
+            Some(change) if !utxos_to_spend.is_empty() => change,
+            _ => return Err("Insufficient UTXOs to cover transaction amount + fee".to_string()),
+        };
+
+        let now = Utc::now();
+        let spent_ids: Vec<Uuid> = utxos_to_spend.iter().map(|u| u.id).collect();
+        if let Some(outputs) = self.spendable.get_mut(&transaction.sender_wallet_id) {
+            outputs.retain(|u| !spent_ids.contains(&u.id));
+        }
+        self.spent.extend(spent_ids.into_iter().map(|id| (id, transaction.transaction_hash.clone())));
+
+        log::info!("✅ Spent {} UTXOs (total: {}) for transaction {}",
+            utxos_to_spend.len(), from_units(total), transaction.transaction_hash);
+
+        // Receiver output, then change (after deducting amount + fee) if any
+        self.add_output(&transaction.receiver_wallet_id, transaction.amount, &transaction.transaction_hash, 0, now);
+        if change > 0 {
+            self.add_output(&transaction.sender_wallet_id, change, &transaction.transaction_hash, 1, now);
+        }
+
+        Ok(transaction.fee)
     }
-    
-    log::info!("✅ Spent {} UTXOs (total: {}) for transaction {}", 
-        utxos_to_spend.len(), total, transaction.transaction_hash);
-    
-    // Create new UTXO for receiver
-    crate::database::queries::create_utxo(
-        client,
-        &transaction.receiver_wallet_id,
-        transaction.amount,
-        &transaction.transaction_hash,
-        0,
-    )
-    .await?;
-    
-    // Create change UTXO if needed (after deducting amount + fee)
-    let change = total - transaction.amount - transaction.fee;
-    if change > 0.0 {
-        crate::database::queries::create_utxo(
-            client,
-            &transaction.sender_wallet_id,
-            change,
-            &transaction.transaction_hash,
-            1,
-        )
-        .await?;
+
+    fn add_output(&mut self, wallet_id: &str, amount: i64, transaction_hash: &str, output_index: i32, created_at: chrono::DateTime<Utc>) {
+        let output = UTXO {
+            id: Uuid::new_v4(),
+            wallet_id: wallet_id.to_string(),
+            amount,
+            transaction_hash: transaction_hash.to_string(),
+            output_index,
+            is_spent: false,
+            created_at,
+            spent_at: None,
+            reserved_by: None,
+        };
+        // Only wallets sending in this block can spend it before the block is written
+        if let Some(outputs) = self.spendable.get_mut(wallet_id) {
+            outputs.push(output.clone());
+        }
+        self.created.push(output);
     }
-    
-    // Update wallet balances
-    let sender_balance = calculate_wallet_balance(client, &transaction.sender_wallet_id).await?;
-    let receiver_balance = calculate_wallet_balance(client, &transaction.receiver_wallet_id).await?;
-    
-    crate::database::queries::update_wallet_balance(client, &transaction.sender_wallet_id, sender_balance).await?;
-    crate::database::queries::update_wallet_balance(client, &transaction.receiver_wallet_id, receiver_balance).await?;
-    
-    // Return the fee for this transaction
-    Ok(transaction.fee)
 }
 
 /// Release reserved UTXOs when mining fails (internal helper)
@@ -549,8 +1316,7 @@ async fn release_reserved_utxos_internal(
         .await?;
     
     // Update wallet balance (coins are now available again)
-    let updated_balance = calculate_wallet_balance(client, wallet_id).await?;
-    crate::database::queries::update_wallet_balance(client, wallet_id, updated_balance).await?;
+    let updated_balance = crate::database::queries::refresh_wallet_balance(client, wallet_id).await?;
     
     log::info!("✅ Released reserved UTXOs for failed transaction {} (balance restored: {})", 
         pending_tx_id, updated_balance);
@@ -566,24 +1332,24 @@ pub async fn calculate_wallet_balance(
     let utxos = crate::database::queries::get_unspent_utxos(client, wallet_id).await?;
     
     // Calculate total balance from all unspent UTXOs
-    let total_balance: f64 = utxos.iter()
+    let total_balance: i64 = utxos.iter()
         .filter(|u| !u.is_spent)
         .map(|u| u.amount)
         .sum();
     
     // Get amount locked in pending outgoing transactions
-    let pending_amount: f64 = match client.query_one(
-        "SELECT COALESCE(SUM(amount)::float8, 0) 
+    let pending_amount: i64 = match client.query_one(
+        "SELECT (COALESCE(SUM(amount), 0) * 100000000)::int8 
          FROM pending_transactions 
          WHERE sender_wallet_id = $1",
         &[&wallet_id],
     ).await {
         Ok(row) => row.get(0),
-        Err(_) => 0.0,
+        Err(_) => 0,
     };
     
     // Available balance = total balance - pending sends
-    Ok(total_balance - pending_amount)
+    Ok(from_units(total_balance - pending_amount))
 }
 
 #[cfg(test)]
@@ -592,16 +1358,299 @@ mod tests {
 
     #[test]
     fn test_genesis_block_creation() {
-        let genesis = create_genesis_block();
+        let genesis = create_genesis_block(&ChainConfig::default()).unwrap();
         assert_eq!(genesis.index, 0);
         assert_eq!(genesis.previous_hash, "0");
         assert!(!genesis.hash.is_empty());
     }
 
+    #[test]
+    fn test_configured_genesis_is_deterministic() {
+        let genesis = GenesisConfig {
+            timestamp: 1_700_000_000,
+            message: Some("first block".to_string()),
+            allocations: vec![crate::config::GenesisAllocation { wallet_id: "treasury".to_string(), amount: 1000.0 }],
+        };
+        let chain = ChainConfig { mining_difficulty: 3, genesis: Some(genesis.clone()), ..ChainConfig::default() };
+
+        let first = create_genesis_block(&chain).unwrap();
+        let second = create_genesis_block(&chain).unwrap();
+        assert_eq!(first.hash, second.hash);
+        assert_eq!(first.timestamp, 1_700_000_000);
+        assert!(validate_block(&first, None, &chain));
+
+        // The message and allocations are part of the hash
+        let other = GenesisConfig { message: Some("another chain".to_string()), ..genesis };
+        let other_chain = ChainConfig { genesis: Some(other), ..chain };
+        assert_ne!(create_genesis_block(&other_chain).unwrap().hash, first.hash);
+    }
+
+    #[test]
+    fn test_block_spends_rejects_unreceivable_receiver() {
+        let funding = UTXO {
+            id: Uuid::new_v4(),
+            wallet_id: "alice".to_string(),
+            amount: to_units(10.0),
+            transaction_hash: "funding".to_string(),
+            output_index: 0,
+            is_spent: false,
+            created_at: Utc::now(),
+            spent_at: None,
+            reserved_by: None,
+        };
+        let transfer = |receiver: &str| PendingTransaction {
+            id: Uuid::new_v4(),
+            transaction_hash: format!("to-{}", receiver),
+            sender_wallet_id: "alice".to_string(),
+            receiver_wallet_id: receiver.to_string(),
+            amount: to_units(4.0),
+            fee: to_units(0.1),
+            note: None,
+            note_encrypted: false,
+            signature: String::new(),
+            timestamp: 0,
+            created_at: Utc::now(),
+        };
+        // bob was frozen (or removed) after his transfer was queued
+        let mut spends = BlockSpends {
+            spendable: [("alice".to_string(), vec![funding.clone()])].into(),
+            receivable: ["alice".to_string(), "carol".to_string()].into(),
+            ..BlockSpends::default()
+        };
+
+        let rejected = spends.apply(&transfer("bob")).unwrap_err();
+        assert_eq!(rejected, "Receiver wallet bob no longer exists or is frozen");
+        assert!(spends.spent.is_empty() && spends.created.is_empty());
+
+        // The inputs are left for the next transfer
+        assert_eq!(spends.apply(&transfer("carol")), Ok(to_units(0.1)));
+        assert_eq!(spends.spent, vec![(funding.id, "to-carol".to_string())]);
+        assert!(spends.created.iter().all(|u| u.wallet_id != "bob"));
+    }
+
+    #[test]
+    fn test_proof_of_work_cancelled() {
+        let mut block = Block {
+            index: 1,
+            timestamp: Utc::now().timestamp(),
+            transactions: vec![],
+            previous_hash: "0".to_string(),
+            hash: String::new(),
+            nonce: 0,
+            merkle_root: None,
+            version: BLOCK_VERSION,
+        };
+
+        // Unreachable difficulty: only cancellation can end the search
+        assert_eq!(
+            proof_of_work_until(&mut block, 256, Arc::new(AtomicBool::new(true))),
+            Err(ProofOfWorkError::Cancelled)
+        );
+        assert!(block.hash.is_empty());
+    }
+
+    #[test]
+    fn test_block_hasher_matches_reference() {
+        let mut block = Block {
+            index: 42,
+            timestamp: 1_700_000_000,
+            transactions: (0..50)
+                .map(|i| Transaction {
+                    id: Uuid::new_v4(),
+                    transaction_hash: sha256_hash(format!("tx{}", i).as_bytes()),
+                    sender_wallet_id: "sender".to_string(),
+                    receiver_wallet_id: "receiver".to_string(),
+                    amount: 1,
+                    note: None,
+                    note_encrypted: false,
+                    fee: None,
+                    signature: String::new(),
+                    block_index: Some(42),
+                    transaction_type: "transfer".to_string(),
+                    timestamp: 1_700_000_000,
+                    created_at: Utc::now(),
+                })
+                .collect(),
+            previous_hash: "ab".repeat(32),
+            hash: String::new(),
+            nonce: 0,
+            merkle_root: None,
+            version: BLOCK_VERSION,
+        };
+        block.merkle_root = Some(calculate_merkle_root(&block.transactions));
+
+        let hasher = BlockHasher::new(&block);
+        for nonce in [0, 1, 7, 99_999, 100_000, -1, i64::MAX] {
+            block.nonce = nonce;
+            assert_eq!(hasher.hash(nonce), calculate_block_hash(&block), "nonce {}", nonce);
+        }
+
+        // Without a merkle root the suffix is empty, as in the reference
+        block.merkle_root = None;
+        assert_eq!(BlockHasher::new(&block).hash(block.nonce), calculate_block_hash(&block));
+
+        // Rough timing: the precomputed prefix should beat re-serializing the block
+        let attempts = 2_000;
+        let started = std::time::Instant::now();
+        for nonce in 0..attempts {
+            std::hint::black_box(hasher.hash(nonce));
+        }
+        let fast = started.elapsed();
+        let started = std::time::Instant::now();
+        for nonce in 0..attempts {
+            let mut test_block = block.clone();
+            test_block.nonce = nonce;
+            std::hint::black_box(calculate_block_hash(&test_block));
+        }
+        let slow = started.elapsed();
+        assert!(fast < slow, "prefix hashing took {:?}, reference took {:?}", fast, slow);
+    }
+
+    #[test]
+    fn test_panicking_worker_is_reported() {
+        let failed = Arc::new(AtomicBool::new(false));
+        let panicking = {
+            let failed = failed.clone();
+            std::thread::spawn(move || {
+                let _stop_on_panic = StopOnPanic(failed);
+                panic!("out of memory cloning block");
+            })
+        };
+        let waiting = {
+            let failed = failed.clone();
+            std::thread::spawn(move || {
+                // Stands in for a worker that would otherwise search forever
+                while !failed.load(Ordering::Relaxed) {
+                    std::thread::yield_now();
+                }
+            })
+        };
+
+        assert_eq!(
+            join_workers(vec![panicking, waiting]),
+            Err(ProofOfWorkError::WorkerPanicked("out of memory cloning block".to_string()))
+        );
+        assert!(join_workers(vec![std::thread::spawn(|| {})]).is_ok());
+    }
+
+    #[test]
+    fn test_mining_stats_follow_chain_config() {
+        let chain = ChainConfig {
+            block_reward: 10.0,
+            halving_interval: 5,
+            max_coin_supply: 100.0,
+            transaction_fee: 0.1,
+            ..ChainConfig::default()
+        };
+
+        let stats = build_mining_stats(&chain, 7, 75.0, None, None);
+        assert_eq!(stats.current_block_reward, calculate_block_reward(7, &chain));
+        assert_eq!(stats.current_block_reward, 5.0);
+        assert_eq!(stats.halving_interval, 5);
+        assert_eq!(stats.next_halving_block, 10);
+        assert_eq!(stats.blocks_until_halving, 3);
+        assert_eq!(stats.max_coin_supply, 100.0);
+        assert_eq!(stats.remaining_coins, 25.0);
+    }
+
+    #[test]
+    fn test_supply_excludes_zakat_pool() {
+        let chain = ChainConfig { max_coin_supply: 1000.0, ..ChainConfig::default() };
+
+        let supply = build_supply_info(&chain, 250.0, 40.0, 0.0, 0.0);
+        assert_eq!(supply.total_mined, 250.0);
+        assert_eq!(supply.circulating, 210.0);
+        assert_eq!(supply.percentage_mined, 25.0);
+
+        assert_eq!(build_supply_info(&chain, 250.0, 0.0, 0.0, 0.0).circulating, 250.0);
+        assert_eq!(build_supply_info(&chain, 250.0, 40.0, 50.0, 0.0).circulating, 160.0);
+    }
+
+    #[test]
+    fn test_expected_coinbase_reward() {
+        let chain = ChainConfig {
+            block_reward: 10.0,
+            halving_interval: 5,
+            max_coin_supply: 100.0,
+            ..ChainConfig::default()
+        };
+
+        let fees = to_units(0.3);
+
+        // Correct: halved subsidy plus fees
+        assert_eq!(expected_coinbase_reward(7, fees, 0, 20.0, &chain), to_units(5.3));
+        // Inflated: the pre-halving subsidy is more than the block may pay
+        assert_ne!(expected_coinbase_reward(7, fees, 0, 20.0, &chain), to_units(10.3));
+        // Capped: only what's left of the supply, and just fees once it's exhausted
+        assert_eq!(expected_coinbase_reward(7, fees, 0, 98.0, &chain), to_units(2.3));
+        assert_eq!(expected_coinbase_reward(7, fees, 0, 100.0, &chain), fees);
+
+        // Burned fees are never paid out
+        let burning = ChainConfig { fee_burn_percentage: 50.0, ..chain };
+        assert_eq!(burned_fees(fees, &burning), to_units(0.15));
+        assert_eq!(expected_coinbase_reward(7, fees, burned_fees(fees, &burning), 20.0, &burning), to_units(5.15));
+        assert_eq!(burned_fees(3, &burning), 2);
+    }
+
+    #[test]
+    fn test_change_has_no_rounding_dust() {
+        // Floats leave a remainder spending 0.1 + 0.2 on 0.3; units leave exactly nothing
+        let (a, b, c) = (0.1_f64, 0.2_f64, 0.3_f64);
+        assert_ne!(a + b - c, 0.0);
+        assert_eq!(change_after_spend(to_units(a) + to_units(b), to_units(c), 0), Some(0));
+
+        // Many small inputs against amount plus fee
+        let inputs: i64 = (0..1000).map(|_| to_units(0.001)).sum();
+        assert_eq!(change_after_spend(inputs, to_units(0.9), to_units(0.1)), Some(0));
+        assert_eq!(change_after_spend(inputs, to_units(0.5), to_units(0.1)), Some(to_units(0.4)));
+
+        // Short by a single unit
+        assert_eq!(change_after_spend(inputs, to_units(0.9), to_units(0.1) + 1), None);
+    }
+
+    /// Needs `TEST_DATABASE_URL` for the persistence half; the proof of work is checked regardless
+    #[tokio::test]
+    async fn test_mining_work_recorded() {
+        let mut block = Block {
+            index: 0,
+            timestamp: Utc::now().timestamp(),
+            transactions: vec![],
+            previous_hash: "0".to_string(),
+            hash: String::new(),
+            nonce: 0,
+            merkle_root: Some(calculate_merkle_root(&[])),
+            version: BLOCK_VERSION,
+        };
+
+        let work = proof_of_work(&mut block, 8).unwrap();
+        assert!(block.hash.starts_with("00"));
+        assert_eq!(work.nonce, block.nonce);
+        assert!(work.attempts > 0);
+        assert!(work.elapsed > std::time::Duration::ZERO);
+        assert!(work.hashrate() > 0.0);
+
+        let pool = match crate::database::create_test_pool("mining_work").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let client = pool.get().await.unwrap();
+
+        assert_eq!(crate::database::queries::get_recent_mining_work(&client, 10).await.unwrap(), (None, None));
+
+        crate::database::queries::create_block(&client, &block).await.unwrap();
+        crate::database::queries::record_block_mining_work(&client, block.index, &work).await.unwrap();
+
+        let (last_seconds, hashrate) = crate::database::queries::get_recent_mining_work(&client, 10).await.unwrap();
+        assert!(last_seconds.unwrap() > 0.0);
+        assert!(hashrate.unwrap() > 0.0);
+    }
+
     #[test]
     fn test_merkle_root_empty() {
-        let root = calculate_merkle_root(&[]);
-        assert!(!root.is_empty());
+        assert_eq!(calculate_merkle_root(&[]), EMPTY_MERKLE_ROOT);
+        // The value blocks were mined with before it became a constant
+        assert_eq!(EMPTY_MERKLE_ROOT, sha256_hash(b"empty"));
     }
 
     #[test]
@@ -614,9 +1663,1085 @@ mod tests {
             hash: String::new(),
             nonce: 0,
             merkle_root: None,
+            version: BLOCK_VERSION,
         };
         
         let hash = calculate_block_hash(&block);
         assert_eq!(hash.len(), 64); // SHA-256 produces 64 hex characters
     }
+
+    /// A legacy block whose hash was computed the old way, from the transaction JSON as it was
+    /// serialized before `Transaction` gained `note_encrypted` and `fee`
+    fn legacy_fixture_block() -> Block {
+        let transactions = vec![Transaction {
+            id: Uuid::parse_str("6f1c2a4e-8b3d-4c5a-9e7f-0a1b2c3d4e5f").unwrap(),
+            transaction_hash: sha256_hash(b"legacy transfer"),
+            sender_wallet_id: "sender".to_string(),
+            receiver_wallet_id: "receiver".to_string(),
+            amount: to_units(12.5),
+            note: Some("rent".to_string()),
+            note_encrypted: false,
+            fee: None,
+            signature: "signature".to_string(),
+            block_index: Some(3),
+            transaction_type: "transfer".to_string(),
+            timestamp: 1_700_000_000,
+            created_at: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+        }];
+        let transactions_json = format!(
+            r#"[{{"id":"6f1c2a4e-8b3d-4c5a-9e7f-0a1b2c3d4e5f","transaction_hash":"{}","sender_wallet_id":"sender","receiver_wallet_id":"receiver","amount":12.5,"note":"rent","signature":"signature","block_index":3,"transaction_type":"transfer","timestamp":1700000000,"created_at":"2023-11-14T22:13:20Z"}}]"#,
+            sha256_hash(b"legacy transfer")
+        );
+        let merkle_root = calculate_merkle_root(&transactions);
+        let previous_hash = "ab".repeat(32);
+        let hash = sha256_hash(format!("{}{}{}{}{}{}", 3, 1_700_000_100, transactions_json, previous_hash, 7, merkle_root).as_bytes());
+
+        Block {
+            index: 3,
+            timestamp: 1_700_000_100,
+            transactions,
+            previous_hash,
+            hash,
+            nonce: 7,
+            merkle_root: Some(merkle_root),
+            version: LEGACY_BLOCK_VERSION,
+        }
+    }
+
+    #[test]
+    fn test_legacy_block_hash_ignores_later_transaction_fields() {
+        let chain = ChainConfig { mining_difficulty: 0, ..ChainConfig::default() };
+        let mut block = legacy_fixture_block();
+        assert_eq!(calculate_block_hash_legacy(&block), block.hash);
+        assert!(check_block(&block, None, &chain).is_ok());
+
+        // Serializing today's `Transaction` would add the newer fields to the preimage
+        assert!(serde_json::to_string(&block.transactions).unwrap().contains("note_encrypted"));
+        block.transactions[0].note_encrypted = true;
+        assert_eq!(calculate_block_hash_legacy(&block), block.hash);
+    }
+
+    #[test]
+    fn test_legacy_block_hash_ignores_recorded_fee() {
+        let chain = ChainConfig { mining_difficulty: 0, ..ChainConfig::default() };
+        let mut block = legacy_fixture_block();
+
+        // Legacy transactions confirmed after fees were recorded load with one
+        block.transactions[0].fee = Some(to_units(0.1));
+        assert!(serde_json::to_string(&block.transactions).unwrap().contains("\"fee\":0.1"));
+        assert_eq!(calculate_block_hash_legacy(&block), block.hash);
+        assert!(check_block(&block, None, &chain).is_ok());
+    }
+
+    fn child_of(parent: &Block, timestamp: i64) -> Block {
+        let mut block = Block {
+            index: parent.index + 1,
+            timestamp,
+            transactions: vec![],
+            previous_hash: parent.hash.clone(),
+            hash: String::new(),
+            nonce: 0,
+            merkle_root: Some(calculate_merkle_root(&[])),
+            version: BLOCK_VERSION,
+        };
+        block.hash = calculate_block_hash(&block);
+        block
+    }
+
+    #[test]
+    fn test_canonical_chain_follows_most_work() {
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        let genesis = create_genesis_block(&chain).unwrap();
+        let a1 = child_of(&genesis, 10);
+        let a2 = child_of(&a1, 20);
+        let b1 = child_of(&genesis, 11);
+        let hashes = |blocks: &[Block]| blocks.iter().map(|b| b.hash.clone()).collect::<Vec<_>>();
+
+        // a1 and b1 compete for height 1; the longer branch wins and b1 is orphaned
+        let blocks = vec![genesis.clone(), a1.clone(), b1.clone(), a2.clone()];
+        let competing = find_competing_blocks(&blocks);
+        assert_eq!(competing.len(), 1);
+        let competing_hashes: Vec<&str> = competing[0].iter().map(|b| b.hash.as_str()).collect();
+        assert_eq!(competing_hashes, vec![a1.hash.as_str(), b1.hash.as_str()]);
+
+        let canonical = find_canonical_chain(blocks, &chain);
+        assert_eq!(hashes(&canonical.blocks), hashes(&[genesis.clone(), a1.clone(), a2.clone()]));
+        assert_eq!(hashes(&canonical.orphans), vec![b1.hash.clone()]);
+
+        // Equal work: the earlier block keeps its place
+        let canonical = find_canonical_chain(vec![b1.clone(), genesis.clone(), a1.clone()], &chain);
+        assert_eq!(hashes(&canonical.blocks), hashes(&[genesis.clone(), a1.clone()]));
+
+        // Once the other branch pulls ahead, it becomes canonical
+        let b2 = child_of(&b1, 21);
+        let b3 = child_of(&b2, 31);
+        let canonical = find_canonical_chain(vec![genesis.clone(), a1.clone(), a2.clone(), b1.clone(), b2.clone(), b3.clone()], &chain);
+        assert_eq!(hashes(&canonical.blocks), hashes(&[genesis.clone(), b1, b2, b3]));
+        assert_eq!(hashes(&canonical.orphans), hashes(&[a1, a2]));
+
+        // A block whose parent is unknown can't be a tip, however high it claims to be
+        let mut stray = child_of(&genesis, 40);
+        stray.index = 50;
+        stray.previous_hash = "f".repeat(64);
+        let canonical = find_canonical_chain(vec![genesis.clone(), stray.clone()], &chain);
+        assert_eq!(hashes(&canonical.blocks), hashes(&[genesis]));
+        assert_eq!(hashes(&canonical.orphans), hashes(&[stray]));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_loaded_transactions_reproduce_merkle_root() {
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        let Some((pool, miner, receiver)) = crate::database::create_funded_test_pair("tx_ordinal", &chain, 0.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let shutdown = Shutdown::new();
+        mine_block(&pool, &miner.wallet_id, &chain, &shutdown).await.unwrap();
+        for amount in 1..=5 {
+            let request = crate::models::CreateTransactionRequest {
+                sender_wallet_id: miner.wallet_id.clone(),
+                receiver_wallet_id: receiver.wallet_id.clone(),
+                amount: amount as f64,
+                note: None,
+                private_note: false,
+                payment_request_id: None,
+            };
+            crate::services::transaction_service::create_transaction(&pool, request, &chain, &aes_key)
+                .await
+                .unwrap();
+        }
+        let mined = mine_block(&pool, &miner.wallet_id, &chain, &shutdown).await.unwrap().block;
+
+        // Reinserting the first rows moves them behind the others on disk and in the index,
+        // so an unordered read would now return a different order
+        let client = pool.get().await.unwrap();
+        client
+            .execute(
+                "WITH moved AS (DELETE FROM transactions WHERE transaction_hash = ANY($1) RETURNING *)
+                 INSERT INTO transactions SELECT * FROM moved",
+                &[&vec![mined.transactions[0].transaction_hash.clone(), mined.transactions[1].transaction_hash.clone()]],
+            )
+            .await
+            .unwrap();
+
+        let loaded = crate::database::queries::get_block_by_index(&client, mined.index).await.unwrap().unwrap();
+        let order = |block: &Block| block.transactions.iter().map(|tx| tx.transaction_hash.clone()).collect::<Vec<_>>();
+        assert_eq!(order(&loaded), order(&mined));
+        assert_eq!(Some(calculate_merkle_root(&loaded.transactions)), loaded.merkle_root);
+        drop(client);
+        assert!(validate_blockchain(&pool, &chain, false, false).await.unwrap().valid);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_validate_ignores_orphans() {
+        let Some(pool) = crate::database::create_test_pool("validate_orphans").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+
+        // A mined block that doesn't extend genesis is flagged, not validated
+        let client = pool.get().await.unwrap();
+        let mut orphan = Block {
+            index: 1,
+            timestamp: Utc::now().timestamp(),
+            transactions: vec![],
+            previous_hash: "f".repeat(64),
+            hash: String::new(),
+            nonce: 0,
+            merkle_root: Some(calculate_merkle_root(&[])),
+            version: BLOCK_VERSION,
+        };
+        proof_of_work(&mut orphan, chain.mining_difficulty).unwrap();
+        crate::database::queries::create_block(&client, &orphan).await.unwrap();
+        drop(client);
+
+        assert!(validate_blockchain(&pool, &chain, false, false).await.unwrap().valid);
+    }
+
+    #[test]
+    fn test_difficulty_target_in_bits() {
+        let rest = "f".repeat(60);
+
+        // 9 bits: the target is 0x0080 00..., so 0x007f ff... is just below it and 0x0080 00... is not
+        assert!(meets_difficulty(&format!("007f{}", rest), 9));
+        assert!(!meets_difficulty(&format!("0080{}", "0".repeat(60)), 9));
+        assert!(meets_difficulty(&format!("0080{}", "0".repeat(60)), 8));
+
+        // One bit at a time, not one hex digit
+        assert!(meets_difficulty(&format!("1fff{}", rest), 3));
+        assert!(!meets_difficulty(&format!("2000{}", rest), 3));
+        assert!(meets_difficulty(&format!("ffff{}", rest), 0));
+        assert!(!meets_difficulty("not hex", 1));
+    }
+
+    #[test]
+    fn test_validate_block_by_version() {
+        let chain = ChainConfig { mining_difficulty: 9, ..ChainConfig::default() };
+        let mut block = Block {
+            index: 0,
+            timestamp: 1234567890,
+            transactions: vec![],
+            previous_hash: "0".to_string(),
+            hash: String::new(),
+            nonce: 0,
+            merkle_root: Some(calculate_merkle_root(&[])),
+            version: BLOCK_VERSION,
+        };
+
+        proof_of_work_lowest_nonce(&mut block, 9);
+        assert!(validate_block(&block, None, &chain));
+
+        // A nonce whose hash has exactly 8 leading zero bits falls just short of the target
+        let mut short = block.clone();
+        short.nonce = 0;
+        loop {
+            short.hash = calculate_block_hash(&short);
+            if meets_difficulty(&short.hash, 8) && !meets_difficulty(&short.hash, 9) {
+                break;
+            }
+            short.nonce += 1;
+        }
+        assert!(!validate_block(&short, None, &chain));
+
+        // Legacy blocks keep the hex-digit rule: 9 bits asks for two leading zero digits
+        short.version = LEGACY_BLOCK_VERSION;
+        assert!(validate_block(&short, None, &chain));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_cached_balance_matches_utxos_after_mining() {
+        let Some((pool, sender, receiver)) = crate::database::create_funded_test_pair("cached_balance", &ChainConfig::default(), 100.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+        let miner = crate::database::register_test_user(&pool, "miner@example.com", "3").await;
+        let (sender, receiver, miner) = (&sender.wallet_id, &receiver.wallet_id, &miner.wallet_id);
+
+        // Spend most of the funds so a double-counted pending amount would go negative
+        let request = crate::models::CreateTransactionRequest {
+            sender_wallet_id: sender.clone(),
+            receiver_wallet_id: receiver.clone(),
+            amount: 70.0,
+            note: None,
+            private_note: false,
+            payment_request_id: None,
+        };
+        crate::services::transaction_service::create_transaction(&pool, request, &ChainConfig::default(), &aes_key)
+            .await
+            .unwrap();
+
+        // Read balances while the block is being mined
+        let shutdown = Shutdown::new();
+        let chain = ChainConfig::default();
+        let reads = async {
+            for _ in 0..20 {
+                crate::services::wallet_service::get_wallet_balance(&pool, sender).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        };
+        let (mined, _) = tokio::join!(mine_block(&pool, miner, &chain, &shutdown), reads);
+        assert!(mined.is_ok());
+
+        let client = pool.get().await.unwrap();
+        for wallet_id in [sender, receiver, miner] {
+            let cached = crate::database::queries::get_wallet(&client, wallet_id).await.unwrap().unwrap().balance;
+            let actual = calculate_wallet_balance(&client, wallet_id).await.unwrap();
+            assert!(cached >= 0.0, "negative cached balance for {}", wallet_id);
+            assert!((cached - actual).abs() < 1e-8, "stale cached balance for {}: {} vs {}", wallet_id, cached, actual);
+        }
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_audit_detects_imbalance() {
+        let pool = match crate::database::create_test_pool("supply_audit").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let sender = crate::database::register_test_user(&pool, "sender@example.com", "1").await;
+        let receiver = crate::database::register_test_user(&pool, "receiver@example.com", "2").await;
+
+        let client = pool.get().await.unwrap();
+        crate::database::queries::create_coinbase_utxo(&client, &sender.wallet_id, to_units(100.0), "coinbase_test", 0)
+            .await
+            .unwrap();
+        drop(client);
+
+        let request = crate::models::CreateTransactionRequest {
+            sender_wallet_id: sender.wallet_id.clone(),
+            receiver_wallet_id: receiver.wallet_id.clone(),
+            amount: 70.0,
+            note: None,
+            private_note: false,
+            payment_request_id: None,
+        };
+        crate::services::transaction_service::create_transaction(&pool, request, &ChainConfig::default(), &aes_key)
+            .await
+            .unwrap();
+
+        let client = pool.get().await.unwrap();
+        let wallets = vec![sender.wallet_id.clone(), receiver.wallet_id.clone()];
+        assert!(check_balance_invariant(&client, &wallets).await.is_ok());
+        let audit = audit_supply(&client, 1000.0).await.unwrap();
+        assert!(audit.within_max_supply);
+        assert!(audit.negative_balances.is_empty());
+
+        // Spend the funding output behind the pending transaction's back
+        client
+            .execute("UPDATE utxos SET is_spent = true WHERE wallet_id = $1", &[&sender.wallet_id])
+            .await
+            .unwrap();
+
+        assert!(check_balance_invariant(&client, &wallets).await.is_err());
+        let audit = audit_supply(&client, 50.0).await.unwrap();
+        assert!(!audit.within_max_supply);
+        assert_eq!(audit.negative_balances.len(), 1);
+        assert_eq!(audit.negative_balances[0].wallet_id, sender.wallet_id);
+        assert!((audit.negative_balances[0].available + 70.0).abs() < 1e-8);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_coinbase_flag_matches_hash_pattern() {
+        let pool = match crate::database::create_test_pool("coinbase_flag").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+
+        let miner = crate::database::register_test_user(&pool, "miner@example.com", "1").await;
+        let miner = miner.wallet_id;
+
+        let backfill = crate::migrations::MIGRATIONS.iter().find(|m| m.version == 6).unwrap().sql;
+        let like_total = "SELECT COALESCE(SUM(amount)::float8, 0) FROM utxos WHERE transaction_hash LIKE 'coinbase_%'";
+
+        // Rows written before the column existed are backfilled from the old pattern
+        let client = pool.get().await.unwrap();
+        crate::database::queries::create_utxo(&client, &miner, to_units(25.0), "coinbase_seed", 0).await.unwrap();
+        crate::database::queries::create_utxo(&client, &miner, to_units(10.0), "regular_tx", 0).await.unwrap();
+        client.batch_execute(backfill).await.unwrap();
+        let old_total: f64 = client.query_one(like_total, &[]).await.unwrap().get(0);
+        assert_eq!(old_total, 25.0);
+        assert_eq!(get_total_mined_coins(&client).await.unwrap(), old_total);
+        drop(client);
+
+        // Mined rewards are flagged on insert, and recovered by the backfill too
+        mine_block(&pool, &miner, &chain, &Shutdown::new()).await.unwrap();
+        let client = pool.get().await.unwrap();
+        let expected = 25.0 + calculate_block_reward(1, &chain);
+        assert_eq!(get_total_mined_coins(&client).await.unwrap(), expected);
+
+        client.execute("UPDATE utxos SET is_coinbase = FALSE", &[]).await.unwrap();
+        client.batch_execute(backfill).await.unwrap();
+        assert_eq!(get_total_mined_coins(&client).await.unwrap(), expected);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_coinbase_reward_in_miner_history() {
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        let Some((pool, miner, receiver)) = crate::database::create_funded_test_pair("coinbase_history", &chain, 0.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let shutdown = Shutdown::new();
+        mine_block(&pool, &miner.wallet_id, &chain, &shutdown).await.unwrap();
+        let transfer = crate::models::CreateTransactionRequest {
+            sender_wallet_id: miner.wallet_id.clone(),
+            receiver_wallet_id: receiver.wallet_id.clone(),
+            amount: 5.0,
+            note: None,
+            private_note: false,
+            payment_request_id: None,
+        };
+        let pending = crate::services::transaction_service::create_transaction(&pool, transfer, &chain, &aes_key)
+            .await
+            .unwrap();
+        let mined = mine_block(&pool, &miner.wallet_id, &chain, &shutdown).await.unwrap().block;
+
+        let client = pool.get().await.unwrap();
+        let rewards = |history: Vec<Transaction>| {
+            history
+                .into_iter()
+                .filter(|tx| tx.transaction_type == "coinbase")
+                .map(|tx| (tx.block_index, tx.sender_wallet_id, tx.amount))
+                .collect::<Vec<_>>()
+        };
+        let history = crate::database::queries::get_wallet_transactions(&client, &miner.wallet_id, 10, 0).await.unwrap();
+        let expected = vec![
+            (Some(mined.index), COINBASE_SENDER.to_string(), to_units(calculate_block_reward(2, &chain)) + pending.fee),
+            (Some(1), COINBASE_SENDER.to_string(), to_units(calculate_block_reward(1, &chain))),
+        ];
+        assert_eq!(rewards(history), expected);
+
+        // The block itself still holds only the transfer its merkle root covers
+        let loaded = crate::database::queries::get_block_by_index(&client, mined.index).await.unwrap().unwrap();
+        assert_eq!(loaded.transactions.len(), 1);
+        assert_eq!(loaded.transactions[0].transaction_hash, pending.transaction_hash);
+        assert!(validate_blockchain(&pool, &chain, true, false).await.unwrap().valid);
+
+        // Chains mined before the rows existed get them from the migration
+        client.execute("DELETE FROM transactions WHERE transaction_type = 'coinbase'", &[]).await.unwrap();
+        let backfill = crate::migrations::MIGRATIONS.iter().find(|m| m.version == 16).unwrap().sql;
+        client.batch_execute(backfill).await.unwrap();
+        let history = crate::database::queries::get_wallet_transactions(&client, &miner.wallet_id, 10, 0).await.unwrap();
+        let mut backfilled = rewards(history);
+        backfilled.sort_by_key(|reward| std::cmp::Reverse(reward.0));
+        assert_eq!(backfilled, expected);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_mining_writes_large_block_in_batches() {
+        let Some(pool) = crate::database::create_test_pool("mining_batched").await else { return };
+        // One connection, so every statement mining issues lands in the stats read below
+        pool.resize(1);
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let sender = crate::database::register_test_user(&pool, "sender@example.com", "1").await;
+        let mut receivers = Vec::new();
+        for i in 2..5 {
+            let user = crate::database::register_test_user(&pool, &format!("r{}@example.com", i), &i.to_string()).await;
+            receivers.push(user.wallet_id);
+        }
+        let client = pool.get().await.unwrap();
+        crate::database::queries::create_utxo(&client, &sender.wallet_id, to_units(1000.0), "funding", 0).await.unwrap();
+        drop(client);
+
+        // A single funding output, so every transfer after the first spends change made earlier in the block
+        let mut sent = 0;
+        for i in 0..100 {
+            let request = crate::models::CreateTransactionRequest {
+                sender_wallet_id: sender.wallet_id.clone(),
+                receiver_wallet_id: receivers[i % receivers.len()].clone(),
+                amount: 1.0 + i as f64 / 100.0,
+                note: None,
+                private_note: false,
+                payment_request_id: None,
+            };
+            let pending = crate::services::transaction_service::create_transaction(&pool, request, &chain, &aes_key)
+                .await
+                .unwrap();
+            sent += pending.amount + pending.fee;
+        }
+
+        let statements = || async {
+            let client = pool.get().await.unwrap();
+            client.batch_execute("SELECT pg_stat_force_next_flush()").await.unwrap();
+            client
+                .query_one("SELECT xact_commit FROM pg_stat_database WHERE datname = current_database()", &[])
+                .await
+                .unwrap()
+                .get::<_, i64>(0)
+        };
+        let before = statements().await;
+        let mined = mine_block(&pool, &sender.wallet_id, &chain, &Shutdown::new()).await.unwrap().block;
+        let issued = statements().await - before;
+        // Writing row by row took several statements per transaction
+        assert!(issued < 100, "mining issued {} statements", issued);
+
+        let client = pool.get().await.unwrap();
+        let count = |sql: &'static str| {
+            let client = &client;
+            async move { client.query_one(sql, &[&mined.index]).await.unwrap().get::<_, i64>(0) }
+        };
+        assert_eq!(mined.transactions.len(), 100);
+        assert_eq!(count("SELECT COUNT(*) FROM transactions WHERE block_index = $1 AND transaction_type = 'transfer'").await, 100);
+        assert_eq!(count("SELECT COUNT(*) FROM utxos u JOIN transactions t USING (transaction_hash) WHERE t.block_index = $1 AND t.transaction_type = 'transfer' AND u.output_index = 0").await, 100);
+        let pending: i64 = client.query_one("SELECT COUNT(*) FROM pending_transactions", &[]).await.unwrap().get(0);
+        assert_eq!(pending, 0);
+
+        // Only the last change output is left unspent
+        let unspent = crate::database::queries::get_unspent_utxos(&client, &sender.wallet_id).await.unwrap();
+        let reward = to_units(calculate_block_reward(mined.index as i32, &chain)) + to_units(chain.transaction_fee) * 100;
+        let mut amounts: Vec<i64> = unspent.iter().map(|u| u.amount).collect();
+        amounts.sort();
+        assert_eq!(amounts, {
+            let mut expected = vec![to_units(1000.0) - sent, reward];
+            expected.sort();
+            expected
+        });
+
+        let loaded = crate::database::queries::get_block_by_index(&client, mined.index).await.unwrap().unwrap();
+        let order = |block: &Block| block.transactions.iter().map(|tx| tx.transaction_hash.clone()).collect::<Vec<_>>();
+        assert_eq!(order(&loaded), order(&mined));
+        drop(client);
+        assert!(validate_blockchain(&pool, &chain, true, false).await.unwrap().valid);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_concurrent_mining_produces_one_block() {
+        let Some(pool) = crate::database::create_test_pool("mining_lock").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig { mining_difficulty: 2, ..ChainConfig::default() };
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "miner").await.unwrap();
+        drop(client);
+
+        let shutdown = Shutdown::new();
+        let (first, second) = tokio::join!(
+            mine_block(&pool, "miner", &chain, &shutdown),
+            mine_block(&pool, "miner", &chain, &shutdown),
+        );
+
+        let (mined, refused): (Vec<_>, Vec<_>) = [first, second].into_iter().partition(|r| r.is_ok());
+        assert_eq!(mined.len(), 1);
+        assert!(refused[0].as_ref().unwrap_err().is::<MiningInProgress>());
+
+        let client = pool.get().await.unwrap();
+        let blocks: i64 = client.query_one("SELECT COUNT(*) FROM blocks", &[]).await.unwrap().get(0);
+        assert_eq!(blocks, 2);
+        drop(client);
+
+        // The lock is released once the winner finishes
+        mine_block(&pool, "miner", &chain, &shutdown).await.unwrap();
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_regtest_mines_instantly() {
+        let Some(pool) = crate::database::create_test_pool("regtest").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig {
+            mode: crate::config::ChainMode::Regtest,
+            mining_difficulty: 0,
+            ..ChainConfig::default()
+        };
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "miner").await.unwrap();
+        drop(client);
+
+        let started = std::time::Instant::now();
+        for _ in 0..5 {
+            mine_block(&pool, "miner", &chain, &Shutdown::new()).await.unwrap();
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(5), "took {:?}", started.elapsed());
+
+        // Every worker's first nonce already meets difficulty 0
+        let client = pool.get().await.unwrap();
+        let attempts: i64 = client
+            .query_one("SELECT MAX(hash_attempts) FROM blocks", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert!(attempts <= num_cpus::get() as i64);
+        drop(client);
+
+        assert!(validate_blockchain(&pool, &chain, true, false).await.unwrap().valid);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_mined_spends_appear_in_spent_listing() {
+        let chain = ChainConfig {
+            mode: crate::config::ChainMode::Regtest,
+            mining_difficulty: 0,
+            ..ChainConfig::default()
+        };
+        let Some((pool, sender, receiver)) = crate::database::create_funded_test_pair("spent_utxos", &chain, 10.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+        let (sender, receiver) = (&sender.wallet_id, &receiver.wallet_id);
+
+        let client = pool.get().await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "miner").await.unwrap();
+        let (before, total) = crate::database::queries::get_spent_utxos_page(&client, sender, 100, 0).await.unwrap();
+        assert!(before.is_empty() && total == 0);
+        drop(client);
+
+        let request = crate::models::CreateTransactionRequest {
+            sender_wallet_id: sender.clone(),
+            receiver_wallet_id: receiver.clone(),
+            amount: 4.0,
+            note: None,
+            private_note: false,
+            payment_request_id: None,
+        };
+        let pending = crate::services::transaction_service::create_transaction(&pool, request, &chain, &aes_key)
+            .await
+            .unwrap();
+        mine_block(&pool, "miner", &chain, &Shutdown::new()).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        let (spent, total) = crate::database::queries::get_spent_utxos_page(&client, sender, 100, 0).await.unwrap();
+        assert_eq!(total, 1);
+        let funding = &spent[0];
+        assert_eq!(funding.utxo.transaction_hash, "funding");
+        assert!(funding.utxo.is_spent);
+        assert!(funding.utxo.spent_at.is_some());
+        assert_eq!(funding.spent_by_transaction.as_deref(), Some(pending.transaction_hash.as_str()));
+
+        // The change output is still unspent and not listed
+        let (unspent, _) = crate::database::queries::get_unspent_utxos_page(&client, sender, 100, 0).await.unwrap();
+        assert!(unspent.iter().all(|u| u.transaction_hash == pending.transaction_hash));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_empty_block_commits_to_empty_merkle_root() {
+        let Some(pool) = crate::database::create_test_pool("empty_merkle").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig {
+            mode: crate::config::ChainMode::Regtest,
+            mining_difficulty: 0,
+            ..ChainConfig::default()
+        };
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+        let client = pool.get().await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "miner").await.unwrap();
+        drop(client);
+
+        let mined = mine_block(&pool, "miner", &chain, &Shutdown::new()).await.unwrap().block;
+
+        let client = pool.get().await.unwrap();
+        let genesis = crate::database::queries::get_block_by_index(&client, 0).await.unwrap().unwrap();
+        let stored = crate::database::queries::get_block_by_index(&client, mined.index).await.unwrap().unwrap();
+        assert!(stored.transactions.is_empty());
+        assert_eq!(genesis.merkle_root.as_deref(), Some(EMPTY_MERKLE_ROOT));
+        assert_eq!(stored.merkle_root.as_deref(), Some(EMPTY_MERKLE_ROOT));
+        assert!(validate_block(&stored, Some(&genesis), &chain));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_deep_validation_rejects_forged_signature() {
+        let Some(pool) = crate::database::create_test_pool("deep_validation").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig {
+            mode: crate::config::ChainMode::Regtest,
+            mining_difficulty: 0,
+            ..ChainConfig::default()
+        };
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let mut wallets = Vec::new();
+        for (email, cnic) in [("payer@example.com", "1"), ("payee@example.com", "2")] {
+            wallets.push(crate::database::register_test_user(&pool, email, cnic).await.wallet_id);
+        }
+        let (payer, payee) = (&wallets[0], &wallets[1]);
+        let shutdown = Shutdown::new();
+        mine_block(&pool, payer, &chain, &shutdown).await.unwrap();
+
+        // A user transfer and a system-signed zakat deduction in the same block
+        let client = pool.get().await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "saver").await.unwrap();
+        crate::database::queries::create_utxo(&client, "saver", to_units(1000.0), "funding", 0).await.unwrap();
+        crate::database::queries::refresh_wallet_balance(&client, "saver").await.unwrap();
+        drop(client);
+        let request = crate::models::CreateTransactionRequest {
+            sender_wallet_id: payer.clone(),
+            receiver_wallet_id: payee.clone(),
+            amount: 1.0,
+            note: Some("rent".to_string()),
+            private_note: false,
+            payment_request_id: None,
+        };
+        let transfer = crate::services::transaction_service::create_transaction(&pool, request, &chain, &aes_key)
+            .await
+            .unwrap();
+        crate::services::zakat_service::process_monthly_zakat(&pool, &chain).await.unwrap();
+        let block = mine_block(&pool, payee, &chain, &shutdown).await.unwrap().block;
+        assert!(block.transactions.iter().any(|tx| tx.sender_wallet_id == "saver"));
+
+        assert!(validate_blockchain(&pool, &chain, false, true).await.unwrap().valid);
+
+        // Re-signed by another key: the merkle root only covers transaction hashes, so only deep validation notices
+        let (forger_key, _) = crate::crypto::generate_keypair().unwrap();
+        let payload = create_transaction_payload(payer, payee, 1.0, transfer.timestamp, &transfer.note);
+        let forged = crate::crypto::sign_data(&forger_key, &payload).unwrap();
+        let client = pool.get().await.unwrap();
+        client
+            .execute(
+                "UPDATE transactions SET signature = $1 WHERE transaction_hash = $2",
+                &[&forged, &transfer.transaction_hash],
+            )
+            .await
+            .unwrap();
+
+        assert!(validate_blockchain(&pool, &chain, false, false).await.unwrap().valid);
+        let report = validate_blockchain(&pool, &chain, false, true).await.unwrap();
+        assert!(!report.valid);
+        assert_eq!(report.first_invalid_index, Some(block.index));
+        assert_eq!(report.reason, Some(format!("Invalid signature on transaction {}", transfer.transaction_hash)));
+
+        // A zakat deduction "signed" with the unkeyed hash anyone can compute isn't a system signature
+        client
+            .execute(
+                "UPDATE transactions SET signature = $1 WHERE transaction_hash = $2",
+                &[&transfer.signature, &transfer.transaction_hash],
+            )
+            .await
+            .unwrap();
+        let deduction = block.transactions.iter().find(|tx| tx.sender_wallet_id == "saver").unwrap();
+        let payload = create_transaction_payload("saver", &deduction.receiver_wallet_id, from_units(deduction.amount), deduction.timestamp, &deduction.note);
+        let unkeyed = sha256_hash(format!("{}_{}", crate::services::zakat_service::DEDUCTION_SIGNATURE_TAG, payload).as_bytes());
+        client
+            .execute(
+                "UPDATE transactions SET signature = $1 WHERE transaction_hash = $2",
+                &[&unkeyed, &deduction.transaction_hash],
+            )
+            .await
+            .unwrap();
+
+        let report = validate_blockchain(&pool, &chain, false, true).await.unwrap();
+        assert!(!report.valid);
+        assert_eq!(report.reason, Some(format!("Sender saver of transaction {} has no signing key", deduction.transaction_hash)));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_mined_block_reports_reward() {
+        let Some(pool) = crate::database::create_test_pool("mined_reward").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig {
+            mode: crate::config::ChainMode::Regtest,
+            mining_difficulty: 0,
+            ..ChainConfig::default()
+        };
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let mut wallets = Vec::new();
+        for (email, cnic) in [("payer@example.com", "1"), ("payee@example.com", "2")] {
+            wallets.push(crate::database::register_test_user(&pool, email, cnic).await.wallet_id);
+        }
+        let (sender, receiver) = (&wallets[0], &wallets[1]);
+        let client = pool.get().await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "miner").await.unwrap();
+        drop(client);
+
+        // An empty block pays only the subsidy
+        let empty = mine_block(&pool, sender, &chain, &Shutdown::new()).await.unwrap();
+        assert_eq!(empty.block_reward, to_units(calculate_block_reward(empty.block.index as i32, &chain)));
+        assert_eq!((empty.total_fees, empty.total_reward), (0, empty.block_reward));
+
+        let request = crate::models::CreateTransactionRequest {
+            sender_wallet_id: sender.clone(),
+            receiver_wallet_id: receiver.clone(),
+            amount: 1.0,
+            note: None,
+            private_note: false,
+            payment_request_id: None,
+        };
+        let pending = crate::services::transaction_service::create_transaction(&pool, request, &chain, &aes_key)
+            .await
+            .unwrap();
+        let mined = mine_block(&pool, "miner", &chain, &Shutdown::new()).await.unwrap();
+
+        assert_eq!(mined.block.transactions.len(), 1);
+        assert_eq!(mined.total_fees, pending.fee);
+        assert_eq!(
+            mined.total_reward,
+            to_units(calculate_block_reward(mined.block.index as i32, &chain)) + pending.fee
+        );
+        let client = pool.get().await.unwrap();
+        assert_eq!(to_units(calculate_wallet_balance(&client, "miner").await.unwrap()), mined.total_reward);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_receiver_frozen_mid_mine_keeps_chain_valid() {
+        let chain = ChainConfig {
+            mode: crate::config::ChainMode::Regtest,
+            mining_difficulty: 0,
+            ..ChainConfig::default()
+        };
+        let Some((pool, sender, receiver)) = crate::database::create_funded_test_pair("frozen_mid_mine", &chain, 10.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+        let (sender, receiver) = (&sender.wallet_id, &receiver.wallet_id);
+        let client = pool.get().await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "miner").await.unwrap();
+        drop(client);
+
+        let request = crate::models::CreateTransactionRequest {
+            sender_wallet_id: sender.clone(),
+            receiver_wallet_id: receiver.clone(),
+            amount: 1.0,
+            note: None,
+            private_note: false,
+            payment_request_id: None,
+        };
+        let pending = crate::services::transaction_service::create_transaction(&pool, request, &chain, &aes_key)
+            .await
+            .unwrap();
+
+        // The transfer is planned into the block, then its receiver is frozen before the block is written
+        let mut client = pool.get().await.unwrap();
+        let mut plan = plan_block(&client, "miner", &chain).await.unwrap();
+        assert_eq!(plan.included.len(), 1);
+        let work = proof_of_work(&mut plan.block, 0).unwrap();
+        crate::database::queries::set_wallet_status(&client, receiver, WalletStatus::Frozen).await.unwrap();
+        assert!(write_block(&mut client, plan, &work, "miner", &chain).await.unwrap().is_none());
+        drop(client);
+
+        // Mining again leaves the transfer pending and the chain valid
+        let mined = mine_block(&pool, "miner", &chain, &Shutdown::new()).await.unwrap();
+        assert!(mined.block.transactions.is_empty());
+        let report = validate_blockchain(&pool, &chain, false, false).await.unwrap();
+        assert!(report.valid, "{:?}", report);
+        let client = pool.get().await.unwrap();
+        let still_pending = crate::database::queries::get_pending_transactions(&client).await.unwrap();
+        assert!(still_pending.iter().any(|tx| tx.transaction_hash == pending.transaction_hash));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_coinbase_spendable_once_mature() {
+        use crate::services::transaction_service::{create_transaction, TransactionError};
+
+        let Some(pool) = crate::database::create_test_pool("coinbase_maturity").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig {
+            mode: crate::config::ChainMode::Regtest,
+            mining_difficulty: 0,
+            coinbase_maturity: 2,
+            ..ChainConfig::default()
+        };
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let mut wallets = Vec::new();
+        for (email, cnic) in [("payer@example.com", "1"), ("payee@example.com", "2")] {
+            wallets.push(crate::database::register_test_user(&pool, email, cnic).await.wallet_id);
+        }
+        let (sender, receiver) = (&wallets[0], &wallets[1]);
+        let client = pool.get().await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "miner").await.unwrap();
+        drop(client);
+        let transfer = || crate::models::CreateTransactionRequest {
+            sender_wallet_id: sender.clone(),
+            receiver_wallet_id: receiver.clone(),
+            amount: 1.0,
+            note: None,
+            private_note: false,
+            payment_request_id: None,
+        };
+
+        // The sender's only coins are a reward with fewer than two blocks on top of it
+        mine_block(&pool, sender, &chain, &Shutdown::new()).await.unwrap();
+        for _ in 0..2 {
+            let result = create_transaction(&pool, transfer(), &chain, &aes_key).await;
+            assert!(matches!(result, Err(TransactionError::InsufficientBalance)));
+            mine_block(&pool, "miner", &chain, &Shutdown::new()).await.unwrap();
+        }
+
+        create_transaction(&pool, transfer(), &chain, &aes_key).await.unwrap();
+        let mined = mine_block(&pool, "miner", &chain, &Shutdown::new()).await.unwrap();
+        assert_eq!(mined.block.transactions.len(), 1);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_supply_leaves_out_immature_coinbase() {
+        let Some(pool) = crate::database::create_test_pool("supply_immature").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig {
+            mode: crate::config::ChainMode::Regtest,
+            mining_difficulty: 0,
+            coinbase_maturity: 1,
+            ..ChainConfig::default()
+        };
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+        let client = pool.get().await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "miner").await.unwrap();
+        drop(client);
+
+        let supply = || async {
+            let client = pool.get().await.unwrap();
+            let total_mined = get_total_mined_coins(&client).await.unwrap();
+            let immature = crate::database::queries::get_immature_coinbase_total(&client, None, chain.coinbase_maturity)
+                .await
+                .unwrap();
+            build_supply_info(&chain, total_mined, 0.0, from_units(immature), 0.0)
+        };
+
+        // The newest reward has no block on top of it yet
+        let first = mine_block(&pool, "miner", &chain, &Shutdown::new()).await.unwrap();
+        let after_first = supply().await;
+        assert_eq!(after_first.immature, from_units(first.total_reward));
+        assert_eq!(after_first.circulating, after_first.total_mined - from_units(first.total_reward));
+
+        // Mining on top matures it, and the new reward takes its place
+        let second = mine_block(&pool, "miner", &chain, &Shutdown::new()).await.unwrap();
+        let after_second = supply().await;
+        assert_eq!(after_second.immature, from_units(second.total_reward));
+        assert_eq!(after_second.circulating, after_first.total_mined);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_validation_checks_coinbase_reward() {
+        // Second block's subsidy is capped at the 10 coins left after the first
+        let chain = ChainConfig { mining_difficulty: 1, max_coin_supply: 60.0, ..ChainConfig::default() };
+        let Some((pool, sender, miner)) = crate::database::create_funded_test_pair("coinbase_validation", &chain, 100.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        // Block 1 carries a fee, block 2 is capped
+        let request = crate::models::CreateTransactionRequest {
+            sender_wallet_id: sender.wallet_id.clone(),
+            receiver_wallet_id: miner.wallet_id.clone(),
+            amount: 5.0,
+            note: None,
+            private_note: false,
+            payment_request_id: None,
+        };
+        crate::services::transaction_service::create_transaction(&pool, request, &chain, &aes_key)
+            .await
+            .unwrap();
+        let shutdown = Shutdown::new();
+        let first = mine_block(&pool, &miner.wallet_id, &chain, &shutdown).await.unwrap().block;
+        let second = mine_block(&pool, &miner.wallet_id, &chain, &shutdown).await.unwrap().block;
+
+        let client = pool.get().await.unwrap();
+        let first_coinbase = crate::database::queries::get_block_coinbase_amount(&client, first.index).await.unwrap();
+        let second_coinbase = crate::database::queries::get_block_coinbase_amount(&client, second.index).await.unwrap();
+        assert_eq!(first_coinbase, to_units(50.0 + chain.transaction_fee));
+        assert_eq!(second_coinbase, to_units(60.0) - first_coinbase);
+        drop(client);
+
+        // Correct and capped rewards validate; without the cap the second block looks underpaid
+        assert!(validate_blockchain(&pool, &chain, false, false).await.unwrap().valid);
+        let uncapped = ChainConfig { max_coin_supply: 1000.0, ..chain.clone() };
+        assert!(!validate_blockchain(&pool, &uncapped, false, false).await.unwrap().valid);
+
+        // Inflated reward
+        let client = pool.get().await.unwrap();
+        client
+            .execute(
+                "UPDATE utxos SET amount = amount + 1 FROM blocks b WHERE b.\"index\" = $1 AND utxos.transaction_hash = b.coinbase_hash",
+                &[&first.index],
+            )
+            .await
+            .unwrap();
+        drop(client);
+        assert!(!validate_blockchain(&pool, &chain, false, false).await.unwrap().valid);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_fee_burn_shrinks_coinbase_and_supply() {
+        let chain = ChainConfig {
+            mining_difficulty: 1,
+            transaction_fee: 1.0,
+            fee_burn_percentage: 50.0,
+            ..ChainConfig::default()
+        };
+        let Some((pool, sender, miner)) = crate::database::create_funded_test_pair("fee_burn", &chain, 100.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let client = pool.get().await.unwrap();
+        let unspent_before = crate::database::queries::get_unspent_supply(&client).await.unwrap();
+        drop(client);
+
+        let request = crate::models::CreateTransactionRequest {
+            sender_wallet_id: sender.wallet_id.clone(),
+            receiver_wallet_id: miner.wallet_id.clone(),
+            amount: 5.0,
+            note: None,
+            private_note: false,
+            payment_request_id: None,
+        };
+        crate::services::transaction_service::create_transaction(&pool, request, &chain, &aes_key)
+            .await
+            .unwrap();
+        let block = mine_block(&pool, &miner.wallet_id, &chain, &Shutdown::new()).await.unwrap().block;
+
+        // The miner gets the subsidy and half the fee; the other half leaves circulation
+        let client = pool.get().await.unwrap();
+        let coinbase = crate::database::queries::get_block_coinbase_amount(&client, block.index).await.unwrap();
+        assert_eq!(coinbase, to_units(50.5));
+        let burned = crate::database::queries::get_total_burned(&client).await.unwrap();
+        assert_eq!(burned, to_units(0.5));
+        // Recorded on the block itself; the log entry is informational only
+        client.execute("DELETE FROM system_logs WHERE log_type = 'fee_burn'", &[]).await.unwrap();
+        assert_eq!(crate::database::queries::get_total_burned(&client).await.unwrap(), burned);
+        let unspent_after = crate::database::queries::get_unspent_supply(&client).await.unwrap();
+        assert_eq!(unspent_after, unspent_before + 50.0 - 0.5);
+
+        let total_mined = get_total_mined_coins(&client).await.unwrap();
+        let supply = build_supply_info(&chain, total_mined, 0.0, 0.0, from_units(burned));
+        assert_eq!(supply.total_burned, 0.5);
+        assert_eq!(supply.circulating, 50.5);
+        drop(client);
+
+        // Validation expects the burn recorded on the block, even after the configured percentage changes
+        assert!(validate_blockchain(&pool, &chain, false, false).await.unwrap().valid);
+        let no_burn = ChainConfig { fee_burn_percentage: 0.0, ..chain.clone() };
+        assert!(validate_blockchain(&pool, &no_burn, false, false).await.unwrap().valid);
+        let client = pool.get().await.unwrap();
+        crate::database::queries::set_block_burned_fees(&client, block.index, 0).await.unwrap();
+        drop(client);
+        assert!(!validate_blockchain(&pool, &chain, false, false).await.unwrap().valid);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_genesis_allocations_fund_wallets() {
+        let pool = match crate::database::create_test_pool("genesis_allocations").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let allocation = |wallet_id: &str, amount: f64| crate::config::GenesisAllocation {
+            wallet_id: wallet_id.to_string(),
+            amount,
+        };
+        let genesis = GenesisConfig {
+            timestamp: 1_700_000_000,
+            message: None,
+            allocations: vec![allocation("treasury", 600.0), allocation("faucet", 400.0)],
+        };
+        let chain = ChainConfig {
+            mining_difficulty: 1,
+            max_coin_supply: 1030.0,
+            genesis: Some(genesis.clone()),
+            ..ChainConfig::default()
+        };
+
+        // More than the supply cap is refused before anything is written
+        let greedy = ChainConfig {
+            genesis: Some(GenesisConfig { allocations: vec![allocation("treasury", 2000.0)], ..genesis }),
+            ..chain.clone()
+        };
+        assert!(greedy.check_premine().is_err());
+        assert!(initialize_blockchain(pool.clone(), &greedy).await.is_err());
+
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+        let balance = |wallet_id: &'static str| {
+            let pool = pool.clone();
+            async move {
+                crate::services::wallet_service::get_wallet_balance(&pool, wallet_id)
+                    .await
+                    .unwrap()
+                    .balance
+            }
+        };
+        assert_eq!(balance("treasury").await, 600.0);
+        assert_eq!(balance("faucet").await, 400.0);
+
+        // The premine counts as mined, so the first block's subsidy is capped at what's left
+        let client = pool.get().await.unwrap();
+        assert_eq!(get_total_mined_coins(&client).await.unwrap(), 1000.0);
+        drop(client);
+        let block = mine_block(&pool, "treasury", &chain, &Shutdown::new()).await.unwrap().block;
+        let client = pool.get().await.unwrap();
+        assert_eq!(
+            crate::database::queries::get_block_coinbase_amount(&client, block.index).await.unwrap(),
+            to_units(30.0)
+        );
+        drop(client);
+        assert!(validate_blockchain(&pool, &chain, false, false).await.unwrap().valid);
+    }
 }
@@ -0,0 +1,304 @@
+use crate::models::UTXO;
+use std::env;
+
+/// Strategy for picking which UTXOs fund a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoinSelection {
+    /// Spend the oldest outputs first (the original behaviour)
+    OldestFirst,
+    /// Spend the largest outputs first, using as few inputs as possible
+    LargestFirst,
+    /// Prefer the selection that leaves the smallest change output
+    MinimizeChange,
+    /// Search for an exact match (no change), falling back to `MinimizeChange`
+    BranchAndBound,
+}
+
+/// Upper bound on branch-and-bound search steps before giving up
+const MAX_BNB_TRIES: usize = 100_000;
+
+impl CoinSelection {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "oldest_first" => Some(CoinSelection::OldestFirst),
+            "largest_first" => Some(CoinSelection::LargestFirst),
+            "minimize_change" => Some(CoinSelection::MinimizeChange),
+            "branch_and_bound" | "bnb" => Some(CoinSelection::BranchAndBound),
+            _ => None,
+        }
+    }
+
+    /// Read `COIN_SELECTION`, defaulting to branch-and-bound
+    pub fn from_env() -> Self {
+        env::var("COIN_SELECTION")
+            .ok()
+            .and_then(|v| CoinSelection::parse(&v.to_lowercase()))
+            .unwrap_or(CoinSelection::BranchAndBound)
+    }
+}
+
+/// Pick UTXOs covering `target` (in smallest units) using the configured strategy.
+/// Returns an empty list if the UTXOs can't cover the target.
+pub fn select_utxos(utxos: &[UTXO], target: i64) -> Vec<UTXO> {
+    select_utxos_with(utxos, target, CoinSelection::from_env())
+}
+
+pub fn select_utxos_with(utxos: &[UTXO], target: i64, strategy: CoinSelection) -> Vec<UTXO> {
+    let available: i64 = utxos.iter().map(|u| u.amount).sum();
+    if target <= 0 || available < target {
+        return Vec::new();
+    }
+
+    let selected = match strategy {
+        CoinSelection::OldestFirst => {
+            let mut ordered: Vec<&UTXO> = utxos.iter().collect();
+            ordered.sort_by_key(|u| u.created_at);
+            accumulate(&ordered, target)
+        }
+        CoinSelection::LargestFirst => largest_first(utxos, target),
+        CoinSelection::MinimizeChange => minimize_change(utxos, target),
+        CoinSelection::BranchAndBound => {
+            branch_and_bound(utxos, target).unwrap_or_else(|| minimize_change(utxos, target))
+        }
+    };
+
+    selected.into_iter().cloned().collect()
+}
+
+/// Unspent, unreserved UTXOs strictly below `dust_threshold`, oldest first
+pub fn select_dust(utxos: &[UTXO], dust_threshold: i64) -> Vec<UTXO> {
+    let mut dust: Vec<UTXO> = utxos
+        .iter()
+        .filter(|u| !u.is_spent && u.reserved_by.is_none() && u.amount < dust_threshold)
+        .cloned()
+        .collect();
+    dust.sort_by_key(|u| u.created_at);
+    dust
+}
+
+/// Dust to merge and the amount of the single output it becomes (total minus `fee`).
+/// `None` when there are fewer than two dust outputs or they can't cover the fee.
+pub fn plan_consolidation(utxos: &[UTXO], dust_threshold: i64, fee: i64) -> Option<(Vec<UTXO>, i64)> {
+    let dust = select_dust(utxos, dust_threshold);
+    let total: i64 = dust.iter().map(|u| u.amount).sum();
+    let amount = total - fee;
+
+    if dust.len() < 2 || amount <= 0 {
+        return None;
+    }
+
+    Some((dust, amount))
+}
+
+fn accumulate<'a>(ordered: &[&'a UTXO], target: i64) -> Vec<&'a UTXO> {
+    let mut total = 0;
+    let mut selected = Vec::new();
+    for utxo in ordered {
+        if total >= target {
+            break;
+        }
+        total += utxo.amount;
+        selected.push(*utxo);
+    }
+    selected
+}
+
+fn largest_first(utxos: &[UTXO], target: i64) -> Vec<&UTXO> {
+    let mut ordered: Vec<&UTXO> = utxos.iter().collect();
+    ordered.sort_by_key(|u| std::cmp::Reverse(u.amount));
+    accumulate(&ordered, target)
+}
+
+fn minimize_change(utxos: &[UTXO], target: i64) -> Vec<&UTXO> {
+    let mut pool: Vec<&UTXO> = utxos.iter().collect();
+    pool.sort_by_key(|u| u.amount);
+
+    let mut picked: Vec<&UTXO> = Vec::new();
+    let mut remaining = target;
+    // (change, inputs) of the best complete selection so far
+    let mut best: Option<(i64, Vec<&UTXO>)> = None;
+
+    loop {
+        // Finishing with the smallest output that covers the remainder is one candidate...
+        if let Some(pos) = pool.iter().position(|u| u.amount >= remaining) {
+            let change = pool[pos].amount - remaining;
+            let better = match &best {
+                Some((best_change, best_inputs)) => {
+                    change < *best_change || (change == *best_change && picked.len() + 1 < best_inputs.len())
+                }
+                None => true,
+            };
+            if better {
+                let mut candidate = picked.clone();
+                candidate.push(pool[pos]);
+                best = Some((change, candidate));
+            }
+            if change == 0 {
+                break;
+            }
+        }
+
+        // ...otherwise keep the largest output below the remainder and continue
+        match pool.iter().rposition(|u| u.amount < remaining) {
+            Some(pos) => {
+                remaining -= pool[pos].amount;
+                picked.push(pool.remove(pos));
+            }
+            None => break,
+        }
+    }
+
+    best.map(|(_, inputs)| inputs).unwrap_or_else(|| largest_first(utxos, target))
+}
+
+/// Depth-first search over UTXOs (largest first) for a selection summing exactly to `target`
+fn branch_and_bound(utxos: &[UTXO], target: i64) -> Option<Vec<&UTXO>> {
+    let mut ordered: Vec<&UTXO> = utxos.iter().collect();
+    ordered.sort_by_key(|u| std::cmp::Reverse(u.amount));
+    let amounts: Vec<i64> = ordered.iter().map(|u| u.amount).collect();
+
+    // remaining[i] = sum of amounts[i..], used to prune branches that can't reach the target
+    let mut remaining = vec![0; amounts.len() + 1];
+    for i in (0..amounts.len()).rev() {
+        remaining[i] = remaining[i + 1] + amounts[i];
+    }
+
+    // Depth-first with an explicit stack, so a wallet with many UTXOs can't exhaust the call stack.
+    // `picked` is the current path: each step tries including the next UTXO, and backtracking
+    // drops the most recent inclusion to explore the branch without it.
+    let mut picked: Vec<usize> = Vec::new();
+    let mut total = 0;
+    let mut index = 0;
+    let mut tries = 0;
+
+    loop {
+        tries += 1;
+        if total == target {
+            return Some(picked.iter().map(|&i| ordered[i]).collect());
+        }
+        if tries > MAX_BNB_TRIES {
+            return None;
+        }
+
+        if total < target && index < amounts.len() && total + remaining[index] >= target {
+            picked.push(index);
+            total += amounts[index];
+            index += 1;
+            continue;
+        }
+
+        let last = picked.pop()?;
+        total -= amounts[last];
+        index = last + 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::to_units;
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    /// UTXOs created in the given order (first is oldest)
+    fn utxos(amounts: &[f64]) -> Vec<UTXO> {
+        let start = Utc::now();
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(i, &amount)| UTXO {
+                id: Uuid::new_v4(),
+                wallet_id: "wallet".to_string(),
+                amount: to_units(amount),
+                transaction_hash: format!("tx{}", i),
+                output_index: 0,
+                is_spent: false,
+                created_at: start + Duration::seconds(i as i64),
+                spent_at: None,
+                reserved_by: None,
+            })
+            .collect()
+    }
+
+    /// (number of inputs, change in smallest units)
+    fn outcome(set: &[UTXO], target: f64, strategy: CoinSelection) -> (usize, i64) {
+        let selected = select_utxos_with(set, to_units(target), strategy);
+        let total: i64 = selected.iter().map(|u| u.amount).sum();
+        (selected.len(), total - to_units(target))
+    }
+
+    #[test]
+    fn test_strategies_compared() {
+        let set = utxos(&[0.5, 1.0, 1.0, 2.0, 10.0, 3.0]);
+
+        assert_eq!(outcome(&set, 4.0, CoinSelection::OldestFirst), (4, 50_000_000));
+        assert_eq!(outcome(&set, 4.0, CoinSelection::LargestFirst), (1, 600_000_000));
+        assert_eq!(outcome(&set, 4.0, CoinSelection::MinimizeChange), (2, 0));
+        assert_eq!(outcome(&set, 4.0, CoinSelection::BranchAndBound), (2, 0));
+    }
+
+    #[test]
+    fn test_minimize_change_prefers_single_close_match() {
+        let set = utxos(&[0.3, 0.3, 0.3, 5.1, 8.0]);
+        assert_eq!(outcome(&set, 5.0, CoinSelection::MinimizeChange), (1, 10_000_000));
+        // No exact match exists, so branch-and-bound falls back to minimize-change
+        assert_eq!(outcome(&set, 5.0, CoinSelection::BranchAndBound), (1, 10_000_000));
+    }
+
+    #[test]
+    fn test_branch_and_bound_handles_many_inputs() {
+        // Deep enough that one stack frame per UTXO would overflow a thread's stack
+        let set = utxos(&vec![0.00000001; 50_000]);
+        let selected = select_utxos_with(&set, 40_000, CoinSelection::BranchAndBound);
+        assert_eq!(selected.len(), 40_000);
+        assert_eq!(selected.iter().map(|u| u.amount).sum::<i64>(), 40_000);
+    }
+
+    #[test]
+    fn test_insufficient_funds_selects_nothing() {
+        let set = utxos(&[1.0, 2.0]);
+        for strategy in [
+            CoinSelection::OldestFirst,
+            CoinSelection::LargestFirst,
+            CoinSelection::MinimizeChange,
+            CoinSelection::BranchAndBound,
+        ] {
+            assert!(select_utxos_with(&set, to_units(3.5), strategy).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_select_dust() {
+        let mut set = utxos(&[0.2, 5.0, 0.1, 0.4, 1.0]);
+        set[3].reserved_by = Some(Uuid::new_v4());
+
+        let dust: Vec<i64> = select_dust(&set, to_units(1.0)).iter().map(|u| u.amount).collect();
+        assert_eq!(dust, vec![to_units(0.2), to_units(0.1)]);
+    }
+
+    #[test]
+    fn test_consolidation_merges_into_single_output() {
+        let mut amounts = vec![0.01; 40];
+        amounts.push(25.0);
+        let set = utxos(&amounts);
+
+        let (dust, amount) = plan_consolidation(&set, to_units(1.0), to_units(0.1)).unwrap();
+        assert_eq!(dust.len(), 40);
+        assert_eq!(amount, to_units(0.3));
+
+        // Mining pays `amount` back to the wallet and leaves no change output
+        let spent: i64 = dust.iter().map(|u| u.amount).sum();
+        assert_eq!(spent - amount - to_units(0.1), 0);
+
+        // Nothing worth merging
+        assert!(plan_consolidation(&utxos(&[0.5, 3.0]), to_units(1.0), to_units(0.1)).is_none());
+        assert!(plan_consolidation(&utxos(&[0.02, 0.03]), to_units(1.0), to_units(0.1)).is_none());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(CoinSelection::parse("bnb"), Some(CoinSelection::BranchAndBound));
+        assert_eq!(CoinSelection::parse("largest_first"), Some(CoinSelection::LargestFirst));
+        assert_eq!(CoinSelection::parse("random"), None);
+    }
+}
@@ -1,35 +1,736 @@
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use serde::Deserialize;
 use std::env;
 
 pub struct Config {
     pub database_url: String,
-    pub jwt_secret: String,
     pub aes_key: Vec<u8>,
+    /// HMAC key for payment request URIs (see `parse_payment_request_secret`)
+    pub payment_request_secret: String,
+    pub zakat_percentage: f64,
+    pub password_policy: PasswordPolicy,
+}
+
+/// Requirements a new password must meet (see `auth_service::validate_password`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_digit: bool,
+    pub require_uppercase: bool,
+    pub require_symbol: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            require_digit: true,
+            require_uppercase: false,
+            require_symbol: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let defaults = PasswordPolicy::default();
+
+        Ok(PasswordPolicy {
+            min_length: parse_or("PASSWORD_MIN_LENGTH", defaults.min_length)?,
+            require_digit: parse_or("PASSWORD_REQUIRE_DIGIT", defaults.require_digit)?,
+            require_uppercase: parse_or("PASSWORD_REQUIRE_UPPERCASE", defaults.require_uppercase)?,
+            require_symbol: parse_or("PASSWORD_REQUIRE_SYMBOL", defaults.require_symbol)?,
+        })
+    }
+}
+
+/// Consensus and economic parameters of the chain. Loaded once at startup and shared as
+/// app data, so reward calculation, mining and reporting can't disagree on defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainConfig {
+    pub mode: ChainMode,
+    /// Leading zero bits a block hash needs; each step doubles the expected work
     pub mining_difficulty: usize,
     pub block_reward: f64,
-    pub zakat_percentage: f64,
+    /// Blocks between reward halvings
+    pub halving_interval: i32,
+    pub max_coin_supply: f64,
+    pub transaction_fee: f64,
+    /// Smallest amount a user transfer may send, keeping dust out of the UTXO set
+    pub min_transaction_amount: f64,
+    /// Longest note, in characters, a user transfer may carry
+    pub max_note_length: usize,
+    /// Percentage (0-100) of each block's fees destroyed instead of paid to the miner
+    pub fee_burn_percentage: f64,
+    /// Blocks that must be mined on top of a coinbase before its coins can be spent
+    pub coinbase_maturity: i64,
+    /// Owner-less wallet that collects zakat deductions and pays out distributions
     pub zakat_pool_wallet_id: String,
+    /// Fixed genesis contents from `GENESIS_CONFIG`; `None` mines a fresh genesis stamped with the current time
+    pub genesis: Option<GenesisConfig>,
+}
+
+/// Which network the node runs, from `CHAIN_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ChainMode {
+    #[default]
+    Mainnet,
+    /// Local testing: difficulty 0 so blocks mine instantly, and `POST /blockchain/generate/{n}` is enabled
+    Regtest,
+}
+
+impl std::str::FromStr for ChainMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mainnet" => Ok(ChainMode::Mainnet),
+            "regtest" => Ok(ChainMode::Regtest),
+            other => Err(format!("unknown chain mode '{}', expected mainnet or regtest", other)),
+        }
+    }
+}
+
+/// Genesis block parameters read from a JSON file, so every node given the same file
+/// builds the same genesis block
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GenesisConfig {
+    /// Unix timestamp of the genesis block
+    pub timestamp: i64,
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Coins credited at genesis, as coinbase outputs
+    #[serde(default)]
+    pub allocations: Vec<GenesisAllocation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GenesisAllocation {
+    pub wallet_id: String,
+    pub amount: f64,
+}
+
+impl GenesisConfig {
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read GENESIS_CONFIG {}: {}", path, e))?;
+        let config: GenesisConfig = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid GENESIS_CONFIG {}: {}", path, e))?;
+
+        if config
+            .allocations
+            .iter()
+            .any(|a| a.wallet_id.trim().is_empty() || !a.amount.is_finite() || a.amount <= 0.0)
+        {
+            return Err("Genesis allocations need a wallet_id and a positive amount".into());
+        }
+
+        Ok(config)
+    }
+
+    /// Coins the allocations premine
+    pub fn total_allocated(&self) -> f64 {
+        self.allocations.iter().map(|a| a.amount).sum()
+    }
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        ChainConfig {
+            mode: ChainMode::Mainnet,
+            mining_difficulty: 20,
+            block_reward: 50.0,
+            halving_interval: 210,
+            max_coin_supply: 21_000_000.0,
+            transaction_fee: 0.1,
+            min_transaction_amount: 0.00000001,
+            max_note_length: 256,
+            fee_burn_percentage: 0.0,
+            coinbase_maturity: 0,
+            zakat_pool_wallet_id: "ZAKAT_POOL".to_string(),
+            genesis: None,
+        }
+    }
+}
+
+impl ChainConfig {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let defaults = ChainConfig::default();
+
+        let mut config = ChainConfig {
+            mode: parse_or("CHAIN_MODE", defaults.mode)?,
+            mining_difficulty: parse_or("MINING_DIFFICULTY", defaults.mining_difficulty)?,
+            block_reward: parse_or("BLOCK_REWARD", defaults.block_reward)?,
+            halving_interval: parse_or("HALVING_INTERVAL", defaults.halving_interval)?,
+            max_coin_supply: parse_or("MAX_COIN_SUPPLY", defaults.max_coin_supply)?,
+            transaction_fee: parse_or("TRANSACTION_FEE", defaults.transaction_fee)?,
+            min_transaction_amount: parse_or("MIN_TRANSACTION_AMOUNT", defaults.min_transaction_amount)?,
+            max_note_length: parse_or("MAX_NOTE_LENGTH", defaults.max_note_length)?,
+            fee_burn_percentage: parse_or("FEE_BURN_PERCENTAGE", defaults.fee_burn_percentage)?,
+            coinbase_maturity: parse_or("COINBASE_MATURITY", defaults.coinbase_maturity)?,
+            zakat_pool_wallet_id: env::var("ZAKAT_POOL_WALLET_ID").unwrap_or(defaults.zakat_pool_wallet_id),
+            genesis: env::var("GENESIS_CONFIG")
+                .ok()
+                .map(|path| GenesisConfig::from_file(&path))
+                .transpose()?,
+        };
+        if config.mode == ChainMode::Regtest {
+            config.mining_difficulty = 0;
+        }
+
+        if config.mining_difficulty > 256 {
+            return Err("MINING_DIFFICULTY can't exceed the 256 bits of a block hash".into());
+        }
+        if config.halving_interval <= 0 {
+            return Err("HALVING_INTERVAL must be positive".into());
+        }
+        if config.block_reward < 0.0 || config.max_coin_supply <= 0.0 || config.transaction_fee < 0.0 {
+            return Err("BLOCK_REWARD and TRANSACTION_FEE must be non-negative and MAX_COIN_SUPPLY positive".into());
+        }
+        if !config.min_transaction_amount.is_finite() || config.min_transaction_amount <= 0.0 {
+            return Err("MIN_TRANSACTION_AMOUNT must be positive".into());
+        }
+        if !(0.0..=100.0).contains(&config.fee_burn_percentage) {
+            return Err("FEE_BURN_PERCENTAGE must be between 0 and 100".into());
+        }
+        if config.coinbase_maturity < 0 {
+            return Err("COINBASE_MATURITY can't be negative".into());
+        }
+        if config.zakat_pool_wallet_id.trim().is_empty() {
+            return Err("ZAKAT_POOL_WALLET_ID can't be empty".into());
+        }
+        config.check_premine()?;
+
+        Ok(config)
+    }
+
+    /// Genesis allocations count against `max_coin_supply` like any other coinbase
+    pub fn check_premine(&self) -> Result<(), String> {
+        match &self.genesis {
+            Some(genesis) if genesis.total_allocated() > self.max_coin_supply => Err(format!(
+                "Genesis allocations total {} but MAX_COIN_SUPPLY is {}",
+                genesis.total_allocated(),
+                self.max_coin_supply
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Deployment environment from `APP_ENV`; anything other than `production` is development
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AppEnv {
+    Development,
+    Production,
+}
+
+impl AppEnv {
+    pub fn from_env() -> Self {
+        match env::var("APP_ENV") {
+            Ok(value) if value.trim().eq_ignore_ascii_case("production") => AppEnv::Production,
+            _ => AppEnv::Development,
+        }
+    }
+}
+
+/// HS256 secret used when `JWT_SECRET` is unset outside production; anyone can forge tokens with it
+pub const DEFAULT_JWT_SECRET: &str = "default-secret-change-in-production";
+
+/// How session tokens are signed and checked: HS256 with `JWT_SECRET`, or RS256 with the
+/// PEM keypair at `JWT_PRIVATE_KEY_PATH` / `JWT_PUBLIC_KEY_PATH`
+#[derive(Clone)]
+pub struct JwtConfig {
+    pub algorithm: Algorithm,
+    pub encoding_key: EncodingKey,
+    pub decoding_key: DecodingKey,
+    /// Hours a token stays valid after it is issued
+    pub expiry_hours: i64,
+    /// Written into `iss` and required on verification when set
+    pub issuer: Option<String>,
+    /// Written into `aud` and required on verification when set
+    pub audience: Option<String>,
+}
+
+impl JwtConfig {
+    /// HS256 with `secret`, a 24-hour expiry and no issuer or audience
+    pub fn hs256(secret: &str) -> Self {
+        JwtConfig {
+            algorithm: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            expiry_hours: 24,
+            issuer: None,
+            audience: None,
+        }
+    }
+
+    /// RS256 with a PEM private key (PKCS#1 or PKCS#8) and its public key
+    pub fn rs256(private_key_pem: &str, public_key_pem: &str) -> Result<Self, String> {
+        Ok(JwtConfig {
+            algorithm: Algorithm::RS256,
+            encoding_key: EncodingKey::from_rsa_pem(private_key_pem.as_bytes())
+                .map_err(|e| format!("Invalid JWT private key: {}", e))?,
+            decoding_key: DecodingKey::from_rsa_pem(public_key_pem.as_bytes())
+                .map_err(|e| format!("Invalid JWT public key: {}", e))?,
+            ..JwtConfig::hs256("")
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(JwtConfig::load(AppEnv::from_env(), &|name| env::var(name).ok())?)
+    }
+
+    /// Build from settings looked up through `var`. The HS256 fallback secret is refused
+    /// in production and only warned about elsewhere.
+    pub fn load(app_env: AppEnv, var: &dyn Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let algorithm = var("JWT_ALGORITHM").unwrap_or_else(|| "HS256".to_string());
+        let config = match algorithm.trim().to_uppercase().as_str() {
+            "HS256" => {
+                let secret = var("JWT_SECRET").filter(|s| !s.trim().is_empty() && s != DEFAULT_JWT_SECRET);
+                match (secret, app_env) {
+                    (Some(secret), _) => JwtConfig::hs256(&secret),
+                    (None, AppEnv::Production) => {
+                        return Err("JWT_SECRET must be set to a real secret when APP_ENV=production".to_string())
+                    }
+                    (None, AppEnv::Development) => {
+                        log::warn!("⚠️ JWT_SECRET is not set; tokens are signed with a public default secret and can be forged. Never run like this in production");
+                        JwtConfig::hs256(DEFAULT_JWT_SECRET)
+                    }
+                }
+            }
+            "RS256" => {
+                let read = |name: &str| -> Result<String, String> {
+                    let path = var(name).ok_or_else(|| format!("{} is required with JWT_ALGORITHM=RS256", name))?;
+                    std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {} {}: {}", name, path, e))
+                };
+                JwtConfig::rs256(&read("JWT_PRIVATE_KEY_PATH")?, &read("JWT_PUBLIC_KEY_PATH")?)?
+            }
+            other => return Err(format!("Unsupported JWT_ALGORITHM {}; use HS256 or RS256", other)),
+        };
+
+        let expiry_hours = match var("JWT_EXPIRY_HOURS") {
+            Some(value) => value.trim().parse().map_err(|e| format!("Invalid JWT_EXPIRY_HOURS: {}", e))?,
+            None => config.expiry_hours,
+        };
+        if expiry_hours <= 0 {
+            return Err("JWT_EXPIRY_HOURS must be positive".to_string());
+        }
+        let non_empty = |name: &str| var(name).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+
+        Ok(JwtConfig {
+            expiry_hours,
+            issuer: non_empty("JWT_ISSUER"),
+            audience: non_empty("JWT_AUDIENCE"),
+            ..config
+        })
+    }
+}
+
+/// Database connection pool sizing and timeouts, from `DB_POOL_SIZE`, `DB_CONNECT_TIMEOUT`
+/// and `DB_RECYCLE_TIMEOUT` (seconds)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbPoolConfig {
+    pub max_size: usize,
+    /// Seconds allowed to open a new connection
+    pub connect_timeout_secs: u64,
+    /// Seconds allowed for the health check when an idle connection is reused
+    pub recycle_timeout_secs: u64,
+}
+
+impl Default for DbPoolConfig {
+    fn default() -> Self {
+        DbPoolConfig {
+            max_size: 10,
+            connect_timeout_secs: 10,
+            recycle_timeout_secs: 5,
+        }
+    }
+}
+
+impl DbPoolConfig {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let defaults = DbPoolConfig::default();
+
+        let config = DbPoolConfig {
+            max_size: parse_or("DB_POOL_SIZE", defaults.max_size)?,
+            connect_timeout_secs: parse_or("DB_CONNECT_TIMEOUT", defaults.connect_timeout_secs)?,
+            recycle_timeout_secs: parse_or("DB_RECYCLE_TIMEOUT", defaults.recycle_timeout_secs)?,
+        };
+
+        if config.max_size == 0 || config.connect_timeout_secs == 0 || config.recycle_timeout_secs == 0 {
+            return Err("DB_POOL_SIZE, DB_CONNECT_TIMEOUT and DB_RECYCLE_TIMEOUT must be positive".into());
+        }
+
+        Ok(config)
+    }
+}
+
+impl std::fmt::Display for DbPoolConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "max {} connections, connect timeout {}s, recycle timeout {}s",
+            self.max_size, self.connect_timeout_secs, self.recycle_timeout_secs
+        )
+    }
+}
+
+/// Who may read a wallet's balance, UTXOs and history. Public by default, for explorer use;
+/// `REQUIRE_AUTH_FOR_WALLET_READS=true` limits those reads to the wallet owner and admins.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WalletReadPolicy {
+    pub require_auth: bool,
+}
+
+impl WalletReadPolicy {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(WalletReadPolicy {
+            require_auth: parse_or("REQUIRE_AUTH_FOR_WALLET_READS", false)?,
+        })
+    }
+}
+
+/// Largest request bodies accepted, in bytes: `MAX_JSON_BODY_BYTES` for JSON payloads and
+/// `MAX_CSV_BODY_BYTES` for CSV uploads. Larger bodies get `413`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BodyLimits {
+    pub json: usize,
+    pub csv: usize,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        BodyLimits { json: 64 * 1024, csv: 256 * 1024 }
+    }
+}
+
+impl BodyLimits {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let defaults = BodyLimits::default();
+        let limits = BodyLimits {
+            json: parse_or("MAX_JSON_BODY_BYTES", defaults.json)?,
+            csv: parse_or("MAX_CSV_BODY_BYTES", defaults.csv)?,
+        };
+        if limits.json == 0 || limits.csv == 0 {
+            return Err("MAX_JSON_BODY_BYTES and MAX_CSV_BODY_BYTES must be positive".into());
+        }
+        Ok(limits)
+    }
+}
+
+/// Origins CORS accepts, parsed once at startup from `ALLOWED_ORIGINS` (comma-separated;
+/// `*.example.com` or `https://*.example.com` match any subdomain) and `ALLOW_ANY_ORIGIN`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsOrigins {
+    /// Development only: accept every origin
+    pub allow_any: bool,
+    pub origins: Vec<String>,
+}
+
+impl CorsOrigins {
+    pub const DEFAULT_ORIGIN: &'static str = "http://localhost:5173";
+
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let origins = match env::var("ALLOWED_ORIGINS") {
+            Ok(list) => list
+                .split(',')
+                .map(|o| o.trim().trim_end_matches('/').to_string())
+                .filter(|o| !o.is_empty())
+                .collect(),
+            Err(_) => {
+                log::warn!("ALLOWED_ORIGINS not set, allowing only {}", Self::DEFAULT_ORIGIN);
+                vec![Self::DEFAULT_ORIGIN.to_string()]
+            }
+        };
+
+        Ok(CorsOrigins {
+            allow_any: parse_or("ALLOW_ANY_ORIGIN", false)?,
+            origins,
+        })
+    }
+
+    pub fn allows(&self, origin: &str) -> bool {
+        self.allow_any || self.origins.iter().any(|pattern| origin_matches(pattern, origin))
+    }
+}
+
+impl std::fmt::Display for CorsOrigins {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.allow_any {
+            write!(f, "any origin (ALLOW_ANY_ORIGIN)")
+        } else if self.origins.is_empty() {
+            write!(f, "no origins")
+        } else {
+            write!(f, "{}", self.origins.join(", "))
+        }
+    }
+}
+
+/// Exact match (ignoring case), or for `[scheme://]*.domain` any subdomain of `domain`
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let origin = origin.to_ascii_lowercase();
+
+    match pattern.split_once("*.") {
+        Some((scheme, domain)) => {
+            let host = if scheme.is_empty() {
+                origin.split_once("://").map(|(_, host)| host)
+            } else {
+                origin.strip_prefix(scheme)
+            };
+            host.and_then(|h| h.strip_suffix(domain))
+                .and_then(|sub| sub.strip_suffix('.'))
+                .is_some_and(|sub| !sub.is_empty() && !sub.contains(['/', ':']))
+        }
+        None => pattern == origin,
+    }
+}
+
+/// Parse `name` if set, falling back to `default` when it isn't
+fn parse_or<T>(name: &str, default: T) -> Result<T, Box<dyn std::error::Error>>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map_err(|e| format!("Invalid {}: {}", name, e).into()),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Bytes in the AES-256 key that encrypts stored private keys
+pub const AES_KEY_LEN: usize = 32;
+
+/// Decode `AES_ENCRYPTION_KEY`, which must be exactly 32 bytes of hex
+pub fn parse_aes_key(hex_key: &str) -> Result<Vec<u8>, String> {
+    let hint = "generate one with `blockchain-wallet-backend generate-aes-key`";
+    let key = hex::decode(hex_key.trim())
+        .map_err(|e| format!("AES_ENCRYPTION_KEY must be hex-encoded ({}); {}", e, hint))?;
+    if key.len() != AES_KEY_LEN {
+        return Err(format!(
+            "AES_ENCRYPTION_KEY must be {} bytes ({} hex characters), got {}; {}",
+            AES_KEY_LEN,
+            AES_KEY_LEN * 2,
+            key.len(),
+            hint
+        ));
+    }
+    Ok(key)
+}
+
+/// Shortest `PAYMENT_REQUEST_SECRET` accepted
+pub const MIN_PAYMENT_REQUEST_SECRET_LEN: usize = 32;
+
+/// Check `PAYMENT_REQUEST_SECRET`, the key payment request URIs are signed with. It is separate
+/// from `JWT_SECRET` and may not reuse it, so leaking one doesn't let anyone forge the other.
+pub fn parse_payment_request_secret(secret: Option<&str>, jwt_secret: Option<&str>) -> Result<String, String> {
+    let secret = secret.map(str::trim).filter(|s| !s.is_empty()).ok_or("PAYMENT_REQUEST_SECRET is not set")?;
+    if secret.len() < MIN_PAYMENT_REQUEST_SECRET_LEN {
+        return Err(format!(
+            "PAYMENT_REQUEST_SECRET must be at least {} characters, got {}",
+            MIN_PAYMENT_REQUEST_SECRET_LEN,
+            secret.len()
+        ));
+    }
+    if jwt_secret.is_some_and(|jwt| jwt.trim() == secret) {
+        return Err("PAYMENT_REQUEST_SECRET must differ from JWT_SECRET".to_string());
+    }
+    Ok(secret.to_string())
+}
+
+/// Key zakat transactions are signed with when `SYSTEM_SIGNING_SECRET` is unset outside production;
+/// anyone can forge zakat deductions and distributions with it
+pub const DEFAULT_SYSTEM_SIGNING_SECRET: &str = "default-system-signing-secret-change-in-production";
+
+/// Shortest `SYSTEM_SIGNING_SECRET` accepted
+pub const MIN_SYSTEM_SIGNING_SECRET_LEN: usize = 32;
+
+/// Check `SYSTEM_SIGNING_SECRET`, the HMAC key of the system signature on zakat deductions and
+/// distributions. Nodes importing each other's chain exports need the same one. The public
+/// default is refused in production and only warned about elsewhere.
+pub fn parse_system_signing_secret(secret: Option<&str>, app_env: AppEnv) -> Result<String, String> {
+    let secret = secret.map(str::trim).filter(|s| !s.is_empty() && *s != DEFAULT_SYSTEM_SIGNING_SECRET);
+    match (secret, app_env) {
+        (Some(secret), _) if secret.len() < MIN_SYSTEM_SIGNING_SECRET_LEN => Err(format!(
+            "SYSTEM_SIGNING_SECRET must be at least {} characters, got {}",
+            MIN_SYSTEM_SIGNING_SECRET_LEN,
+            secret.len()
+        )),
+        (Some(secret), _) => Ok(secret.to_string()),
+        (None, AppEnv::Production) => {
+            Err("SYSTEM_SIGNING_SECRET must be set to a real secret when APP_ENV=production".to_string())
+        }
+        (None, AppEnv::Development) => {
+            log::warn!("⚠️ SYSTEM_SIGNING_SECRET is not set; zakat transactions are signed with a public default secret and can be forged. Never run like this in production");
+            Ok(DEFAULT_SYSTEM_SIGNING_SECRET.to_string())
+        }
+    }
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
-        let aes_key_hex = env::var("AES_ENCRYPTION_KEY")?;
-        let aes_key = hex::decode(aes_key_hex)?;
+        Config::load(&|name| env::var(name).ok())
+    }
+
+    /// Build from settings looked up through `var`. Token signing settings (`JWT_SECRET` or the
+    /// RS256 keypair) are read by `JwtConfig` alone, so they aren't required here.
+    pub fn load(var: &dyn Fn(&str) -> Option<String>) -> Result<Self, Box<dyn std::error::Error>> {
+        let aes_key_hex = var("AES_ENCRYPTION_KEY").ok_or("AES_ENCRYPTION_KEY is not set")?;
+        let aes_key = parse_aes_key(&aes_key_hex)?;
 
         Ok(Config {
-            database_url: env::var("DATABASE_URL")?,
-            jwt_secret: env::var("JWT_SECRET")?,
+            database_url: var("DATABASE_URL").ok_or("DATABASE_URL is not set")?,
             aes_key,
-            mining_difficulty: env::var("MINING_DIFFICULTY")
-                .unwrap_or_else(|_| "5".to_string())
-                .parse()?,
-            block_reward: env::var("BLOCK_REWARD")
-                .unwrap_or_else(|_| "50.0".to_string())
+            payment_request_secret: parse_payment_request_secret(
+                var("PAYMENT_REQUEST_SECRET").as_deref(),
+                var("JWT_SECRET").as_deref(),
+            )?,
+            zakat_percentage: var("ZAKAT_PERCENTAGE")
+                .unwrap_or_else(|| "2.5".to_string())
                 .parse()?,
-            zakat_percentage: env::var("ZAKAT_PERCENTAGE")
-                .unwrap_or_else(|_| "2.5".to_string())
-                .parse()?,
-            zakat_pool_wallet_id: env::var("ZAKAT_POOL_WALLET_ID")
-                .unwrap_or_else(|_| "ZAKAT_POOL".to_string()),
+            password_policy: PasswordPolicy::from_env()?,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origins(list: &[&str]) -> CorsOrigins {
+        CorsOrigins { allow_any: false, origins: list.iter().map(|o| o.to_string()).collect() }
+    }
+
+    #[test]
+    fn test_chain_mode_parse() {
+        assert_eq!("regtest".parse::<ChainMode>(), Ok(ChainMode::Regtest));
+        assert_eq!("Mainnet".parse::<ChainMode>(), Ok(ChainMode::Mainnet));
+        assert!("testnet".parse::<ChainMode>().is_err());
+        assert_eq!(ChainConfig::default().mode, ChainMode::Mainnet);
+    }
+
+    #[test]
+    fn test_aes_key_length_checked_at_load() {
+        let key = hex::encode(crate::crypto::generate_aes_key());
+        assert_eq!(parse_aes_key(&key).unwrap().len(), AES_KEY_LEN);
+
+        // 16 bytes: valid hex, wrong length
+        let err = parse_aes_key(&key[..32]).unwrap_err();
+        assert!(err.contains("must be 32 bytes"), "{}", err);
+        assert!(parse_aes_key("not hex").is_err());
+        assert!(parse_aes_key("").is_err());
+    }
+
+    #[test]
+    fn test_payment_request_secret_checked_at_load() {
+        let secret = "p".repeat(MIN_PAYMENT_REQUEST_SECRET_LEN);
+        assert_eq!(parse_payment_request_secret(Some(&secret), Some("jwt")).unwrap(), secret);
+        assert_eq!(parse_payment_request_secret(Some(&secret), None).unwrap(), secret);
+
+        assert!(parse_payment_request_secret(None, Some("jwt")).is_err());
+        assert!(parse_payment_request_secret(Some("  "), None).is_err());
+        let err = parse_payment_request_secret(Some("short"), None).unwrap_err();
+        assert!(err.contains("at least"), "{}", err);
+        // Reusing the token secret defeats keeping them apart
+        let err = parse_payment_request_secret(Some(&secret), Some(&secret)).unwrap_err();
+        assert!(err.contains("JWT_SECRET"), "{}", err);
+    }
+
+    #[test]
+    fn test_system_signing_secret_checked_at_load() {
+        let secret = "s".repeat(MIN_SYSTEM_SIGNING_SECRET_LEN);
+        assert_eq!(parse_system_signing_secret(Some(&secret), AppEnv::Production).unwrap(), secret);
+
+        let err = parse_system_signing_secret(Some("short"), AppEnv::Development).unwrap_err();
+        assert!(err.contains("at least"), "{}", err);
+        // The public default only stands in outside production
+        assert!(parse_system_signing_secret(None, AppEnv::Production).is_err());
+        assert!(parse_system_signing_secret(Some(DEFAULT_SYSTEM_SIGNING_SECRET), AppEnv::Production).is_err());
+        assert_eq!(
+            parse_system_signing_secret(None, AppEnv::Development).unwrap(),
+            DEFAULT_SYSTEM_SIGNING_SECRET
+        );
+    }
+
+    #[test]
+    fn test_config_loads_without_jwt_secret_outside_production() {
+        let aes_key = hex::encode(crate::crypto::generate_aes_key());
+        let payment_secret = "p".repeat(MIN_PAYMENT_REQUEST_SECRET_LEN);
+        let vars = |name: &str| match name {
+            "DATABASE_URL" => Some("postgres://localhost/wallet".to_string()),
+            "AES_ENCRYPTION_KEY" => Some(aes_key.clone()),
+            "PAYMENT_REQUEST_SECRET" => Some(payment_secret.clone()),
+            _ => None,
+        };
+
+        let config = Config::load(&vars).unwrap();
+        assert_eq!(config.aes_key.len(), AES_KEY_LEN);
+        // Tokens fall back to the default secret, with a warning, rather than failing startup
+        let jwt = JwtConfig::load(AppEnv::Development, &vars).unwrap();
+        assert_eq!(jwt.algorithm, Algorithm::HS256);
+        assert!(JwtConfig::load(AppEnv::Production, &vars).is_err());
+    }
+
+    #[test]
+    fn test_rs256_production_config_needs_no_jwt_secret() {
+        let (private_key, public_key) = crate::crypto::generate_keypair().unwrap();
+        let dir = std::env::temp_dir().join(format!("jwt_rs256_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let private_path = dir.join("private.pem");
+        let public_path = dir.join("public.pem");
+        std::fs::write(&private_path, crate::crypto::export_private_key_pem(&private_key).unwrap()).unwrap();
+        std::fs::write(&public_path, crate::crypto::export_public_key_pem(&public_key).unwrap()).unwrap();
+
+        let aes_key = hex::encode(crate::crypto::generate_aes_key());
+        let payment_secret = "p".repeat(MIN_PAYMENT_REQUEST_SECRET_LEN);
+        let vars = |name: &str| match name {
+            "DATABASE_URL" => Some("postgres://localhost/wallet".to_string()),
+            "AES_ENCRYPTION_KEY" => Some(aes_key.clone()),
+            "PAYMENT_REQUEST_SECRET" => Some(payment_secret.clone()),
+            "JWT_ALGORITHM" => Some("RS256".to_string()),
+            "JWT_PRIVATE_KEY_PATH" => Some(private_path.display().to_string()),
+            "JWT_PUBLIC_KEY_PATH" => Some(public_path.display().to_string()),
+            _ => None,
+        };
+
+        assert!(Config::load(&vars).is_ok());
+        let jwt = JwtConfig::load(AppEnv::Production, &vars).unwrap();
+        assert_eq!(jwt.algorithm, Algorithm::RS256);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cors_exact_origins() {
+        let cors = origins(&["http://localhost:5173", "https://app.example.com"]);
+        assert!(cors.allows("http://localhost:5173"));
+        assert!(cors.allows("https://APP.example.com"));
+        assert!(!cors.allows("https://evil.com"));
+        assert!(!cors.allows("http://app.example.com"));
+        assert!(!cors.allows("http://localhost:3000"));
+    }
+
+    #[test]
+    fn test_cors_wildcard_subdomains() {
+        let cors = origins(&["https://*.example.com", "*.preview.dev"]);
+        assert!(cors.allows("https://app.example.com"));
+        assert!(cors.allows("https://a.b.example.com"));
+        assert!(!cors.allows("https://example.com"));
+        assert!(!cors.allows("http://app.example.com"));
+        assert!(!cors.allows("https://evilexample.com"));
+        assert!(!cors.allows("https://app.example.com.evil.com"));
+
+        // No scheme in the pattern: any scheme
+        assert!(cors.allows("http://pr-12.preview.dev"));
+        assert!(cors.allows("https://pr-12.preview.dev"));
+    }
+
+    #[test]
+    fn test_cors_allow_any() {
+        let cors = CorsOrigins { allow_any: true, ..origins(&[]) };
+        assert!(cors.allows("https://anything.test"));
+        assert!(!origins(&[]).allows("https://anything.test"));
+    }
+}
@@ -1,4 +1,4 @@
-use rsa::{RsaPrivateKey, RsaPublicKey, pkcs8::{EncodePrivateKey, EncodePublicKey, DecodePrivateKey, DecodePublicKey, LineEnding}, Pkcs1v15Sign};
+use rsa::{RsaPrivateKey, RsaPublicKey, pkcs8::{EncodePrivateKey, EncodePublicKey, DecodePrivateKey, DecodePublicKey, LineEnding}, Oaep, Pkcs1v15Sign};
 use sha2::{Sha256, Digest};
 use rand::rngs::OsRng;
 use aes_gcm::{
@@ -6,6 +6,7 @@ use aes_gcm::{
     Aes256Gcm, Nonce,
 };
 use base64::{Engine as _, engine::general_purpose};
+use subtle::ConstantTimeEq;
 
 const KEY_SIZE: usize = 2048;
 
@@ -117,6 +118,17 @@ pub fn verify_signature(public_key: &RsaPublicKey, data: &str, signature_hex: &s
     }
 }
 
+/// Compare secrets (OTP codes, signatures, tokens) without leaking through timing how much of
+/// them matched. Only the length can differ in timing, which isn't secret for fixed-size values.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Random AES-256 key, for `AES_ENCRYPTION_KEY` (hex-encoded)
+pub fn generate_aes_key() -> [u8; 32] {
+    Aes256Gcm::generate_key(&mut AesOsRng).into()
+}
+
 /// Encrypt private key with AES-256-GCM
 pub fn encrypt_private_key(private_key_pem: &str, aes_key: &[u8]) -> Result<String, CryptoError> {
     if aes_key.len() != 32 {
@@ -173,6 +185,37 @@ pub fn decrypt_private_key(encrypted_base64: &str, aes_key: &[u8]) -> Result<Str
         .map_err(|e| CryptoError::DecryptionError(format!("Invalid UTF-8: {}", e)))
 }
 
+/// Longest note (in bytes) RSA-2048 OAEP-SHA256 can encrypt in one block
+pub const MAX_ENCRYPTED_NOTE_BYTES: usize = KEY_SIZE / 8 - 2 * 32 - 2;
+
+/// Encrypt a transaction note to the receiver's public key (RSA-OAEP/SHA-256), base64-encoded
+pub fn encrypt_note(public_key: &RsaPublicKey, note: &str) -> Result<String, CryptoError> {
+    if note.len() > MAX_ENCRYPTED_NOTE_BYTES {
+        return Err(CryptoError::EncryptionError(format!(
+            "Private notes are limited to {} bytes",
+            MAX_ENCRYPTED_NOTE_BYTES
+        )));
+    }
+
+    let ciphertext = public_key
+        .encrypt(&mut OsRng, Oaep::new::<Sha256>(), note.as_bytes())
+        .map_err(|e| CryptoError::EncryptionError(e.to_string()))?;
+    Ok(general_purpose::STANDARD.encode(ciphertext))
+}
+
+/// Decrypt a note produced by `encrypt_note` with the receiver's private key
+pub fn decrypt_note(private_key: &RsaPrivateKey, encrypted_base64: &str) -> Result<String, CryptoError> {
+    let ciphertext = general_purpose::STANDARD
+        .decode(encrypted_base64)
+        .map_err(|e| CryptoError::DecryptionError(e.to_string()))?;
+    let plaintext = private_key
+        .decrypt(Oaep::new::<Sha256>(), &ciphertext)
+        .map_err(|e| CryptoError::DecryptionError(e.to_string()))?;
+
+    String::from_utf8(plaintext)
+        .map_err(|e| CryptoError::DecryptionError(format!("Invalid UTF-8: {}", e)))
+}
+
 /// Create transaction payload for signing
 pub fn create_transaction_payload(
     sender_id: &str,
@@ -195,6 +238,15 @@ pub fn create_transaction_payload(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"123456", b"123456"));
+        assert!(!constant_time_eq(b"123456", b"123457"));
+        assert!(!constant_time_eq(b"123456", b"12345"));
+        assert!(!constant_time_eq(b"", b"1"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
     #[test]
     fn test_keypair_generation() {
         let result = generate_keypair();
@@ -244,6 +296,19 @@ mod tests {
         assert_eq!(private_key_pem, decrypted);
     }
 
+    #[test]
+    fn test_note_encryption_round_trip() {
+        let (private_key, public_key) = generate_keypair().unwrap();
+        let (other_key, _) = generate_keypair().unwrap();
+
+        let encrypted = encrypt_note(&public_key, "rent for March").unwrap();
+        assert!(!encrypted.contains("rent"));
+        assert_eq!(decrypt_note(&private_key, &encrypted).unwrap(), "rent for March");
+        assert!(decrypt_note(&other_key, &encrypted).is_err());
+
+        assert!(encrypt_note(&public_key, &"x".repeat(MAX_ENCRYPTED_NOTE_BYTES + 1)).is_err());
+    }
+
     #[test]
     fn test_sha256_hash() {
         let data = b"hello world";
@@ -1,35 +1,304 @@
-use deadpool_postgres::{Config, Manager, ManagerConfig, Pool, RecyclingMethod, Runtime};
+use crate::config::DbPoolConfig;
+use deadpool_postgres::{Config, Manager, ManagerConfig, Pool, PoolConfig, RecyclingMethod, Runtime};
 use tokio_postgres::NoTls;
 use std::env;
+use std::time::Duration;
 
 pub type DbPool = Pool;
 
-pub async fn create_pool() -> Result<DbPool, Box<dyn std::error::Error>> {
+pub async fn create_pool(settings: &DbPoolConfig) -> Result<DbPool, Box<dyn std::error::Error>> {
     let database_url = env::var("DATABASE_URL")?;
+    let pool = build_pool(&database_url, settings)?;
     
+    log::info!("✅ Database connection pool created ({})", settings);
+    
+    Ok(pool)
+}
+
+/// Pool for `database_url` with the configured size and timeouts. Connections are opened lazily.
+pub fn build_pool(database_url: &str, settings: &DbPoolConfig) -> Result<DbPool, Box<dyn std::error::Error>> {
     let mut cfg = Config::new();
-    cfg.url = Some(database_url);
+    cfg.url = Some(database_url.to_string());
     
-    // Limit pool size for Supabase free tier (max 10 connections in session mode)
-    cfg.pool = Some(deadpool_postgres::PoolConfig::new(10));
+    let mut pool_config = PoolConfig::new(settings.max_size);
+    pool_config.timeouts.create = Some(Duration::from_secs(settings.connect_timeout_secs));
+    pool_config.timeouts.recycle = Some(Duration::from_secs(settings.recycle_timeout_secs));
+    cfg.pool = Some(pool_config);
     
     cfg.manager = Some(ManagerConfig {
         recycling_method: RecyclingMethod::Fast,
     });
 
-    let pool = cfg.create_pool(Some(Runtime::Tokio1), NoTls)?;
-    
-    log::info!("✅ Database connection pool created");
-    
-    Ok(pool)
+    Ok(cfg.create_pool(Some(Runtime::Tokio1), NoTls)?)
+}
+
+/// Failure of a pooled read: either getting a connection or running the query
+#[derive(Debug)]
+pub enum DbError {
+    Pool(deadpool_postgres::PoolError),
+    Query(tokio_postgres::Error),
+}
+
+impl std::fmt::Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DbError::Pool(e) => write!(f, "Database pool error: {}", e),
+            DbError::Query(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<deadpool_postgres::PoolError> for DbError {
+    fn from(e: deadpool_postgres::PoolError) -> Self {
+        DbError::Pool(e)
+    }
+}
+
+impl From<tokio_postgres::Error> for DbError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        DbError::Query(e)
+    }
+}
+
+/// Whether the same read could succeed if tried again
+pub trait Transient {
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for tokio_postgres::Error {
+    fn is_transient(&self) -> bool {
+        match self.code() {
+            // Connection exceptions, server shutting down or out of connections
+            Some(state) => {
+                let code = state.code();
+                code.starts_with("08") || code.starts_with("57P") || code == "53300"
+            }
+            // No SQLSTATE: only a dropped connection or socket error is worth retrying
+            None => {
+                self.is_closed()
+                    || std::error::Error::source(self).is_some_and(|source| source.is::<std::io::Error>())
+            }
+        }
+    }
+}
+
+impl Transient for deadpool_postgres::PoolError {
+    fn is_transient(&self) -> bool {
+        match self {
+            deadpool_postgres::PoolError::Timeout(_) => true,
+            deadpool_postgres::PoolError::Backend(e) => e.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+impl Transient for DbError {
+    fn is_transient(&self) -> bool {
+        match self {
+            DbError::Pool(e) => e.is_transient(),
+            DbError::Query(e) => e.is_transient(),
+        }
+    }
+}
+
+/// How `with_retry` retries: up to `DB_RETRY_ATTEMPTS` more tries (default 2), waiting
+/// `DB_RETRY_BASE_DELAY_MS` (default 50) before the first and doubling each time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        let retries = env::var("DB_RETRY_ATTEMPTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(2);
+        let base_delay_ms = env::var("DB_RETRY_BASE_DELAY_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(50);
+
+        RetryPolicy { retries, base_delay: Duration::from_millis(base_delay_ms) }
+    }
+}
+
+/// Run a read, retrying connection-level failures (pool timeouts, dropped connections) with
+/// exponential backoff. Statement errors such as constraint violations fail at once.
+/// Reads only: a write retried after a dropped connection may already have been applied.
+pub async fn with_retry<T, E, F, Fut>(op: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+    E: Transient + std::fmt::Display,
+{
+    with_retry_policy(RetryPolicy::from_env(), op).await
+}
+
+pub async fn with_retry_policy<T, E, F, Fut>(policy: RetryPolicy, mut op: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+    E: Transient + std::fmt::Display,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Err(e) if attempt < policy.retries && e.is_transient() => {
+                let delay = policy.base_delay * 2u32.saturating_pow(attempt);
+                attempt += 1;
+                log::warn!("Transient database error, retry {}/{} in {:?}: {}", attempt, policy.retries, delay, e);
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Session-level advisory lock held on a pooled connection. Release it with `unlock`. If the guard is
+/// dropped while holding the lock (a cancelled request, shutdown, an error path), the connection is
+/// detached from the pool and closed, which ends the session and frees the lock. Returning it to the
+/// pool would leave the lock held by an idle session, and since session locks are re-entrant, a
+/// later unlock from that session would only lower its hold count.
+pub struct AdvisoryLock {
+    client: Option<deadpool_postgres::Client>,
+    key: i64,
+}
+
+impl AdvisoryLock {
+    /// Take the lock if it is free; `None` when another session holds it
+    pub async fn try_acquire(pool: &DbPool, key: i64) -> Result<Option<Self>, DbError> {
+        let mut lock = AdvisoryLock { client: Some(pool.get().await?), key };
+        let locked: bool = lock.client().query_one("SELECT pg_try_advisory_lock($1)", &[&key]).await?.get(0);
+        if !locked {
+            // Nothing held, so the connection can go straight back to the pool
+            lock.client.take();
+            return Ok(None);
+        }
+        Ok(Some(lock))
+    }
+
+    /// Wait for the lock
+    pub async fn acquire(pool: &DbPool, key: i64) -> Result<Self, DbError> {
+        let lock = AdvisoryLock { client: Some(pool.get().await?), key };
+        lock.client().execute("SELECT pg_advisory_lock($1)", &[&key]).await?;
+        Ok(lock)
+    }
+
+    /// The connection holding the lock
+    pub fn client(&self) -> &deadpool_postgres::Client {
+        self.client.as_ref().expect("advisory lock already released")
+    }
+
+    pub fn client_mut(&mut self) -> &mut deadpool_postgres::Client {
+        self.client.as_mut().expect("advisory lock already released")
+    }
+
+    /// Release the lock and return the connection to the pool
+    pub async fn unlock(mut self) -> Result<(), tokio_postgres::Error> {
+        self.client().execute("SELECT pg_advisory_unlock($1)", &[&self.key]).await?;
+        self.client.take();
+        Ok(())
+    }
+}
+
+impl Drop for AdvisoryLock {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            log::warn!("Advisory lock {:#x} dropped while held; closing its connection", self.key);
+            drop(deadpool_postgres::Object::take(client));
+        }
+    }
+}
+
+/// Pool on a freshly created, empty database `test_<name>` on the server in `TEST_DATABASE_URL`.
+/// Returns `None` when the variable is unset so database tests skip.
+#[cfg(test)]
+pub async fn create_test_pool(name: &str) -> Option<DbPool> {
+    let url = env::var("TEST_DATABASE_URL").ok()?;
+    let dbname = format!("test_{}", name);
+
+    let (admin, connection) = tokio_postgres::connect(&url, NoTls)
+        .await
+        .expect("Failed to connect to TEST_DATABASE_URL");
+    tokio::spawn(connection);
+
+    // CREATE DATABASE can't share a batch (implicit transaction) with the drop
+    admin
+        .batch_execute(&format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", dbname))
+        .await
+        .expect("Failed to drop test database");
+    admin
+        .batch_execute(&format!("CREATE DATABASE {}", dbname))
+        .await
+        .expect("Failed to create test database");
+
+    let mut cfg = Config::new();
+    cfg.url = Some(url);
+    cfg.dbname = Some(dbname);
+
+    Some(cfg.create_pool(Some(Runtime::Tokio1), NoTls).expect("Failed to create test pool"))
+}
+
+/// AES key that database tests register users and sign transactions under
+#[cfg(test)]
+pub const TEST_AES_KEY: [u8; 32] = [7u8; 32];
+
+/// Registers `email` with a fixed name and password, encrypting its key under [`TEST_AES_KEY`]
+#[cfg(test)]
+pub async fn register_test_user(pool: &DbPool, email: &str, cnic: &str) -> crate::models::User {
+    let request = crate::models::RegisterRequest {
+        email: email.to_string(),
+        full_name: "Test User".to_string(),
+        cnic: cnic.to_string(),
+        password: "password123".to_string(),
+    };
+    crate::services::auth_service::register_user(pool, request, &TEST_AES_KEY)
+        .await
+        .expect("Failed to register test user")
+}
+
+/// Migrated test database with the genesis block for `chain`, a `sender@example.com` user holding
+/// `funding` coins in one confirmed UTXO and an empty `receiver@example.com` user.
+/// Returns `None` when `TEST_DATABASE_URL` is unset so database tests skip.
+#[cfg(test)]
+pub async fn create_funded_test_pair(
+    name: &str,
+    chain: &crate::config::ChainConfig,
+    funding: f64,
+) -> Option<(DbPool, crate::models::User, crate::models::User)> {
+    let pool = create_test_pool(name).await?;
+    crate::migrations::run_migrations(&pool).await.expect("Failed to run migrations");
+    crate::blockchain::initialize_blockchain(pool.clone(), chain)
+        .await
+        .expect("Failed to initialize blockchain");
+
+    let sender = register_test_user(&pool, "sender@example.com", "1").await;
+    let receiver = register_test_user(&pool, "receiver@example.com", "2").await;
+
+    if funding > 0.0 {
+        let client = pool.get().await.expect("Failed to get test client");
+        queries::create_utxo(&client, &sender.wallet_id, crate::utils::to_units(funding), "funding", 0)
+            .await
+            .expect("Failed to fund test sender");
+        queries::refresh_wallet_balance(&client, &sender.wallet_id)
+            .await
+            .expect("Failed to refresh test sender balance");
+    }
+
+    Some((pool, sender, receiver))
 }
 
 pub mod queries {
     use crate::models::*;
     use crate::models::Transaction as TxModel;
-    use deadpool_postgres::Client;
+    use deadpool_postgres::{Client, GenericClient};
     use tokio_postgres::Transaction;
     use uuid::Uuid;
+    use std::collections::{HashMap, HashSet};
     use chrono::{Utc, DateTime};
 
     // User queries
@@ -46,7 +315,7 @@ pub mod queries {
             .query_one(
                 "INSERT INTO users (email, full_name, cnic, wallet_id, public_key, encrypted_private_key) 
                  VALUES ($1, $2, $3, $4, $5, $6) 
-                 RETURNING id, email, full_name, cnic, wallet_id, public_key, encrypted_private_key, is_verified, created_at, updated_at",
+                 RETURNING id, email, full_name, cnic, wallet_id, public_key, encrypted_private_key, is_verified, created_at, updated_at, role",
                 &[&email, &full_name, &cnic, &wallet_id, &public_key, &encrypted_private_key],
             )
             .await?;
@@ -62,13 +331,14 @@ pub mod queries {
             is_verified: row.get(7),
             created_at: row.get(8),
             updated_at: row.get(9),
+            role: row.get(10),
         })
     }
 
     pub async fn find_user_by_email(client: &Client, email: &str) -> Result<Option<User>, tokio_postgres::Error> {
         let result = client
             .query_opt(
-                "SELECT id, email, full_name, cnic, wallet_id, public_key, encrypted_private_key, is_verified, created_at, updated_at 
+                "SELECT id, email, full_name, cnic, wallet_id, public_key, encrypted_private_key, is_verified, created_at, updated_at, role 
                  FROM users WHERE email = $1",
                 &[&email],
             )
@@ -85,13 +355,14 @@ pub mod queries {
             is_verified: row.get(7),
             created_at: row.get(8),
             updated_at: row.get(9),
+            role: row.get(10),
         }))
     }
 
     pub async fn find_user_by_id(client: &Client, user_id: Uuid) -> Result<Option<User>, tokio_postgres::Error> {
         let result = client
             .query_opt(
-                "SELECT id, email, full_name, cnic, wallet_id, public_key, encrypted_private_key, is_verified, created_at, updated_at 
+                "SELECT id, email, full_name, cnic, wallet_id, public_key, encrypted_private_key, is_verified, created_at, updated_at, role 
                  FROM users WHERE id = $1",
                 &[&user_id],
             )
@@ -108,38 +379,148 @@ pub mod queries {
             is_verified: row.get(7),
             created_at: row.get(8),
             updated_at: row.get(9),
+            role: row.get(10),
+        }))
+    }
+
+    /// `SET` clause for a dynamic UPDATE. Each assignment is pushed together with its value,
+    /// so placeholders always line up with the parameter list.
+    #[derive(Default)]
+    pub struct UpdateSet {
+        assignments: Vec<String>,
+        values: Vec<Box<dyn tokio_postgres::types::ToSql + Sync + Send>>,
+    }
+
+    impl UpdateSet {
+        pub fn push<T>(&mut self, column: &str, value: T)
+        where
+            T: tokio_postgres::types::ToSql + Sync + Send + 'static,
+        {
+            self.values.push(Box::new(value));
+            self.assignments.push(format!("{} = ${}", column, self.values.len()));
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.assignments.is_empty()
+        }
+
+        /// `UPDATE <table> SET ... WHERE <key_column> = $n`, the key being the last parameter
+        pub fn to_sql(&self, table: &str, key_column: &str, returning: &str) -> String {
+            format!(
+                "UPDATE {} SET {} WHERE {} = ${} RETURNING {}",
+                table,
+                self.assignments.join(", "),
+                key_column,
+                self.values.len() + 1,
+                returning
+            )
+        }
+
+        pub fn params<'a>(
+            &'a self,
+            key: &'a (dyn tokio_postgres::types::ToSql + Sync),
+        ) -> Vec<&'a (dyn tokio_postgres::types::ToSql + Sync)> {
+            self.values
+                .iter()
+                .map(|v| v.as_ref() as &(dyn tokio_postgres::types::ToSql + Sync))
+                .chain(std::iter::once(key))
+                .collect()
+        }
+    }
+
+    pub async fn update_user(client: &Client, user_id: Uuid, set: &UpdateSet) -> Result<Option<User>, tokio_postgres::Error> {
+        let query = set.to_sql(
+            "users",
+            "id",
+            "id, email, full_name, cnic, wallet_id, public_key, encrypted_private_key, is_verified, created_at, updated_at, role",
+        );
+        let result = client.query_opt(&query, &set.params(&user_id)).await?;
+
+        Ok(result.map(|row| User {
+            id: row.get(0),
+            email: row.get(1),
+            full_name: row.get(2),
+            cnic: row.get(3),
+            wallet_id: row.get(4),
+            public_key: row.get(5),
+            encrypted_private_key: row.get(6),
+            is_verified: row.get(7),
+            created_at: row.get(8),
+            updated_at: row.get(9),
+            role: row.get(10),
         }))
     }
 
     // Wallet queries
-    pub async fn create_wallet(
+    /// Owner-less wallet row for an id that may not belong to any user yet; true when this call created it.
+    /// Safe to race: a concurrent insert of the same id is a no-op rather than a unique violation
+    pub async fn ensure_wallet(client: &Client, wallet_id: &str) -> Result<bool, tokio_postgres::Error> {
+        let inserted = client
+            .execute(
+                "INSERT INTO wallets (wallet_id) VALUES ($1) ON CONFLICT (wallet_id) DO NOTHING",
+                &[&wallet_id],
+            )
+            .await?;
+        Ok(inserted == 1)
+    }
+
+    /// Page of all wallets except the coinbase placeholder, newest first, and their total
+    pub async fn list_wallets(
         client: &Client,
-        wallet_id: &str,
-        user_id: Option<Uuid>,
-    ) -> Result<Wallet, tokio_postgres::Error> {
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<AdminWalletEntry>, i64), tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT w.wallet_id, w.user_id, u.email, COALESCE(b.balance, 0)::float8, w.created_at 
+                 FROM wallets w 
+                 LEFT JOIN users u ON u.id = w.user_id 
+                 LEFT JOIN (SELECT wallet_id, SUM(amount) AS balance FROM utxos WHERE is_spent = false GROUP BY wallet_id) b 
+                     ON b.wallet_id = w.wallet_id 
+                 WHERE w.wallet_id <> $3 
+                 ORDER BY w.created_at DESC, w.wallet_id 
+                 LIMIT $1 OFFSET $2",
+                &[&limit, &offset, &crate::blockchain::COINBASE_SENDER],
+            )
+            .await?;
+        let total: i64 = client
+            .query_one("SELECT COUNT(*) FROM wallets WHERE wallet_id <> $1", &[&crate::blockchain::COINBASE_SENDER])
+            .await?
+            .get(0);
+
+        let wallets = rows
+            .into_iter()
+            .map(|row| AdminWalletEntry {
+                wallet_id: row.get(0),
+                user_id: row.get(1),
+                email: row.get(2),
+                balance: row.get(3),
+                created_at: row.get(4),
+            })
+            .collect();
+
+        Ok((wallets, total))
+    }
+
+    /// How many of `wallet_ids` belong to `user_id`
+    pub async fn count_owned_wallets(
+        client: &Client,
+        user_id: Uuid,
+        wallet_ids: &[String],
+    ) -> Result<i64, tokio_postgres::Error> {
         let row = client
             .query_one(
-                "INSERT INTO wallets (wallet_id, user_id, balance) 
-                 VALUES ($1, $2, 0) 
-                 RETURNING wallet_id, user_id, balance::float8, last_zakat_date, created_at, updated_at",
-                &[&wallet_id, &user_id],
+                "SELECT COUNT(*) FROM wallets WHERE wallet_id = ANY($1) AND user_id = $2",
+                &[&wallet_ids, &user_id],
             )
             .await?;
-
-        Ok(Wallet {
-            wallet_id: row.get(0),
-            user_id: row.get(1),
-            balance: row.get(2),
-            last_zakat_date: row.get(3),
-            created_at: row.get(4),
-            updated_at: row.get(5),
-        })
+        Ok(row.get(0))
     }
 
     pub async fn get_wallet(client: &Client, wallet_id: &str) -> Result<Option<Wallet>, tokio_postgres::Error> {
         let result = client
             .query_opt(
-                "SELECT wallet_id, user_id, balance::float8, last_zakat_date, created_at, updated_at 
+                "SELECT wallet_id, user_id, balance::float8, last_zakat_date, created_at, updated_at, status, zakat_exempt, zakat_rate::float8 
                  FROM wallets WHERE wallet_id = $1",
                 &[&wallet_id],
             )
@@ -150,38 +531,61 @@ pub mod queries {
             user_id: row.get(1),
             balance: row.get(2),
             last_zakat_date: row.get(3),
+            status: WalletStatus::from_db(row.get(6)),
+            zakat_exempt: row.get(7),
+            zakat_rate: row.get(8),
             created_at: row.get(4),
             updated_at: row.get(5),
         }))
     }
 
-    pub async fn update_wallet_balance(
-        client: &Client,
-        wallet_id: &str,
-        new_balance: f64,
-    ) -> Result<(), tokio_postgres::Error> {
+    /// Recompute the cached `wallets.balance` (unspent UTXOs minus pending sends) and store it
+    /// in a single statement, so there is no window between reading the UTXOs and writing the cache
+    pub async fn refresh_wallet_balance(client: &impl GenericClient, wallet_id: &str) -> Result<f64, tokio_postgres::Error> {
+        let row = client
+            .query_opt(
+                "UPDATE wallets SET
+                    balance = COALESCE((SELECT SUM(amount) FROM utxos WHERE wallet_id = $1 AND is_spent = false), 0)
+                            - COALESCE((SELECT SUM(amount) FROM pending_transactions WHERE sender_wallet_id = $1), 0),
+                    updated_at = NOW()
+                 WHERE wallet_id = $1
+                 RETURNING balance::float8",
+                &[&wallet_id],
+            )
+            .await?;
+        Ok(row.map(|r| r.get(0)).unwrap_or(0.0))
+    }
+
+    /// [`refresh_wallet_balance`] for every wallet in `wallet_ids`, in one statement
+    pub async fn refresh_wallet_balances(client: &impl GenericClient, wallet_ids: &[String]) -> Result<(), tokio_postgres::Error> {
         client
             .execute(
-                "UPDATE wallets SET balance = $1::float8, updated_at = $2 WHERE wallet_id = $3",
-                &[&new_balance, &Utc::now(), &wallet_id],
+                "UPDATE wallets w SET
+                    balance = COALESCE((SELECT SUM(amount) FROM utxos WHERE wallet_id = w.wallet_id AND is_spent = false), 0)
+                            - COALESCE((SELECT SUM(amount) FROM pending_transactions WHERE sender_wallet_id = w.wallet_id), 0),
+                    updated_at = NOW()
+                 WHERE w.wallet_id = ANY($1)",
+                &[&wallet_ids],
             )
             .await?;
         Ok(())
     }
 
     // UTXO queries
+    /// Single unspent output; mining writes its outputs with [`create_utxos`]
+    #[cfg(test)]
     pub async fn create_utxo(
         client: &Client,
         wallet_id: &str,
-        amount: f64,
+        amount: i64,
         transaction_hash: &str,
         output_index: i32,
     ) -> Result<UTXO, tokio_postgres::Error> {
         let row = client
             .query_one(
                 "INSERT INTO utxos (wallet_id, amount, transaction_hash, output_index) 
-                 VALUES ($1, $2::float8, $3, $4) 
-                 RETURNING id, wallet_id, amount::float8, transaction_hash, output_index, is_spent, created_at, spent_at",
+                 VALUES ($1, $2::int8 * 0.00000001, $3, $4) 
+                 RETURNING id, wallet_id, (amount * 100000000)::int8, transaction_hash, output_index, is_spent, created_at, spent_at",
                 &[&wallet_id, &amount, &transaction_hash, &output_index],
             )
             .await?;
@@ -199,10 +603,31 @@ pub mod queries {
         })
     }
 
+    /// Mining reward output, flagged so mined-supply totals don't depend on the hash format.
+    /// Only genesis allocations use an `output_index` other than 0.
+    pub async fn create_coinbase_utxo(
+        client: &impl GenericClient,
+        wallet_id: &str,
+        amount: i64,
+        transaction_hash: &str,
+        output_index: i32,
+    ) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "INSERT INTO utxos (wallet_id, amount, transaction_hash, output_index, is_coinbase) 
+                 VALUES ($1, $2::int8 * 0.00000001, $3, $4, TRUE)",
+                &[&wallet_id, &amount, &transaction_hash, &output_index],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Every unspent output of a wallet, oldest first; for balance math and coin selection.
+    /// Listings page through [`get_unspent_utxos_page`] instead.
     pub async fn get_unspent_utxos(client: &Client, wallet_id: &str) -> Result<Vec<UTXO>, tokio_postgres::Error> {
         let rows = client
             .query(
-                "SELECT id, wallet_id, amount::float8, transaction_hash, output_index, is_spent, created_at, spent_at, reserved_by 
+                "SELECT id, wallet_id, (amount * 100000000)::int8, transaction_hash, output_index, is_spent, created_at, spent_at, reserved_by 
                  FROM utxos WHERE wallet_id = $1 AND is_spent = false 
                  ORDER BY created_at ASC",
                 &[&wallet_id],
@@ -225,125 +650,579 @@ pub mod queries {
             .collect())
     }
 
-    pub async fn mark_utxo_spent(client: &Client, utxo_id: Uuid) -> Result<(), tokio_postgres::Error> {
-        client
-            .execute(
-                "UPDATE utxos SET is_spent = true, spent_at = $1 WHERE id = $2",
-                &[&Utc::now(), &utxo_id],
+    /// One page of a wallet's unspent outputs, oldest first, with the total number unspent
+    pub async fn get_unspent_utxos_page(
+        client: &Client,
+        wallet_id: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<UTXO>, i64), tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT id, wallet_id, (amount * 100000000)::int8, transaction_hash, output_index, is_spent, created_at, spent_at, reserved_by 
+                 FROM utxos WHERE wallet_id = $1 AND is_spent = false 
+                 ORDER BY created_at ASC, id ASC 
+                 LIMIT $2 OFFSET $3",
+                &[&wallet_id, &limit, &offset],
             )
             .await?;
-        Ok(())
+        let total: i64 = client
+            .query_one("SELECT COUNT(*) FROM utxos WHERE wallet_id = $1 AND is_spent = false", &[&wallet_id])
+            .await?
+            .get(0);
+
+        let utxos = rows
+            .into_iter()
+            .map(|row| UTXO {
+                id: row.get(0),
+                wallet_id: row.get(1),
+                amount: row.get(2),
+                transaction_hash: row.get(3),
+                output_index: row.get(4),
+                is_spent: row.get(5),
+                created_at: row.get(6),
+                spent_at: row.get(7),
+                reserved_by: row.get(8),
+            })
+            .collect();
+        Ok((utxos, total))
     }
 
-    // Block queries
-    pub async fn create_block(client: &Client, block: &Block) -> Result<(), tokio_postgres::Error> {
-        client
-            .execute(
-                "INSERT INTO blocks (\"index\", timestamp, previous_hash, hash, nonce, merkle_root) 
-                 VALUES ($1, $2, $3, $4, $5, $6)",
-                &[
-                    &block.index,
-                    &block.timestamp,
-                    &block.previous_hash,
-                    &block.hash,
-                    &block.nonce,
-                    &block.merkle_root,
-                ],
+    /// One page of a wallet's spent outputs, most recently spent first, with the total number spent
+    pub async fn get_spent_utxos_page(
+        client: &Client,
+        wallet_id: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<SpentUTXO>, i64), tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT id, wallet_id, (amount * 100000000)::int8, transaction_hash, output_index, is_spent, created_at, spent_at, reserved_by, spent_by_transaction 
+                 FROM utxos WHERE wallet_id = $1 AND is_spent = true 
+                 ORDER BY spent_at DESC NULLS LAST, id ASC 
+                 LIMIT $2 OFFSET $3",
+                &[&wallet_id, &limit, &offset],
             )
             .await?;
-        Ok(())
+        let total: i64 = client
+            .query_one("SELECT COUNT(*) FROM utxos WHERE wallet_id = $1 AND is_spent = true", &[&wallet_id])
+            .await?
+            .get(0);
+
+        let utxos = rows
+            .into_iter()
+            .map(|row| SpentUTXO {
+                utxo: UTXO {
+                    id: row.get(0),
+                    wallet_id: row.get(1),
+                    amount: row.get(2),
+                    transaction_hash: row.get(3),
+                    output_index: row.get(4),
+                    is_spent: row.get(5),
+                    created_at: row.get(6),
+                    spent_at: row.get(7),
+                    reserved_by: row.get(8),
+                },
+                spent_by_transaction: row.get(9),
+            })
+            .collect();
+        Ok((utxos, total))
     }
 
-    pub async fn get_latest_block(client: &Client) -> Result<Option<Block>, tokio_postgres::Error> {
-        let result = client
-            .query_opt(
-                "SELECT index, timestamp, previous_hash, hash, nonce, merkle_root 
-                 FROM blocks ORDER BY index DESC LIMIT 1",
-                &[],
+    /// Unspent outputs of all of `wallet_ids`, oldest first
+    pub async fn get_unspent_utxos_for_wallets(client: &Client, wallet_ids: &[String]) -> Result<Vec<UTXO>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT id, wallet_id, (amount * 100000000)::int8, transaction_hash, output_index, is_spent, created_at, spent_at, reserved_by 
+                 FROM utxos WHERE wallet_id = ANY($1) AND is_spent = false 
+                 ORDER BY created_at ASC",
+                &[&wallet_ids],
             )
             .await?;
 
-        if let Some(row) = result {
-            let index: i64 = row.get(0);
-            
-            // Get transactions for this block
-            let tx_rows = client
-                .query(
-                    "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, amount::float8, note, 
-                     signature, block_index, transaction_type, timestamp, created_at 
-                     FROM transactions WHERE block_index = $1",
-                    &[&index],
-                )
-                .await?;
-
-            let transactions = tx_rows
-                .into_iter()
-                .map(|tx_row| TxModel {
-                    id: tx_row.get(0),
-                    transaction_hash: tx_row.get(1),
-                    sender_wallet_id: tx_row.get(2),
-                    receiver_wallet_id: tx_row.get(3),
-                    amount: tx_row.get(4),
-                    note: tx_row.get(5),
-                    signature: tx_row.get(6),
-                    block_index: tx_row.get(7),
-                    transaction_type: tx_row.get(8),
-                    timestamp: tx_row.get(9),
-                    created_at: tx_row.get(10),
-                })
-                .collect();
+        Ok(rows
+            .into_iter()
+            .map(|row| UTXO {
+                id: row.get(0),
+                wallet_id: row.get(1),
+                amount: row.get(2),
+                transaction_hash: row.get(3),
+                output_index: row.get(4),
+                is_spent: row.get(5),
+                created_at: row.get(6),
+                spent_at: row.get(7),
+                reserved_by: row.get(8),
+            })
+            .collect())
+    }
 
-            Ok(Some(Block {
-                index,
-                timestamp: row.get(1),
-                previous_hash: row.get(2),
-                hash: row.get(3),
-                nonce: row.get(4),
-                merkle_root: row.get(5),
-                transactions,
-            }))
-        } else {
-            Ok(None)
+    /// Insert `utxos` in one statement, keeping their ids and spent state
+    pub async fn create_utxos(client: &impl GenericClient, utxos: &[UTXO]) -> Result<(), tokio_postgres::Error> {
+        if utxos.is_empty() {
+            return Ok(());
         }
-    }
+        let ids: Vec<Uuid> = utxos.iter().map(|u| u.id).collect();
+        let wallets: Vec<&str> = utxos.iter().map(|u| u.wallet_id.as_str()).collect();
+        let amounts: Vec<i64> = utxos.iter().map(|u| u.amount).collect();
+        let hashes: Vec<&str> = utxos.iter().map(|u| u.transaction_hash.as_str()).collect();
+        let output_indexes: Vec<i32> = utxos.iter().map(|u| u.output_index).collect();
+        let spent: Vec<bool> = utxos.iter().map(|u| u.is_spent).collect();
+        let created_at: Vec<DateTime<Utc>> = utxos.iter().map(|u| u.created_at).collect();
+        let spent_at: Vec<Option<DateTime<Utc>>> = utxos.iter().map(|u| u.spent_at).collect();
 
-    pub async fn get_block_by_index(client: &Client, block_index: i64) -> Result<Option<Block>, tokio_postgres::Error> {
-        let result = client
-            .query_opt(
-                "SELECT index, timestamp, previous_hash, hash, nonce, merkle_root 
-                 FROM blocks WHERE index = $1",
-                &[&block_index],
+        client
+            .execute(
+                "INSERT INTO utxos (id, wallet_id, amount, transaction_hash, output_index, is_spent, created_at, spent_at) 
+                 SELECT id, wallet_id, amount * 0.00000001, transaction_hash, output_index, is_spent, created_at, spent_at 
+                 FROM UNNEST($1::uuid[], $2::varchar[], $3::int8[], $4::varchar[], $5::int4[], $6::bool[], $7::timestamptz[], $8::timestamptz[]) 
+                     AS t(id, wallet_id, amount, transaction_hash, output_index, is_spent, created_at, spent_at)",
+                &[&ids, &wallets, &amounts, &hashes, &output_indexes, &spent, &created_at, &spent_at],
             )
             .await?;
+        Ok(())
+    }
 
-        if let Some(row) = result {
+    /// Largest holders by unspent UTXO total, optionally skipping one wallet (e.g. the zakat pool)
+    pub async fn get_utxo_holders(
+        client: &Client,
+        limit: i64,
+        exclude_wallet_id: Option<&str>,
+    ) -> Result<Vec<(String, f64)>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT wallet_id, SUM(amount)::float8 AS balance 
+                 FROM utxos 
+                 WHERE is_spent = false AND ($2::VARCHAR IS NULL OR wallet_id <> $2) 
+                 GROUP BY wallet_id 
+                 ORDER BY balance DESC, wallet_id ASC 
+                 LIMIT $1",
+                &[&limit, &exclude_wallet_id],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    /// Unspent total and output count per wallet, for every listed wallet that has unspent outputs
+    pub async fn get_unspent_totals(
+        client: &Client,
+        wallet_ids: &[String],
+    ) -> Result<Vec<(String, f64, i64)>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT wallet_id, SUM(amount)::float8, COUNT(*) 
+                 FROM utxos 
+                 WHERE wallet_id = ANY($1) AND is_spent = false 
+                 GROUP BY wallet_id",
+                &[&wallet_ids],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect())
+    }
+
+    /// Amount locked in pending outgoing transactions per listed sender
+    pub async fn get_pending_send_totals(
+        client: &Client,
+        wallet_ids: &[String],
+    ) -> Result<Vec<(String, f64)>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT sender_wallet_id, SUM(amount)::float8 
+                 FROM pending_transactions 
+                 WHERE sender_wallet_id = ANY($1) 
+                 GROUP BY sender_wallet_id",
+                &[&wallet_ids],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    /// Creation time of each of `wallet_ids` that exists
+    pub async fn get_wallet_creation_times(
+        client: &Client,
+        wallet_ids: &[String],
+    ) -> Result<Vec<(String, DateTime<Utc>)>, tokio_postgres::Error> {
+        let rows = client
+            .query("SELECT wallet_id, created_at FROM wallets WHERE wallet_id = ANY($1)", &[&wallet_ids])
+            .await?;
+
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    /// The subset of `wallet_ids` that are frozen
+    pub async fn get_frozen_wallet_ids(
+        client: &Client,
+        wallet_ids: &[String],
+    ) -> Result<HashSet<String>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT wallet_id FROM wallets WHERE wallet_id = ANY($1) AND status = 'frozen'",
+                &[&wallet_ids],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// The subset of `wallet_ids` that exist and are not frozen.
+    /// The rows are share-locked, so inside a transaction a status change waits for it to finish.
+    pub async fn get_receivable_wallet_ids(
+        client: &impl GenericClient,
+        wallet_ids: &[String],
+    ) -> Result<HashSet<String>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT wallet_id FROM wallets WHERE wallet_id = ANY($1) AND status <> 'frozen' FOR SHARE",
+                &[&wallet_ids],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// PEM public keys `wallet_ids` sign with: the owner's, or the one kept for an imported wallet.
+    /// Wallets with neither have no entry.
+    pub async fn get_wallet_public_keys(
+        client: &Client,
+        wallet_ids: &[String],
+    ) -> Result<HashMap<String, String>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT w.wallet_id, COALESCE(u.public_key, w.public_key) FROM wallets w
+                 LEFT JOIN users u ON u.id = w.user_id
+                 WHERE w.wallet_id = ANY($1) AND COALESCE(u.public_key, w.public_key) IS NOT NULL",
+                &[&wallet_ids],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    /// Set a wallet's status, returning the updated wallet or `None` when it doesn't exist
+    pub async fn set_wallet_status(
+        client: &Client,
+        wallet_id: &str,
+        status: WalletStatus,
+    ) -> Result<Option<Wallet>, tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE wallets SET status = $2 WHERE wallet_id = $1",
+                &[&wallet_id, &status.as_str()],
+            )
+            .await?;
+        get_wallet(client, wallet_id).await
+    }
+
+    /// Set whether a wallet is exempt from zakat, returning the updated wallet or `None` when it doesn't exist
+    pub async fn set_zakat_exempt(
+        client: &Client,
+        wallet_id: &str,
+        exempt: bool,
+    ) -> Result<Option<Wallet>, tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE wallets SET zakat_exempt = $2 WHERE wallet_id = $1",
+                &[&wallet_id, &exempt],
+            )
+            .await?;
+        get_wallet(client, wallet_id).await
+    }
+
+    /// Set or clear (`None`) a wallet's zakat rate override, returning the updated wallet or `None` when it doesn't exist
+    pub async fn set_zakat_rate(
+        client: &Client,
+        wallet_id: &str,
+        rate: Option<f64>,
+    ) -> Result<Option<Wallet>, tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE wallets SET zakat_rate = $2::float8 WHERE wallet_id = $1",
+                &[&wallet_id, &rate],
+            )
+            .await?;
+        get_wallet(client, wallet_id).await
+    }
+
+    /// Total of all unspent outputs
+    pub async fn get_unspent_supply(client: &Client) -> Result<f64, tokio_postgres::Error> {
+        let row = client
+            .query_one("SELECT COALESCE(SUM(amount), 0)::float8 FROM utxos WHERE is_spent = false", &[])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    /// Units of fees burned by mining so far, summed over every block's `burned_fees`
+    pub async fn get_total_burned(client: &Client) -> Result<i64, tokio_postgres::Error> {
+        let row = client
+            .query_one("SELECT (COALESCE(SUM(burned_fees), 0) * 100000000)::int8 FROM blocks", &[])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    /// Wallets whose unspent UTXOs don't cover their pending sends, optionally limited to `wallet_ids`
+    pub async fn get_negative_balances(
+        client: &impl GenericClient,
+        wallet_ids: Option<&[String]>,
+    ) -> Result<Vec<NegativeBalance>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT w.wallet_id, (COALESCE(u.total, 0) - COALESCE(p.total, 0))::float8
+                 FROM wallets w
+                 LEFT JOIN (SELECT wallet_id, SUM(amount) AS total FROM utxos WHERE is_spent = false GROUP BY wallet_id) u
+                    ON u.wallet_id = w.wallet_id
+                 LEFT JOIN (SELECT sender_wallet_id, SUM(amount) AS total FROM pending_transactions GROUP BY sender_wallet_id) p
+                    ON p.sender_wallet_id = w.wallet_id
+                 WHERE ($1::text[] IS NULL OR w.wallet_id = ANY($1))
+                   AND COALESCE(u.total, 0) - COALESCE(p.total, 0) < 0
+                 ORDER BY w.wallet_id",
+                &[&wallet_ids],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| NegativeBalance {
+                wallet_id: row.get(0),
+                available: row.get(1),
+            })
+            .collect())
+    }
+
+    /// Mark every output in `utxo_ids` spent in one statement
+    /// Mark outputs spent, each by the transaction hash paired with it
+    pub async fn mark_utxos_spent(client: &impl GenericClient, spends: &[(Uuid, String)]) -> Result<(), tokio_postgres::Error> {
+        if spends.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<Uuid> = spends.iter().map(|(id, _)| *id).collect();
+        let hashes: Vec<&str> = spends.iter().map(|(_, hash)| hash.as_str()).collect();
+        client
+            .execute(
+                "UPDATE utxos u SET is_spent = true, spent_at = $1, spent_by_transaction = t.hash 
+                 FROM UNNEST($2::uuid[], $3::varchar[]) AS t(id, hash) 
+                 WHERE u.id = t.id",
+                &[&Utc::now(), &ids, &hashes],
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Block queries
+    pub async fn create_block(client: &impl GenericClient, block: &Block) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "INSERT INTO blocks (\"index\", timestamp, previous_hash, hash, nonce, merkle_root, version) 
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                &[
+                    &block.index,
+                    &block.timestamp,
+                    &block.previous_hash,
+                    &block.hash,
+                    &block.nonce,
+                    &block.merkle_root,
+                    &block.version,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn record_block_mining_work(
+        client: &impl GenericClient,
+        block_index: i64,
+        work: &crate::blockchain::MiningWork,
+    ) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE blocks SET hash_attempts = $2, mining_seconds = $3 WHERE \"index\" = $1",
+                &[&block_index, &work.attempts, &work.elapsed.as_secs_f64()],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Mining time of the latest block with recorded work, and the hashrate over the last `window` such blocks
+    pub async fn get_recent_mining_work(
+        client: &Client,
+        window: i64,
+    ) -> Result<(Option<f64>, Option<f64>), tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT hash_attempts, mining_seconds FROM blocks
+                 WHERE hash_attempts IS NOT NULL AND mining_seconds IS NOT NULL
+                 ORDER BY \"index\" DESC LIMIT $1",
+                &[&window],
+            )
+            .await?;
+
+        let last_seconds = rows.first().map(|row| row.get::<_, f64>(1));
+        let attempts: i64 = rows.iter().map(|row| row.get::<_, i64>(0)).sum();
+        let seconds: f64 = rows.iter().map(|row| row.get::<_, f64>(1)).sum();
+        let hashrate = if seconds > 0.0 { Some(attempts as f64 / seconds) } else { None };
+
+        Ok((last_seconds, hashrate))
+    }
+
+    pub async fn set_block_burned_fees(client: &impl GenericClient, block_index: i64, burned: i64) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE blocks SET burned_fees = $2::int8 * 0.00000001 WHERE \"index\" = $1",
+                &[&block_index, &burned],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Units of the block's fees burned when it was mined
+    pub async fn get_block_burned_fees(client: &Client, block_index: i64) -> Result<i64, tokio_postgres::Error> {
+        let row = client
+            .query_one(
+                "SELECT (burned_fees * 100000000)::int8 FROM blocks WHERE \"index\" = $1",
+                &[&block_index],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+
+    pub async fn set_block_coinbase(client: &impl GenericClient, block_index: i64, coinbase_hash: &str) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE blocks SET coinbase_hash = $2 WHERE \"index\" = $1",
+                &[&block_index, &coinbase_hash],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Units paid by the block's coinbase output; 0 when the block paid no reward or its output is missing
+    pub async fn get_block_coinbase_amount(client: &Client, block_index: i64) -> Result<i64, tokio_postgres::Error> {
+        let row = client
+            .query_one(
+                "SELECT (COALESCE(SUM(u.amount), 0) * 100000000)::int8
+                 FROM blocks b
+                 JOIN utxos u ON u.transaction_hash = b.coinbase_hash AND u.is_coinbase
+                 WHERE b.\"index\" = $1",
+                &[&block_index],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+
+    /// The block's coinbase outputs: at most one for a mined block, one per allocation for genesis
+    pub async fn get_block_coinbase_outputs(
+        client: &Client,
+        block_index: i64,
+    ) -> Result<Vec<CoinbaseOutput>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT u.wallet_id, (u.amount * 100000000)::int8, u.transaction_hash
+                 FROM blocks b
+                 JOIN utxos u ON u.transaction_hash = b.coinbase_hash AND u.is_coinbase
+                 WHERE b.\"index\" = $1
+                 ORDER BY u.output_index",
+                &[&block_index],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| CoinbaseOutput {
+                wallet_id: row.get(0),
+                amount: row.get(1),
+                transaction_hash: row.get(2),
+            })
+            .collect())
+    }
+
+    /// Ids of the wallets' unspent coinbase outputs with fewer than `maturity` blocks mined on top
+    /// of them. Genesis allocations are spendable at once
+    pub async fn get_immature_coinbase_ids(
+        client: &Client,
+        wallet_ids: &[String],
+        maturity: i64,
+    ) -> Result<HashSet<Uuid>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT u.id
+                 FROM utxos u
+                 JOIN blocks b ON b.coinbase_hash = u.transaction_hash AND u.is_coinbase
+                 WHERE NOT u.is_spent AND u.wallet_id = ANY($1) AND b.\"index\" > 0
+                   AND b.\"index\" > (SELECT COALESCE(MAX(\"index\"), 0) FROM blocks) - $2",
+                &[&wallet_ids, &maturity],
+            )
+            .await?;
+        Ok(rows.into_iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Units in immature coinbase outputs (see [`get_immature_coinbase_ids`]), for one wallet or,
+    /// with `None`, across every wallet
+    pub async fn get_immature_coinbase_total(
+        client: &Client,
+        wallet_id: Option<&str>,
+        maturity: i64,
+    ) -> Result<i64, tokio_postgres::Error> {
+        let row = client
+            .query_one(
+                "SELECT (COALESCE(SUM(u.amount), 0) * 100000000)::int8
+                 FROM utxos u
+                 JOIN blocks b ON b.coinbase_hash = u.transaction_hash AND u.is_coinbase
+                 WHERE NOT u.is_spent AND ($1::varchar IS NULL OR u.wallet_id = $1) AND b.\"index\" > 0
+                   AND b.\"index\" > (SELECT COALESCE(MAX(\"index\"), 0) FROM blocks) - $2",
+                &[&wallet_id, &maturity],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+
+    pub async fn get_block_transaction_fees(
+        client: &Client,
+        block_index: i64,
+    ) -> Result<Vec<TransactionFee>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT transaction_hash, (fee * 100000000)::int8
+                 FROM transactions WHERE block_index = $1 AND transaction_type <> 'coinbase' AND fee IS NOT NULL",
+                &[&block_index],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TransactionFee {
+                transaction_hash: row.get(0),
+                fee: row.get(1),
+            })
+            .collect())
+    }
+
+    /// Fees (in units) of the block's transactions, or `None` if any was confirmed before fees were recorded
+    pub async fn get_block_fees(client: &Client, block_index: i64) -> Result<Option<i64>, tokio_postgres::Error> {
+        let row = client
+            .query_one(
+                "SELECT (COALESCE(SUM(fee), 0) * 100000000)::int8, COUNT(*) FILTER (WHERE fee IS NULL)
+                 FROM transactions WHERE block_index = $1 AND transaction_type <> 'coinbase'",
+                &[&block_index],
+            )
+            .await?;
+        let unknown: i64 = row.get(1);
+        Ok((unknown == 0).then(|| row.get(0)))
+    }
+
+    pub async fn get_latest_block(client: &Client) -> Result<Option<Block>, tokio_postgres::Error> {
+        let result = client
+            .query_opt(
+                "SELECT index, timestamp, previous_hash, hash, nonce, merkle_root, version 
+                 FROM blocks ORDER BY index DESC LIMIT 1",
+                &[],
+            )
+            .await?;
+
+        if let Some(row) = result {
             let index: i64 = row.get(0);
             
-            let tx_rows = client
-                .query(
-                    "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, amount::float8, note, 
-                     signature, block_index, transaction_type, timestamp, created_at 
-                     FROM transactions WHERE block_index = $1",
-                    &[&index],
-                )
-                .await?;
-
-            let transactions = tx_rows
-                .into_iter()
-                .map(|tx_row| TxModel {
-                    id: tx_row.get(0),
-                    transaction_hash: tx_row.get(1),
-                    sender_wallet_id: tx_row.get(2),
-                    receiver_wallet_id: tx_row.get(3),
-                    amount: tx_row.get(4),
-                    note: tx_row.get(5),
-                    signature: tx_row.get(6),
-                    block_index: tx_row.get(7),
-                    transaction_type: tx_row.get(8),
-                    timestamp: tx_row.get(9),
-                    created_at: tx_row.get(10),
-                })
-                .collect();
+            let transactions = get_block_transactions(client, index).await?;
 
             Ok(Some(Block {
                 index,
@@ -352,6 +1231,7 @@ pub mod queries {
                 hash: row.get(3),
                 nonce: row.get(4),
                 merkle_root: row.get(5),
+                version: row.get(6),
                 transactions,
             }))
         } else {
@@ -359,68 +1239,253 @@ pub mod queries {
         }
     }
 
-    pub async fn get_all_blocks(client: &Client, limit: i64, offset: i64) -> Result<Vec<Block>, tokio_postgres::Error> {
-        let rows = client
-            .query(
-                "SELECT index, timestamp, previous_hash, hash, nonce, merkle_root 
-                 FROM blocks ORDER BY index DESC LIMIT $1 OFFSET $2",
-                &[&limit, &offset],
+    pub async fn get_block_by_index(client: &Client, block_index: i64) -> Result<Option<Block>, tokio_postgres::Error> {
+        let result = client
+            .query_opt(
+                "SELECT index, timestamp, previous_hash, hash, nonce, merkle_root, version 
+                 FROM blocks WHERE index = $1",
+                &[&block_index],
             )
             .await?;
 
-        let mut blocks = Vec::new();
-        for row in rows {
+        if let Some(row) = result {
             let index: i64 = row.get(0);
             
-            let tx_rows = client
-                .query(
-                    "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, amount::float8, note, 
-                     signature, block_index, transaction_type, timestamp, created_at 
-                     FROM transactions WHERE block_index = $1",
-                    &[&index],
-                )
-                .await?;
-
-            let transactions = tx_rows
-                .into_iter()
-                .map(|tx_row| TxModel {
-                    id: tx_row.get(0),
-                    transaction_hash: tx_row.get(1),
-                    sender_wallet_id: tx_row.get(2),
-                    receiver_wallet_id: tx_row.get(3),
-                    amount: tx_row.get(4),
-                    note: tx_row.get(5),
-                    signature: tx_row.get(6),
-                    block_index: tx_row.get(7),
-                    transaction_type: tx_row.get(8),
-                    timestamp: tx_row.get(9),
-                    created_at: tx_row.get(10),
-                })
-                .collect();
+            let transactions = get_block_transactions(client, index).await?;
 
-            blocks.push(Block {
+            Ok(Some(Block {
                 index,
                 timestamp: row.get(1),
                 previous_hash: row.get(2),
                 hash: row.get(3),
                 nonce: row.get(4),
                 merkle_root: row.get(5),
+                version: row.get(6),
                 transactions,
-            });
+            }))
+        } else {
+            Ok(None)
         }
+    }
 
-        Ok(blocks)
+    /// The latest block's index, hash and timestamp, without loading its transactions
+    pub async fn get_chain_tip(client: &Client) -> Result<Option<ChainTip>, tokio_postgres::Error> {
+        let row = client
+            .query_opt("SELECT index, hash, timestamp FROM blocks ORDER BY index DESC LIMIT 1", &[])
+            .await?;
+
+        Ok(row.map(|row| ChainTip {
+            index: row.get(0),
+            hash: row.get(1),
+            timestamp: row.get(2),
+        }))
     }
 
-    // Transaction queries
-    pub async fn create_pending_transaction(
-        client: &Client,
-        transaction: &PendingTransaction,
-    ) -> Result<(), tokio_postgres::Error> {
-        client
-            .execute(
-                "INSERT INTO pending_transactions (id, transaction_hash, sender_wallet_id, receiver_wallet_id, amount, fee, note, signature, timestamp) 
-                 VALUES ($1, $2, $3, $4, $5::float8, $6::float8, $7, $8, $9)",
+    pub async fn get_block_header(client: &Client, block_index: i64) -> Result<Option<BlockHeader>, tokio_postgres::Error> {
+        let row = client
+            .query_opt(
+                "SELECT index, timestamp, previous_hash, hash, nonce, merkle_root, version, 
+                     (SELECT COUNT(*) FROM transactions t WHERE t.block_index = b.index AND t.transaction_type <> 'coinbase') 
+                 FROM blocks b WHERE index = $1",
+                &[&block_index],
+            )
+            .await?;
+
+        Ok(row.map(|row| BlockHeader {
+            index: row.get(0),
+            timestamp: row.get(1),
+            previous_hash: row.get(2),
+            hash: row.get(3),
+            nonce: row.get(4),
+            merkle_root: row.get(5),
+            version: row.get(6),
+            transaction_count: row.get(7),
+        }))
+    }
+
+    /// One page of a block's transactions, in block order
+    pub async fn get_block_transactions_page(
+        client: &Client,
+        block_index: i64,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<TxModel>, tokio_postgres::Error> {
+        let tx_rows = client
+            .query(
+                "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, (amount * 100000000)::int8, note, 
+                 signature, block_index, transaction_type, timestamp, created_at, note_encrypted, (fee * 100000000)::int8 
+                 FROM transactions WHERE block_index = $1 AND transaction_type <> 'coinbase' ORDER BY ordinal, created_at, id 
+                 LIMIT $2 OFFSET $3",
+                &[&block_index, &limit, &offset],
+            )
+            .await?;
+
+        Ok(tx_rows.iter().map(row_to_block_transaction).collect())
+    }
+
+    /// Blocks from `from` upwards, oldest first
+    pub async fn get_blocks_from(client: &Client, from: i64, limit: i64) -> Result<Vec<Block>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT index, timestamp, previous_hash, hash, nonce, merkle_root, version 
+                 FROM blocks WHERE index >= $1 ORDER BY index ASC LIMIT $2",
+                &[&from, &limit],
+            )
+            .await?;
+
+        rows_to_blocks(client, rows).await
+    }
+
+    pub async fn get_block_by_hash(client: &Client, hash: &str) -> Result<Option<Block>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT index, timestamp, previous_hash, hash, nonce, merkle_root, version 
+                 FROM blocks WHERE hash = $1",
+                &[&hash],
+            )
+            .await?;
+
+        Ok(rows_to_blocks(client, rows).await?.into_iter().next())
+    }
+
+    fn row_to_block_transaction(tx_row: &tokio_postgres::Row) -> TxModel {
+        TxModel {
+            id: tx_row.get(0),
+            transaction_hash: tx_row.get(1),
+            sender_wallet_id: tx_row.get(2),
+            receiver_wallet_id: tx_row.get(3),
+            amount: tx_row.get(4),
+            note: tx_row.get(5),
+            signature: tx_row.get(6),
+            block_index: tx_row.get(7),
+            transaction_type: tx_row.get(8),
+            timestamp: tx_row.get(9),
+            created_at: tx_row.get(10),
+            note_encrypted: tx_row.get(11),
+            fee: tx_row.get(12),
+        }
+    }
+
+    async fn get_block_transactions(client: &Client, block_index: i64) -> Result<Vec<TxModel>, tokio_postgres::Error> {
+        let tx_rows = client
+            .query(
+                "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, (amount * 100000000)::int8, note, 
+                 signature, block_index, transaction_type, timestamp, created_at, note_encrypted, (fee * 100000000)::int8 
+                 FROM transactions WHERE block_index = $1 AND transaction_type <> 'coinbase' ORDER BY ordinal, created_at",
+                &[&block_index],
+            )
+            .await?;
+
+        Ok(tx_rows.iter().map(row_to_block_transaction).collect())
+    }
+
+    /// Blocks `from..=to`, oldest first, with all their transactions loaded in a single query
+    pub async fn get_blocks_in_range(client: &Client, from: i64, to: i64) -> Result<Vec<Block>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT index, timestamp, previous_hash, hash, nonce, merkle_root, version 
+                 FROM blocks WHERE index BETWEEN $1 AND $2 ORDER BY index ASC",
+                &[&from, &to],
+            )
+            .await?;
+        let indexes: Vec<i64> = rows.iter().map(|row| row.get(0)).collect();
+
+        let tx_rows = client
+            .query(
+                "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, (amount * 100000000)::int8, note, 
+                 signature, block_index, transaction_type, timestamp, created_at, note_encrypted, (fee * 100000000)::int8 
+                 FROM transactions WHERE block_index = ANY($1) AND transaction_type <> 'coinbase' ORDER BY block_index, ordinal, created_at",
+                &[&indexes],
+            )
+            .await?;
+        let mut by_block: std::collections::HashMap<i64, Vec<TxModel>> = std::collections::HashMap::new();
+        for tx in tx_rows.iter().map(row_to_block_transaction) {
+            if let Some(block_index) = tx.block_index {
+                by_block.entry(block_index).or_default().push(tx);
+            }
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let index: i64 = row.get(0);
+                Block {
+                    index,
+                    timestamp: row.get(1),
+                    previous_hash: row.get(2),
+                    hash: row.get(3),
+                    nonce: row.get(4),
+                    merkle_root: row.get(5),
+                    version: row.get(6),
+                    transactions: by_block.remove(&index).unwrap_or_default(),
+                }
+            })
+            .collect())
+    }
+
+    async fn rows_to_blocks(client: &Client, rows: Vec<tokio_postgres::Row>) -> Result<Vec<Block>, tokio_postgres::Error> {
+        let mut blocks = Vec::new();
+        for row in rows {
+            let index: i64 = row.get(0);
+            
+            let transactions = get_block_transactions(client, index).await?;
+
+            blocks.push(Block {
+                index,
+                timestamp: row.get(1),
+                previous_hash: row.get(2),
+                hash: row.get(3),
+                nonce: row.get(4),
+                merkle_root: row.get(5),
+                version: row.get(6),
+                transactions,
+            });
+        }
+
+        Ok(blocks)
+    }
+
+    pub async fn get_all_blocks(client: &Client, limit: i64, offset: i64) -> Result<Vec<Block>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT index, timestamp, previous_hash, hash, nonce, merkle_root, version 
+                 FROM blocks ORDER BY index DESC LIMIT $1 OFFSET $2",
+                &[&limit, &offset],
+            )
+            .await?;
+
+        rows_to_blocks(client, rows).await
+    }
+
+    /// Keyset page of blocks strictly below `before` (newest first); `None` starts at the chain tip
+    pub async fn get_blocks_before(
+        client: &Client,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Block>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT index, timestamp, previous_hash, hash, nonce, merkle_root, version 
+                 FROM blocks WHERE ($1::BIGINT IS NULL OR index < $1) 
+                 ORDER BY index DESC LIMIT $2",
+                &[&before, &limit],
+            )
+            .await?;
+
+        rows_to_blocks(client, rows).await
+    }
+
+    // Transaction queries
+    /// `false` if a pending transaction with the same hash already exists; nothing is inserted then
+    pub async fn create_pending_transaction(
+        client: &impl GenericClient,
+        transaction: &PendingTransaction,
+    ) -> Result<bool, tokio_postgres::Error> {
+        let inserted = client
+            .execute(
+                "INSERT INTO pending_transactions (id, transaction_hash, sender_wallet_id, receiver_wallet_id, amount, fee, note, signature, timestamp, note_encrypted) 
+                 VALUES ($1, $2, $3, $4, $5::int8 * 0.00000001, $6::int8 * 0.00000001, $7, $8, $9, $10) 
+                 ON CONFLICT (transaction_hash) DO NOTHING",
                 &[
                     &transaction.id,
                     &transaction.transaction_hash,
@@ -431,16 +1496,17 @@ pub mod queries {
                     &transaction.note,
                     &transaction.signature,
                     &transaction.timestamp,
+                    &transaction.note_encrypted,
                 ],
             )
             .await?;
-        Ok(())
+        Ok(inserted == 1)
     }
 
     pub async fn get_pending_transactions(client: &Client) -> Result<Vec<PendingTransaction>, tokio_postgres::Error> {
         let rows = client
             .query(
-                "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, amount::float8, fee::float8, note, signature, timestamp, created_at 
+                "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, (amount * 100000000)::int8, (fee * 100000000)::int8, note, signature, timestamp, created_at, note_encrypted 
                  FROM pending_transactions ORDER BY created_at ASC",
                 &[],
             )
@@ -448,271 +1514,1196 @@ pub mod queries {
 
         Ok(rows
             .into_iter()
-            .map(|row| PendingTransaction {
-                id: row.get(0),
-                transaction_hash: row.get(1),
-                sender_wallet_id: row.get(2),
-                receiver_wallet_id: row.get(3),
-                amount: row.get(4),
-                fee: row.get(5),
-                note: row.get(6),
-                signature: row.get(7),
-                timestamp: row.get(8),
-                created_at: row.get(9),
+            .map(|row| PendingTransaction {
+                id: row.get(0),
+                transaction_hash: row.get(1),
+                sender_wallet_id: row.get(2),
+                receiver_wallet_id: row.get(3),
+                amount: row.get(4),
+                fee: row.get(5),
+                note: row.get(6),
+                signature: row.get(7),
+                timestamp: row.get(8),
+                created_at: row.get(9),
+                note_encrypted: row.get(10),
+            })
+            .collect())
+    }
+
+    /// Median is the interpolated 50th percentile, rounded to whole units
+    pub async fn get_mempool_stats(client: &Client) -> Result<MempoolStats, tokio_postgres::Error> {
+        let row = client
+            .query_one(
+                "SELECT COUNT(*), 
+                 (COALESCE(SUM(amount), 0) * 100000000)::int8, 
+                 (COALESCE(SUM(fee), 0) * 100000000)::int8, 
+                 (MIN(fee) * 100000000)::int8, 
+                 ROUND((PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY fee) * 100000000)::numeric)::int8, 
+                 (MAX(fee) * 100000000)::int8, 
+                 EXTRACT(EPOCH FROM NOW() - MIN(created_at))::float8 
+                 FROM pending_transactions",
+                &[],
+            )
+            .await?;
+
+        Ok(MempoolStats {
+            transaction_count: row.get(0),
+            total_amount: row.get(1),
+            total_fees: row.get(2),
+            min_fee: row.get(3),
+            median_fee: row.get(4),
+            max_fee: row.get(5),
+            oldest_pending_seconds: row.get(6),
+        })
+    }
+
+    pub async fn get_pending_transaction_by_hash(
+        client: &Client,
+        tx_hash: &str,
+    ) -> Result<Option<PendingTransaction>, tokio_postgres::Error> {
+        let result = client
+            .query_opt(
+                "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, (amount * 100000000)::int8, (fee * 100000000)::int8, note, signature, timestamp, created_at, note_encrypted 
+                 FROM pending_transactions WHERE transaction_hash = $1",
+                &[&tx_hash],
+            )
+            .await?;
+
+        Ok(result.map(|row| PendingTransaction {
+            id: row.get(0),
+            transaction_hash: row.get(1),
+            sender_wallet_id: row.get(2),
+            receiver_wallet_id: row.get(3),
+            amount: row.get(4),
+            fee: row.get(5),
+            note: row.get(6),
+            signature: row.get(7),
+            timestamp: row.get(8),
+            created_at: row.get(9),
+            note_encrypted: row.get(10),
+        }))
+    }
+
+    pub async fn delete_pending_transactions(client: &impl GenericClient, tx_ids: &[Uuid]) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute("DELETE FROM pending_transactions WHERE id = ANY($1)", &[&tx_ids])
+            .await?;
+        Ok(())
+    }
+
+    /// Which of `hashes` are already confirmed in `transactions`
+    pub async fn get_confirmed_hashes(
+        client: &Client,
+        hashes: &[String],
+    ) -> Result<std::collections::HashSet<String>, tokio_postgres::Error> {
+        let rows = client
+            .query("SELECT transaction_hash FROM transactions WHERE transaction_hash = ANY($1)", &[&hashes])
+            .await?;
+        Ok(rows.into_iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Move mined pending transactions into `transactions` in one statement, each with its
+    /// position in the block
+    pub async fn create_transactions(
+        client: &impl GenericClient,
+        mined: &[(&PendingTransaction, i32)],
+        block_index: i64,
+        transaction_type: &str,
+    ) -> Result<(), tokio_postgres::Error> {
+        if mined.is_empty() {
+            return Ok(());
+        }
+        let column = |f: fn(&PendingTransaction) -> String| mined.iter().map(|(tx, _)| f(tx)).collect::<Vec<_>>();
+        let hashes = column(|tx| tx.transaction_hash.clone());
+        let senders = column(|tx| tx.sender_wallet_id.clone());
+        let receivers = column(|tx| tx.receiver_wallet_id.clone());
+        let signatures = column(|tx| tx.signature.clone());
+        let amounts: Vec<i64> = mined.iter().map(|(tx, _)| tx.amount).collect();
+        let fees: Vec<i64> = mined.iter().map(|(tx, _)| tx.fee).collect();
+        let notes: Vec<Option<String>> = mined.iter().map(|(tx, _)| tx.note.clone()).collect();
+        let encrypted: Vec<bool> = mined.iter().map(|(tx, _)| tx.note_encrypted).collect();
+        let timestamps: Vec<i64> = mined.iter().map(|(tx, _)| tx.timestamp).collect();
+        let ordinals: Vec<i32> = mined.iter().map(|(_, ordinal)| *ordinal).collect();
+
+        client
+            .execute(
+                "INSERT INTO transactions (transaction_hash, sender_wallet_id, receiver_wallet_id, amount, note, signature, block_index, transaction_type, timestamp, fee, note_encrypted, ordinal) 
+                 SELECT hash, sender, receiver, amount * 0.00000001, note, signature, $11, $12, timestamp, fee * 0.00000001, note_encrypted, ordinal 
+                 FROM UNNEST($1::varchar[], $2::varchar[], $3::varchar[], $4::int8[], $5::text[], $6::text[], $7::int8[], $8::int8[], $9::bool[], $10::int4[]) 
+                     AS t(hash, sender, receiver, amount, note, signature, timestamp, fee, note_encrypted, ordinal)",
+                &[
+                    &hashes,
+                    &senders,
+                    &receivers,
+                    &amounts,
+                    &notes,
+                    &signatures,
+                    &timestamps,
+                    &fees,
+                    &encrypted,
+                    &ordinals,
+                    &block_index,
+                    &transaction_type,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Record a block's mining reward in the miner's history, sent from [`crate::blockchain::COINBASE_SENDER`]
+    pub async fn create_coinbase_transaction(
+        client: &impl GenericClient,
+        transaction_hash: &str,
+        miner_wallet_id: &str,
+        amount: i64,
+        block_index: i64,
+        ordinal: i32,
+        timestamp: i64,
+    ) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "INSERT INTO transactions (transaction_hash, sender_wallet_id, receiver_wallet_id, amount, fee, signature, block_index, transaction_type, timestamp, ordinal) 
+                 VALUES ($1, $2, $3, $4::int8 * 0.00000001, 0, '', $5, 'coinbase', $6, $7)",
+                &[
+                    &transaction_hash,
+                    &crate::blockchain::COINBASE_SENDER,
+                    &miner_wallet_id,
+                    &amount,
+                    &block_index,
+                    &timestamp,
+                    &ordinal,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_transaction_by_hash(client: &Client, tx_hash: &str) -> Result<Option<TxModel>, tokio_postgres::Error> {
+        let result = client
+            .query_opt(
+                "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, (amount * 100000000)::int8, note, 
+                 signature, block_index, transaction_type, timestamp, created_at, note_encrypted, (fee * 100000000)::int8 
+                 FROM transactions WHERE transaction_hash = $1",
+                &[&tx_hash],
+            )
+            .await?;
+
+        Ok(result.map(|row| TxModel {
+            id: row.get(0),
+            transaction_hash: row.get(1),
+            sender_wallet_id: row.get(2),
+            receiver_wallet_id: row.get(3),
+            amount: row.get(4),
+            note: row.get(5),
+            signature: row.get(6),
+            block_index: row.get(7),
+            transaction_type: row.get(8),
+            timestamp: row.get(9),
+            created_at: row.get(10),
+            note_encrypted: row.get(11),
+            fee: row.get(12),
+        }))
+    }
+
+    pub async fn get_wallet_transactions(
+        client: &Client,
+        wallet_id: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<TxModel>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, (amount * 100000000)::int8, note, 
+                 signature, block_index, transaction_type, timestamp, created_at, note_encrypted, (fee * 100000000)::int8 
+                 FROM transactions 
+                 WHERE sender_wallet_id = $1 OR receiver_wallet_id = $1 
+                 ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+                &[&wallet_id, &limit, &offset],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TxModel {
+                id: row.get(0),
+                transaction_hash: row.get(1),
+                sender_wallet_id: row.get(2),
+                receiver_wallet_id: row.get(3),
+                amount: row.get(4),
+                note: row.get(5),
+                signature: row.get(6),
+                block_index: row.get(7),
+                transaction_type: row.get(8),
+                timestamp: row.get(9),
+                created_at: row.get(10),
+                note_encrypted: row.get(11),
+                fee: row.get(12),
+            })
+            .collect())
+    }
+
+    /// A wallet's transactions whose note contains `q` (case-insensitive), newest first.
+    /// Private notes are ciphertext and never match.
+    pub async fn search_wallet_transactions(
+        client: &Client,
+        wallet_id: &str,
+        q: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<TxModel>, tokio_postgres::Error> {
+        let pattern = format!("%{}%", crate::utils::escape_like(q));
+        let rows = client
+            .query(
+                "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, (amount * 100000000)::int8, note, 
+                 signature, block_index, transaction_type, timestamp, created_at, note_encrypted, (fee * 100000000)::int8 
+                 FROM transactions 
+                 WHERE (sender_wallet_id = $1 OR receiver_wallet_id = $1) 
+                 AND NOT note_encrypted AND note ILIKE $2 
+                 ORDER BY created_at DESC LIMIT $3 OFFSET $4",
+                &[&wallet_id, &pattern, &limit, &offset],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TxModel {
+                id: row.get(0),
+                transaction_hash: row.get(1),
+                sender_wallet_id: row.get(2),
+                receiver_wallet_id: row.get(3),
+                amount: row.get(4),
+                note: row.get(5),
+                signature: row.get(6),
+                block_index: row.get(7),
+                transaction_type: row.get(8),
+                timestamp: row.get(9),
+                created_at: row.get(10),
+                note_encrypted: row.get(11),
+                fee: row.get(12),
+            })
+            .collect())
+    }
+
+    pub async fn get_wallet_summary(
+        client: &Client,
+        wallet_id: &str,
+    ) -> Result<WalletSummary, tokio_postgres::Error> {
+        let row = client
+            .query_one(
+                "SELECT COUNT(*), 
+                 (COALESCE(SUM(amount) FILTER (WHERE sender_wallet_id = $1), 0) * 100000000)::int8, 
+                 (COALESCE(SUM(amount) FILTER (WHERE receiver_wallet_id = $1), 0) * 100000000)::int8, 
+                 MIN(created_at), MAX(created_at), 
+                 (SELECT COUNT(*) FROM utxos WHERE wallet_id = $1 AND is_spent = false) 
+                 FROM transactions 
+                 WHERE sender_wallet_id = $1 OR receiver_wallet_id = $1",
+                &[&wallet_id],
+            )
+            .await?;
+
+        Ok(WalletSummary {
+            wallet_id: wallet_id.to_string(),
+            transaction_count: row.get(0),
+            total_sent: row.get(1),
+            total_received: row.get(2),
+            first_transaction_at: row.get(3),
+            last_activity_at: row.get(4),
+            unspent_utxo_count: row.get(5),
+        })
+    }
+
+    /// Keyset page of a wallet's transactions ordered by (created_at, id) descending
+    pub async fn get_wallet_transactions_before(
+        client: &Client,
+        wallet_id: &str,
+        before: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<TxModel>, tokio_postgres::Error> {
+        let (before_created_at, before_id) = match before {
+            Some((created_at, id)) => (Some(created_at), Some(id)),
+            None => (None, None),
+        };
+
+        let rows = client
+            .query(
+                "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, (amount * 100000000)::int8, note, 
+                 signature, block_index, transaction_type, timestamp, created_at, note_encrypted, (fee * 100000000)::int8 
+                 FROM transactions 
+                 WHERE (sender_wallet_id = $1 OR receiver_wallet_id = $1) 
+                 AND ($2::TIMESTAMPTZ IS NULL OR (created_at, id) < ($2, $3::UUID)) 
+                 ORDER BY created_at DESC, id DESC LIMIT $4",
+                &[&wallet_id, &before_created_at, &before_id, &limit],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TxModel {
+                id: row.get(0),
+                transaction_hash: row.get(1),
+                sender_wallet_id: row.get(2),
+                receiver_wallet_id: row.get(3),
+                amount: row.get(4),
+                note: row.get(5),
+                signature: row.get(6),
+                block_index: row.get(7),
+                transaction_type: row.get(8),
+                timestamp: row.get(9),
+                created_at: row.get(10),
+                note_encrypted: row.get(11),
+                fee: row.get(12),
+            })
+            .collect())
+    }
+
+    // OTP queries
+    pub async fn create_otp(
+        client: &Client,
+        email: &str,
+        otp: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<EmailOtp, tokio_postgres::Error> {
+        let row = client
+            .query_one(
+                "INSERT INTO email_otps (email, otp, expires_at) 
+                 VALUES ($1, $2, $3) 
+                 RETURNING id, email, otp, is_verified, expires_at, created_at",
+                &[&email, &otp, &expires_at],
+            )
+            .await?;
+
+        Ok(EmailOtp {
+            id: row.get(0),
+            email: row.get(1),
+            otp: row.get(2),
+            is_verified: row.get(3),
+            expires_at: row.get(4),
+            created_at: row.get(5),
+        })
+    }
+
+    /// Expire every still-usable code for `email` so only the next one issued works
+    pub async fn invalidate_otps(
+        client: &Client,
+        email: &str,
+    ) -> Result<u64, tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE email_otps SET expires_at = NOW()
+                 WHERE email = $1 AND is_verified = FALSE AND expires_at > NOW()",
+                &[&email],
+            )
+            .await
+    }
+
+    pub async fn latest_otp_created_at(
+        client: &Client,
+        email: &str,
+    ) -> Result<Option<DateTime<Utc>>, tokio_postgres::Error> {
+        let row = client
+            .query_one(
+                "SELECT MAX(created_at) FROM email_otps WHERE email = $1",
+                &[&email],
+            )
+            .await?;
+
+        Ok(row.get(0))
+    }
+
+    /// Unused, unexpired codes for `email` as `(id, otp)`; the caller compares them in constant time
+    pub async fn get_active_otps(
+        client: &Client,
+        email: &str,
+    ) -> Result<Vec<(Uuid, String)>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT id, otp FROM email_otps 
+                 WHERE email = $1 AND is_verified = FALSE AND expires_at > NOW()",
+                &[&email],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    /// Use up a code; false if it was verified or expired in the meantime
+    pub async fn mark_otp_verified(client: &Client, id: Uuid) -> Result<bool, tokio_postgres::Error> {
+        let updated = client
+            .execute(
+                "UPDATE email_otps SET is_verified = TRUE 
+                 WHERE id = $1 AND is_verified = FALSE AND expires_at > NOW()",
+                &[&id],
+            )
+            .await?;
+
+        Ok(updated == 1)
+    }
+
+    pub async fn mark_user_verified(
+        client: &Client,
+        email: &str,
+    ) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE users SET is_verified = TRUE WHERE email = $1",
+                &[&email],
+            )
+            .await?;
+        Ok(())
+    }
+
+    // System logs
+    pub async fn create_system_log(
+        client: &impl GenericClient,
+        log_type: &str,
+        user_id: Option<Uuid>,
+        message: &str,
+        ip_address: Option<String>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "INSERT INTO system_logs (log_type, user_id, message, ip_address, metadata) 
+                 VALUES ($1, $2, $3, $4, $5)",
+                &[&log_type, &user_id, &message, &ip_address, &metadata],
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Transaction logs
+    pub async fn create_transaction_log(
+        client: &impl GenericClient,
+        wallet_id: &str,
+        action: &str,
+        transaction_hash: Option<String>,
+        block_hash: Option<String>,
+        status: &str,
+        ip_address: Option<String>,
+        user_agent: Option<String>,
+        note: Option<String>,
+    ) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "INSERT INTO transaction_logs (wallet_id, action, transaction_hash, block_hash, status, ip_address, user_agent, note) 
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                &[
+                    &wallet_id,
+                    &action,
+                    &transaction_hash,
+                    &block_hash,
+                    &status,
+                    &ip_address,
+                    &user_agent,
+                    &note,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Beneficiary queries
+    pub async fn get_user_beneficiaries(
+        client: &Client,
+        user_id: Uuid,
+    ) -> Result<Vec<crate::models::Beneficiary>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT id, user_id, beneficiary_wallet_id, nickname, created_at 
+                 FROM beneficiaries WHERE user_id = $1 ORDER BY created_at DESC",
+                &[&user_id],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| crate::models::Beneficiary {
+                id: row.get(0),
+                user_id: row.get(1),
+                beneficiary_wallet_id: row.get(2),
+                nickname: row.get(3),
+                created_at: row.get(4),
+            })
+            .collect())
+    }
+
+    pub async fn add_beneficiary(
+        client: &Client,
+        user_id: Uuid,
+        beneficiary_wallet_id: &str,
+        nickname: Option<String>,
+    ) -> Result<crate::models::Beneficiary, tokio_postgres::Error> {
+        let row = client
+            .query_one(
+                "INSERT INTO beneficiaries (user_id, beneficiary_wallet_id, nickname) 
+                 VALUES ($1, $2, $3) 
+                 RETURNING id, user_id, beneficiary_wallet_id, nickname, created_at",
+                &[&user_id, &beneficiary_wallet_id, &nickname],
+            )
+            .await?;
+
+        Ok(crate::models::Beneficiary {
+            id: row.get(0),
+            user_id: row.get(1),
+            beneficiary_wallet_id: row.get(2),
+            nickname: row.get(3),
+            created_at: row.get(4),
+        })
+    }
+
+    pub async fn delete_beneficiary(
+        client: &Client,
+        beneficiary_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<u64, tokio_postgres::Error> {
+        let result = client
+            .execute(
+                "DELETE FROM beneficiaries WHERE id = $1 AND user_id = $2",
+                &[&beneficiary_id, &user_id],
+            )
+            .await?;
+        Ok(result)
+    }
+
+    // Webhook queries
+    pub async fn create_webhook(
+        client: &Client,
+        wallet_id: &str,
+        url: &str,
+        secret: &str,
+    ) -> Result<Webhook, tokio_postgres::Error> {
+        let row = client
+            .query_one(
+                "INSERT INTO webhooks (wallet_id, url, secret) 
+                 VALUES ($1, $2, $3) 
+                 RETURNING id, wallet_id, url, secret, created_at",
+                &[&wallet_id, &url, &secret],
+            )
+            .await?;
+
+        Ok(Webhook {
+            id: row.get(0),
+            wallet_id: row.get(1),
+            url: row.get(2),
+            secret: row.get(3),
+            created_at: row.get(4),
+        })
+    }
+
+    pub async fn get_user_webhooks(client: &Client, user_id: Uuid) -> Result<Vec<Webhook>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT w.id, w.wallet_id, w.url, w.secret, w.created_at 
+                 FROM webhooks w JOIN wallets wl ON wl.wallet_id = w.wallet_id 
+                 WHERE wl.user_id = $1 ORDER BY w.created_at DESC",
+                &[&user_id],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Webhook {
+                id: row.get(0),
+                wallet_id: row.get(1),
+                url: row.get(2),
+                secret: row.get(3),
+                created_at: row.get(4),
+            })
+            .collect())
+    }
+
+    pub async fn get_webhooks_for_wallets(
+        client: &Client,
+        wallet_ids: &[String],
+    ) -> Result<Vec<Webhook>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT id, wallet_id, url, secret, created_at 
+                 FROM webhooks WHERE wallet_id = ANY($1)",
+                &[&wallet_ids],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Webhook {
+                id: row.get(0),
+                wallet_id: row.get(1),
+                url: row.get(2),
+                secret: row.get(3),
+                created_at: row.get(4),
+            })
+            .collect())
+    }
+
+    pub async fn delete_webhook(
+        client: &Client,
+        webhook_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<u64, tokio_postgres::Error> {
+        client
+            .execute(
+                "DELETE FROM webhooks w USING wallets wl 
+                 WHERE w.id = $1 AND wl.wallet_id = w.wallet_id AND wl.user_id = $2",
+                &[&webhook_id, &user_id],
+            )
+            .await
+    }
+
+    /// Queue several `(webhook_id, body)` deliveries in one statement
+    pub async fn enqueue_webhook_deliveries(client: &Client, deliveries: &[(Uuid, String)]) -> Result<(), tokio_postgres::Error> {
+        if deliveries.is_empty() {
+            return Ok(());
+        }
+        let webhook_ids: Vec<Uuid> = deliveries.iter().map(|d| d.0).collect();
+        let bodies: Vec<&str> = deliveries.iter().map(|d| d.1.as_str()).collect();
+        client
+            .execute(
+                "INSERT INTO webhook_deliveries (webhook_id, body) 
+                 SELECT * FROM UNNEST($1::uuid[], $2::text[])",
+                &[&webhook_ids, &bodies],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Queued deliveries whose next attempt is due, oldest first
+    pub async fn get_due_webhook_deliveries(client: &Client, limit: i64) -> Result<Vec<WebhookDelivery>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT d.id, w.url, w.secret, d.body, d.attempts 
+                 FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhook_id 
+                 WHERE d.status = 'queued' AND d.next_attempt_at <= NOW() 
+                 ORDER BY d.next_attempt_at LIMIT $1",
+                &[&limit],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| WebhookDelivery {
+                id: row.get(0),
+                url: row.get(1),
+                secret: row.get(2),
+                body: row.get(3),
+                attempts: row.get(4),
+            })
+            .collect())
+    }
+
+    pub async fn mark_webhook_delivered(client: &Client, id: Uuid) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE webhook_deliveries SET status = 'delivered', attempts = attempts + 1, delivered_at = NOW(), last_error = NULL 
+                 WHERE id = $1",
+                &[&id],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Count a failed attempt and push the next one back `retry_delay_secs`, doubled for each
+    /// earlier failure; gives up once the delivery has had `max_attempts`
+    pub async fn record_webhook_failure(
+        client: &Client,
+        id: Uuid,
+        error: &str,
+        max_attempts: i32,
+        retry_delay_secs: f64,
+    ) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE webhook_deliveries SET attempts = attempts + 1, last_error = $2, 
+                 next_attempt_at = NOW() + make_interval(secs => $4 * power(2, attempts)), 
+                 status = CASE WHEN attempts + 1 >= $3 THEN 'failed' ELSE status END 
+                 WHERE id = $1",
+                &[&id, &error, &max_attempts, &retry_delay_secs],
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Notification queries
+    pub async fn get_notification_preferences(
+        client: &Client,
+        user_id: Uuid,
+    ) -> Result<Option<NotificationPreferences>, tokio_postgres::Error> {
+        let row = client
+            .query_opt(
+                "SELECT payment_received, zakat_deducted, (low_balance_threshold * 100000000)::int8 
+                 FROM notification_preferences WHERE user_id = $1",
+                &[&user_id],
+            )
+            .await?;
+
+        Ok(row.map(|row| NotificationPreferences {
+            payment_received: row.get(0),
+            zakat_deducted: row.get(1),
+            low_balance_threshold: row.get(2),
+        }))
+    }
+
+    pub async fn upsert_notification_preferences(
+        client: &Client,
+        user_id: Uuid,
+        preferences: &NotificationPreferences,
+    ) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "INSERT INTO notification_preferences (user_id, payment_received, zakat_deducted, low_balance_threshold) 
+                 VALUES ($1, $2, $3, $4::int8 * 0.00000001) 
+                 ON CONFLICT (user_id) DO UPDATE SET 
+                 payment_received = EXCLUDED.payment_received, 
+                 zakat_deducted = EXCLUDED.zakat_deducted, 
+                 low_balance_threshold = EXCLUDED.low_balance_threshold, 
+                 updated_at = NOW()",
+                &[
+                    &user_id,
+                    &preferences.payment_received,
+                    &preferences.zakat_deducted,
+                    &preferences.low_balance_threshold,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_notification_preferences(client: &Client, user_id: Uuid) -> Result<u64, tokio_postgres::Error> {
+        client
+            .execute("DELETE FROM notification_preferences WHERE user_id = $1", &[&user_id])
+            .await
+    }
+
+    /// Owners of `wallet_ids` with their preferences; ownerless wallets are left out
+    pub async fn get_notification_recipients(
+        client: &Client,
+        wallet_ids: &[String],
+    ) -> Result<Vec<NotificationRecipient>, tokio_postgres::Error> {
+        let rows = client
+            .query(
+                "SELECT u.wallet_id, u.email, (w.balance * 100000000)::int8, p.user_id IS NOT NULL, 
+                 p.payment_received, p.zakat_deducted, (p.low_balance_threshold * 100000000)::int8 
+                 FROM users u 
+                 JOIN wallets w ON w.wallet_id = u.wallet_id 
+                 LEFT JOIN notification_preferences p ON p.user_id = u.id 
+                 WHERE u.wallet_id = ANY($1)",
+                &[&wallet_ids],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let has_preferences: bool = row.get(3);
+                let preferences = if has_preferences {
+                    NotificationPreferences {
+                        payment_received: row.get(4),
+                        zakat_deducted: row.get(5),
+                        low_balance_threshold: row.get(6),
+                    }
+                } else {
+                    NotificationPreferences::default()
+                };
+                NotificationRecipient {
+                    wallet_id: row.get(0),
+                    email: row.get(1),
+                    preferences,
+                    balance: row.get(2),
+                }
             })
             .collect())
     }
 
-    pub async fn delete_pending_transaction(client: &Client, tx_id: Uuid) -> Result<(), tokio_postgres::Error> {
+    pub async fn enqueue_email(
+        client: &Client,
+        recipient: &str,
+        event: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<(), tokio_postgres::Error> {
         client
-            .execute("DELETE FROM pending_transactions WHERE id = $1", &[&tx_id])
+            .execute(
+                "INSERT INTO email_outbox (recipient, event, subject, body) VALUES ($1, $2, $3, $4)",
+                &[&recipient, &event, &subject, &body],
+            )
             .await?;
         Ok(())
     }
 
-    pub async fn create_transaction(
-        client: &Client,
-        pending_tx: &PendingTransaction,
-        block_index: i64,
-        transaction_type: &str,
-    ) -> Result<TxModel, tokio_postgres::Error> {
-        let row = client
-            .query_one(
-                "INSERT INTO transactions (transaction_hash, sender_wallet_id, receiver_wallet_id, amount, note, signature, block_index, transaction_type, timestamp) 
-                 VALUES ($1, $2, $3, $4::float8, $5, $6, $7, $8, $9) 
-                 RETURNING id, transaction_hash, sender_wallet_id, receiver_wallet_id, amount::float8, note, signature, block_index, transaction_type, timestamp, created_at",
-                &[
-                    &pending_tx.transaction_hash,
-                    &pending_tx.sender_wallet_id,
-                    &pending_tx.receiver_wallet_id,
-                    &pending_tx.amount,
-                    &pending_tx.note,
-                    &pending_tx.signature,
-                    &block_index,
-                    &transaction_type,
-                    &pending_tx.timestamp,
-                ],
+    /// Queue several `(recipient, event, subject, body)` emails in one statement
+    pub async fn enqueue_emails(client: &Client, emails: &[(String, &str, &str, String)]) -> Result<(), tokio_postgres::Error> {
+        if emails.is_empty() {
+            return Ok(());
+        }
+        let recipients: Vec<&str> = emails.iter().map(|e| e.0.as_str()).collect();
+        let events: Vec<&str> = emails.iter().map(|e| e.1).collect();
+        let subjects: Vec<&str> = emails.iter().map(|e| e.2).collect();
+        let bodies: Vec<&str> = emails.iter().map(|e| e.3.as_str()).collect();
+        client
+            .execute(
+                "INSERT INTO email_outbox (recipient, event, subject, body) 
+                 SELECT * FROM UNNEST($1::varchar[], $2::varchar[], $3::text[], $4::text[])",
+                &[&recipients, &events, &subjects, &bodies],
             )
             .await?;
-
-        Ok(TxModel {
-            id: row.get(0),
-            transaction_hash: row.get(1),
-            sender_wallet_id: row.get(2),
-            receiver_wallet_id: row.get(3),
-            amount: row.get(4),
-            note: row.get(5),
-            signature: row.get(6),
-            block_index: row.get(7),
-            transaction_type: row.get(8),
-            timestamp: row.get(9),
-            created_at: row.get(10),
-        })
+        Ok(())
     }
 
-    pub async fn get_wallet_transactions(
-        client: &Client,
-        wallet_id: &str,
-        limit: i64,
-        offset: i64,
-    ) -> Result<Vec<TxModel>, tokio_postgres::Error> {
+    /// Oldest queued emails first
+    pub async fn get_queued_emails(client: &Client, limit: i64) -> Result<Vec<OutboxEmail>, tokio_postgres::Error> {
         let rows = client
             .query(
-                "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, amount::float8, note, 
-                 signature, block_index, transaction_type, timestamp, created_at 
-                 FROM transactions 
-                 WHERE sender_wallet_id = $1 OR receiver_wallet_id = $1 
-                 ORDER BY created_at DESC LIMIT $2 OFFSET $3",
-                &[&wallet_id, &limit, &offset],
+                "SELECT id, recipient, subject, body, attempts 
+                 FROM email_outbox WHERE status = 'queued' ORDER BY created_at LIMIT $1",
+                &[&limit],
             )
             .await?;
 
         Ok(rows
             .into_iter()
-            .map(|row| TxModel {
+            .map(|row| OutboxEmail {
                 id: row.get(0),
-                transaction_hash: row.get(1),
-                sender_wallet_id: row.get(2),
-                receiver_wallet_id: row.get(3),
-                amount: row.get(4),
-                note: row.get(5),
-                signature: row.get(6),
-                block_index: row.get(7),
-                transaction_type: row.get(8),
-                timestamp: row.get(9),
-                created_at: row.get(10),
+                recipient: row.get(1),
+                subject: row.get(2),
+                body: row.get(3),
+                attempts: row.get(4),
             })
             .collect())
     }
 
-    // OTP queries
-    pub async fn create_otp(
+    pub async fn mark_email_sent(client: &Client, id: Uuid) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE email_outbox SET status = 'sent', attempts = attempts + 1, sent_at = NOW(), last_error = NULL WHERE id = $1",
+                &[&id],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Count a failed attempt, giving up on the email once it has had `max_attempts`
+    pub async fn record_email_failure(
         client: &Client,
-        email: &str,
-        otp: &str,
-        expires_at: DateTime<Utc>,
-    ) -> Result<EmailOtp, tokio_postgres::Error> {
-        let row = client
-            .query_one(
-                "INSERT INTO email_otps (email, otp, expires_at) 
-                 VALUES ($1, $2, $3) 
-                 RETURNING id, email, otp, is_verified, expires_at, created_at",
-                &[&email, &otp, &expires_at],
+        id: Uuid,
+        error: &str,
+        max_attempts: i32,
+    ) -> Result<(), tokio_postgres::Error> {
+        client
+            .execute(
+                "UPDATE email_outbox SET attempts = attempts + 1, last_error = $2, 
+                 status = CASE WHEN attempts + 1 >= $3 THEN 'failed' ELSE status END 
+                 WHERE id = $1",
+                &[&id, &error, &max_attempts],
             )
             .await?;
+        Ok(())
+    }
 
-        Ok(EmailOtp {
-            id: row.get(0),
-            email: row.get(1),
-            otp: row.get(2),
-            is_verified: row.get(3),
-            expires_at: row.get(4),
-            created_at: row.get(5),
-        })
+    // Idempotency key queries
+    /// Forget keys older than `ttl_seconds` so they can be reused
+    pub async fn delete_expired_idempotency_keys(client: &Client, ttl_seconds: i64) -> Result<u64, tokio_postgres::Error> {
+        client
+            .execute(
+                "DELETE FROM idempotency_keys WHERE created_at < NOW() - make_interval(secs => $1)",
+                &[&(ttl_seconds as f64)],
+            )
+            .await
     }
 
-    pub async fn verify_otp(
+    /// Reserve `key` for the sender; `false` if it is already taken
+    pub async fn claim_idempotency_key(
         client: &Client,
-        email: &str,
-        otp: &str,
+        sender_wallet_id: &str,
+        key: &str,
+        request_hash: &str,
     ) -> Result<bool, tokio_postgres::Error> {
-        let result = client
+        let inserted = client
+            .execute(
+                "INSERT INTO idempotency_keys (sender_wallet_id, idempotency_key, request_hash)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT DO NOTHING",
+                &[&sender_wallet_id, &key, &request_hash],
+            )
+            .await?;
+
+        Ok(inserted == 1)
+    }
+
+    /// Request hash and stored response (`None` while still processing) for a claimed key
+    pub async fn get_idempotency_key(
+        client: &Client,
+        sender_wallet_id: &str,
+        key: &str,
+    ) -> Result<Option<(String, Option<serde_json::Value>)>, tokio_postgres::Error> {
+        let row = client
             .query_opt(
-                "UPDATE email_otps 
-                 SET is_verified = TRUE 
-                 WHERE email = $1 AND otp = $2 AND is_verified = FALSE AND expires_at > NOW() 
-                 RETURNING id",
-                &[&email, &otp],
+                "SELECT request_hash, response FROM idempotency_keys WHERE sender_wallet_id = $1 AND idempotency_key = $2",
+                &[&sender_wallet_id, &key],
             )
             .await?;
 
-        Ok(result.is_some())
+        Ok(row.map(|row| (row.get(0), row.get(1))))
     }
 
-    pub async fn mark_user_verified(
+    pub async fn complete_idempotency_key(
         client: &Client,
-        email: &str,
-    ) -> Result<(), tokio_postgres::Error> {
+        sender_wallet_id: &str,
+        key: &str,
+        transaction_hash: &str,
+        response: &serde_json::Value,
+    ) -> Result<u64, tokio_postgres::Error> {
         client
             .execute(
-                "UPDATE users SET is_verified = TRUE WHERE email = $1",
-                &[&email],
+                "UPDATE idempotency_keys SET transaction_hash = $3, response = $4
+                 WHERE sender_wallet_id = $1 AND idempotency_key = $2",
+                &[&sender_wallet_id, &key, &transaction_hash, &response],
             )
-            .await?;
-        Ok(())
+            .await
     }
 
-    // System logs
-    pub async fn create_system_log(
+    pub async fn release_idempotency_key(
         client: &Client,
-        log_type: &str,
-        user_id: Option<Uuid>,
-        message: &str,
-        ip_address: Option<String>,
-        metadata: Option<serde_json::Value>,
-    ) -> Result<(), tokio_postgres::Error> {
+        sender_wallet_id: &str,
+        key: &str,
+    ) -> Result<u64, tokio_postgres::Error> {
         client
             .execute(
-                "INSERT INTO system_logs (log_type, user_id, message, ip_address, metadata) 
-                 VALUES ($1, $2, $3, $4, $5)",
-                &[&log_type, &user_id, &message, &ip_address, &metadata],
+                "DELETE FROM idempotency_keys WHERE sender_wallet_id = $1 AND idempotency_key = $2",
+                &[&sender_wallet_id, &key],
             )
-            .await?;
-        Ok(())
+            .await
     }
 
-    // Transaction logs
-    pub async fn create_transaction_log(
-        client: &Client,
-        wallet_id: &str,
-        action: &str,
-        transaction_hash: Option<String>,
-        block_hash: Option<String>,
-        status: &str,
-        ip_address: Option<String>,
-        user_agent: Option<String>,
-        note: Option<String>,
-    ) -> Result<(), tokio_postgres::Error> {
+    pub async fn create_payment_request(client: &Client, request: &PaymentRequest) -> Result<(), tokio_postgres::Error> {
         client
             .execute(
-                "INSERT INTO transaction_logs (wallet_id, action, transaction_hash, block_hash, status, ip_address, user_agent, note) 
-                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                "INSERT INTO payment_requests (id, wallet_id, amount, note, expires_at, created_at)
+                 VALUES ($1, $2, $3::int8 * 0.00000001, $4, $5, $6)",
                 &[
-                    &wallet_id,
-                    &action,
-                    &transaction_hash,
-                    &block_hash,
-                    &status,
-                    &ip_address,
-                    &user_agent,
-                    &note,
+                    &request.id,
+                    &request.wallet_id,
+                    &request.amount,
+                    &request.note,
+                    &request.expires_at,
+                    &request.created_at,
                 ],
             )
             .await?;
         Ok(())
     }
 
-    // Beneficiary queries
-    pub async fn get_user_beneficiaries(
-        client: &Client,
-        user_id: Uuid,
-    ) -> Result<Vec<crate::models::Beneficiary>, tokio_postgres::Error> {
-        let rows = client
-            .query(
-                "SELECT id, user_id, beneficiary_wallet_id, nickname, created_at 
-                 FROM beneficiaries WHERE user_id = $1 ORDER BY created_at DESC",
-                &[&user_id],
+    pub async fn get_payment_request(client: &Client, id: Uuid) -> Result<Option<PaymentRequest>, tokio_postgres::Error> {
+        let row = client
+            .query_opt(
+                "SELECT id, wallet_id, (amount * 100000000)::int8, note, expires_at, fulfilled_by, fulfilled_at, created_at
+                 FROM payment_requests WHERE id = $1",
+                &[&id],
             )
             .await?;
 
-        Ok(rows
-            .into_iter()
-            .map(|row| crate::models::Beneficiary {
-                id: row.get(0),
-                user_id: row.get(1),
-                beneficiary_wallet_id: row.get(2),
-                nickname: row.get(3),
-                created_at: row.get(4),
-            })
-            .collect())
+        Ok(row.map(|row| PaymentRequest {
+            id: row.get(0),
+            wallet_id: row.get(1),
+            amount: row.get(2),
+            note: row.get(3),
+            expires_at: row.get(4),
+            fulfilled_by: row.get(5),
+            fulfilled_at: row.get(6),
+            created_at: row.get(7),
+        }))
     }
 
-    pub async fn add_beneficiary(
+    /// Mark an unpaid, unexpired request as paid by `transaction_hash`. Returns false when
+    /// another transfer got there first or the request has expired.
+    pub async fn fulfill_payment_request(
         client: &Client,
-        user_id: Uuid,
-        beneficiary_wallet_id: &str,
-        nickname: Option<String>,
-    ) -> Result<crate::models::Beneficiary, tokio_postgres::Error> {
-        let row = client
-            .query_one(
-                "INSERT INTO beneficiaries (user_id, beneficiary_wallet_id, nickname) 
-                 VALUES ($1, $2, $3) 
-                 RETURNING id, user_id, beneficiary_wallet_id, nickname, created_at",
-                &[&user_id, &beneficiary_wallet_id, &nickname],
+        id: Uuid,
+        transaction_hash: &str,
+    ) -> Result<bool, tokio_postgres::Error> {
+        let updated = client
+            .execute(
+                "UPDATE payment_requests SET fulfilled_by = $2, fulfilled_at = NOW()
+                 WHERE id = $1 AND fulfilled_by IS NULL AND expires_at > NOW()",
+                &[&id, &transaction_hash],
             )
             .await?;
-
-        Ok(crate::models::Beneficiary {
-            id: row.get(0),
-            user_id: row.get(1),
-            beneficiary_wallet_id: row.get(2),
-            nickname: row.get(3),
-            created_at: row.get(4),
-        })
+        Ok(updated == 1)
     }
 
-    pub async fn delete_beneficiary(
+    pub async fn release_payment_request(
         client: &Client,
-        beneficiary_id: Uuid,
-        user_id: Uuid,
+        id: Uuid,
+        transaction_hash: &str,
     ) -> Result<u64, tokio_postgres::Error> {
-        let result = client
+        client
             .execute(
-                "DELETE FROM beneficiaries WHERE id = $1 AND user_id = $2",
-                &[&beneficiary_id, &user_id],
+                "UPDATE payment_requests SET fulfilled_by = NULL, fulfilled_at = NULL
+                 WHERE id = $1 AND fulfilled_by = $2",
+                &[&id, &transaction_hash],
             )
-            .await?;
-        Ok(result)
+            .await
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::queries::UpdateSet;
+    use super::*;
+
+    /// Fails with a transient error the first `failures` times it is called
+    struct Flaky {
+        failures: u32,
+        calls: std::cell::Cell<u32>,
+    }
+
+    #[derive(Debug)]
+    struct MockError {
+        transient: bool,
+    }
+
+    impl std::fmt::Display for MockError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "mock error (transient: {})", self.transient)
+        }
+    }
+
+    impl Transient for MockError {
+        fn is_transient(&self) -> bool {
+            self.transient
+        }
+    }
+
+    impl Flaky {
+        async fn read(&self, transient: bool) -> Result<u32, MockError> {
+            let call = self.calls.get() + 1;
+            self.calls.set(call);
+            if call <= self.failures {
+                Err(MockError { transient })
+            } else {
+                Ok(call)
+            }
+        }
+    }
+
+    const FAST: RetryPolicy = RetryPolicy { retries: 2, base_delay: Duration::from_millis(1) };
+
+    #[tokio::test]
+    async fn test_retry_recovers_from_transient_failure() {
+        let flaky = Flaky { failures: 1, calls: Default::default() };
+        let result = with_retry_policy(FAST, || flaky.read(true)).await;
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(flaky.calls.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up_and_skips_permanent_errors() {
+        // Still failing after the configured retries
+        let flaky = Flaky { failures: 5, calls: Default::default() };
+        assert!(with_retry_policy(FAST, || flaky.read(true)).await.is_err());
+        assert_eq!(flaky.calls.get(), 3);
+
+        // Not a connection problem: no retry
+        let flaky = Flaky { failures: 1, calls: Default::default() };
+        assert!(with_retry_policy(FAST, || flaky.read(false)).await.is_err());
+        assert_eq!(flaky.calls.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_pool_uses_configured_size() {
+        let settings = DbPoolConfig { max_size: 3, connect_timeout_secs: 2, recycle_timeout_secs: 1 };
+        let pool = build_pool("postgres://postgres@localhost/unused", &settings).unwrap();
+
+        assert_eq!(pool.status().max_size, 3);
+        let timeouts = pool.timeouts();
+        assert_eq!(timeouts.create, Some(Duration::from_secs(2)));
+        assert_eq!(timeouts.recycle, Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_update_set_placeholders_follow_values() {
+        let mut set = UpdateSet::default();
+        set.push("full_name", "Jane".to_string());
+        set.push("is_verified", false);
+
+        assert_eq!(
+            set.to_sql("users", "id", "id"),
+            "UPDATE users SET full_name = $1, is_verified = $2 WHERE id = $3 RETURNING id"
+        );
+        assert_eq!(set.params(&7i32).len(), 3);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_utxo_pages_cover_every_output_once() {
+        let Some(pool) = create_test_pool("utxo_pages").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let client = pool.get().await.unwrap();
+        queries::ensure_wallet(&client, "dusty").await.unwrap();
+        // Identical timestamps, so only the id tie-break keeps pages stable
+        client
+            .execute(
+                "INSERT INTO utxos (wallet_id, amount, transaction_hash, output_index, created_at)
+                 SELECT 'dusty', 0.0001, 'dust', i, '2025-01-01T00:00:00Z' FROM generate_series(0, 22) i",
+                &[],
+            )
+            .await
+            .unwrap();
+        client
+            .execute("UPDATE utxos SET is_spent = true WHERE wallet_id = 'dusty' AND output_index = 22", &[])
+            .await
+            .unwrap();
+
+        let mut seen = std::collections::HashSet::new();
+        for offset in (0..30).step_by(10) {
+            let (page, total) = queries::get_unspent_utxos_page(&client, "dusty", 10, offset).await.unwrap();
+            assert_eq!(total, 22);
+            assert_eq!(page.len(), (22 - offset as usize).min(10));
+            for utxo in page {
+                assert!(!utxo.is_spent);
+                assert!(seen.insert(utxo.id), "{} returned twice", utxo.id);
+            }
+        }
+        assert_eq!(seen.len(), 22);
+        assert_eq!(queries::get_unspent_utxos(&client, "dusty").await.unwrap().len(), 22);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_dropped_advisory_lock_is_released() {
+        let Some(pool) = create_test_pool("advisory_lock").await else { return };
+        const KEY: i64 = 0x7465_7374;
+
+        // A separate session, never the one holding the lock
+        let observer = pool.get().await.unwrap();
+        let observer_locks = || async {
+            let locked: bool = observer.query_one("SELECT pg_try_advisory_lock($1)", &[&KEY]).await.unwrap().get(0);
+            if locked {
+                observer.execute("SELECT pg_advisory_unlock($1)", &[&KEY]).await.unwrap();
+            }
+            locked
+        };
+
+        observer.execute("SELECT pg_advisory_lock($1)", &[&KEY]).await.unwrap();
+        assert!(AdvisoryLock::try_acquire(&pool, KEY).await.unwrap().is_none());
+        observer.execute("SELECT pg_advisory_unlock($1)", &[&KEY]).await.unwrap();
+
+        let lock = AdvisoryLock::try_acquire(&pool, KEY).await.unwrap().unwrap();
+        assert!(!observer_locks().await);
+
+        // A holder cancelled mid-run must not leave the lock with a pooled session
+        drop(lock);
+        let mut released = false;
+        for _ in 0..50 {
+            if observer_locks().await {
+                released = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(released, "lock still held after its guard was dropped");
+
+        let lock = AdvisoryLock::try_acquire(&pool, KEY).await.unwrap().unwrap();
+        lock.unlock().await.unwrap();
+        assert!(observer_locks().await);
+    }
+}
@@ -0,0 +1,261 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use crate::services::auth_service::AuthError;
+use crate::services::chain_export_service::ChainImportError;
+use crate::services::otp_service::OtpError;
+use crate::services::payment_request_service::PaymentRequestError;
+use crate::services::transaction_service::TransactionError;
+use crate::services::wallet_service::WalletError;
+use serde::Serialize;
+
+/// Error returned by handlers. Each variant maps to one HTTP status and renders as
+/// `{ "success": false, "code": "...", "message": "..." }`.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    UnprocessableEntity(String),
+    PayloadTooLarge(String),
+    TooManyRequests { message: String, retry_after: u64 },
+    ServiceUnavailable(String),
+    /// Logged in full; clients only see a generic message
+    Internal(String),
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub success: bool,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::UnprocessableEntity(_) => "unprocessable_entity",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::TooManyRequests { .. } => "too_many_requests",
+            ApiError::ServiceUnavailable(_) => "service_unavailable",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// Message shown to the client
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::Internal(_) => "Internal server error".to_string(),
+            ApiError::TooManyRequests { message, .. } => message.clone(),
+            ApiError::BadRequest(msg)
+            | ApiError::Unauthorized(msg)
+            | ApiError::Forbidden(msg)
+            | ApiError::NotFound(msg)
+            | ApiError::Conflict(msg)
+            | ApiError::UnprocessableEntity(msg)
+            | ApiError::PayloadTooLarge(msg)
+            | ApiError::ServiceUnavailable(msg) => msg.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ApiError::Internal(msg) => write!(f, "Internal error: {}", msg),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let ApiError::Internal(detail) = self {
+            log::error!("{}", detail);
+        }
+
+        let mut response = HttpResponse::build(self.status_code());
+        if let ApiError::TooManyRequests { retry_after, .. } = self {
+            response.insert_header(("Retry-After", retry_after.to_string()));
+        }
+
+        response.json(ErrorBody {
+            success: false,
+            code: self.code(),
+            message: self.message(),
+        })
+    }
+}
+
+impl From<deadpool_postgres::PoolError> for ApiError {
+    fn from(e: deadpool_postgres::PoolError) -> Self {
+        ApiError::Internal(format!("Database pool error: {}", e))
+    }
+}
+
+impl From<tokio_postgres::Error> for ApiError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        ApiError::Internal(format!("Database error: {}", e))
+    }
+}
+
+impl From<crate::database::DbError> for ApiError {
+    fn from(e: crate::database::DbError) -> Self {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl From<WalletError> for ApiError {
+    fn from(e: WalletError) -> Self {
+        match e {
+            WalletError::WalletNotFound => ApiError::NotFound(e.to_string()),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<PaymentRequestError> for ApiError {
+    fn from(e: PaymentRequestError) -> Self {
+        match e {
+            PaymentRequestError::WalletNotFound => ApiError::NotFound(e.to_string()),
+            PaymentRequestError::DatabaseError(_) => ApiError::Internal(e.to_string()),
+            _ => ApiError::BadRequest(e.to_string()),
+        }
+    }
+}
+
+impl From<TransactionError> for ApiError {
+    fn from(e: TransactionError) -> Self {
+        match e {
+            TransactionError::IdempotencyConflict => ApiError::Conflict(e.to_string()),
+            TransactionError::IdempotencyKeyReused => ApiError::UnprocessableEntity(e.to_string()),
+            TransactionError::NotFound => ApiError::NotFound(e.to_string()),
+            TransactionError::NotReceiver | TransactionError::WalletFrozen(_) => ApiError::Forbidden(e.to_string()),
+            TransactionError::DatabaseError(_) | TransactionError::CryptoError(_) => {
+                ApiError::Internal(e.to_string())
+            }
+            _ => ApiError::BadRequest(e.to_string()),
+        }
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::UserAlreadyExists | AuthError::CnicAlreadyExists | AuthError::EmailInUse => {
+                ApiError::Conflict(e.to_string())
+            }
+            AuthError::UserNotFound => ApiError::NotFound(e.to_string()),
+            AuthError::AccountNotEmpty(_) => ApiError::Conflict(e.to_string()),
+            AuthError::NoFieldsToUpdate | AuthError::WeakPassword(_) => ApiError::BadRequest(e.to_string()),
+            AuthError::InvalidCredentials | AuthError::TokenError(_) => ApiError::Unauthorized(e.to_string()),
+            AuthError::DatabaseError(_) | AuthError::WalletError(_) => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<ChainImportError> for ApiError {
+    fn from(e: ChainImportError) -> Self {
+        match e {
+            ChainImportError::ChainNotEmpty => ApiError::Conflict(e.to_string()),
+            ChainImportError::DatabaseError(_) => ApiError::Internal(e.to_string()),
+            _ => ApiError::BadRequest(e.to_string()),
+        }
+    }
+}
+
+impl From<OtpError> for ApiError {
+    fn from(e: OtpError) -> Self {
+        match e {
+            OtpError::InvalidOtp | OtpError::ExpiredOtp => ApiError::BadRequest(e.to_string()),
+            OtpError::ResendTooSoon(secs) => ApiError::TooManyRequests { message: e.to_string(), retry_after: secs },
+            OtpError::DatabaseError(_) | OtpError::SendError(_) => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    async fn render(error: ApiError) -> (StatusCode, serde_json::Value) {
+        let response = error.error_response();
+        let status = response.status();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_variants_map_to_status_and_body() {
+        let cases = [
+            (ApiError::BadRequest("bad".into()), StatusCode::BAD_REQUEST, "bad_request", "bad"),
+            (ApiError::Unauthorized("who".into()), StatusCode::UNAUTHORIZED, "unauthorized", "who"),
+            (ApiError::Forbidden("no".into()), StatusCode::FORBIDDEN, "forbidden", "no"),
+            (ApiError::NotFound("gone".into()), StatusCode::NOT_FOUND, "not_found", "gone"),
+            (ApiError::Conflict("dup".into()), StatusCode::CONFLICT, "conflict", "dup"),
+            (ApiError::UnprocessableEntity("odd".into()), StatusCode::UNPROCESSABLE_ENTITY, "unprocessable_entity", "odd"),
+            (ApiError::ServiceUnavailable("busy".into()), StatusCode::SERVICE_UNAVAILABLE, "service_unavailable", "busy"),
+        ];
+
+        for (error, status, code, message) in cases {
+            let (actual_status, body) = render(error).await;
+            assert_eq!(actual_status, status);
+            assert_eq!(body, serde_json::json!({ "success": false, "code": code, "message": message }));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_internal_error_hides_detail() {
+        let (status, body) = render(ApiError::Internal("connection refused on 10.0.0.5".into())).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "internal_error");
+        assert_eq!(body["message"], "Internal server error");
+    }
+
+    #[actix_web::test]
+    async fn test_too_many_requests_sets_retry_after() {
+        let response = ApiError::TooManyRequests { message: "slow down".into(), retry_after: 30 }.error_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "30");
+    }
+
+    #[test]
+    fn test_service_errors_convert() {
+        assert_eq!(ApiError::from(WalletError::WalletNotFound).status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(ApiError::from(AuthError::CnicAlreadyExists).status_code(), StatusCode::CONFLICT);
+        assert_eq!(ApiError::from(AuthError::InvalidCredentials).status_code(), StatusCode::UNAUTHORIZED);
+        assert_eq!(ApiError::from(TransactionError::InsufficientBalance).status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(ApiError::from(TransactionError::IdempotencyKeyReused).status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            ApiError::from(TransactionError::DatabaseError("x".into())).status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
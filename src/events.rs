@@ -0,0 +1,128 @@
+use crate::models::Block;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Shared channel used to fan out chain events to WebSocket subscribers
+pub type EventBus = broadcast::Sender<BlockEvent>;
+
+const EVENT_BUS_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfirmedTransaction {
+    pub transaction_hash: String,
+    pub sender_wallet_id: String,
+    pub receiver_wallet_id: String,
+    pub amount: f64,
+}
+
+/// Summary of a newly mined block and the transactions it confirmed
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockEvent {
+    pub event: String,
+    pub block_index: i64,
+    pub block_hash: String,
+    pub timestamp: i64,
+    pub transactions: Vec<ConfirmedTransaction>,
+}
+
+impl BlockEvent {
+    pub fn block_mined(block: &Block) -> Self {
+        BlockEvent {
+            event: "block_mined".to_string(),
+            block_index: block.index,
+            block_hash: block.hash.clone(),
+            timestamp: block.timestamp,
+            transactions: block
+                .transactions
+                .iter()
+                .map(|tx| ConfirmedTransaction {
+                    transaction_hash: tx.transaction_hash.clone(),
+                    sender_wallet_id: tx.sender_wallet_id.clone(),
+                    receiver_wallet_id: tx.receiver_wallet_id.clone(),
+                    amount: crate::utils::from_units(tx.amount),
+                })
+                .collect(),
+        }
+    }
+
+    /// Narrow the event to the transactions involving `wallet_id`, or `None` if there are none
+    pub fn for_wallet(&self, wallet_id: &str) -> Option<BlockEvent> {
+        let transactions: Vec<ConfirmedTransaction> = self
+            .transactions
+            .iter()
+            .filter(|tx| tx.sender_wallet_id == wallet_id || tx.receiver_wallet_id == wallet_id)
+            .cloned()
+            .collect();
+
+        if transactions.is_empty() {
+            return None;
+        }
+
+        Some(BlockEvent {
+            transactions,
+            ..self.clone()
+        })
+    }
+}
+
+pub fn create_event_bus() -> EventBus {
+    let (tx, _rx) = broadcast::channel(EVENT_BUS_CAPACITY);
+    tx
+}
+
+/// Publish an event; having no subscribers connected is not an error
+pub fn publish(bus: &EventBus, event: BlockEvent) {
+    let _ = bus.send(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_event() -> BlockEvent {
+        BlockEvent {
+            event: "block_mined".to_string(),
+            block_index: 7,
+            block_hash: "00abc".to_string(),
+            timestamp: 1234567890,
+            transactions: vec![
+                ConfirmedTransaction {
+                    transaction_hash: "tx1".to_string(),
+                    sender_wallet_id: "alice".to_string(),
+                    receiver_wallet_id: "bob".to_string(),
+                    amount: 1.0,
+                },
+                ConfirmedTransaction {
+                    transaction_hash: "tx2".to_string(),
+                    sender_wallet_id: "carol".to_string(),
+                    receiver_wallet_id: "dave".to_string(),
+                    amount: 2.0,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_for_wallet_filters_transactions() {
+        let event = sample_event();
+
+        let bob = event.for_wallet("bob").unwrap();
+        assert_eq!(bob.transactions.len(), 1);
+        assert_eq!(bob.transactions[0].transaction_hash, "tx1");
+        assert_eq!(bob.block_index, 7);
+
+        assert!(event.for_wallet("nobody").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_receives_published_event() {
+        let bus = create_event_bus();
+        let mut rx = bus.subscribe();
+
+        publish(&bus, sample_event());
+
+        let received = rx.recv().await.unwrap();
+        assert_eq!(received.block_hash, "00abc");
+        assert_eq!(received.transactions.len(), 2);
+    }
+}
@@ -0,0 +1,149 @@
+use actix_web::{web, HttpResponse};
+use crate::models::{ApiResponse, OffsetPage, UpdateWalletStatusRequest, ZakatRateRequest};
+use crate::database::DbPool;
+use crate::errors::ApiError;
+use crate::middleware::RequireAdmin;
+
+const MAX_PAGE_SIZE: i64 = 200;
+
+pub async fn list_wallets(
+    pool: web::Data<DbPool>,
+    _admin: RequireAdmin,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(50i64).clamp(1, MAX_PAGE_SIZE);
+    let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0i64).max(0);
+
+    let client = pool.get().await?;
+    let (items, total) = crate::database::queries::list_wallets(&client, limit, offset).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(OffsetPage { items, total, limit, offset }),
+        message: None,
+    }))
+}
+
+/// Freeze or unfreeze a wallet. Pending transactions touching a frozen wallet stay pending.
+pub async fn set_wallet_status(
+    pool: web::Data<DbPool>,
+    admin: RequireAdmin,
+    path: web::Path<String>,
+    body: web::Json<UpdateWalletStatusRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = path.into_inner();
+    let status = body.into_inner().status;
+
+    let client = pool.get().await?;
+    let wallet = crate::database::queries::set_wallet_status(&client, &wallet_id, status)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Wallet not found".to_string()))?;
+
+    log::info!("Wallet {} set to {} by {}", wallet_id, status.as_str(), admin.0.email);
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(wallet),
+        message: Some(format!("Wallet is now {}", status.as_str())),
+    }))
+}
+
+/// Charge a wallet its own zakat percentage, or clear the override with `null`
+pub async fn set_zakat_rate(
+    pool: web::Data<DbPool>,
+    admin: RequireAdmin,
+    path: web::Path<String>,
+    body: web::Json<ZakatRateRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = path.into_inner();
+    let rate = body.into_inner().zakat_rate;
+    if let Some(rate) = rate {
+        if !(0.0..=100.0).contains(&rate) {
+            return Err(ApiError::BadRequest("zakat_rate must be between 0 and 100".to_string()));
+        }
+    }
+
+    let client = pool.get().await?;
+    let wallet = crate::database::queries::set_zakat_rate(&client, &wallet_id, rate)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Wallet not found".to_string()))?;
+
+    log::info!("Wallet {} zakat rate set to {:?} by {}", wallet_id, rate, admin.0.email);
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(wallet),
+        message: Some(match rate {
+            Some(rate) => format!("Wallet is charged {}% zakat", rate),
+            None => "Wallet is charged the default zakat rate".to_string(),
+        }),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App};
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_list_wallets_paginates_for_admins() {
+        let pool = match crate::database::create_test_pool("admin_wallets").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let owner = crate::database::register_test_user(&pool, "owner@example.com", "1").await;
+
+        let client = pool.get().await.unwrap();
+        client
+            .batch_execute(&format!(
+                "UPDATE wallets SET created_at = '2025-01-04T00:00:00Z' WHERE wallet_id = '{}';
+                 INSERT INTO wallets (wallet_id, created_at) VALUES
+                     ('w1', '2025-01-01T00:00:00Z'), ('w2', '2025-01-02T00:00:00Z'), ('w3', '2025-01-03T00:00:00Z');
+                 INSERT INTO utxos (wallet_id, amount, transaction_hash, output_index, is_spent) VALUES
+                     ('w3', 2.5, 'a', 0, false), ('w3', 1, 'b', 0, false), ('w3', 9, 'c', 0, true);",
+                owner.wallet_id
+            ))
+            .await
+            .unwrap();
+        drop(client);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .route("/admin/wallets", web::get().to(list_wallets)),
+        )
+        .await;
+        let admin = crate::services::auth_service::generate_token("1", "admin@example.com", "admin").unwrap();
+        let list = |uri: &str, token: &str| test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, list("/admin/wallets?limit=2", &admin)).await;
+        let page = &body["data"];
+        assert_eq!(page["total"], 4);
+        assert_eq!(page["items"][0]["wallet_id"], owner.wallet_id.as_str());
+        assert_eq!(page["items"][0]["email"], "owner@example.com");
+        assert_eq!(page["items"][1]["wallet_id"], "w3");
+        assert_eq!(page["items"][1]["balance"], 3.5);
+        assert!(page["items"][1]["email"].is_null());
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, list("/admin/wallets?limit=2&offset=2", &admin)).await;
+        let ids: Vec<&str> = body["data"]["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|w| w["wallet_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["w2", "w1"]);
+
+        let user = crate::services::auth_service::generate_token("2", "user@example.com", "user").unwrap();
+        let resp = test::call_service(&app, list("/admin/wallets", &user)).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -1,388 +1,271 @@
 use actix_web::{web, HttpResponse, HttpRequest};
 use crate::models::{RegisterRequest, LoginRequest, VerifyOtpRequest, SendOtpRequest, ApiResponse};
 use crate::database::DbPool;
+use crate::errors::ApiError;
+use crate::handlers::authenticate;
 use crate::services::{auth_service, otp_service};
+use crate::services::login_attempt_service::LoginAttemptTracker;
 use crate::config::Config;
+use crate::metrics::Metrics;
+use std::time::{Duration, Instant};
 
 pub async fn register(
     pool: web::Data<DbPool>,
     req: web::Json<RegisterRequest>,
-) -> HttpResponse {
-    let config = match Config::from_env() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Config error: {}", e)),
-            });
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let config = Config::from_env().map_err(|e| ApiError::Internal(format!("Config error: {}", e)))?;
+    auth_service::validate_password(&req.password, &config.password_policy)?;
 
-    match auth_service::register_user(&pool, req.into_inner(), &config.aes_key).await {
-        Ok(user) => {
-            match auth_service::generate_token(&user.id.to_string(), &user.email) {
-                Ok(token) => HttpResponse::Ok().json(serde_json::json!({
-                    "success": true,
-                    "data": {
-                        "user": user,
-                        "token": token
-                    },
-                    "message": "User registered successfully"
-                })),
-                Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                    success: false,
-                    data: None,
-                    message: Some(format!("Token generation failed: {}", e)),
-                }),
-            }
-        }
-        Err(e) => HttpResponse::BadRequest().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(e.to_string()),
-        }),
+    let user = auth_service::register_user(&pool, req.into_inner(), &config.aes_key).await?;
+    let token = auth_service::generate_token(&user.id.to_string(), &user.email, &user.role)
+        .map_err(|e| ApiError::Internal(format!("Token generation failed: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "data": {
+            "user": user,
+            "token": token
+        },
+        "message": "User registered successfully"
+    })))
+}
+
+fn too_many_attempts(retry_after: Duration) -> ApiError {
+    // Round up so clients never retry a moment too early
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+    ApiError::TooManyRequests {
+        message: format!("Too many failed login attempts, retry in {} seconds", seconds),
+        retry_after: seconds,
     }
 }
 
 pub async fn login(
     pool: web::Data<DbPool>,
+    metrics: web::Data<Metrics>,
+    attempts: web::Data<LoginAttemptTracker>,
+    http_req: HttpRequest,
     req: web::Json<LoginRequest>,
-) -> HttpResponse {
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
+) -> Result<HttpResponse, ApiError> {
+    let now = Instant::now();
+    let email_key = format!("email:{}", req.email.to_lowercase());
+    // Forwarded headers are client-supplied, so only a trusted proxy may pick the lockout key
+    let ip = if attempts.trusts_proxy_headers() {
+        http_req.connection_info().realip_remote_addr().map(|s| s.to_string())
+    } else {
+        http_req.peer_addr().map(|addr| addr.ip().to_string())
     };
+    let ip_key = ip.as_ref().map(|ip| format!("ip:{}", ip));
 
-    match crate::database::queries::find_user_by_email(&client, &req.email).await {
-        Ok(Some(user)) => {
+    let locked = std::iter::once(&email_key)
+        .chain(ip_key.as_ref())
+        .filter_map(|key| attempts.retry_after(key, now))
+        .max();
+    if let Some(retry_after) = locked {
+        return Err(too_many_attempts(retry_after));
+    }
+
+    let client = pool.get().await?;
+
+    match crate::database::queries::find_user_by_email(&client, &req.email).await? {
+        Some(user) => {
+            metrics.login_attempts.with_label_values(&["success"]).inc();
+            // Only the account's counter is reset; the IP keeps counting within its window
+            attempts.record_success(&email_key);
             // Note: In production, you should verify password hash here
-            match auth_service::generate_token(&user.id.to_string(), &user.email) {
-                Ok(token) => HttpResponse::Ok().json(serde_json::json!({
-                    "success": true,
-                    "data": {
-                        "user": user,
-                        "token": token
-                    },
-                    "message": "Login successful"
-                })),
-                Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                    success: false,
-                    data: None,
-                    message: Some(format!("Token generation failed: {}", e)),
-                }),
+            let token = auth_service::generate_token(&user.id.to_string(), &user.email, &user.role)
+                .map_err(|e| ApiError::Internal(format!("Token generation failed: {}", e)))?;
+
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "data": {
+                    "user": user,
+                    "token": token
+                },
+                "message": "Login successful"
+            })))
+        }
+        None => {
+            metrics.login_attempts.with_label_values(&["failure"]).inc();
+
+            let lockout = std::iter::once(&email_key)
+                .chain(ip_key.as_ref())
+                .filter_map(|key| attempts.record_failure(key, now))
+                .max();
+
+            if let Some(lockout) = lockout {
+                if let Err(e) = crate::database::queries::create_system_log(
+                    &client,
+                    "login_lockout",
+                    None,
+                    &format!("Login locked out for {} after repeated failures", req.email),
+                    ip.clone(),
+                    Some(serde_json::json!({
+                        "email": req.email,
+                        "lockout_seconds": lockout.as_secs(),
+                    })),
+                )
+                .await
+                {
+                    log::error!("Failed to record login lockout: {}", e);
+                }
+
+                return Err(too_many_attempts(lockout));
             }
+
+            Err(ApiError::Unauthorized("Invalid credentials".to_string()))
         }
-        Ok(None) => HttpResponse::Unauthorized().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some("Invalid credentials".to_string()),
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
     }
 }
 
 pub async fn get_profile(
     pool: web::Data<DbPool>,
     req: HttpRequest,
-) -> HttpResponse {
-    // Extract JWT from Authorization header
-    let token = match extract_token(&req) {
-        Some(t) => t,
-        None => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("No token provided".to_string()),
-            });
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let user_id = authenticate(&req)?;
+    let client = pool.get().await?;
 
-    let claims = match auth_service::verify_token(&token) {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Invalid token: {}", e)),
-            });
-        }
-    };
+    let user = crate::database::queries::find_user_by_id(&client, user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
 
-    let user_id = match uuid::Uuid::parse_str(&claims.sub) {
-        Ok(id) => id,
-        Err(e) => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Invalid user ID: {}", e)),
-            });
-        }
-    };
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(user),
+        message: Some("Profile retrieved successfully".to_string()),
+    }))
+}
 
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+pub async fn send_otp(
+    pool: web::Data<DbPool>,
+    metrics: web::Data<Metrics>,
+    req: web::Json<SendOtpRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let otp = otp_service::send_otp(&pool, &req.email, &metrics).await?;
 
-    match crate::database::queries::find_user_by_id(&client, user_id).await {
-        Ok(Some(user)) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(user),
-            message: Some("Profile retrieved successfully".to_string()),
-        }),
-        Ok(None) => HttpResponse::NotFound().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some("User not found".to_string()),
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
-    }
+    // In production, don't send OTP in response
+    // This is only for testing/development
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({
+            "message": "OTP sent successfully",
+            "otp": otp // Remove in production!
+        })),
+        message: Some("Check your email for verification code".to_string()),
+    }))
 }
 
-pub async fn send_otp(
+/// Same as `send_otp`, for clients that ask for a code again
+pub async fn resend_otp(
     pool: web::Data<DbPool>,
+    metrics: web::Data<Metrics>,
     req: web::Json<SendOtpRequest>,
-) -> HttpResponse {
-    match otp_service::send_otp(&pool, &req.email).await {
-        Ok(otp) => {
-            // In production, don't send OTP in response
-            // This is only for testing/development
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(serde_json::json!({
-                    "message": "OTP sent successfully",
-                    "otp": otp // Remove in production!
-                })),
-                message: Some("Check your email for verification code".to_string()),
-            })
-        }
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(e.to_string()),
-        }),
-    }
+) -> Result<HttpResponse, ApiError> {
+    let otp = otp_service::resend_otp(&pool, &req.email, &metrics).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({
+            "message": "OTP resent successfully",
+            "otp": otp // Remove in production!
+        })),
+        message: Some("Previous codes are no longer valid".to_string()),
+    }))
 }
 
 pub async fn verify_otp(
     pool: web::Data<DbPool>,
     req: web::Json<VerifyOtpRequest>,
-) -> HttpResponse {
-    match otp_service::verify_otp(&pool, &req.email, &req.otp).await {
-        Ok(_) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(serde_json::json!({"verified": true})),
-            message: Some("Email verified successfully".to_string()),
-        }),
-        Err(e) => HttpResponse::BadRequest().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(e.to_string()),
-        }),
-    }
+) -> Result<HttpResponse, ApiError> {
+    otp_service::verify_otp(&pool, &req.email, &req.otp).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({"verified": true})),
+        message: Some("Email verified successfully".to_string()),
+    }))
 }
 
-pub async fn update_profile(
+/// Delete the caller's account, keeping its wallet and history but not who owned it
+pub async fn delete_account(
     pool: web::Data<DbPool>,
     req: HttpRequest,
-    body: web::Json<crate::models::UpdateProfileRequest>,
-) -> HttpResponse {
-    // Extract JWT token
-    let token = match extract_token(&req) {
-        Some(t) => t,
-        None => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("No authorization token provided".to_string()),
-            });
-        }
-    };
-
-    // Verify token
-    let claims = match crate::services::auth_service::verify_token(&token) {
-        Ok(c) => c,
-        Err(_) => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Invalid or expired token".to_string()),
-            });
-        }
-    };
-
-    let user_id = match uuid::Uuid::parse_str(&claims.sub) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Invalid user ID in token".to_string()),
-            });
-        }
-    };
-
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
-
-    // Get current user
-    let current_user = match crate::database::queries::find_user_by_id(&client, user_id).await {
-        Ok(Some(u)) => u,
-        Ok(None) => {
-            return HttpResponse::NotFound().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("User not found".to_string()),
-            });
-        }
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let user_id = authenticate(&req)?;
+    auth_service::delete_account(&pool, user_id).await?;
 
-    let mut updates = Vec::new();
-    let params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = vec![];
-    let mut param_count = 1;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({"deleted": true})),
+        message: Some("Account deleted".to_string()),
+    }))
+}
 
-    // Build dynamic UPDATE query
-    if let Some(ref full_name) = body.full_name {
-        updates.push(format!("full_name = ${}", param_count));
-        param_count += 1;
-    }
+pub async fn update_profile(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    body: web::Json<crate::models::UpdateProfileRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = authenticate(&req)?;
+    let updated_user = auth_service::update_profile(&pool, user_id, &body).await?;
 
-    if let Some(ref email) = body.email {
-        if email != &current_user.email {
-            // Check if email already exists
-            let email_exists = client
-                .query_opt("SELECT id FROM users WHERE email = $1 AND id != $2", &[email, &user_id])
-                .await;
-
-            if let Ok(Some(_)) = email_exists {
-                return HttpResponse::BadRequest().json(ApiResponse::<()> {
-                    success: false,
-                    data: None,
-                    message: Some("Email already in use".to_string()),
-                });
-            }
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({
+            "id": updated_user.id,
+            "email": updated_user.email,
+            "full_name": updated_user.full_name,
+            "cnic": updated_user.cnic,
+            "wallet_id": updated_user.wallet_id,
+            "public_key": updated_user.public_key,
+            "is_verified": updated_user.is_verified,
+        })),
+        message: Some("Profile updated successfully".to_string()),
+    }))
+}
 
-            updates.push(format!("email = ${}", param_count));
-            updates.push(format!("is_verified = ${}", param_count + 1));
-            param_count += 2;
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
 
-    if updates.is_empty() {
-        return HttpResponse::BadRequest().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some("No fields to update".to_string()),
-        });
-    }
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_ip_lockout_ignores_forwarded_headers_unless_trusted() {
+        let Some(pool) = crate::database::create_test_pool("login_ip_key").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
 
-    updates.push(format!("updated_at = ${}", param_count));
+        let app_with = |attempts: LoginAttemptTracker| {
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(Metrics::new().unwrap()))
+                .app_data(web::Data::new(attempts))
+                .route("/login", web::post().to(login))
+        };
+        let tracker = || LoginAttemptTracker::new(2, Duration::from_secs(60), Duration::from_secs(300));
+        let attempt = |email: &str, forwarded_for: &str| {
+            test::TestRequest::post()
+                .uri("/login")
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .insert_header(("X-Forwarded-For", forwarded_for.to_string()))
+                .set_json(serde_json::json!({ "email": email, "password": "wrong" }))
+                .to_request()
+        };
 
-    let query = format!(
-        "UPDATE users SET {} WHERE id = ${} RETURNING id, email, full_name, cnic, wallet_id, public_key, encrypted_private_key, is_verified, created_at, updated_at",
-        updates.join(", "),
-        param_count + 1
-    );
+        // Rotating X-Forwarded-For doesn't reset the peer's counter
+        let app = test::init_service(app_with(tracker())).await;
+        let resp = test::call_service(&app, attempt("a@example.com", "1.1.1.1")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        let resp = test::call_service(&app, attempt("b@example.com", "2.2.2.2")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::TOO_MANY_REQUESTS);
+        let resp = test::call_service(&app, attempt("c@example.com", "3.3.3.3")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::TOO_MANY_REQUESTS);
 
-    // Build params vector
-    let mut query_params: Vec<Box<dyn tokio_postgres::types::ToSql + Sync + Send>> = vec![];
-    
-    if let Some(ref full_name) = body.full_name {
-        query_params.push(Box::new(full_name.clone()));
-    }
-    
-    if let Some(ref email) = body.email {
-        if email != &current_user.email {
-            query_params.push(Box::new(email.clone()));
-            query_params.push(Box::new(false)); // Reset is_verified
+        // Behind a trusted proxy each forwarded client is counted separately
+        let app = test::init_service(app_with(tracker().with_trusted_proxy(true))).await;
+        for (email, forwarded_for) in [("a@example.com", "1.1.1.1"), ("b@example.com", "2.2.2.2"), ("c@example.com", "3.3.3.3")] {
+            let resp = test::call_service(&app, attempt(email, forwarded_for)).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
         }
     }
-    
-    query_params.push(Box::new(chrono::Utc::now()));
-    query_params.push(Box::new(user_id));
-
-    let params_refs: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = query_params
-        .iter()
-        .map(|p| p.as_ref() as &(dyn tokio_postgres::types::ToSql + Sync))
-        .collect();
-
-    match client.query_one(&query, &params_refs).await {
-        Ok(row) => {
-            let updated_user = crate::models::User {
-                id: row.get(0),
-                email: row.get(1),
-                full_name: row.get(2),
-                cnic: row.get(3),
-                wallet_id: row.get(4),
-                public_key: row.get(5),
-                encrypted_private_key: row.get(6),
-                is_verified: row.get(7),
-                created_at: row.get(8),
-                updated_at: row.get(9),
-            };
-
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(serde_json::json!({
-                    "id": updated_user.id,
-                    "email": updated_user.email,
-                    "full_name": updated_user.full_name,
-                    "wallet_id": updated_user.wallet_id,
-                    "public_key": updated_user.public_key,
-                    "is_verified": updated_user.is_verified,
-                })),
-                message: Some("Profile updated successfully".to_string()),
-            })
-        }
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Failed to update profile: {}", e)),
-        }),
-    }
-}
-
-fn extract_token(req: &HttpRequest) -> Option<String> {
-    req.headers()
-        .get("Authorization")?
-        .to_str()
-        .ok()?
-        .strip_prefix("Bearer ")
-        .map(|s| s.to_string())
 }
@@ -1,362 +1,897 @@
-use actix_web::{web, HttpResponse, HttpRequest};
-use crate::models::{ApiResponse, BlockchainInfo, MiningStats};
-use crate::database::DbPool;
+use actix_web::{web, FromRequest, HttpResponse, HttpRequest};
+use crate::models::{ApiResponse, BlockchainInfo, CursorPage, OffsetPage, WalletStatus};
+use crate::database::{with_retry, DbError, DbPool, queries};
 use crate::blockchain;
-use crate::services::{transaction_service, auth_service};
-use uuid::Uuid;
-use std::env;
+use crate::config::{ChainConfig, ChainMode};
+use crate::errors::ApiError;
+use crate::events::{self, BlockEvent, EventBus};
+use crate::handlers::authenticate;
+use crate::metrics::Metrics;
+use crate::middleware::RequireAdmin;
+use crate::shutdown::Shutdown;
+use crate::utils::from_units;
+use crate::services::{chain_export_service, transaction_service, explorer_service, wallet_service};
+
+/// Most blocks one `/blocks` page may return
+const MAX_BLOCK_PAGE: i64 = 100;
+/// Most blocks one `/blocks/range` request may return
+const MAX_BLOCK_RANGE: i64 = 100;
+/// Most transactions one `/block/{index}/transactions` page may return
+const MAX_BLOCK_TX_PAGE: i64 = 500;
+/// Most blocks one regtest `/generate/{n}` request may mine
+const MAX_GENERATED_BLOCKS: u32 = 100;
 
 pub async fn get_blocks(
     pool: web::Data<DbPool>,
     query: web::Query<std::collections::HashMap<String, String>>,
-) -> HttpResponse {
-    let limit = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(50);
+) -> Result<HttpResponse, ApiError> {
+    let limit = query
+        .get("limit")
+        .and_then(|l| l.parse::<i64>().ok())
+        .unwrap_or(50)
+        .clamp(1, MAX_BLOCK_PAGE);
     let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0);
-    
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+    let pool = pool.get_ref();
 
-    match crate::database::queries::get_all_blocks(&client, limit, offset).await {
-        Ok(blocks) => HttpResponse::Ok().json(ApiResponse {
+    // Cursor mode: `?before=<index>` (empty `before` starts at the chain tip)
+    if let Some(before) = query.get("before") {
+        let before = if before.is_empty() {
+            None
+        } else {
+            Some(before.parse::<i64>().map_err(|_| {
+                ApiError::BadRequest("Invalid cursor: before must be a block index".to_string())
+            })?)
+        };
+
+        let blocks = with_retry(|| async move {
+            let client = pool.get().await?;
+            Ok::<_, DbError>(queries::get_blocks_before(&client, before, limit).await?)
+        })
+        .await?;
+        let next_cursor = if blocks.len() as i64 == limit {
+            blocks.last().map(|b| b.index.to_string())
+        } else {
+            None
+        };
+
+        return Ok(HttpResponse::Ok().json(ApiResponse {
             success: true,
-            data: Some(blocks),
+            data: Some(CursorPage { items: blocks, next_cursor }),
             message: None,
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
+        }));
+    }
+
+    let blocks = with_retry(|| async move {
+        let client = pool.get().await?;
+        Ok::<_, DbError>(queries::get_all_blocks(&client, limit, offset).await?)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(blocks),
+        message: None,
+    }))
+}
+
+/// Blocks `from..=to` with their transactions, for clients syncing the chain without a request per block
+pub async fn get_blocks_range(
+    pool: web::Data<DbPool>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let bound = |name: &str| {
+        query
+            .get(name)
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|v| *v >= 0)
+            .ok_or_else(|| ApiError::BadRequest(format!("{} must be a block index", name)))
+    };
+    let (from, to) = (bound("from")?, bound("to")?);
+    if from > to {
+        return Err(ApiError::BadRequest("from must not be greater than to".to_string()));
     }
+    if to - from + 1 > MAX_BLOCK_RANGE {
+        return Err(ApiError::BadRequest(format!("A range may span at most {} blocks", MAX_BLOCK_RANGE)));
+    }
+    let pool = pool.get_ref();
+
+    let blocks = with_retry(|| async move {
+        let client = pool.get().await?;
+        Ok::<_, DbError>(queries::get_blocks_in_range(&client, from, to).await?)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(blocks),
+        message: None,
+    }))
 }
 
+/// A block with its transactions, or only its header with `?include_transactions=false`
+/// (large blocks' transactions can then be paged through `get_block_transactions`)
 pub async fn get_block(
     pool: web::Data<DbPool>,
     path: web::Path<i64>,
-) -> HttpResponse {
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
     let block_index = path.into_inner();
-    
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+    let pool = pool.get_ref();
+
+    if query.get("include_transactions").is_some_and(|v| v == "false") {
+        let header = with_retry(|| async move {
+            let client = pool.get().await?;
+            Ok::<_, DbError>(queries::get_block_header(&client, block_index).await?)
+        })
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Block not found".to_string()))?;
 
-    match crate::database::queries::get_block_by_index(&client, block_index).await {
-        Ok(Some(block)) => HttpResponse::Ok().json(ApiResponse {
+        return Ok(HttpResponse::Ok().json(ApiResponse {
             success: true,
-            data: Some(block),
+            data: Some(header),
             message: None,
-        }),
-        Ok(None) => HttpResponse::NotFound().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some("Block not found".to_string()),
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
+        }));
     }
+
+    let block = with_retry(|| async move {
+        let client = pool.get().await?;
+        Ok::<_, DbError>(queries::get_block_by_index(&client, block_index).await?)
+    })
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Block not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(block),
+        message: None,
+    }))
 }
 
-pub async fn validate_chain(pool: web::Data<DbPool>) -> HttpResponse {
-    match blockchain::validate_blockchain(&pool).await {
-        Ok(is_valid) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(serde_json::json!({
-                "is_valid": is_valid
-            })),
-            message: Some(if is_valid {
-                "Blockchain is valid".to_string()
-            } else {
-                "Blockchain validation failed".to_string()
-            }),
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Validation error: {}", e)),
-        }),
-    }
+/// One page of a block's transactions, in the order its merkle root covers them
+pub async fn get_block_transactions(
+    pool: web::Data<DbPool>,
+    path: web::Path<i64>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let block_index = path.into_inner();
+    let limit = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(100i64).clamp(1, MAX_BLOCK_TX_PAGE);
+    let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0i64).max(0);
+    let pool = pool.get_ref();
+
+    let (header, items) = with_retry(|| async move {
+        let client = pool.get().await?;
+        let Some(header) = queries::get_block_header(&client, block_index).await? else {
+            return Ok(None);
+        };
+        let items = queries::get_block_transactions_page(&client, block_index, limit, offset).await?;
+        Ok::<_, DbError>(Some((header, items)))
+    })
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Block not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(OffsetPage { items, total: header.transaction_count, limit, offset }),
+        message: None,
+    }))
 }
 
-pub async fn mine_block(pool: web::Data<DbPool>, req: HttpRequest) -> HttpResponse {
-    // Extract token from Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(header) => {
-            match header.to_str() {
-                Ok(auth_str) => {
-                    auth_str.strip_prefix("Bearer ").unwrap_or("")
-                }
-                Err(_) => {
-                    return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                        success: false,
-                        data: None,
-                        message: Some("Invalid authorization header".to_string()),
-                    });
-                }
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Missing authorization header".to_string()),
-            });
-        }
-    };
+/// Validation report for the canonical chain; `?full=true` keeps going past the first invalid block
+/// and `?deep=true` also re-verifies transaction signatures
+pub async fn validate_chain(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let full = query.get("full").is_some_and(|v| v == "true");
+    let deep = query.get("deep").is_some_and(|v| v == "true");
+    // Deep validation loads every sender's key and re-verifies every signature; too costly to leave open
+    if deep {
+        RequireAdmin::extract(&req).await?;
+    }
+    let report = blockchain::validate_blockchain(&pool, &chain, full, deep)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Validation error: {}", e)))?;
 
-    // Verify token and get user_id
-    let claims = match auth_service::verify_token(token) {
-        Ok(c) => c,
-        Err(_) => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Invalid or expired token".to_string()),
-            });
-        }
+    let message = match (&report.first_invalid_index, &report.reason) {
+        (_, None) => "Blockchain is valid".to_string(),
+        (Some(index), Some(reason)) => format!("Blockchain validation failed at block {}: {}", index, reason),
+        (None, Some(reason)) => format!("Blockchain validation failed: {}", reason),
     };
 
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(report),
+        message: Some(message),
+    }))
+}
+
+/// Admin check that total supply stays within the configured max supply and no wallet is overdrawn
+pub async fn audit(
+    pool: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+    _admin: RequireAdmin,
+) -> Result<HttpResponse, ApiError> {
+    let client = pool.get().await?;
+    let audit = blockchain::audit_supply(&client, chain.max_coin_supply).await?;
+    let healthy = audit.within_max_supply && audit.negative_balances.is_empty();
+    if !healthy {
+        log::error!("❌ Supply audit failed: {:?}", audit);
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(audit),
+        message: Some(if healthy {
+            "Supply audit passed".to_string()
+        } else {
+            "Supply audit found inconsistencies".to_string()
+        }),
+    }))
+}
+
+pub async fn mine_block(
+    pool: web::Data<DbPool>,
+    events: web::Data<EventBus>,
+    metrics: web::Data<Metrics>,
+    shutdown: web::Data<Shutdown>,
+    chain: web::Data<ChainConfig>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    if shutdown.is_triggered() {
+        return Err(ApiError::ServiceUnavailable("Server is shutting down".to_string()));
+    }
+
+    let user_id = authenticate(&req)?;
+
     // Get user from database to retrieve wallet_id
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+    let client = pool.get().await?;
+    let user_row = client
+        .query_opt("SELECT wallet_id FROM users WHERE id = $1", &[&user_id])
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized("User not found".to_string()))?;
 
-    // Parse user_id from claims
-    let user_id = match Uuid::parse_str(&claims.sub) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Invalid user ID in token".to_string()),
-            });
-        }
-    };
+    let wallet_id: String = user_row.get(0);
 
-    let user_row = match client
-        .query_one("SELECT wallet_id FROM users WHERE id = $1", &[&user_id])
-        .await
-    {
-        Ok(row) => row,
-        Err(e) => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("User not found: {}", e)),
-            });
+    if let Some(wallet) = queries::get_wallet(&client, &wallet_id).await? {
+        if wallet.status == WalletStatus::Frozen {
+            return Err(ApiError::Forbidden(format!("Wallet frozen: miner wallet {}", wallet_id)));
         }
+    }
+    drop(client);
+
+    let started = std::time::Instant::now();
+    let mined = match blockchain::mine_block(&pool, &wallet_id, &chain, &shutdown).await {
+        Ok(mined) => mined,
+        Err(e) if shutdown.is_triggered() => return Err(ApiError::ServiceUnavailable(e.to_string())),
+        Err(e) if e.is::<blockchain::MiningInProgress>() => return Err(ApiError::Conflict(e.to_string())),
+        Err(e) => return Err(ApiError::Internal(format!("Mining failed: {}", e))),
     };
 
-    let wallet_id: String = user_row.get(0);
+    metrics.last_block_mining_seconds.set(started.elapsed().as_secs_f64());
+    let block = &mined.block;
+    events::publish(&events, BlockEvent::block_mined(block));
 
-    match blockchain::mine_block(&pool, &wallet_id).await {
-        Ok(block) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(serde_json::json!({
-                "block_index": block.index,
-                "block_hash": block.hash,
-                "transactions_count": block.transactions.len(),
-                "nonce": block.nonce,
-                "timestamp": block.timestamp,
-            })),
-            message: Some("Block mined successfully".to_string()),
-        }),
-        Err(e) => HttpResponse::BadRequest().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(e.to_string()),
-        }),
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({
+            "block_index": block.index,
+            "block_hash": block.hash,
+            "transactions_count": block.transactions.len(),
+            "nonce": block.nonce,
+            "timestamp": block.timestamp,
+            "block_reward": from_units(mined.block_reward),
+            "total_fees": from_units(mined.total_fees),
+            "total_reward": from_units(mined.total_reward),
+        })),
+        message: Some("Block mined successfully".to_string()),
+    }))
+}
+
+/// Regtest only: mine `n` blocks back to back to the caller's wallet. The first block picks up
+/// whatever is pending, as `/mine` would; the rest are empty.
+pub async fn generate_blocks(
+    pool: web::Data<DbPool>,
+    events: web::Data<EventBus>,
+    shutdown: web::Data<Shutdown>,
+    chain: web::Data<ChainConfig>,
+    path: web::Path<u32>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    if chain.mode != ChainMode::Regtest {
+        return Err(ApiError::NotFound("Block generation is only available in regtest mode".to_string()));
+    }
+    let count = path.into_inner();
+    if count == 0 || count > MAX_GENERATED_BLOCKS {
+        return Err(ApiError::BadRequest(format!("n must be between 1 and {}", MAX_GENERATED_BLOCKS)));
     }
+
+    let user_id = authenticate(&req)?;
+    let client = pool.get().await?;
+    let wallet_id: String = client
+        .query_opt("SELECT wallet_id FROM users WHERE id = $1", &[&user_id])
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized("User not found".to_string()))?
+        .get(0);
+    drop(client);
+
+    let mut hashes = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let block = match blockchain::mine_block(&pool, &wallet_id, &chain, &shutdown).await {
+            Ok(mined) => mined.block,
+            Err(e) if shutdown.is_triggered() => return Err(ApiError::ServiceUnavailable(e.to_string())),
+            Err(e) if e.is::<blockchain::MiningInProgress>() => return Err(ApiError::Conflict(e.to_string())),
+            Err(e) => return Err(ApiError::Internal(format!("Mining failed: {}", e))),
+        };
+        events::publish(&events, BlockEvent::block_mined(&block));
+        hashes.push(block.hash);
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({ "block_hashes": hashes })),
+        message: Some(format!("Generated {} block(s)", count)),
+    }))
 }
 
-pub async fn get_info(pool: web::Data<DbPool>) -> HttpResponse {
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
+/// Latest block height, hash and timestamp; a cheap poll target next to `/info`
+pub async fn get_tip(pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let pool = pool.get_ref();
+    let tip = with_retry(|| async move {
+        let client = pool.get().await?;
+        Ok::<_, DbError>(queries::get_chain_tip(&client).await?)
+    })
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Blockchain has no blocks".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(tip),
+        message: None,
+    }))
+}
+
+pub async fn get_info(pool: web::Data<DbPool>, chain: web::Data<ChainConfig>) -> Result<HttpResponse, ApiError> {
+    let client = pool.get().await?;
+
+    let total_blocks: i64 = client.query_one("SELECT COUNT(*) FROM blocks", &[]).await?.get(0);
+    let total_transactions: i64 = client.query_one("SELECT COUNT(*) FROM transactions", &[]).await?.get(0);
+    let total_wallets: i64 = client.query_one("SELECT COUNT(*) FROM wallets", &[]).await?.get(0);
+    let latest_block = crate::database::queries::get_latest_block(&client).await?;
+    let pending_count = transaction_service::get_pending_count(&pool)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to count pending transactions: {}", e)))?;
+
+    let current_block_height = latest_block.as_ref().map(|b| b.index).unwrap_or(0);
+    let current_block_reward = blockchain::calculate_block_reward(current_block_height as i32, &chain);
+    let transaction_fee = chain.transaction_fee;
+    
+    let info = BlockchainInfo {
+        total_blocks,
+        latest_block,
+        pending_transactions: pending_count,
+        total_transactions,
+        total_wallets,
+        mining_difficulty: chain.mining_difficulty as i32,
+        current_block_reward,
+        transaction_fee,
     };
 
-    let total_blocks_result = client
-        .query_one("SELECT COUNT(*) FROM blocks", &[])
-        .await;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(info),
+        message: None,
+    }))
+}
 
-    let total_transactions_result = client
-        .query_one("SELECT COUNT(*) FROM transactions", &[])
-        .await;
+pub async fn get_richlist(
+    pool: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query
+        .get("limit")
+        .and_then(|l| l.parse::<i64>().ok())
+        .unwrap_or(100)
+        .clamp(1, 1000);
 
-    let total_wallets_result = client
-        .query_one("SELECT COUNT(*) FROM wallets", &[])
-        .await;
+    let exclude_pool = query
+        .get("exclude_zakat_pool")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false);
+    let excluded = exclude_pool.then_some(chain.zakat_pool_wallet_id.as_str());
 
-    let latest_block_result = crate::database::queries::get_latest_block(&client).await;
-
-    let pending_count_result = transaction_service::get_pending_count(&pool).await;
-
-    match (total_blocks_result, total_transactions_result, total_wallets_result, latest_block_result, pending_count_result) {
-        (Ok(blocks_row), Ok(tx_row), Ok(wallets_row), Ok(latest_block), Ok(pending_count)) => {
-            let total_blocks: i64 = blocks_row.get(0);
-            let total_transactions: i64 = tx_row.get(0);
-            let total_wallets: i64 = wallets_row.get(0);
-            
-            // Get mining configuration
-            let mining_difficulty: i32 = env::var("MINING_DIFFICULTY")
-                .unwrap_or("3".to_string())
-                .parse()
-                .unwrap_or(3);
-            
-            let current_block_height = latest_block.as_ref().map(|b| b.index).unwrap_or(0);
-            let current_block_reward = blockchain::calculate_block_reward(current_block_height as i32);
-            
-            let transaction_fee: f64 = env::var("TRANSACTION_FEE")
-                .unwrap_or("0.1".to_string())
-                .parse()
-                .unwrap_or(0.1);
-            
-            let info = BlockchainInfo {
-                total_blocks,
-                latest_block,
-                pending_transactions: pending_count,
-                total_transactions,
-                total_wallets,
-                mining_difficulty,
-                current_block_reward,
-                transaction_fee,
-            };
+    let client = pool.get().await?;
+    let holders = crate::database::queries::get_utxo_holders(&client, limit, excluded).await?;
+    let supply = crate::database::queries::get_unspent_supply(&client).await?;
 
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(info),
-                message: None,
-            })
-        }
-        _ => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some("Failed to retrieve blockchain info".to_string()),
-        }),
-    }
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(wallet_service::rank_holders(holders, supply)),
+        message: None,
+    }))
 }
 
-pub async fn get_mining_stats(pool: web::Data<DbPool>) -> HttpResponse {
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
+pub async fn search(
+    pool: web::Data<DbPool>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let q = match query.get("q") {
+        Some(q) if !q.trim().is_empty() => q,
+        _ => return Err(ApiError::BadRequest("q is required".to_string())),
     };
 
+    let client = pool.get().await?;
+
+    let result = explorer_service::search(&client, q)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("No block, transaction or wallet matches the query".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(result),
+        message: None,
+    }))
+}
+
+pub async fn get_mining_stats(
+    pool: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let client = pool.get().await?;
+
     // Get latest block to determine current height
-    let latest_block = match crate::database::queries::get_latest_block(&client).await {
-        Ok(Some(block)) => block,
-        Ok(None) => {
-            return HttpResponse::Ok().json(ApiResponse {
+    let latest_block = match crate::database::queries::get_latest_block(&client).await? {
+        Some(block) => block,
+        None => {
+            return Ok(HttpResponse::Ok().json(ApiResponse {
                 success: true,
-                data: Some(MiningStats {
-                    current_block_height: 0,
-                    current_block_reward: blockchain::calculate_block_reward(0),
-                    next_halving_block: env::var("HALVING_INTERVAL").unwrap_or("210".to_string()).parse().unwrap_or(210) as i64,
-                    blocks_until_halving: env::var("HALVING_INTERVAL").unwrap_or("210".to_string()).parse().unwrap_or(210) as i64,
-                    total_mined_coins: 0.0,
-                    max_coin_supply: env::var("MAX_COIN_SUPPLY").unwrap_or("21000000.0".to_string()).parse().unwrap_or(21000000.0),
-                    remaining_coins: env::var("MAX_COIN_SUPPLY").unwrap_or("21000000.0".to_string()).parse().unwrap_or(21000000.0),
-                    halving_interval: env::var("HALVING_INTERVAL").unwrap_or("210".to_string()).parse().unwrap_or(210),
-                    percentage_mined: 0.0,
-                }),
+                data: Some(blockchain::build_mining_stats(&chain, 0, 0.0, None, None)),
                 message: Some("No blocks mined yet".to_string()),
-            });
-        }
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Failed to get latest block: {}", e)),
-            });
+            }));
         }
     };
 
-    let current_height = latest_block.index;
-    
-    // Get configuration
-    let halving_interval: i32 = env::var("HALVING_INTERVAL")
-        .unwrap_or("210".to_string())
-        .parse()
-        .unwrap_or(210);
-    
-    let max_supply: f64 = env::var("MAX_COIN_SUPPLY")
-        .unwrap_or("21000000.0".to_string())
-        .parse()
-        .unwrap_or(21000000.0);
-    
-    // Calculate current reward
-    let current_reward = blockchain::calculate_block_reward(current_height as i32);
-    
-    // Calculate next halving block
-    let next_halving_block = ((current_height / halving_interval as i64) + 1) * halving_interval as i64;
-    let blocks_until_halving = next_halving_block - current_height;
-    
     // Get total mined coins
-    let total_mined = match blockchain::get_total_mined_coins(&client).await {
-        Ok(total) => total,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Failed to calculate total mined coins: {}", e)),
-            });
-        }
-    };
-    
-    let remaining = (max_supply - total_mined).max(0.0);
-    let percentage_mined = (total_mined / max_supply * 100.0).min(100.0);
-    
-    let stats = MiningStats {
-        current_block_height: current_height,
-        current_block_reward: current_reward,
-        next_halving_block,
-        blocks_until_halving,
-        total_mined_coins: total_mined,
-        max_coin_supply: max_supply,
-        remaining_coins: remaining,
-        halving_interval,
-        percentage_mined,
-    };
+    let total_mined = blockchain::get_total_mined_coins(&client).await?;
+
+    // Hashrate averaged over the last 10 blocks with recorded work
+    let (last_block_mining_seconds, estimated_hashrate) =
+        crate::database::queries::get_recent_mining_work(&client, 10).await?;
+
+    let stats = blockchain::build_mining_stats(
+        &chain,
+        latest_block.index,
+        total_mined,
+        last_block_mining_seconds,
+        estimated_hashrate,
+    );
 
-    HttpResponse::Ok().json(ApiResponse {
+    Ok(HttpResponse::Ok().json(ApiResponse {
         success: true,
         data: Some(stats),
         message: None,
-    })
+    }))
+}
+
+pub async fn get_supply(
+    pool: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let client = pool.get().await?;
+
+    let total_mined = blockchain::get_total_mined_coins(&client).await?;
+    let total_burned = queries::get_total_burned(&client).await?;
+
+    let zakat_pool_balance = crate::database::queries::get_unspent_totals(&client, std::slice::from_ref(&chain.zakat_pool_wallet_id))
+        .await?
+        .first()
+        .map_or(0.0, |(_, total, _)| *total);
+    let immature = queries::get_immature_coinbase_total(&client, None, chain.coinbase_maturity).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(blockchain::build_supply_info(
+            &chain,
+            total_mined,
+            zakat_pool_balance,
+            crate::utils::from_units(immature),
+            crate::utils::from_units(total_burned),
+        )),
+        message: None,
+    }))
+}
+
+/// Largest chain export accepted by `import_chain`
+const MAX_IMPORT_BYTES: usize = 512 * 1024 * 1024;
+
+/// Every block, oldest first, as newline-delimited JSON; fetched in batches while streaming
+pub async fn export_chain(pool: web::Data<DbPool>, _admin: RequireAdmin) -> Result<HttpResponse, ApiError> {
+    let pool = pool.get_ref().clone();
+    let lines = futures_util::stream::unfold(Some(0i64), move |from| {
+        let pool = pool.clone();
+        async move {
+            let from = from?;
+            let batch = match pool.get().await {
+                Ok(client) => chain_export_service::export_blocks(&client, from, chain_export_service::EXPORT_BATCH_SIZE)
+                    .await
+                    .map_err(ApiError::from),
+                Err(e) => Err(ApiError::from(e)),
+            };
+
+            match batch {
+                Ok(blocks) if blocks.is_empty() => None,
+                Ok(blocks) => {
+                    let next = (blocks.len() as i64 == chain_export_service::EXPORT_BATCH_SIZE)
+                        .then(|| blocks[blocks.len() - 1].block.index + 1);
+                    let mut body = String::new();
+                    for block in &blocks {
+                        body.push_str(&serde_json::to_string(block).unwrap_or_default());
+                        body.push('\n');
+                    }
+                    Some((Ok::<_, actix_web::Error>(web::Bytes::from(body)), next))
+                }
+                Err(e) => {
+                    log::error!("❌ Chain export failed at block {}: {:?}", from, e);
+                    Some((Err(e.into()), None))
+                }
+            }
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines))
+}
+
+/// Load a chain export into a node that has no history beyond its genesis block
+pub async fn import_chain(
+    pool: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+    _admin: RequireAdmin,
+    payload: web::Payload,
+) -> Result<HttpResponse, ApiError> {
+    let body = payload
+        .to_bytes_limited(MAX_IMPORT_BYTES)
+        .await
+        .map_err(|_| ApiError::PayloadTooLarge(format!("Export larger than {} bytes", MAX_IMPORT_BYTES)))?
+        .map_err(|e| ApiError::BadRequest(format!("Failed to read export: {}", e)))?;
+    let ndjson = std::str::from_utf8(&body)
+        .map_err(|_| ApiError::BadRequest("Export is not valid UTF-8".to_string()))?;
+
+    let summary = chain_export_service::import_chain(&pool, &chain, ndjson).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(summary),
+        message: Some("Chain imported".to_string()),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_info_reports_enforced_difficulty() {
+        let pool = match crate::database::create_test_pool("info_difficulty").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig { mining_difficulty: 8, ..ChainConfig::default() };
+        blockchain::initialize_blockchain(pool.clone(), &chain).await.unwrap();
+
+        let miner = crate::database::register_test_user(&pool, "miner@example.com", "1").await;
+
+        let block = blockchain::mine_block(&pool, &miner.wallet_id, &chain, &Shutdown::new())
+            .await
+            .unwrap()
+            .block;
+        assert!(block.hash.starts_with("00"));
+        assert!(blockchain::validate_blockchain(&pool, &chain, false, false).await.unwrap().valid);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(chain.clone()))
+                .route("/info", web::get().to(get_info)),
+        )
+        .await;
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, test::TestRequest::get().uri("/info").to_request()).await;
+
+        assert_eq!(body["data"]["mining_difficulty"], serde_json::json!(chain.mining_difficulty));
+        assert_eq!(body["data"]["latest_block"]["hash"], serde_json::json!(block.hash));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_tip_follows_mined_blocks() {
+        let Some(pool) = crate::database::create_test_pool("chain_tip").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig { mode: ChainMode::Regtest, mining_difficulty: 0, ..ChainConfig::default() };
+        blockchain::initialize_blockchain(pool.clone(), &chain).await.unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .route("/tip", web::get().to(get_tip)),
+        )
+        .await;
+        let tip = || test::TestRequest::get().uri("/tip").to_request();
+
+        let genesis: serde_json::Value = test::call_and_read_body_json(&app, tip()).await;
+        assert_eq!(genesis["data"]["index"], 0);
+
+        queries::ensure_wallet(&pool.get().await.unwrap(), "miner").await.unwrap();
+        let shutdown = Shutdown::new();
+        blockchain::mine_block(&pool, "miner", &chain, &shutdown).await.unwrap();
+        let block = blockchain::mine_block(&pool, "miner", &chain, &shutdown).await.unwrap().block;
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, tip()).await;
+        assert_eq!(
+            body["data"],
+            serde_json::json!({ "index": block.index, "hash": block.hash, "timestamp": block.timestamp })
+        );
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_block_range_matches_single_block_reads() {
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        let Some((pool, miner, receiver)) = crate::database::create_funded_test_pair("block_range", &chain, 0.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        // Blocks with and without transactions
+        let shutdown = Shutdown::new();
+        blockchain::mine_block(&pool, &miner.wallet_id, &chain, &shutdown).await.unwrap();
+        for amount in [1.0, 2.0] {
+            let request = crate::models::CreateTransactionRequest {
+                sender_wallet_id: miner.wallet_id.clone(),
+                receiver_wallet_id: receiver.wallet_id.clone(),
+                amount,
+                note: None,
+                private_note: false,
+                payment_request_id: None,
+            };
+            transaction_service::create_transaction(&pool, request, &chain, &aes_key).await.unwrap();
+        }
+        blockchain::mine_block(&pool, &miner.wallet_id, &chain, &shutdown).await.unwrap();
+        blockchain::mine_block(&pool, &miner.wallet_id, &chain, &shutdown).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        let batched = queries::get_blocks_in_range(&client, 0, 3).await.unwrap();
+        assert_eq!(batched.len(), 4);
+        assert_eq!(batched[2].transactions.len(), 2);
+        for block in batched {
+            let single = queries::get_block_by_index(&client, block.index).await.unwrap().unwrap();
+            assert_eq!(serde_json::to_value(block).unwrap(), serde_json::to_value(single).unwrap());
+        }
+
+        // Past the tip is just shorter
+        assert_eq!(queries::get_blocks_in_range(&client, 2, 50).await.unwrap().len(), 2);
+        drop(client);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .route("/blocks/range", web::get().to(get_blocks_range)),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, get("/blocks/range?from=1&to=2")).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 2);
+        assert_eq!(body["data"][0]["index"], serde_json::json!(1));
+
+        for uri in ["/blocks/range?from=0&to=100", "/blocks/range?from=2&to=1", "/blocks/range?from=1"] {
+            let resp = test::call_service(&app, get(uri)).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_block_header_and_transaction_pages() {
+        let chain = ChainConfig { mode: ChainMode::Regtest, mining_difficulty: 0, ..ChainConfig::default() };
+        let Some((pool, miner, receiver)) = crate::database::create_funded_test_pair("block_tx_pages", &chain, 0.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let shutdown = Shutdown::new();
+        for _ in 0..3 {
+            blockchain::mine_block(&pool, &miner.wallet_id, &chain, &shutdown).await.unwrap();
+        }
+        for amount in [1.0, 2.0, 3.0] {
+            let request = crate::models::CreateTransactionRequest {
+                sender_wallet_id: miner.wallet_id.clone(),
+                receiver_wallet_id: receiver.wallet_id.clone(),
+                amount,
+                note: None,
+                private_note: false,
+                payment_request_id: None,
+            };
+            transaction_service::create_transaction(&pool, request, &chain, &aes_key).await.unwrap();
+        }
+        let block = blockchain::mine_block(&pool, &miner.wallet_id, &chain, &shutdown).await.unwrap().block;
+        assert_eq!(block.transactions.len(), 3);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .route("/block/{index}", web::get().to(get_block))
+                .route("/block/{index}/transactions", web::get().to(get_block_transactions)),
+        )
+        .await;
+        let get = |uri: String| test::TestRequest::get().uri(&uri).to_request();
+
+        let full: serde_json::Value = test::call_and_read_body_json(&app, get(format!("/block/{}", block.index))).await;
+        let header: serde_json::Value =
+            test::call_and_read_body_json(&app, get(format!("/block/{}?include_transactions=false", block.index))).await;
+        assert!(header["data"].get("transactions").is_none());
+        assert_eq!(header["data"]["transaction_count"], 3);
+        for field in ["index", "timestamp", "previous_hash", "hash", "nonce", "merkle_root", "version"] {
+            assert_eq!(header["data"][field], full["data"][field], "{}", field);
+        }
+
+        // Pages together list the block's transactions once each, in block order
+        let mut paged = Vec::new();
+        for offset in [0, 2] {
+            let page: serde_json::Value = test::call_and_read_body_json(
+                &app,
+                get(format!("/block/{}/transactions?limit=2&offset={}", block.index, offset)),
+            )
+            .await;
+            assert_eq!(page["data"]["total"], 3);
+            paged.extend(page["data"]["items"].as_array().unwrap().iter().map(|tx| tx["transaction_hash"].clone()));
+        }
+        let expected: Vec<serde_json::Value> =
+            full["data"]["transactions"].as_array().unwrap().iter().map(|tx| tx["transaction_hash"].clone()).collect();
+        assert_eq!(paged, expected);
+
+        for uri in ["/block/99?include_transactions=false", "/block/99/transactions"] {
+            let resp = test::call_service(&app, get(uri.to_string())).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_validation_report_locates_corrupted_blocks() {
+        let Some(pool) = crate::database::create_test_pool("validation_report").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        blockchain::initialize_blockchain(pool.clone(), &chain).await.unwrap();
+        let client = pool.get().await.unwrap();
+        queries::ensure_wallet(&client, "miner").await.unwrap();
+        drop(client);
+        let shutdown = Shutdown::new();
+        for _ in 0..3 {
+            blockchain::mine_block(&pool, "miner", &chain, &shutdown).await.unwrap();
+        }
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(chain.clone()))
+                .route("/validate", web::get().to(validate_chain)),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, get("/validate")).await;
+        assert_eq!(body["data"]["valid"], serde_json::json!(true));
+        assert_eq!(body["data"]["total_blocks"], serde_json::json!(4));
+        assert!(body["data"]["first_invalid_index"].is_null());
+
+        // Deep validation is for admins only
+        let deep = |token: Option<String>| {
+            let request = test::TestRequest::get().uri("/validate?deep=true");
+            match token {
+                Some(token) => request.insert_header(("Authorization", format!("Bearer {}", token))),
+                None => request,
+            }
+            .to_request()
+        };
+        let user = crate::services::auth_service::generate_token("1", "user@example.com", "user").unwrap();
+        let admin = crate::services::auth_service::generate_token("2", "admin@example.com", "admin").unwrap();
+        assert_eq!(test::call_service(&app, deep(None)).await.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        assert_eq!(test::call_service(&app, deep(Some(user))).await.status(), actix_web::http::StatusCode::FORBIDDEN);
+        let body: serde_json::Value = test::call_and_read_body_json(&app, deep(Some(admin))).await;
+        assert_eq!(body["data"]["valid"], serde_json::json!(true));
+
+        // Block 2's contents no longer match its hash, and block 3 overpays its miner
+        let client = pool.get().await.unwrap();
+        client
+            .execute("UPDATE blocks SET nonce = nonce + 1 WHERE \"index\" = 2", &[])
+            .await
+            .unwrap();
+        client
+            .execute(
+                "UPDATE utxos SET amount = amount + 1 FROM blocks b WHERE b.\"index\" = 3 AND utxos.transaction_hash = b.coinbase_hash",
+                &[],
+            )
+            .await
+            .unwrap();
+        drop(client);
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, get("/validate")).await;
+        let report = &body["data"];
+        assert_eq!(report["valid"], serde_json::json!(false));
+        assert_eq!(report["first_invalid_index"], serde_json::json!(2));
+        assert_eq!(report["reason"], serde_json::json!("Invalid block hash (tried both new and legacy methods)"));
+        assert_eq!(report["failures"].as_array().unwrap().len(), 1);
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, get("/validate?full=true")).await;
+        let report = &body["data"];
+        assert_eq!(report["first_invalid_index"], serde_json::json!(2));
+        assert_eq!(
+            report["failures"],
+            serde_json::json!([
+                { "index": 2, "reason": "Invalid block hash (tried both new and legacy methods)" },
+                { "index": 3, "reason": "Invalid coinbase: paid 51, expected 50" },
+            ])
+        );
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_block_cursor_pages_survive_new_blocks() {
+        let Some(pool) = crate::database::create_test_pool("block_cursor_paging").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig { mode: ChainMode::Regtest, mining_difficulty: 0, ..ChainConfig::default() };
+        blockchain::initialize_blockchain(pool.clone(), &chain).await.unwrap();
+        let client = pool.get().await.unwrap();
+        queries::ensure_wallet(&client, "miner").await.unwrap();
+        drop(client);
+        let shutdown = Shutdown::new();
+        for _ in 0..5 {
+            blockchain::mine_block(&pool, "miner", &chain, &shutdown).await.unwrap();
+        }
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .route("/blocks", web::get().to(get_blocks)),
+        )
+        .await;
+        let page = |uri: String| {
+            let app = &app;
+            async move {
+                let body: serde_json::Value =
+                    test::call_and_read_body_json(app, test::TestRequest::get().uri(&uri).to_request()).await;
+                let indexes: Vec<i64> = body["data"]["items"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|b| b["index"].as_i64().unwrap())
+                    .collect();
+                (indexes, body["data"]["next_cursor"].as_str().map(str::to_string))
+            }
+        };
+
+        // Blocks mined while paging sit above the cursor and never shift a later page
+        let mut seen = Vec::new();
+        let mut cursor = Some(String::new());
+        let mut added = 0;
+        while let Some(before) = cursor {
+            let (indexes, next) = page(format!("/blocks?before={}&limit=2", before)).await;
+            seen.extend(indexes);
+            blockchain::mine_block(&pool, "miner", &chain, &shutdown).await.unwrap();
+            added += 1;
+            cursor = next;
+        }
+        assert_eq!(seen, vec![5, 4, 3, 2, 1, 0]);
+
+        // Out-of-range limits are clamped rather than passed to the database
+        let (indexes, _) = page("/blocks?before=&limit=0".to_string()).await;
+        assert_eq!(indexes.len(), 1);
+        let (indexes, _) = page("/blocks?before=&limit=-5".to_string()).await;
+        assert_eq!(indexes.len(), 1);
+        let (indexes, _) = page(format!("/blocks?before=&limit={}", i64::MAX)).await;
+        assert_eq!(indexes.len(), 6 + added);
+    }
 }
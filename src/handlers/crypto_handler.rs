@@ -0,0 +1,59 @@
+use actix_web::{web, HttpResponse};
+use crate::crypto;
+use crate::errors::ApiError;
+use crate::models::{ApiResponse, VerifySignatureRequest};
+
+/// Check a signature the same way transactions are checked: RSA PKCS#1 v1.5 over the SHA-256
+/// of `payload`. A well-formed signature that doesn't match is `valid: false`, not an error.
+pub async fn verify_signature(body: web::Json<VerifySignatureRequest>) -> Result<HttpResponse, ApiError> {
+    let public_key = crypto::import_public_key_pem(&body.public_key_pem)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid public key PEM: {}", e)))?;
+    let valid = crypto::verify_signature(&public_key, &body.payload, &body.signature)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({ "valid": valid })),
+        message: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App};
+
+    #[actix_web::test]
+    async fn test_verify_signature() {
+        let app = test::init_service(App::new().route("/verify", web::post().to(verify_signature))).await;
+        let (private_key, public_key) = crypto::generate_keypair().unwrap();
+        let public_key_pem = crypto::export_public_key_pem(&public_key).unwrap();
+        let payload = crypto::create_transaction_payload("sender", "receiver", 1.5, 1733571000, &None);
+        let signature = crypto::sign_data(&private_key, &payload).unwrap();
+
+        let verify = |payload: &str, public_key_pem: &str, signature: &str| {
+            test::TestRequest::post()
+                .uri("/verify")
+                .set_json(serde_json::json!({
+                    "public_key_pem": public_key_pem,
+                    "payload": payload,
+                    "signature": signature,
+                }))
+                .to_request()
+        };
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, verify(&payload, &public_key_pem, &signature)).await;
+        assert_eq!(body["data"]["valid"], serde_json::json!(true));
+
+        // Tampered payload: still a well-formed request, just not a valid signature
+        let tampered = crypto::create_transaction_payload("sender", "receiver", 150.0, 1733571000, &None);
+        let body: serde_json::Value = test::call_and_read_body_json(&app, verify(&tampered, &public_key_pem, &signature)).await;
+        assert_eq!(body["data"]["valid"], serde_json::json!(false));
+
+        let resp = test::call_service(&app, verify(&payload, "not a key", &signature)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = test::call_service(&app, verify(&payload, &public_key_pem, "zz")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,277 +1,268 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::config::WalletReadPolicy;
 use crate::models::ApiResponse;
 use crate::database::DbPool;
+use crate::errors::ApiError;
+use crate::handlers::authorize_wallet_read;
+use crate::middleware::RequireAdmin;
+use crate::services::analytics_service::{self, TimeseriesInterval, TimeseriesMetric};
 
 pub async fn get_transaction_logs(
     pool: web::Data<DbPool>,
+    req: HttpRequest,
+    policy: web::Data<WalletReadPolicy>,
     query: web::Query<std::collections::HashMap<String, String>>,
-) -> HttpResponse {
-    let wallet_id = match query.get("wallet_id") {
-        Some(id) => id,
-        None => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("wallet_id is required".to_string()),
-            });
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = query
+        .get("wallet_id")
+        .ok_or_else(|| ApiError::BadRequest("wallet_id is required".to_string()))?;
+    authorize_wallet_read(&pool, &req, &policy, std::slice::from_ref(wallet_id)).await?;
 
-    let limit = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(50);
-    let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0);
-
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+    let limit = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(50i64);
+    let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0i64);
 
-    let result = client
+    let client = pool.get().await?;
+
+    let rows = client
         .query(
-            "SELECT id, wallet_id, action, transaction_hash, block_hash, status, ip_address, 
-             user_agent, note, created_at 
-             FROM transaction_logs 
-             WHERE wallet_id = $1 
+            "SELECT id, wallet_id, action, transaction_hash, block_hash, status, ip_address,
+             user_agent, note, created_at
+             FROM transaction_logs
+             WHERE wallet_id = $1
              ORDER BY created_at DESC LIMIT $2 OFFSET $3",
             &[&wallet_id, &limit, &offset],
         )
-        .await;
-
-    match result {
-        Ok(rows) => {
-            let logs: Vec<crate::models::TransactionLog> = rows
-                .iter()
-                .map(|row| crate::models::TransactionLog {
-                    id: row.get(0),
-                    wallet_id: row.get(1),
-                    action: row.get(2),
-                    transaction_hash: row.get(3),
-                    block_hash: row.get(4),
-                    status: row.get(5),
-                    ip_address: row.get(6),
-                    user_agent: row.get(7),
-                    note: row.get(8),
-                    created_at: row.get(9),
-                })
-                .collect();
-
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(logs),
-                message: None,
-            })
-        }
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
-    }
+        .await?;
+
+    let logs: Vec<crate::models::TransactionLog> = rows
+        .iter()
+        .map(|row| crate::models::TransactionLog {
+            id: row.get(0),
+            wallet_id: row.get(1),
+            action: row.get(2),
+            transaction_hash: row.get(3),
+            block_hash: row.get(4),
+            status: row.get(5),
+            ip_address: row.get(6),
+            user_agent: row.get(7),
+            note: row.get(8),
+            created_at: row.get(9),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(logs),
+        message: None,
+    }))
 }
 
 pub async fn get_system_logs(
     pool: web::Data<DbPool>,
+    _admin: RequireAdmin,
     query: web::Query<std::collections::HashMap<String, String>>,
-) -> HttpResponse {
-    let limit = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(100);
-    let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0);
+) -> Result<HttpResponse, ApiError> {
+    let limit = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(100i64);
+    let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0i64);
     let log_type = query.get("type");
 
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+    let client = pool.get().await?;
 
-    let result = if let Some(lt) = log_type {
+    let rows = if let Some(lt) = log_type {
         client
             .query(
-                "SELECT id, log_type, user_id, message, ip_address, metadata, created_at 
-                 FROM system_logs 
-                 WHERE log_type = $1 
+                "SELECT id, log_type, user_id, message, ip_address, metadata, created_at
+                 FROM system_logs
+                 WHERE log_type = $1
                  ORDER BY created_at DESC LIMIT $2 OFFSET $3",
                 &[&lt, &limit, &offset],
             )
-            .await
+            .await?
     } else {
         client
             .query(
-                "SELECT id, log_type, user_id, message, ip_address, metadata, created_at 
-                 FROM system_logs 
+                "SELECT id, log_type, user_id, message, ip_address, metadata, created_at
+                 FROM system_logs
                  ORDER BY created_at DESC LIMIT $1 OFFSET $2",
                 &[&limit, &offset],
             )
-            .await
+            .await?
     };
 
-    match result {
-        Ok(rows) => {
-            let logs: Vec<crate::models::SystemLog> = rows
-                .iter()
-                .map(|row| crate::models::SystemLog {
-                    id: row.get(0),
-                    log_type: row.get(1),
-                    user_id: row.get(2),
-                    message: row.get(3),
-                    ip_address: row.get(4),
-                    metadata: row.get(5),
-                    created_at: row.get(6),
-                })
-                .collect();
-
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(logs),
-                message: None,
-            })
-        }
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
-    }
+    let logs: Vec<crate::models::SystemLog> = rows
+        .iter()
+        .map(|row| crate::models::SystemLog {
+            id: row.get(0),
+            log_type: row.get(1),
+            user_id: row.get(2),
+            message: row.get(3),
+            ip_address: row.get(4),
+            metadata: row.get(5),
+            created_at: row.get(6),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(logs),
+        message: None,
+    }))
 }
 
 pub async fn get_monthly_report(
     pool: web::Data<DbPool>,
+    req: HttpRequest,
+    policy: web::Data<WalletReadPolicy>,
     path: web::Path<String>,
-) -> HttpResponse {
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
     let wallet_id = path.into_inner();
+    authorize_wallet_read(&pool, &req, &policy, std::slice::from_ref(&wallet_id)).await?;
 
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+    let (period_start, period_end) = analytics_service::report_range(
+        query.get("from").map(|s| s.as_str()),
+        query.get("to").map(|s| s.as_str()),
+        chrono::Utc::now(),
+    )
+    .map_err(ApiError::BadRequest)?;
 
-    // Get monthly statistics
-    let result = client
+    let client = pool.get().await?;
+
+    // Get statistics for the requested period
+    let row = client
         .query_one(
-            "SELECT 
+            "SELECT
                 COUNT(*) as total_transactions,
                 COALESCE(SUM(CASE WHEN sender_wallet_id = $1 THEN amount ELSE 0 END)::float8, 0) as total_sent,
                 COALESCE(SUM(CASE WHEN receiver_wallet_id = $1 THEN amount ELSE 0 END)::float8, 0) as total_received
-             FROM transactions 
+             FROM transactions
              WHERE (sender_wallet_id = $1 OR receiver_wallet_id = $1)
-             AND created_at >= NOW() - INTERVAL '30 days'",
-            &[&wallet_id],
+             AND created_at >= $2 AND created_at < $3",
+            &[&wallet_id, &period_start, &period_end],
         )
-        .await;
+        .await?;
 
-    // Get zakat paid in last 30 days
-    let zakat_result = client
+    // Get zakat paid in the period
+    let zakat_row = client
         .query_one(
             "SELECT COALESCE(SUM(amount)::float8, 0) as total_zakat
              FROM zakat_records
              WHERE wallet_id = $1
-             AND deduction_date >= NOW() - INTERVAL '30 days'",
-            &[&wallet_id],
+             AND record_type = 'deduction'
+             AND deduction_date >= $2 AND deduction_date < $3",
+            &[&wallet_id, &period_start, &period_end],
         )
-        .await;
+        .await?;
 
     // Get current wallet balance
-    let wallet_result = client
+    let wallet_row = client
         .query_opt(
             "SELECT balance::float8 FROM wallets WHERE wallet_id = $1",
             &[&wallet_id],
         )
-        .await;
+        .await?;
 
     // Get total transactions count (all time)
-    let all_time_tx_result = client
+    let all_tx_row = client
         .query_one(
             "SELECT COUNT(*) FROM transactions WHERE sender_wallet_id = $1 OR receiver_wallet_id = $1",
             &[&wallet_id],
         )
-        .await;
-
-    match (result, zakat_result, wallet_result, all_time_tx_result) {
-        (Ok(row), Ok(zakat_row), Ok(wallet_row), Ok(all_tx_row)) => {
-            let transaction_count: i64 = row.get(0);
-            let total_sent: f64 = row.get::<_, Option<f64>>(1).unwrap_or(0.0);
-            let total_received: f64 = row.get::<_, Option<f64>>(2).unwrap_or(0.0);
-            let zakat_paid: f64 = zakat_row.get::<_, Option<f64>>(0).unwrap_or(0.0);
-            let current_balance: f64 = wallet_row.and_then(|r| r.get::<_, Option<f64>>(0)).unwrap_or(0.0);
-            let all_time_transactions: i64 = all_tx_row.get(0);
-
-            let report = serde_json::json!({
-                "wallet_id": wallet_id,
-                "period": "30_days",
-                "transaction_count": transaction_count,
-                "total_sent": total_sent,
-                "total_received": total_received,
-                "net_change": total_received - total_sent,
-                "zakat_paid": zakat_paid,
-                "current_balance": current_balance,
-                "all_time_transactions": all_time_transactions,
-            });
-
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(report),
-                message: None,
-            })
-        }
-        _ => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some("Failed to fetch report data".to_string()),
-        }),
-    }
+        .await?;
+
+    let transaction_count: i64 = row.get(0);
+    let total_sent: f64 = row.get::<_, Option<f64>>(1).unwrap_or(0.0);
+    let total_received: f64 = row.get::<_, Option<f64>>(2).unwrap_or(0.0);
+    let zakat_paid: f64 = zakat_row.get::<_, Option<f64>>(0).unwrap_or(0.0);
+    let current_balance: f64 = wallet_row.and_then(|r| r.get::<_, Option<f64>>(0)).unwrap_or(0.0);
+    let all_time_transactions: i64 = all_tx_row.get(0);
+
+    let report = serde_json::json!({
+        "wallet_id": wallet_id,
+        "period_start": period_start,
+        "period_end": period_end,
+        "transaction_count": transaction_count,
+        "total_sent": total_sent,
+        "total_received": total_received,
+        "net_change": total_received - total_sent,
+        "zakat_paid": zakat_paid,
+        "current_balance": current_balance,
+        "all_time_transactions": all_time_transactions,
+    });
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(report),
+        message: None,
+    }))
+}
+
+pub async fn get_analytics(pool: web::Data<DbPool>, _admin: RequireAdmin) -> Result<HttpResponse, ApiError> {
+    let client = pool.get().await?;
+
+    let blocks: i64 = client.query_one("SELECT COUNT(*) FROM blocks", &[]).await?.get(0);
+    let transactions: i64 = client.query_one("SELECT COUNT(*) FROM transactions", &[]).await?.get(0);
+    let wallets: i64 = client.query_one("SELECT COUNT(*) FROM wallets", &[]).await?.get(0);
+    let users: i64 = client.query_one("SELECT COUNT(*) FROM users", &[]).await?.get(0);
+
+    let analytics = serde_json::json!({
+        "total_blocks": blocks,
+        "total_transactions": transactions,
+        "total_wallets": wallets,
+        "total_users": users,
+    });
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(analytics),
+        message: None,
+    }))
 }
 
-pub async fn get_analytics(pool: web::Data<DbPool>) -> HttpResponse {
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
+pub async fn get_timeseries(
+    pool: web::Data<DbPool>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let metric = query
+        .get("metric")
+        .and_then(|m| TimeseriesMetric::parse(m))
+        .ok_or_else(|| ApiError::BadRequest("metric must be one of volume, tx_count, blocks".to_string()))?;
+
+    let interval = match query.get("interval") {
+        Some(i) => TimeseriesInterval::parse(i)
+            .ok_or_else(|| ApiError::BadRequest("interval must be day or hour".to_string()))?,
+        None => TimeseriesInterval::Day,
+    };
+
+    let to = match query.get("to") {
+        Some(t) => analytics_service::parse_range_bound(t)
+            .ok_or_else(|| ApiError::BadRequest("Invalid 'to' date".to_string()))?,
+        None => chrono::Utc::now(),
     };
 
-    let blocks_result = client.query_one("SELECT COUNT(*) FROM blocks", &[]).await;
-    let transactions_result = client.query_one("SELECT COUNT(*) FROM transactions", &[]).await;
-    let wallets_result = client.query_one("SELECT COUNT(*) FROM wallets", &[]).await;
-    let users_result = client.query_one("SELECT COUNT(*) FROM users", &[]).await;
-
-    match (blocks_result, transactions_result, wallets_result, users_result) {
-        (Ok(b), Ok(t), Ok(w), Ok(u)) => {
-            let analytics = serde_json::json!({
-                "total_blocks": b.get::<_, i64>(0),
-                "total_transactions": t.get::<_, i64>(0),
-                "total_wallets": w.get::<_, i64>(0),
-                "total_users": u.get::<_, i64>(0),
-            });
-
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(analytics),
-                message: None,
-            })
-        }
-        _ => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some("Failed to retrieve analytics".to_string()),
-        }),
-    }
+    // Default to the last 30 days or 48 hours
+    let from = match query.get("from") {
+        Some(f) => analytics_service::parse_range_bound(f)
+            .ok_or_else(|| ApiError::BadRequest("Invalid 'from' date".to_string()))?,
+        None => match interval {
+            TimeseriesInterval::Day => to - chrono::Duration::days(30),
+            TimeseriesInterval::Hour => to - chrono::Duration::hours(48),
+        },
+    };
+
+    analytics_service::validate_range(from, to, interval).map_err(ApiError::BadRequest)?;
+
+    let points = analytics_service::get_timeseries(&pool, metric, interval, from, to).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({
+            "metric": query.get("metric"),
+            "interval": interval.as_str(),
+            "from": from,
+            "to": to,
+            "points": points,
+        })),
+        message: None,
+    }))
 }
@@ -0,0 +1,30 @@
+use actix_web::{web, HttpResponse};
+use crate::database::DbPool;
+use crate::metrics::Metrics;
+
+/// Prometheus scrape endpoint; gauges backed by the database are refreshed on each scrape
+pub async fn get_metrics(pool: web::Data<DbPool>, metrics: web::Data<Metrics>) -> HttpResponse {
+    let status = pool.status();
+    metrics
+        .db_pool_in_use
+        .set(status.size.saturating_sub(status.available) as i64);
+
+    match pool.get().await {
+        Ok(client) => {
+            if let Ok(row) = client.query_one("SELECT COUNT(*) FROM blocks", &[]).await {
+                metrics.total_blocks.set(row.get::<_, i64>(0));
+            }
+            if let Ok(row) = client.query_one("SELECT COUNT(*) FROM pending_transactions", &[]).await {
+                metrics.pending_transactions.set(row.get::<_, i64>(0));
+            }
+        }
+        Err(e) => log::error!("Failed to refresh metrics from database: {}", e),
+    }
+
+    match metrics.render() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(body),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Failed to encode metrics: {}", e)),
+    }
+}
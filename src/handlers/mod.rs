@@ -3,65 +3,242 @@ pub mod wallet_handler;
 pub mod transaction_handler;
 pub mod blockchain_handler;
 pub mod logs_handler;
+pub mod ws_handler;
+pub mod metrics_handler;
+pub mod webhook_handler;
+pub mod admin_handler;
+pub mod crypto_handler;
+pub mod notification_handler;
 
-use actix_web::web;
+use actix_web::{error::JsonPayloadError, web, HttpRequest};
+use crate::config::{BodyLimits, WalletReadPolicy};
+use crate::errors::ApiError;
+use crate::services::auth_service::Claims;
+use uuid::Uuid;
+
+/// Resolve the user id from the request's Bearer token
+pub fn authenticate(req: &HttpRequest) -> Result<Uuid, ApiError> {
+    let claims = authenticate_claims(req)?;
+    Uuid::parse_str(&claims.sub).map_err(|_| ApiError::Unauthorized("Invalid user ID in token".to_string()))
+}
+
+/// Verify the request's Bearer token and return its claims, for handlers that also need the role
+pub fn authenticate_claims(req: &HttpRequest) -> Result<Claims, ApiError> {
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .ok_or_else(|| ApiError::Unauthorized("No authorization token provided".to_string()))?;
+
+    crate::services::auth_service::verify_token(token)
+        .map_err(|_| ApiError::Unauthorized("Invalid or expired token".to_string()))
+}
+
+/// Under [`WalletReadPolicy::require_auth`], only an admin or the owner of every wallet in
+/// `wallet_ids` may read them; in public mode anyone may
+pub async fn authorize_wallet_read(
+    pool: &crate::database::DbPool,
+    req: &actix_web::HttpRequest,
+    policy: &WalletReadPolicy,
+    wallet_ids: &[String],
+) -> Result<(), ApiError> {
+    if !policy.require_auth {
+        return Ok(());
+    }
+    let claims = authenticate_claims(req)?;
+    if claims.is_admin() {
+        return Ok(());
+    }
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::Unauthorized("Invalid user ID in token".to_string()))?;
+
+    let client = pool.get().await?;
+    let owned = crate::database::queries::count_owned_wallets(&client, user_id, wallet_ids).await?;
+    if owned < wallet_ids.len() as i64 {
+        return Err(ApiError::Forbidden("Wallet not owned by user".to_string()));
+    }
+    Ok(())
+}
+
+/// Largest JSON body the unauthenticated `/auth` routes accept, whatever the global limit
+const AUTH_JSON_LIMIT: usize = 8 * 1024;
+
+/// JSON extractor settings: bodies over `limit` bytes get `413`, other parse failures `400`,
+/// both in the usual error shape
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(move |err, _req| {
+            let api_error = match err {
+                JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+                    ApiError::PayloadTooLarge(format!("Request body exceeds {} bytes", limit))
+                }
+                other => ApiError::BadRequest(format!("Invalid JSON body: {}", other)),
+            };
+            api_error.into()
+        })
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig, limits: &BodyLimits) {
+    cfg.app_data(json_config(limits.json));
+    cfg.app_data(web::Data::new(*limits));
+    cfg.route("/metrics", web::get().to(metrics_handler::get_metrics));
 
-pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api")
+            .route("/ws", web::get().to(ws_handler::subscribe))
             .service(
                 web::scope("/auth")
+                    .app_data(json_config(AUTH_JSON_LIMIT.min(limits.json)))
                     .route("/register", web::post().to(auth_handler::register))
                     .route("/login", web::post().to(auth_handler::login))
                     .route("/send-otp", web::post().to(auth_handler::send_otp))
+                    .route("/resend-otp", web::post().to(auth_handler::resend_otp))
                     .route("/verify-otp", web::post().to(auth_handler::verify_otp))
                     .route("/profile", web::get().to(auth_handler::get_profile))
                     .route("/profile", web::put().to(auth_handler::update_profile))
+                    .route("/account", web::delete().to(auth_handler::delete_account))
             )
             .service(
                 web::scope("/wallet")
                     .route("/generate", web::post().to(wallet_handler::generate_wallet))
+                    .route("/balances", web::post().to(wallet_handler::get_balances))
+                    .route("/request/parse", web::post().to(wallet_handler::parse_payment_uri))
                     .route("/{wallet_id}", web::get().to(wallet_handler::get_wallet))
+                    .route("/{wallet_id}/summary", web::get().to(wallet_handler::get_wallet_summary))
                     .route("/{wallet_id}/balance", web::get().to(wallet_handler::get_balance))
                     .route("/{wallet_id}/utxos", web::get().to(wallet_handler::get_utxos))
+                    .route("/{wallet_id}/utxos/spent", web::get().to(wallet_handler::get_spent_utxos))
+                    .route("/{wallet_id}/consolidate", web::post().to(wallet_handler::consolidate_utxos))
+                    .route("/{wallet_id}/zakat-exempt", web::put().to(wallet_handler::set_zakat_exempt))
                     .route("/{wallet_id}/transactions", web::get().to(wallet_handler::get_transactions))
+                    .route("/{wallet_id}/transactions/search", web::get().to(wallet_handler::search_transactions))
+                    .route("/{wallet_id}/transactions.csv", web::get().to(wallet_handler::export_transactions_csv))
+                    .route("/{wallet_id}/qr", web::get().to(wallet_handler::get_wallet_qr))
+                    .route("/{wallet_id}/request", web::post().to(wallet_handler::create_payment_request))
             )
             .service(
                 web::scope("/transaction")
                     .route("/create", web::post().to(transaction_handler::create_transaction))
+                    .route("/payload", web::post().to(transaction_handler::get_signing_payload))
                     .route("/pending", web::get().to(transaction_handler::get_pending))
+                    .route("/mempool/stats", web::get().to(transaction_handler::get_mempool_stats))
                     .route("/{tx_hash}", web::get().to(transaction_handler::get_transaction))
+                    .route("/{tx_hash}/note", web::get().to(transaction_handler::get_note))
+            )
+            .service(
+                web::scope("/crypto")
+                    .route("/verify", web::post().to(crypto_handler::verify_signature))
             )
             .service(
                 web::scope("/blockchain")
                     .route("/blocks", web::get().to(blockchain_handler::get_blocks))
+                    .route("/blocks/range", web::get().to(blockchain_handler::get_blocks_range))
                     .route("/block/{index}", web::get().to(blockchain_handler::get_block))
+                    .route("/block/{index}/transactions", web::get().to(blockchain_handler::get_block_transactions))
                     .route("/validate", web::get().to(blockchain_handler::validate_chain))
+                    .route("/audit", web::get().to(blockchain_handler::audit))
+                    .route("/export", web::get().to(blockchain_handler::export_chain))
+                    .route("/import", web::post().to(blockchain_handler::import_chain))
                     .route("/mine", web::post().to(blockchain_handler::mine_block))
+                    .route("/generate/{n}", web::post().to(blockchain_handler::generate_blocks))
                     .route("/info", web::get().to(blockchain_handler::get_info))
+                    .route("/tip", web::get().to(blockchain_handler::get_tip))
                     .route("/mining-stats", web::get().to(blockchain_handler::get_mining_stats))
+                    .route("/supply", web::get().to(blockchain_handler::get_supply))
+                    .route("/richlist", web::get().to(blockchain_handler::get_richlist))
+                    .route("/search", web::get().to(blockchain_handler::search))
             )
             .service(
                 web::scope("/beneficiaries")
                     .route("", web::get().to(wallet_handler::get_beneficiaries))
                     .route("", web::post().to(wallet_handler::add_beneficiary))
+                    .route("/import", web::post().to(wallet_handler::import_beneficiaries))
                     .route("/{id}", web::delete().to(wallet_handler::delete_beneficiary))
             )
+            .service(
+                web::scope("/webhooks")
+                    .route("", web::get().to(webhook_handler::get_webhooks))
+                    .route("", web::post().to(webhook_handler::create_webhook))
+                    .route("/{id}", web::delete().to(webhook_handler::delete_webhook))
+            )
+            .service(
+                web::scope("/notifications")
+                    .route("/preferences", web::get().to(notification_handler::get_preferences))
+                    .route("/preferences", web::put().to(notification_handler::update_preferences))
+                    .route("/preferences", web::delete().to(notification_handler::reset_preferences))
+            )
             .service(
                 web::scope("/zakat")
-                    .route("/records", web::get().to(wallet_handler::get_zakat_records))
+                    .route("/records/{wallet_id}", web::get().to(wallet_handler::get_zakat_records))
                     .route("/pool", web::get().to(wallet_handler::get_zakat_pool))
                     .route("/trigger", web::post().to(wallet_handler::trigger_zakat))
+                    .route("/distribute", web::post().to(wallet_handler::distribute_zakat))
             )
             .service(
                 web::scope("/logs")
                     .route("/transaction", web::get().to(logs_handler::get_transaction_logs))
                     .route("/system", web::get().to(logs_handler::get_system_logs))
             )
+            .service(
+                web::scope("/admin")
+                    .route("/wallets", web::get().to(admin_handler::list_wallets))
+                    .route("/wallet/{wallet_id}/status", web::put().to(admin_handler::set_wallet_status))
+                    .route("/wallet/{wallet_id}/zakat-rate", web::put().to(admin_handler::set_zakat_rate))
+            )
             .service(
                 web::scope("/reports")
                     .route("/monthly/{wallet_id}", web::get().to(logs_handler::get_monthly_report))
                     .route("/analytics", web::get().to(logs_handler::get_analytics))
+                    .route("/timeseries", web::get().to(logs_handler::get_timeseries))
             )
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App, HttpResponse};
+
+    async fn echo(body: web::Json<serde_json::Value>) -> HttpResponse {
+        HttpResponse::Ok().json(body.into_inner())
+    }
+
+    #[actix_web::test]
+    async fn test_oversize_json_body_rejected() {
+        let app = test::init_service(
+            App::new()
+                .app_data(json_config(1024))
+                .route("/echo", web::post().to(echo))
+                .service(web::scope("/small").app_data(json_config(64)).route("/echo", web::post().to(echo))),
+        )
+        .await;
+        let body = |len: usize| serde_json::json!({ "note": "x".repeat(len) });
+        let post = |uri: &str, body: serde_json::Value| test::TestRequest::post().uri(uri).set_json(body).to_request();
+
+        let resp = test::call_service(&app, post("/echo", body(100))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = test::call_service(&app, post("/echo", body(2000))).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let error: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(error["success"], false);
+        assert_eq!(error["code"], "payload_too_large");
+        assert_eq!(error["message"], "Request body exceeds 1024 bytes");
+
+        // The scope's own limit wins over the app-wide one
+        let resp = test::call_service(&app, post("/small/echo", body(100))).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let malformed = test::TestRequest::post()
+            .uri("/echo")
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload("{not json")
+            .to_request();
+        let resp = test::call_service(&app, malformed).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let error: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(error["code"], "bad_request");
+    }
+}
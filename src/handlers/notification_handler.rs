@@ -0,0 +1,56 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::models::{ApiResponse, NotificationPreferences};
+use crate::database::DbPool;
+use crate::errors::ApiError;
+use crate::handlers::authenticate;
+use crate::services::notification_service;
+
+pub async fn get_preferences(pool: web::Data<DbPool>, req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    let user_id = authenticate(&req)?;
+    let client = pool.get().await?;
+
+    let preferences = notification_service::get_preferences(&client, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(preferences),
+        message: None,
+    }))
+}
+
+/// Replace the user's preferences; omitted fields take their defaults
+pub async fn update_preferences(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    body: web::Json<NotificationPreferences>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = authenticate(&req)?;
+    let preferences = body.into_inner();
+
+    if preferences.low_balance_threshold.is_some_and(|t| t <= 0) {
+        return Err(ApiError::BadRequest("low_balance_threshold must be positive".to_string()));
+    }
+
+    let client = pool.get().await?;
+    crate::database::queries::upsert_notification_preferences(&client, user_id, &preferences).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(preferences),
+        message: Some("Notification preferences updated".to_string()),
+    }))
+}
+
+/// Forget the user's saved preferences, going back to the defaults
+pub async fn reset_preferences(pool: web::Data<DbPool>, req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    let user_id = authenticate(&req)?;
+    let client = pool.get().await?;
+
+    crate::database::queries::delete_notification_preferences(&client, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(NotificationPreferences::default()),
+        message: Some("Notification preferences reset to defaults".to_string()),
+    }))
+}
@@ -1,120 +1,215 @@
-use actix_web::{web, HttpResponse};
-use crate::models::{ApiResponse, CreateTransactionRequest};
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::models::{ApiResponse, CreateTransactionRequest, TransactionPayloadRequest, TransactionView};
 use crate::database::DbPool;
+use crate::errors::ApiError;
+use crate::handlers::authenticate;
 use crate::services::transaction_service;
-use crate::config::Config;
+use crate::config::{ChainConfig, Config};
+use crate::utils::{from_units, to_units};
 
 pub async fn create_transaction(
     pool: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
     req: web::Json<CreateTransactionRequest>,
-) -> HttpResponse {
-    let config = match Config::from_env() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Config error: {}", e)),
-            });
-        }
+    http_req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let config = Config::from_env().map_err(|e| ApiError::Internal(format!("Config error: {}", e)))?;
+
+    // Optional Idempotency-Key lets clients safely retry after a timeout
+    let idempotency_key = match http_req.headers().get("Idempotency-Key") {
+        Some(value) => match value.to_str() {
+            Ok(key) if !key.trim().is_empty() && key.len() <= 255 => Some(key.trim().to_string()),
+            _ => {
+                return Err(ApiError::BadRequest(
+                    "Idempotency-Key must be 1-255 visible ASCII characters".to_string(),
+                ));
+            }
+        },
+        None => None,
     };
 
-    match transaction_service::create_transaction(&pool, req.into_inner(), &config.aes_key).await {
-        Ok(pending_tx) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(pending_tx),
-            message: Some("Transaction created successfully and added to pending pool".to_string()),
-        }),
-        Err(e) => HttpResponse::BadRequest().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(e.to_string()),
-        }),
-    }
+    let pending_tx = match idempotency_key {
+        Some(key) => transaction_service::create_transaction_idempotent(&pool, req.into_inner(), &chain, &config.aes_key, &key).await?,
+        None => transaction_service::create_transaction(&pool, req.into_inner(), &chain, &config.aes_key).await?,
+    };
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(pending_tx),
+        message: Some("Transaction created successfully and added to pending pool".to_string()),
+    }))
 }
 
-pub async fn get_pending(pool: web::Data<DbPool>) -> HttpResponse {
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+/// The canonical string the server signs for these fields, so clients can sign the exact bytes.
+/// The amount is rounded to whole units first, as `create_transaction` does.
+pub async fn get_signing_payload(body: web::Json<TransactionPayloadRequest>) -> Result<HttpResponse, ApiError> {
+    let payload = crate::crypto::create_transaction_payload(
+        &body.sender_wallet_id,
+        &body.receiver_wallet_id,
+        from_units(to_units(body.amount)),
+        body.timestamp,
+        &body.note,
+    );
 
-    match crate::database::queries::get_pending_transactions(&client).await {
-        Ok(transactions) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(transactions),
-            message: None,
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
-    }
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({ "payload": payload })),
+        message: None,
+    }))
+}
+
+pub async fn get_pending(pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let client = pool.get().await?;
+    let transactions: Vec<TransactionView> = crate::database::queries::get_pending_transactions(&client)
+        .await?
+        .into_iter()
+        .map(TransactionView::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(transactions),
+        message: None,
+    }))
+}
+
+/// Aggregate counts, value and fee spread of the pending pool, for fee estimation and dashboards
+pub async fn get_mempool_stats(pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let client = pool.get().await?;
+    let stats = crate::database::queries::get_mempool_stats(&client).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(stats),
+        message: None,
+    }))
 }
 
 pub async fn get_transaction(
     pool: web::Data<DbPool>,
     path: web::Path<String>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
+    let tx_hash = path.into_inner();
+    let client = pool.get().await?;
+
+    let transaction = transaction_service::find_transaction(&client, &tx_hash)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Transaction not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(TransactionView::from(transaction)),
+        message: None,
+    }))
+}
+
+/// Plaintext note for the transaction's receiver, decrypting it when it was sent as private
+pub async fn get_note(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
     let tx_hash = path.into_inner();
-    
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
+    let user_id = authenticate(&req)?;
+    let config = Config::from_env().map_err(|e| ApiError::Internal(format!("Config error: {}", e)))?;
+    let client = pool.get().await?;
+
+    let user = crate::database::queries::find_user_by_id(&client, user_id)
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized("User not found".to_string()))?;
+    let note = transaction_service::read_note(&client, &tx_hash, &user, &config.aes_key).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({ "note": note })),
+        message: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_signing_payload_matches_server() {
+        let app = test::init_service(App::new().route("/payload", web::post().to(get_signing_payload))).await;
+
+        for note in [None, Some("rent".to_string())] {
+            let req = test::TestRequest::post()
+                .uri("/payload")
+                .set_json(serde_json::json!({
+                    "sender_wallet_id": "sender",
+                    "receiver_wallet_id": "receiver",
+                    "amount": 12.5,
+                    "timestamp": 1733571000,
+                    "note": note,
+                }))
+                .to_request();
+            let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+            let expected = crate::crypto::create_transaction_payload("sender", "receiver", 12.5, 1733571000, &note);
+            assert_eq!(body["data"]["payload"], serde_json::json!(expected));
+            if note.is_none() {
+                assert_eq!(expected, "sender|receiver|12.5|1733571000|");
+            }
         }
-    };
+    }
 
-    let result = client
-        .query_opt(
-            "SELECT id, transaction_hash, sender_wallet_id, receiver_wallet_id, amount, note, 
-             signature, block_index, transaction_type, timestamp, created_at 
-             FROM transactions WHERE transaction_hash = $1",
-            &[&tx_hash],
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_mempool_stats_aggregate_pending_pool() {
+        let Some(pool) = crate::database::create_test_pool("mempool_stats").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .route("/mempool/stats", web::get().to(get_mempool_stats)),
         )
         .await;
 
-    match result {
-        Ok(Some(row)) => {
-            let transaction = crate::models::Transaction {
-                id: row.get(0),
-                transaction_hash: row.get(1),
-                sender_wallet_id: row.get(2),
-                receiver_wallet_id: row.get(3),
-                amount: row.get(4),
-                note: row.get(5),
-                signature: row.get(6),
-                block_index: row.get(7),
-                transaction_type: row.get(8),
-                timestamp: row.get(9),
-                created_at: row.get(10),
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, test::TestRequest::get().uri("/mempool/stats").to_request()).await;
+        assert_eq!(body["data"]["transaction_count"], serde_json::json!(0));
+        assert_eq!(body["data"]["total_fees"], serde_json::json!(0.0));
+        assert!(body["data"]["median_fee"].is_null());
+        assert!(body["data"]["oldest_pending_seconds"].is_null());
+
+        let client = pool.get().await.unwrap();
+        for (i, (amount, fee)) in [(1.0, 0.1), (2.5, 0.3), (4.0, 0.2), (0.5, 0.6)].into_iter().enumerate() {
+            let pending = crate::models::PendingTransaction {
+                id: uuid::Uuid::new_v4(),
+                transaction_hash: format!("{:064x}", i),
+                sender_wallet_id: "sender".to_string(),
+                receiver_wallet_id: "receiver".to_string(),
+                amount: to_units(amount),
+                fee: to_units(fee),
+                note: None,
+                note_encrypted: false,
+                signature: "sig".to_string(),
+                timestamp: 0,
+                created_at: chrono::Utc::now(),
             };
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(transaction),
-                message: None,
-            })
+            crate::database::queries::create_pending_transaction(&client, &pending).await.unwrap();
         }
-        Ok(None) => HttpResponse::NotFound().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some("Transaction not found".to_string()),
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
+        client
+            .execute(
+                "UPDATE pending_transactions SET created_at = NOW() - INTERVAL '90 seconds' WHERE transaction_hash = $1",
+                &[&format!("{:064x}", 2)],
+            )
+            .await
+            .unwrap();
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, test::TestRequest::get().uri("/mempool/stats").to_request()).await;
+        let stats = &body["data"];
+        assert_eq!(stats["transaction_count"], serde_json::json!(4));
+        assert_eq!(stats["total_amount"], serde_json::json!(8.0));
+        assert_eq!(stats["total_fees"], serde_json::json!(1.2));
+        assert_eq!(stats["min_fee"], serde_json::json!(0.1));
+        assert_eq!(stats["median_fee"], serde_json::json!(0.25));
+        assert_eq!(stats["max_fee"], serde_json::json!(0.6));
+        let age = stats["oldest_pending_seconds"].as_f64().unwrap();
+        assert!((90.0..120.0).contains(&age), "oldest age {}", age);
     }
 }
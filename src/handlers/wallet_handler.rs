@@ -1,508 +1,1132 @@
 use actix_web::{web, HttpResponse};
-use crate::models::{ApiResponse, AddBeneficiaryRequest};
+use crate::models::{ApiResponse, AddBeneficiaryRequest, BeneficiaryImportStatus, CursorPage, DistributeZakatRequest, NewPaymentRequest, OffsetPage, ParsePaymentUriRequest, TransactionView, WalletBalancesRequest, ZakatExemptionRequest};
 use crate::database::DbPool;
-use crate::services::{wallet_service, zakat_service};
-use crate::config::Config;
+use crate::errors::ApiError;
+use crate::handlers::{authenticate, authenticate_claims, authorize_wallet_read};
+use crate::middleware::RequireAdmin;
+use crate::services::{payment_request_service, transaction_service, wallet_service, zakat_service};
+use crate::services::transaction_service::TransferSide;
+use crate::config::{BodyLimits, ChainConfig, Config, WalletReadPolicy};
+use crate::utils::{from_units, to_units};
+use base64::{Engine as _, engine::general_purpose};
 use uuid::Uuid;
-use std::env;
-
-pub async fn generate_wallet(_pool: web::Data<DbPool>) -> HttpResponse {
-    let config = match Config::from_env() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Config error: {}", e)),
-            });
-        }
-    };
 
-    match wallet_service::generate_wallet_keypair(&config.aes_key) {
-        Ok(keypair) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(keypair),
-            message: Some("Wallet generated successfully".to_string()),
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(e.to_string()),
-        }),
+/// Most UTXOs one `/utxos` or `/utxos/spent` page may return
+const MAX_UTXO_PAGE: i64 = 500;
+/// Most transactions one `/transactions` page may return
+const MAX_TRANSACTION_PAGE: i64 = 500;
+
+pub async fn generate_wallet(_pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let config = Config::from_env().map_err(|e| ApiError::Internal(format!("Config error: {}", e)))?;
+    let keypair = wallet_service::generate_wallet_keypair(&config.aes_key)?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(keypair),
+        message: Some("Wallet generated successfully".to_string()),
+    }))
+}
+
+/// 404 for an unknown wallet, so its sub-resources aren't served as an empty 200
+async fn require_wallet_exists(pool: &DbPool, wallet_id: &str) -> Result<(), ApiError> {
+    if !wallet_service::wallet_exists(pool, wallet_id).await? {
+        return Err(ApiError::NotFound("Wallet not found".to_string()));
     }
+    Ok(())
 }
 
 pub async fn get_wallet(
     pool: web::Data<DbPool>,
     path: web::Path<String>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let wallet_id = path.into_inner();
-    
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+    let client = pool.get().await?;
 
-    match crate::database::queries::get_wallet(&client, &wallet_id).await {
-        Ok(Some(wallet)) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(wallet),
-            message: None,
-        }),
-        Ok(None) => HttpResponse::NotFound().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some("Wallet not found".to_string()),
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
-    }
+    let wallet = crate::database::queries::get_wallet(&client, &wallet_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Wallet not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(wallet),
+        message: None,
+    }))
+}
+
+/// Transaction totals and activity dates; kept apart from `get_wallet` since it aggregates
+pub async fn get_wallet_summary(
+    pool: web::Data<DbPool>,
+    policy: web::Data<WalletReadPolicy>,
+    req: actix_web::HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = path.into_inner();
+    authorize_wallet_read(&pool, &req, &policy, std::slice::from_ref(&wallet_id)).await?;
+    require_wallet_exists(&pool, &wallet_id).await?;
+    let client = pool.get().await?;
+
+    let summary = crate::database::queries::get_wallet_summary(&client, &wallet_id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(summary),
+        message: None,
+    }))
 }
 
 pub async fn get_balance(
     pool: web::Data<DbPool>,
+    policy: web::Data<WalletReadPolicy>,
+    req: actix_web::HttpRequest,
     path: web::Path<String>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let wallet_id = path.into_inner();
+    authorize_wallet_read(&pool, &req, &policy, std::slice::from_ref(&wallet_id)).await?;
+    let balance = wallet_service::get_wallet_balance(&pool, &wallet_id).await?;
 
-    match wallet_service::get_wallet_balance(&pool, &wallet_id).await {
-        Ok(balance) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(balance),
-            message: None,
-        }),
-        Err(e) => {
-            // Return 404 if wallet not found, otherwise 400
-            let mut status_code = if e.to_string().contains("Wallet not found") {
-                HttpResponse::NotFound()
-            } else {
-                HttpResponse::BadRequest()
-            };
-            status_code.json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(e.to_string()),
-            })
-        }
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(balance),
+        message: None,
+    }))
+}
+
+pub async fn get_balances(
+    pool: web::Data<DbPool>,
+    policy: web::Data<WalletReadPolicy>,
+    req: actix_web::HttpRequest,
+    body: web::Json<WalletBalancesRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut wallet_ids = body.into_inner().wallet_ids;
+    wallet_ids.sort();
+    wallet_ids.dedup();
+
+    if wallet_ids.is_empty() || wallet_ids.len() > wallet_service::MAX_BALANCE_BATCH {
+        return Err(ApiError::BadRequest(format!(
+            "wallet_ids must contain between 1 and {} wallet ids",
+            wallet_service::MAX_BALANCE_BATCH
+        )));
     }
+
+    authorize_wallet_read(&pool, &req, &policy, &wallet_ids).await?;
+
+    let balances = wallet_service::get_wallet_balances(&pool, &wallet_ids).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(balances),
+        message: None,
+    }))
 }
 
 pub async fn get_utxos(
     pool: web::Data<DbPool>,
+    policy: web::Data<WalletReadPolicy>,
+    req: actix_web::HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = path.into_inner();
+    authorize_wallet_read(&pool, &req, &policy, std::slice::from_ref(&wallet_id)).await?;
+    require_wallet_exists(&pool, &wallet_id).await?;
+    let client = pool.get().await?;
+
+    let limit = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(100i64).clamp(1, MAX_UTXO_PAGE);
+    let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0i64).max(0);
+
+    let (items, total) = crate::database::queries::get_unspent_utxos_page(&client, &wallet_id, limit, offset).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(OffsetPage { items, total, limit, offset }),
+        message: None,
+    }))
+}
+
+/// Spent outputs with when and by which transaction they were spent, most recent first
+pub async fn get_spent_utxos(
+    pool: web::Data<DbPool>,
+    policy: web::Data<WalletReadPolicy>,
+    req: actix_web::HttpRequest,
     path: web::Path<String>,
-) -> HttpResponse {
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
     let wallet_id = path.into_inner();
-    
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+    authorize_wallet_read(&pool, &req, &policy, std::slice::from_ref(&wallet_id)).await?;
+    require_wallet_exists(&pool, &wallet_id).await?;
+    let client = pool.get().await?;
 
-    match crate::database::queries::get_unspent_utxos(&client, &wallet_id).await {
-        Ok(utxos) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(utxos),
-            message: None,
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
+    let limit = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(100i64).clamp(1, MAX_UTXO_PAGE);
+    let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0i64).max(0);
+
+    let (items, total) = crate::database::queries::get_spent_utxos_page(&client, &wallet_id, limit, offset).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(OffsetPage { items, total, limit, offset }),
+        message: None,
+    }))
+}
+
+pub async fn consolidate_utxos(
+    pool: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+    req: actix_web::HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = path.into_inner();
+    let user_id = authenticate(&req)?;
+    let config = Config::from_env().map_err(|e| ApiError::Internal(format!("Config error: {}", e)))?;
+
+    let client = pool.get().await?;
+
+    // Only the wallet owner may consolidate it
+    match crate::database::queries::get_wallet(&client, &wallet_id).await? {
+        Some(wallet) if wallet.user_id == Some(user_id) => {}
+        Some(_) => return Err(ApiError::Forbidden("Wallet not owned by user".to_string())),
+        None => return Err(ApiError::NotFound("Wallet not found".to_string())),
+    }
+
+    let owner = crate::database::queries::find_user_by_id(&client, user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    drop(client);
+
+    let pending_tx = transaction_service::consolidate_dust(&pool, &wallet_id, &owner, &chain, &config.aes_key).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(pending_tx),
+        message: Some("Consolidation transaction added to pending pool".to_string()),
+    }))
+}
+
+/// Exempt a wallet from zakat or make it liable again. Allowed for the wallet owner and admins.
+pub async fn set_zakat_exempt(
+    pool: web::Data<DbPool>,
+    req: actix_web::HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<ZakatExemptionRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = path.into_inner();
+    let claims = authenticate_claims(&req)?;
+    let exempt = body.into_inner().exempt;
+
+    let client = pool.get().await?;
+
+    match crate::database::queries::get_wallet(&client, &wallet_id).await? {
+        Some(_) if claims.is_admin() => {}
+        Some(wallet) if wallet.user_id.map(|id| id.to_string()) == Some(claims.sub.clone()) => {}
+        Some(_) => return Err(ApiError::Forbidden("Wallet not owned by user".to_string())),
+        None => return Err(ApiError::NotFound("Wallet not found".to_string())),
     }
+
+    let wallet = crate::database::queries::set_zakat_exempt(&client, &wallet_id, exempt)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Wallet not found".to_string()))?;
+
+    log::info!("Wallet {} zakat exemption set to {} by {}", wallet_id, exempt, claims.email);
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(wallet),
+        message: Some(if exempt { "Wallet is exempt from zakat" } else { "Wallet is liable for zakat" }.to_string()),
+    }))
 }
 
 pub async fn get_transactions(
     pool: web::Data<DbPool>,
+    policy: web::Data<WalletReadPolicy>,
+    req: actix_web::HttpRequest,
     path: web::Path<String>,
     query: web::Query<std::collections::HashMap<String, String>>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let wallet_id = path.into_inner();
-    let limit = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(50);
+    authorize_wallet_read(&pool, &req, &policy, std::slice::from_ref(&wallet_id)).await?;
+    require_wallet_exists(&pool, &wallet_id).await?;
+    let limit = query
+        .get("limit")
+        .and_then(|l| l.parse::<i64>().ok())
+        .unwrap_or(50)
+        .clamp(1, MAX_TRANSACTION_PAGE);
     let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0);
-    
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
 
-    match crate::database::queries::get_wallet_transactions(&client, &wallet_id, limit, offset).await {
-        Ok(transactions) => HttpResponse::Ok().json(ApiResponse {
+    let client = pool.get().await?;
+
+    // Cursor mode: `?before=<cursor>` using the `next_cursor` of the previous page
+    if let Some(before) = query.get("before") {
+        let before = if before.is_empty() {
+            None
+        } else {
+            Some(
+                crate::utils::decode_tx_cursor(before)
+                    .ok_or_else(|| ApiError::BadRequest("Invalid cursor".to_string()))?,
+            )
+        };
+
+        let transactions =
+            crate::database::queries::get_wallet_transactions_before(&client, &wallet_id, before, limit).await?;
+        let next_cursor = if transactions.len() as i64 == limit {
+            transactions
+                .last()
+                .map(|tx| crate::utils::encode_tx_cursor(tx.created_at, tx.id))
+        } else {
+            None
+        };
+
+        let items = transactions.into_iter().map(TransactionView::from).collect();
+
+        return Ok(HttpResponse::Ok().json(ApiResponse {
             success: true,
-            data: Some(transactions),
+            data: Some(CursorPage::<TransactionView> { items, next_cursor }),
             message: None,
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
+        }));
     }
+
+    let transactions: Vec<TransactionView> =
+        crate::database::queries::get_wallet_transactions(&client, &wallet_id, limit, offset)
+            .await?
+            .into_iter()
+            .map(TransactionView::from)
+            .collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(transactions),
+        message: None,
+    }))
 }
 
-pub async fn get_beneficiaries(
+/// Transactions of a wallet whose note contains `?q=` (case-insensitive)
+pub async fn search_transactions(
     pool: web::Data<DbPool>,
+    policy: web::Data<WalletReadPolicy>,
     req: actix_web::HttpRequest,
-) -> HttpResponse {
-    // Extract user_id from JWT token
-    let token = match req.headers().get("Authorization") {
-        Some(h) => match h.to_str() {
-            Ok(t) => t.trim_start_matches("Bearer ").to_string(),
-            Err(_) => {
-                return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                    success: false,
-                    data: None,
-                    message: Some("Invalid authorization header".to_string()),
-                });
-            }
-        },
-        None => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("No authorization token provided".to_string()),
-            });
-        }
+    path: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = path.into_inner();
+    authorize_wallet_read(&pool, &req, &policy, std::slice::from_ref(&wallet_id)).await?;
+    require_wallet_exists(&pool, &wallet_id).await?;
+    let q = match query.get("q") {
+        Some(q) if !q.trim().is_empty() => q.trim(),
+        _ => return Err(ApiError::BadRequest("q is required".to_string())),
     };
+    let limit = query
+        .get("limit")
+        .and_then(|l| l.parse::<i64>().ok())
+        .unwrap_or(50)
+        .clamp(1, MAX_TRANSACTION_PAGE);
+    let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0i64).max(0);
 
-    let claims = match crate::services::auth_service::verify_token(&token) {
-        Ok(c) => c,
-        Err(_) => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Invalid or expired token".to_string()),
-            });
-        }
-    };
+    let client = pool.get().await?;
+    let transactions: Vec<TransactionView> =
+        crate::database::queries::search_wallet_transactions(&client, &wallet_id, q, limit, offset)
+            .await?
+            .into_iter()
+            .map(TransactionView::from)
+            .collect();
 
-    let user_id = match uuid::Uuid::parse_str(&claims.sub) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Invalid user ID in token".to_string()),
-            });
-        }
-    };
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(transactions),
+        message: None,
+    }))
+}
 
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+pub async fn export_transactions_csv(
+    pool: web::Data<DbPool>,
+    policy: web::Data<WalletReadPolicy>,
+    req: actix_web::HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = path.into_inner();
+    authorize_wallet_read(&pool, &req, &policy, std::slice::from_ref(&wallet_id)).await?;
+    require_wallet_exists(&pool, &wallet_id).await?;
+    let limit = query
+        .get("limit")
+        .and_then(|l| l.parse::<i64>().ok())
+        .unwrap_or(50)
+        .clamp(1, MAX_TRANSACTION_PAGE);
+    let offset = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0i64).max(0);
 
-    match crate::database::queries::get_user_beneficiaries(&client, user_id).await {
-        Ok(beneficiaries) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(beneficiaries),
-            message: None,
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Failed to fetch beneficiaries: {}", e)),
-        }),
-    }
+    let client = pool.get().await?;
+    let transactions = crate::database::queries::get_wallet_transactions(&client, &wallet_id, limit, offset).await?;
+
+    let body = wallet_service::transactions_to_csv(&wallet_id, &transactions)
+        .map_err(|e| ApiError::Internal(format!("Failed to render CSV: {}", e)))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header((
+            actix_web::http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"transactions_{}.csv\"", wallet_id),
+        ))
+        .body(body))
 }
 
-pub async fn add_beneficiary(
+/// QR code for receiving into a wallet; `?amount=` and `?note=` turn it into a payment request
+pub async fn get_wallet_qr(
     pool: web::Data<DbPool>,
-    req: actix_web::HttpRequest,
-    body: web::Json<AddBeneficiaryRequest>,
-) -> HttpResponse {
-    // Extract user_id from JWT token
-    let token = match req.headers().get("Authorization") {
-        Some(h) => match h.to_str() {
-            Ok(t) => t.trim_start_matches("Bearer ").to_string(),
-            Err(_) => {
-                return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                    success: false,
-                    data: None,
-                    message: Some("Invalid authorization header".to_string()),
-                });
-            }
+    path: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = path.into_inner();
+    require_wallet_exists(&pool, &wallet_id).await?;
+
+    let amount = match query.get("amount") {
+        Some(a) => match a.parse::<f64>() {
+            Ok(a) if a.is_finite() && a > 0.0 => Some(to_units(a)),
+            _ => return Err(ApiError::BadRequest("amount must be a positive number".to_string())),
         },
-        None => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("No authorization token provided".to_string()),
-            });
-        }
+        None => None,
     };
+    let payload = wallet_service::payment_uri(&wallet_id, amount, query.get("note").map(String::as_str));
 
-    let claims = match crate::services::auth_service::verify_token(&token) {
-        Ok(c) => c,
-        Err(_) => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Invalid or expired token".to_string()),
-            });
-        }
-    };
+    match query.get("format").map(String::as_str) {
+        None | Some("png") => Ok(HttpResponse::Ok()
+            .content_type("image/png")
+            .body(wallet_service::render_qr_png(&payload)?)),
+        Some("svg") => Ok(HttpResponse::Ok()
+            .content_type("image/svg+xml")
+            .body(wallet_service::render_qr_svg(&payload)?)),
+        Some(_) => Err(ApiError::BadRequest("format must be png or svg".to_string())),
+    }
+}
 
-    let user_id = match uuid::Uuid::parse_str(&claims.sub) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Invalid user ID in token".to_string()),
-            });
-        }
-    };
+/// Create a signed payment request for one of the caller's wallets, returned as a
+/// `blockwallet:` URI and a QR code of that URI
+pub async fn create_payment_request(
+    pool: web::Data<DbPool>,
+    req: actix_web::HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<NewPaymentRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = path.into_inner();
+    let user_id = authenticate(&req)?;
+    let config = Config::from_env().map_err(|e| ApiError::Internal(format!("Config error: {}", e)))?;
 
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+    let client = pool.get().await?;
 
-    // Verify the beneficiary wallet exists
-    match crate::database::queries::get_wallet(&client, &body.beneficiary_wallet_id).await {
-        Ok(None) => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Beneficiary wallet not found".to_string()),
-            });
-        }
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Failed to verify wallet: {}", e)),
-            });
-        }
-        Ok(Some(_)) => {}
+    // Only the wallet owner may ask to be paid into it
+    match crate::database::queries::get_wallet(&client, &wallet_id).await? {
+        Some(wallet) if wallet.user_id == Some(user_id) => {}
+        Some(_) => return Err(ApiError::Forbidden("Wallet not owned by user".to_string())),
+        None => return Err(ApiError::NotFound("Wallet not found".to_string())),
     }
+    drop(client);
+
+    let body = body.into_inner();
+    if !body.amount.is_finite() {
+        return Err(ApiError::BadRequest("Amount must be positive".to_string()));
+    }
+    let request = payment_request_service::create_payment_request(
+        &pool,
+        &wallet_id,
+        to_units(body.amount),
+        body.note,
+        body.expires_in_seconds,
+    )
+    .await?;
+
+    let uri = payment_request_service::request_uri(&request, &config.payment_request_secret).to_uri();
+    let qr_code = format!(
+        "data:image/png;base64,{}",
+        general_purpose::STANDARD.encode(wallet_service::render_qr_png(&uri)?)
+    );
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({
+            "request": request,
+            "uri": uri,
+            "qr_code": qr_code,
+        })),
+        message: Some("Payment request created".to_string()),
+    }))
+}
+
+/// Decode a `blockwallet:` URI for clients, reporting whether this server signed it and
+/// whether it has expired
+pub async fn parse_payment_uri(body: web::Json<ParsePaymentUriRequest>) -> Result<HttpResponse, ApiError> {
+    let config = Config::from_env().map_err(|e| ApiError::Internal(format!("Config error: {}", e)))?;
+    let parsed = payment_request_service::parse_uri(&body.uri)?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({
+            "wallet_id": parsed.wallet_id,
+            "amount": parsed.amount.map(from_units),
+            "note": parsed.note,
+            "expires": parsed.expires,
+            "request_id": parsed.request_id,
+            "signature_valid": parsed.verify(&config.payment_request_secret),
+            "expired": parsed.is_expired(chrono::Utc::now().timestamp()),
+        })),
+        message: None,
+    }))
+}
+
+pub async fn get_beneficiaries(
+    pool: web::Data<DbPool>,
+    req: actix_web::HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = authenticate(&req)?;
+    let client = pool.get().await?;
 
-    match crate::database::queries::add_beneficiary(
+    let beneficiaries = crate::database::queries::get_user_beneficiaries(&client, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(beneficiaries),
+        message: None,
+    }))
+}
+
+pub async fn add_beneficiary(
+    pool: web::Data<DbPool>,
+    req: actix_web::HttpRequest,
+    body: web::Json<AddBeneficiaryRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = authenticate(&req)?;
+    let client = pool.get().await?;
+
+    // Beneficiaries are future receivers, so they must exist already
+    transaction_service::require_wallet(&client, &body.beneficiary_wallet_id, TransferSide::Receiver).await?;
+
+    let beneficiary = crate::database::queries::add_beneficiary(
         &client,
         user_id,
         &body.beneficiary_wallet_id,
         body.nickname.clone(),
     )
-    .await
-    {
-        Ok(beneficiary) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(beneficiary),
-            message: Some("Beneficiary added successfully".to_string()),
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Failed to add beneficiary: {}", e)),
-        }),
+    .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(beneficiary),
+        message: Some("Beneficiary added successfully".to_string()),
+    }))
+}
+
+/// Add beneficiaries in bulk from a `wallet_id,nickname` CSV body
+pub async fn import_beneficiaries(
+    pool: web::Data<DbPool>,
+    limits: web::Data<BodyLimits>,
+    req: actix_web::HttpRequest,
+    payload: web::Payload,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = authenticate(&req)?;
+
+    let body = payload
+        .to_bytes_limited(limits.csv)
+        .await
+        .map_err(|_| ApiError::PayloadTooLarge(format!("CSV larger than {} bytes", limits.csv)))?
+        .map_err(|e| ApiError::BadRequest(format!("Failed to read CSV: {}", e)))?;
+    let body = std::str::from_utf8(&body)
+        .map_err(|_| ApiError::BadRequest("CSV is not valid UTF-8".to_string()))?;
+
+    let rows = body.lines().filter(|l| !l.trim().is_empty()).count();
+    if rows == 0 || rows > wallet_service::MAX_BENEFICIARY_IMPORT + 1 {
+        return Err(ApiError::BadRequest(format!(
+            "CSV must contain between 1 and {} rows",
+            wallet_service::MAX_BENEFICIARY_IMPORT
+        )));
     }
+
+    let results = wallet_service::import_beneficiaries(&pool, user_id, body).await?;
+    let added = results
+        .iter()
+        .filter(|r| r.status == BeneficiaryImportStatus::Added)
+        .count();
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(results),
+        message: Some(format!("Imported {} beneficiaries", added)),
+    }))
 }
 
 pub async fn delete_beneficiary(
     pool: web::Data<DbPool>,
     req: actix_web::HttpRequest,
     path: web::Path<Uuid>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let beneficiary_id = path.into_inner();
+    let user_id = authenticate(&req)?;
+    let client = pool.get().await?;
 
-    // Extract user_id from JWT token
-    let token = match req.headers().get("Authorization") {
-        Some(h) => match h.to_str() {
-            Ok(t) => t.trim_start_matches("Bearer ").to_string(),
-            Err(_) => {
-                return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                    success: false,
-                    data: None,
-                    message: Some("Invalid authorization header".to_string()),
-                });
-            }
-        },
-        None => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("No authorization token provided".to_string()),
-            });
-        }
-    };
+    let rows = crate::database::queries::delete_beneficiary(&client, beneficiary_id, user_id).await?;
+    if rows == 0 {
+        return Err(ApiError::NotFound("Beneficiary not found or not owned by user".to_string()));
+    }
 
-    let claims = match crate::services::auth_service::verify_token(&token) {
-        Ok(c) => c,
-        Err(_) => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Invalid or expired token".to_string()),
-            });
-        }
-    };
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({"deleted": rows})),
+        message: Some("Beneficiary deleted successfully".to_string()),
+    }))
+}
 
-    let user_id = match uuid::Uuid::parse_str(&claims.sub) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("Invalid user ID in token".to_string()),
-            });
-        }
-    };
+/// A wallet's zakat history. Allowed for the wallet owner and admins.
+pub async fn get_zakat_records(
+    pool: web::Data<DbPool>,
+    req: actix_web::HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = path.into_inner();
+    let claims = authenticate_claims(&req)?;
 
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
-        }
-    };
+    let client = pool.get().await?;
 
-    match crate::database::queries::delete_beneficiary(&client, beneficiary_id, user_id).await {
-        Ok(rows) if rows > 0 => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(serde_json::json!({"deleted": rows})),
-            message: Some("Beneficiary deleted successfully".to_string()),
-        }),
-        Ok(_) => HttpResponse::NotFound().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some("Beneficiary not found or not owned by user".to_string()),
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Failed to delete beneficiary: {}", e)),
-        }),
+    match crate::database::queries::get_wallet(&client, &wallet_id).await? {
+        Some(_) if claims.is_admin() => {}
+        Some(wallet) if wallet.user_id.map(|id| id.to_string()) == Some(claims.sub.clone()) => {}
+        Some(_) => return Err(ApiError::Forbidden("Wallet not owned by user".to_string())),
+        None => return Err(ApiError::NotFound("Wallet not found".to_string())),
     }
+
+    let rows = client
+        .query(
+            "SELECT id, wallet_id, amount::float8, transaction_hash, deduction_date, record_type, created_at, 
+                    balance_at_deduction::float8, rate::float8 
+             FROM zakat_records WHERE wallet_id = $1 ORDER BY deduction_date DESC",
+            &[&wallet_id],
+        )
+        .await?;
+
+    let records: Vec<crate::models::ZakatRecord> = rows
+        .iter()
+        .map(|row| crate::models::ZakatRecord {
+            id: row.get(0),
+            wallet_id: row.get(1),
+            amount: row.get(2),
+            transaction_hash: row.get(3),
+            deduction_date: row.get(4),
+            record_type: row.get(5),
+            balance_at_deduction: row.get(7),
+            rate: row.get(8),
+            created_at: row.get(6),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(records),
+        message: None,
+    }))
 }
 
-pub async fn get_zakat_records(
+pub async fn get_zakat_pool(pool: web::Data<DbPool>, chain: web::Data<ChainConfig>) -> Result<HttpResponse, ApiError> {
+    let balance = wallet_service::get_wallet_balance(&pool, &chain.zakat_pool_wallet_id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(balance),
+        message: None,
+    }))
+}
+
+pub async fn trigger_zakat(
     pool: web::Data<DbPool>,
-    query: web::Query<std::collections::HashMap<String, String>>,
-) -> HttpResponse {
-    let wallet_id = match query.get("wallet_id") {
-        Some(id) => id,
-        None => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some("wallet_id is required".to_string()),
-            });
-        }
+    chain: web::Data<ChainConfig>,
+    admin: RequireAdmin,
+) -> Result<HttpResponse, ApiError> {
+    log::info!("Manual zakat deduction triggered by {}", admin.0.email);
+
+    zakat_service::trigger_zakat_deduction(&pool, &chain)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Zakat deduction failed: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({"message": "Zakat deduction triggered"})),
+        message: Some("Zakat deduction process completed".to_string()),
+    }))
+}
+
+pub async fn distribute_zakat(
+    pool: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+    admin: RequireAdmin,
+    body: Option<web::Json<DistributeZakatRequest>>,
+) -> Result<HttpResponse, ApiError> {
+    log::info!("Zakat pool distribution requested by {}", admin.0.email);
+
+    let recipients: Vec<(String, f64)> = match body.and_then(|b| b.into_inner().recipients) {
+        Some(list) => list.into_iter().map(|r| (r.wallet_id, r.weight)).collect(),
+        None => zakat_service::configured_recipients(),
     };
 
-    let client = match pool.get().await {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                message: Some(format!("Database error: {}", e)),
-            });
+    let transactions = zakat_service::distribute_zakat_pool(&pool, &chain, &recipients)
+        .await
+        .map_err(|e| {
+            if e.downcast_ref::<zakat_service::DistributionError>().is_some() {
+                ApiError::BadRequest(e.to_string())
+            } else {
+                ApiError::Internal(format!("Zakat distribution failed: {}", e))
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(transactions),
+        message: Some("Zakat pool distributed".to_string()),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_wallet_qr_renders_png() {
+        let pool = match crate::database::create_test_pool("wallet_qr").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let user = crate::database::register_test_user(&pool, "qr@example.com", "1").await;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .route("/wallet/{wallet_id}/qr", web::get().to(get_wallet_qr)),
+        )
+        .await;
+
+        let uri = format!("/wallet/{}/qr?amount=2.5&note=lunch", user.wallet_id);
+        let resp = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "image/png");
+        let body = test::read_body(resp).await;
+        assert!(body.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        let uri = format!("/wallet/{}/qr?format=svg", user.wallet_id);
+        let resp = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(resp.headers().get("content-type").unwrap(), "image/svg+xml");
+
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/wallet/missing/qr").to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_zakat_exemption_owner_or_admin() {
+        let pool = match crate::database::create_test_pool("zakat_exempt_auth").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let owner = crate::database::register_test_user(&pool, "owner@example.com", "1").await;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .route("/wallet/{wallet_id}/zakat-exempt", web::put().to(set_zakat_exempt)),
+        )
+        .await;
+        let uri = format!("/wallet/{}/zakat-exempt", owner.wallet_id);
+        let put = |token: String, exempt: bool| test::TestRequest::put()
+            .uri(&uri)
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(serde_json::json!({ "exempt": exempt }))
+            .to_request();
+        let exempt = || async {
+            let client = pool.get().await.unwrap();
+            crate::database::queries::get_wallet(&client, &owner.wallet_id).await.unwrap().unwrap().zakat_exempt
+        };
+
+        let owner_token = crate::services::auth_service::generate_token(&owner.id.to_string(), &owner.email, "user").unwrap();
+        let stranger = crate::services::auth_service::generate_token(&Uuid::new_v4().to_string(), "x@example.com", "user").unwrap();
+        let admin = crate::services::auth_service::generate_token(&Uuid::new_v4().to_string(), "admin@example.com", "admin").unwrap();
+
+        let resp = test::call_service(&app, put(owner_token, true)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        assert!(exempt().await);
+
+        let resp = test::call_service(&app, put(stranger, false)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+        assert!(exempt().await);
+
+        let resp = test::call_service(&app, put(admin, false)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        assert!(!exempt().await);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_zakat_records_owner_or_admin() {
+        let Some(pool) = crate::database::create_test_pool("zakat_records_auth").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let owner = crate::database::register_test_user(&pool, "zakat-owner@example.com", "1").await;
+        pool.get().await.unwrap()
+            .execute(
+                "INSERT INTO zakat_records (wallet_id, amount, deduction_date, balance_at_deduction, rate)
+                 VALUES ($1, 2.5, NOW(), 100, 2.5)",
+                &[&owner.wallet_id],
+            )
+            .await
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .route("/zakat/records/{wallet_id}", web::get().to(get_zakat_records)),
+        )
+        .await;
+        let uri = format!("/zakat/records/{}", owner.wallet_id);
+        let get = |token: Option<String>| {
+            let mut req = test::TestRequest::get().uri(&uri);
+            if let Some(token) = token {
+                req = req.insert_header(("Authorization", format!("Bearer {}", token)));
+            }
+            req.to_request()
+        };
+
+        let owner_token = crate::services::auth_service::generate_token(&owner.id.to_string(), &owner.email, "user").unwrap();
+        let stranger = crate::services::auth_service::generate_token(&Uuid::new_v4().to_string(), "x@example.com", "user").unwrap();
+        let admin = crate::services::auth_service::generate_token(&Uuid::new_v4().to_string(), "admin@example.com", "admin").unwrap();
+
+        let resp = test::call_service(&app, get(Some(owner_token))).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["success"], true);
+        assert_eq!(body["data"][0]["wallet_id"], owner.wallet_id.as_str());
+        assert_eq!(body["data"][0]["amount"], 2.5);
+
+        let resp = test::call_service(&app, get(Some(stranger))).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+        let resp = test::call_service(&app, get(None)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let resp = test::call_service(&app, get(Some(admin))).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_wallet_reads_public_or_owner_only() {
+        let Some(pool) = crate::database::create_test_pool("wallet_read_policy").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let owner = crate::database::register_test_user(&pool, "private@example.com", "1").await;
+        let client = pool.get().await.unwrap();
+        crate::database::queries::create_utxo(&client, &owner.wallet_id, to_units(3.0), "funding", 0).await.unwrap();
+        drop(client);
+
+        let owner_token = crate::services::auth_service::generate_token(&owner.id.to_string(), &owner.email, "user").unwrap();
+        let stranger = crate::services::auth_service::generate_token(&Uuid::new_v4().to_string(), "x@example.com", "user").unwrap();
+        let admin = crate::services::auth_service::generate_token(&Uuid::new_v4().to_string(), "admin@example.com", "admin").unwrap();
+
+        let mut paths = ["balance", "utxos", "transactions"].map(|p| format!("/wallet/{}/{}", owner.wallet_id, p)).to_vec();
+        paths.push(format!("/logs/transaction?wallet_id={}", owner.wallet_id));
+        paths.push(format!("/logs/monthly/{}", owner.wallet_id));
+        paths.push(format!("/ws?wallet_id={}", owner.wallet_id));
+        let get = |uri: &str, token: Option<&String>| {
+            let mut req = test::TestRequest::get().uri(uri);
+            if uri.starts_with("/ws") {
+                req = req
+                    .insert_header(("Upgrade", "websocket"))
+                    .insert_header(("Connection", "Upgrade"))
+                    .insert_header(("Sec-WebSocket-Version", "13"))
+                    .insert_header(("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="));
+            }
+            if let Some(token) = token {
+                req = req.insert_header(("Authorization", format!("Bearer {}", token)));
+            }
+            req.to_request()
+        };
+        let batch = |token: Option<&String>| {
+            let mut req = test::TestRequest::post()
+                .uri("/wallet/balances")
+                .set_json(serde_json::json!({ "wallet_ids": [owner.wallet_id] }));
+            if let Some(token) = token {
+                req = req.insert_header(("Authorization", format!("Bearer {}", token)));
+            }
+            req.to_request()
+        };
+
+        for require_auth in [false, true] {
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(pool.clone()))
+                    .app_data(web::Data::new(WalletReadPolicy { require_auth }))
+                    .app_data(web::Data::new(crate::events::create_event_bus()))
+                    .route("/logs/transaction", web::get().to(crate::handlers::logs_handler::get_transaction_logs))
+                    .route("/logs/monthly/{wallet_id}", web::get().to(crate::handlers::logs_handler::get_monthly_report))
+                    .route("/ws", web::get().to(crate::handlers::ws_handler::subscribe))
+                    .route("/wallet/balances", web::post().to(get_balances))
+                    .route("/wallet/{wallet_id}/balance", web::get().to(get_balance))
+                    .route("/wallet/{wallet_id}/utxos", web::get().to(get_utxos))
+                    .route("/wallet/{wallet_id}/transactions", web::get().to(get_transactions)),
+            )
+            .await;
+            let status = |resp: actix_web::dev::ServiceResponse| resp.status().as_u16();
+
+            for uri in &paths {
+                // A WebSocket handshake that is let through switches protocols
+                let ok = if uri.starts_with("/ws") { 101 } else { 200 };
+                let anonymous = status(test::call_service(&app, get(uri, None)).await);
+                let other = status(test::call_service(&app, get(uri, Some(&stranger))).await);
+                if require_auth {
+                    assert_eq!((anonymous, other), (401, 403), "{}", uri);
+                } else {
+                    assert_eq!((anonymous, other), (ok, ok), "{}", uri);
+                }
+                assert_eq!(status(test::call_service(&app, get(uri, Some(&owner_token))).await), ok, "{}", uri);
+                assert_eq!(status(test::call_service(&app, get(uri, Some(&admin))).await), ok, "{}", uri);
+            }
+
+            let page: serde_json::Value = test::call_and_read_body_json(
+                &app,
+                get(&format!("{}?limit=1", paths[1]), Some(&owner_token)),
+            )
+            .await;
+            assert_eq!(page["data"]["total"], 1);
+            assert_eq!(page["data"]["items"][0]["amount"], 3.0);
+
+            let expected = if require_auth { 403 } else { 200 };
+            assert_eq!(status(test::call_service(&app, batch(Some(&stranger))).await), expected);
+            assert_eq!(status(test::call_service(&app, batch(Some(&owner_token))).await), 200);
         }
-    };
+    }
 
-    let result = client
-        .query(
-            "SELECT id, wallet_id, amount::float8, transaction_hash, deduction_date, created_at 
-             FROM zakat_records WHERE wallet_id = $1 ORDER BY deduction_date DESC",
-            &[&wallet_id],
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_search_transactions_by_note() {
+        let pool = match crate::database::create_test_pool("tx_note_search").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        client
+            .batch_execute(
+                "INSERT INTO wallets (wallet_id) VALUES ('alice'), ('bob'), ('carol');
+                 INSERT INTO transactions (transaction_hash, sender_wallet_id, receiver_wallet_id, amount, note, signature, timestamp) VALUES
+                     ('t1', 'alice', 'bob', 1, 'March Rent', 'sig', 1),
+                     ('t2', 'alice', 'bob', 2, 'groceries', 'sig', 2),
+                     ('t3', 'carol', 'bob', 3, 'rent share', 'sig', 3),
+                     ('t4', 'bob', 'alice', 4, '100% of rent', 'sig', 4);",
+            )
+            .await
+            .unwrap();
+        drop(client);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(WalletReadPolicy::default()))
+                .route("/wallet/{wallet_id}/transactions/search", web::get().to(search_transactions)),
         )
         .await;
 
-    match result {
-        Ok(rows) => {
-            let records: Vec<crate::models::ZakatRecord> = rows
+        let search = |q: &str| test::TestRequest::get()
+            .uri(&format!("/wallet/alice/transactions/search?q={}", q))
+            .to_request();
+        let hashes = |body: serde_json::Value| -> Vec<String> {
+            let mut hashes: Vec<String> = body["data"]
+                .as_array()
+                .unwrap()
                 .iter()
-                .map(|row| crate::models::ZakatRecord {
-                    id: row.get(0),
-                    wallet_id: row.get(1),
-                    amount: row.get(2),
-                    transaction_hash: row.get(3),
-                    deduction_date: row.get(4),
-                    created_at: row.get(5),
-                })
+                .map(|tx| tx["transaction_hash"].as_str().unwrap().to_string())
                 .collect();
-            
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(records),
-                message: None,
-            })
-        }
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Database error: {}", e)),
-        }),
+            hashes.sort();
+            hashes
+        };
+
+        // Case-insensitive substring, limited to alice's transactions
+        let body: serde_json::Value = test::call_and_read_body_json(&app, search("RENT")).await;
+        assert_eq!(hashes(body), vec!["t1", "t4"]);
+
+        // Wildcards match literally
+        let body: serde_json::Value = test::call_and_read_body_json(&app, search("%25")).await;
+        assert_eq!(hashes(body), vec!["t4"]);
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, search("utilities")).await;
+        assert!(hashes(body).is_empty());
+
+        let resp = test::call_service(&app, search("")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        // Out-of-range paging is clamped rather than passed to the database
+        let body: serde_json::Value = test::call_and_read_body_json(&app, search("rent&limit=-1&offset=-5")).await;
+        assert_eq!(hashes(body).len(), 1);
+        let body: serde_json::Value = test::call_and_read_body_json(&app, search(&format!("rent&limit={}", i64::MAX))).await;
+        assert_eq!(hashes(body), vec!["t1", "t4"]);
     }
-}
 
-pub async fn get_zakat_pool(pool: web::Data<DbPool>) -> HttpResponse {
-    let zakat_pool_wallet_id = env::var("ZAKAT_POOL_WALLET_ID")
-        .unwrap_or_else(|_| "ZAKAT_POOL".to_string());
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_wallet_summary_aggregates_activity() {
+        let pool = match crate::database::create_test_pool("wallet_summary").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
 
-    match wallet_service::get_wallet_balance(&pool, &zakat_pool_wallet_id).await {
-        Ok(balance) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(balance),
-            message: None,
-        }),
-        Err(e) => HttpResponse::BadRequest().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(e.to_string()),
-        }),
+        let client = pool.get().await.unwrap();
+        client
+            .batch_execute(
+                "INSERT INTO wallets (wallet_id) VALUES ('alice'), ('bob'), ('carol'), ('idle');
+                 INSERT INTO transactions (transaction_hash, sender_wallet_id, receiver_wallet_id, amount, signature, timestamp, created_at) VALUES
+                     ('t1', 'alice', 'bob', 1.5, 'sig', 1, '2025-01-01T00:00:00Z'),
+                     ('t2', 'bob', 'alice', 0.25, 'sig', 2, '2025-02-01T00:00:00Z'),
+                     ('t3', 'alice', 'carol', 2, 'sig', 3, '2025-03-01T00:00:00Z'),
+                     ('t4', 'carol', 'bob', 9, 'sig', 4, '2025-04-01T00:00:00Z');
+                 INSERT INTO utxos (wallet_id, amount, transaction_hash, output_index, is_spent) VALUES
+                     ('alice', 0.25, 't2', 0, false),
+                     ('alice', 3.5, 'seed', 0, true),
+                     ('alice', 1, 't3', 1, false);",
+            )
+            .await
+            .unwrap();
+        drop(client);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(WalletReadPolicy::default()))
+                .route("/wallet/{wallet_id}/summary", web::get().to(get_wallet_summary)),
+        )
+        .await;
+        let summary = |wallet_id: &str| test::TestRequest::get()
+            .uri(&format!("/wallet/{}/summary", wallet_id))
+            .to_request();
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, summary("alice")).await;
+        let data = &body["data"];
+        assert_eq!(data["transaction_count"], 3);
+        assert_eq!(data["total_sent"], 3.5);
+        assert_eq!(data["total_received"], 0.25);
+        assert_eq!(data["unspent_utxo_count"], 2);
+        assert!(data["first_transaction_at"].as_str().unwrap().starts_with("2025-01-01"));
+        assert!(data["last_activity_at"].as_str().unwrap().starts_with("2025-03-01"));
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, summary("idle")).await;
+        assert_eq!(body["data"]["transaction_count"], 0);
+        assert_eq!(body["data"]["total_sent"], 0.0);
+        assert!(body["data"]["last_activity_at"].is_null());
+
+        let resp = test::call_service(&app, summary("nobody")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
     }
-}
 
-pub async fn trigger_zakat(pool: web::Data<DbPool>) -> HttpResponse {
-    match zakat_service::trigger_zakat_deduction(&pool).await {
-        Ok(_) => HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(serde_json::json!({"message": "Zakat deduction triggered"})),
-            message: Some("Zakat deduction process completed".to_string()),
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
-            success: false,
-            data: None,
-            message: Some(format!("Zakat deduction failed: {}", e)),
-        }),
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_unknown_wallet_sub_resources_are_404() {
+        let Some(pool) = crate::database::create_test_pool("wallet_not_found").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let client = pool.get().await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "known").await.unwrap();
+        drop(client);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(WalletReadPolicy::default()))
+                .configure(|cfg| crate::handlers::configure_routes(cfg, &BodyLimits::default())),
+        )
+        .await;
+
+        let resources = [
+            "",
+            "/summary",
+            "/balance",
+            "/utxos",
+            "/utxos/spent",
+            "/transactions",
+            "/transactions?before=",
+            "/transactions/search?q=rent",
+            "/transactions.csv",
+            "/qr",
+        ];
+        for resource in resources {
+            let uri = format!("/api/wallet/unknown{}", resource);
+            let resp = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND, "{}", uri);
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(body["message"], "Wallet not found", "{}", uri);
+
+            let uri = format!("/api/wallet/known{}", resource);
+            let resp = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::OK, "{}", uri);
+        }
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_transaction_cursor_pages_survive_new_transactions() {
+        let Some(pool) = crate::database::create_test_pool("tx_cursor_paging").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        client
+            .batch_execute(
+                "INSERT INTO wallets (wallet_id) VALUES ('alice'), ('bob');
+                 INSERT INTO transactions (transaction_hash, sender_wallet_id, receiver_wallet_id, amount, signature, timestamp, created_at)
+                 SELECT 'old' || n, 'alice', 'bob', 1, 'sig', n, NOW() - make_interval(mins => 10 - n)
+                 FROM generate_series(1, 5) n;",
+            )
+            .await
+            .unwrap();
+        drop(client);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(WalletReadPolicy::default()))
+                .route("/wallet/{wallet_id}/transactions", web::get().to(get_transactions)),
+        )
+        .await;
+        let page = |uri: String| {
+            let app = &app;
+            async move {
+                let body: serde_json::Value =
+                    test::call_and_read_body_json(app, test::TestRequest::get().uri(&uri).to_request()).await;
+                let hashes: Vec<String> = body["data"]["items"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|tx| tx["transaction_hash"].as_str().unwrap().to_string())
+                    .collect();
+                (hashes, body["data"]["next_cursor"].as_str().map(str::to_string))
+            }
+        };
+
+        // Transactions recorded while paging are newer than the cursor and never shift a later page
+        let mut seen = Vec::new();
+        let mut cursor = Some(String::new());
+        let mut added = 0;
+        while let Some(before) = cursor {
+            let (hashes, next) = page(format!("/wallet/alice/transactions?before={}&limit=2", before)).await;
+            seen.extend(hashes);
+            added += 1;
+            let client = pool.get().await.unwrap();
+            client
+                .execute(
+                    "INSERT INTO transactions (transaction_hash, sender_wallet_id, receiver_wallet_id, amount, signature, timestamp)
+                     VALUES ($1, 'bob', 'alice', 1, 'sig', 0)",
+                    &[&format!("new{}", added)],
+                )
+                .await
+                .unwrap();
+            cursor = next;
+        }
+        assert_eq!(seen, vec!["old5", "old4", "old3", "old2", "old1"]);
+
+        // Out-of-range limits are clamped rather than passed to the database
+        let (hashes, _) = page("/wallet/alice/transactions?before=&limit=0".to_string()).await;
+        assert_eq!(hashes.len(), 1);
+        let (hashes, _) = page(format!("/wallet/alice/transactions?before=&limit={}", i64::MAX)).await;
+        assert_eq!(hashes.len(), 5 + added);
     }
 }
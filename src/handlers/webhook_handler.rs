@@ -0,0 +1,82 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::models::{ApiResponse, CreateWebhookRequest};
+use crate::database::DbPool;
+use crate::errors::ApiError;
+use crate::handlers::authenticate;
+use crate::services::webhook_service;
+use uuid::Uuid;
+
+pub async fn get_webhooks(pool: web::Data<DbPool>, req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    let user_id = authenticate(&req)?;
+    let client = pool.get().await?;
+
+    let webhooks = crate::database::queries::get_user_webhooks(&client, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(webhooks),
+        message: None,
+    }))
+}
+
+pub async fn create_webhook(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    body: web::Json<CreateWebhookRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = authenticate(&req)?;
+
+    // Checked again before every delivery, in case the host's DNS changes
+    webhook_service::resolve_target(&body.url, webhook_service::allow_private_targets())
+        .await
+        .map_err(ApiError::BadRequest)?;
+
+    let client = pool.get().await?;
+
+    // Only the wallet owner may register webhooks for it
+    match crate::database::queries::get_wallet(&client, &body.wallet_id).await? {
+        Some(wallet) if wallet.user_id == Some(user_id) => {}
+        Some(_) => return Err(ApiError::Forbidden("Wallet not owned by user".to_string())),
+        None => return Err(ApiError::NotFound("Wallet not found".to_string())),
+    }
+
+    let secret = body
+        .secret
+        .clone()
+        .unwrap_or_else(|| hex::encode(rand::random::<[u8; 32]>()));
+
+    let webhook = crate::database::queries::create_webhook(&client, &body.wallet_id, &body.url, &secret).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({
+            "id": webhook.id,
+            "wallet_id": webhook.wallet_id,
+            "url": webhook.url,
+            "secret": webhook.secret,
+            "created_at": webhook.created_at,
+        })),
+        message: Some("Webhook created successfully".to_string()),
+    }))
+}
+
+pub async fn delete_webhook(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let webhook_id = path.into_inner();
+    let user_id = authenticate(&req)?;
+    let client = pool.get().await?;
+
+    let rows = crate::database::queries::delete_webhook(&client, webhook_id, user_id).await?;
+    if rows == 0 {
+        return Err(ApiError::NotFound("Webhook not found or not owned by user".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(serde_json::json!({"deleted": rows})),
+        message: Some("Webhook deleted successfully".to_string()),
+    }))
+}
@@ -0,0 +1,229 @@
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use crate::config::WalletReadPolicy;
+use crate::database::DbPool;
+use crate::events::{BlockEvent, EventBus};
+use crate::handlers::{authenticate_claims, authorize_wallet_read};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// WebSocket session forwarding chain events, optionally filtered to one wallet
+struct EventSession {
+    rx: Option<broadcast::Receiver<BlockEvent>>,
+    wallet_id: Option<String>,
+    /// Unfiltered stream for a caller who may not read every wallet: blocks only, no transactions
+    hide_transactions: bool,
+}
+
+impl Actor for EventSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if let Some(rx) = self.rx.take() {
+            let events = futures_util::stream::unfold(rx, |mut rx| async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => return Some((event, rx)),
+                        Err(RecvError::Lagged(skipped)) => {
+                            log::warn!("WebSocket subscriber lagged, skipped {} events", skipped);
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            });
+            ctx.add_stream(events);
+        }
+    }
+}
+
+impl StreamHandler<BlockEvent> for EventSession {
+    fn handle(&mut self, event: BlockEvent, ctx: &mut Self::Context) {
+        let event = match &self.wallet_id {
+            Some(wallet_id) => match event.for_wallet(wallet_id) {
+                Some(e) => e,
+                None => return,
+            },
+            None if self.hide_transactions => BlockEvent { transactions: Vec::new(), ..event },
+            None => event,
+        };
+
+        if let Ok(json) = serde_json::to_string(&event) {
+            ctx.text(json);
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for EventSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(bytes)) => ctx.pong(&bytes),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Err(_) => ctx.stop(),
+            _ => {}
+        }
+    }
+}
+
+/// Subscribe to mined blocks and confirmed transactions (`?wallet_id=` to filter). Under
+/// [`WalletReadPolicy::require_auth`] a wallet filter needs its owner's or an admin's token, and
+/// the unfiltered stream carries other wallets' transactions to admins only.
+pub async fn subscribe(
+    req: HttpRequest,
+    stream: web::Payload,
+    pool: web::Data<DbPool>,
+    policy: web::Data<WalletReadPolicy>,
+    events: web::Data<EventBus>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, Error> {
+    let wallet_id = query.get("wallet_id").cloned();
+    let hide_transactions = match &wallet_id {
+        Some(wallet_id) => {
+            authorize_wallet_read(&pool, &req, &policy, std::slice::from_ref(wallet_id)).await?;
+            false
+        }
+        None => policy.require_auth && !authenticate_claims(&req).is_ok_and(|claims| claims.is_admin()),
+    };
+    let session = EventSession {
+        rx: Some(events.subscribe()),
+        wallet_id,
+        hide_transactions,
+    };
+
+    ws::start(session, &req, stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::mine_block;
+    use crate::config::{ChainConfig, ChainMode};
+    use crate::events::{self, create_event_bus};
+    use crate::shutdown::Shutdown;
+    use actix_codec::Framed;
+    use actix_http::ws::{Codec, Frame};
+    use actix_web::{App, HttpServer};
+    use futures_util::StreamExt;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    /// Open a WebSocket to `path` on a running server, returning the connection once upgraded
+    async fn connect(addr: SocketAddr, path: &str) -> Framed<TcpStream, Codec> {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let handshake = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path, addr
+        );
+        stream.write_all(handshake.as_bytes()).await.unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        assert!(head.starts_with(b"HTTP/1.1 101"), "{}", String::from_utf8_lossy(&head));
+        Framed::new(stream, Codec::new().client_mode())
+    }
+
+    /// The next event sent to `socket`, or `None` if nothing arrives within `wait`
+    async fn next_event(socket: &mut Framed<TcpStream, Codec>, wait: Duration) -> Option<serde_json::Value> {
+        match tokio::time::timeout(wait, socket.next()).await {
+            Ok(Some(Ok(Frame::Text(text)))) => Some(serde_json::from_slice(&text).unwrap()),
+            Ok(other) => panic!("unexpected frame: {:?}", other),
+            Err(_) => None,
+        }
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[actix_web::test]
+    async fn test_mined_block_reaches_subscribers() {
+        let chain = ChainConfig { mode: ChainMode::Regtest, mining_difficulty: 0, ..ChainConfig::default() };
+        let Some((pool, payer, payee)) = crate::database::create_funded_test_pair("ws_subscribe", &chain, 100.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let other = crate::database::register_test_user(&pool, "other@example.com", "3").await;
+        let (payer, payee, other) = (&payer.wallet_id, &payee.wallet_id, &other.wallet_id);
+        let client = pool.get().await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "miner").await.unwrap();
+        crate::database::queries::ensure_wallet(&client, "bystander").await.unwrap();
+        drop(client);
+
+        let bus = create_event_bus();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app_pool = pool.clone();
+        let app_bus = bus.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(app_pool.clone()))
+                .app_data(web::Data::new(app_bus.clone()))
+                .app_data(web::Data::new(WalletReadPolicy::default()))
+                .route("/ws", web::get().to(subscribe))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        let server_handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let mut everything = connect(addr, "/ws").await;
+        let mut payee_only = connect(addr, &format!("/ws?wallet_id={}", payee)).await;
+        let mut bystander = connect(addr, "/ws?wallet_id=bystander").await;
+
+        let mut hashes = Vec::new();
+        for receiver in [payee, other] {
+            let request = crate::models::CreateTransactionRequest {
+                sender_wallet_id: payer.clone(),
+                receiver_wallet_id: receiver.clone(),
+                amount: 1.0,
+                note: None,
+                private_note: false,
+                payment_request_id: None,
+            };
+            let pending = crate::services::transaction_service::create_transaction(&pool, request, &chain, &aes_key)
+                .await
+                .unwrap();
+            hashes.push(pending.transaction_hash);
+        }
+        let block = mine_block(&pool, "miner", &chain, &Shutdown::new()).await.unwrap().block;
+        events::publish(&bus, BlockEvent::block_mined(&block));
+
+        let wait = Duration::from_secs(5);
+        let event = next_event(&mut everything, wait).await.expect("unfiltered subscriber got no event");
+        assert_eq!(event["event"], "block_mined");
+        assert_eq!(event["block_index"], block.index);
+        assert_eq!(event["block_hash"], block.hash.as_str());
+        let sent: Vec<&str> = event["transactions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tx| tx["transaction_hash"].as_str().unwrap())
+            .collect();
+        for hash in &hashes {
+            assert!(sent.contains(&hash.as_str()), "{} missing from {:?}", hash, sent);
+        }
+
+        // A wallet filter narrows the event to that wallet's transactions
+        let event = next_event(&mut payee_only, wait).await.expect("payee subscriber got no event");
+        assert_eq!(event["block_hash"], block.hash.as_str());
+        assert_eq!(
+            event["transactions"],
+            serde_json::json!([{
+                "transaction_hash": hashes[0],
+                "sender_wallet_id": payer,
+                "receiver_wallet_id": payee,
+                "amount": 1.0,
+            }])
+        );
+
+        // and a block that doesn't touch the wallet isn't sent at all
+        assert!(next_event(&mut bystander, Duration::from_millis(500)).await.is_none());
+
+        server_handle.stop(false).await;
+    }
+}
@@ -0,0 +1,123 @@
+use chrono::{Datelike, NaiveDate};
+
+/// A date in the tabular (arithmetical) Islamic calendar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HijriDate {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+/// Julian Day Number of 1 Muharram 1 AH (civil epoch, 16 July 622 Julian)
+const HIJRI_EPOCH_JDN: i64 = 1948440;
+
+/// Leap years add a 30th day to Dhu al-Hijjah (11 leap years per 30-year cycle)
+pub fn is_leap_year(year: i64) -> bool {
+    (14 + 11 * year).rem_euclid(30) < 11
+}
+
+/// Odd months have 30 days, even months 29, except Dhu al-Hijjah in leap years
+pub fn month_length(year: i64, month: u32) -> u32 {
+    if month % 2 == 1 || (month == 12 && is_leap_year(year)) {
+        30
+    } else {
+        29
+    }
+}
+
+fn julian_day_number(date: NaiveDate) -> i64 {
+    // 0001-01-01 (proleptic Gregorian) is day 1 from CE and JDN 1721426
+    date.num_days_from_ce() as i64 + 1721425
+}
+
+impl HijriDate {
+    pub fn from_gregorian(date: NaiveDate) -> HijriDate {
+        let days = julian_day_number(date) - HIJRI_EPOCH_JDN;
+
+        let cycle = days.div_euclid(10631);
+        let mut remaining = days.rem_euclid(10631);
+
+        let mut year = cycle * 30 + 1;
+        loop {
+            let year_length = if is_leap_year(year) { 355 } else { 354 };
+            if remaining < year_length {
+                break;
+            }
+            remaining -= year_length;
+            year += 1;
+        }
+
+        let mut month = 1;
+        loop {
+            let length = month_length(year, month) as i64;
+            if remaining < length {
+                break;
+            }
+            remaining -= length;
+            month += 1;
+        }
+
+        HijriDate {
+            year,
+            month,
+            day: remaining as u32 + 1,
+        }
+    }
+
+    /// Same month and day one Hijri year later, clamped when that month is shorter
+    pub fn next_anniversary(&self) -> HijriDate {
+        let year = self.year + 1;
+        HijriDate {
+            year,
+            month: self.month,
+            day: self.day.min(month_length(year, self.month)),
+        }
+    }
+}
+
+/// Whether a full Hijri year has passed between `since` and `now`
+pub fn full_year_elapsed(since: NaiveDate, now: NaiveDate) -> bool {
+    HijriDate::from_gregorian(now) >= HijriDate::from_gregorian(since).next_anniversary()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_epoch() {
+        // 16 July 622 Julian is 19 July 622 proleptic Gregorian
+        assert_eq!(
+            HijriDate::from_gregorian(date(622, 7, 19)),
+            HijriDate { year: 1, month: 1, day: 1 }
+        );
+    }
+
+    #[test]
+    fn test_known_date() {
+        // Tabular calendar: 1 Ramadan 1445 fell on 11 March 2024
+        assert_eq!(
+            HijriDate::from_gregorian(date(2024, 3, 11)),
+            HijriDate { year: 1445, month: 9, day: 1 }
+        );
+    }
+
+    #[test]
+    fn test_year_lengths() {
+        let total: u32 = (1..=12).map(|m| month_length(1445, m)).sum();
+        assert_eq!(total, if is_leap_year(1445) { 355 } else { 354 });
+    }
+
+    #[test]
+    fn test_full_year_boundary() {
+        let since = date(2024, 3, 11); // 1 Ramadan 1445
+        let anniversary = date(2025, 3, 1); // 1 Ramadan 1446 (354 days later)
+
+        assert!(!full_year_elapsed(since, anniversary.pred_opt().unwrap()));
+        assert!(full_year_elapsed(since, anniversary));
+    }
+}
@@ -7,6 +7,13 @@ mod database;
 mod utils;
 mod middleware;
 mod config;
+mod events;
+mod metrics;
+mod hijri;
+mod coin_selection;
+mod migrations;
+mod errors;
+mod shutdown;
 
 use actix_web::{web, App, HttpServer, middleware::Logger};
 use actix_cors::Cors;
@@ -18,6 +25,23 @@ async fn main() -> std::io::Result<()> {
     dotenv().ok();
     env_logger::init();
 
+    // `generate-aes-key` prints a fresh AES_ENCRYPTION_KEY instead of starting the server
+    if env::args().nth(1).as_deref() == Some("generate-aes-key") {
+        println!("{}", hex::encode(crypto::generate_aes_key()));
+        return Ok(());
+    }
+
+    // Fail at startup rather than on the first request that needs a missing or malformed setting
+    config::Config::from_env().expect("Invalid configuration");
+    if let Err(e) = config::JwtConfig::from_env() {
+        log::error!("❌ FATAL: invalid JWT configuration: {}", e);
+        std::process::exit(1);
+    }
+    if let Err(e) = config::parse_system_signing_secret(env::var("SYSTEM_SIGNING_SECRET").ok().as_deref(), config::AppEnv::from_env()) {
+        log::error!("❌ FATAL: {}", e);
+        std::process::exit(1);
+    }
+
     let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
     let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let address = format!("{}:{}", host, port);
@@ -25,39 +49,145 @@ async fn main() -> std::io::Result<()> {
     log::info!("🚀 Starting Blockchain Wallet Backend on {}", address);
 
     // Initialize database pool
-    let db_pool = database::create_pool().await.expect("Failed to create database pool");
+    let pool_config = config::DbPoolConfig::from_env().expect("Invalid database pool configuration");
+    let db_pool = database::create_pool(&pool_config).await.expect("Failed to create database pool");
+
+    // Bring the schema up to date before anything touches it
+    match migrations::run_migrations(&db_pool).await {
+        Ok(applied) if applied.is_empty() => log::info!("✅ Database schema up to date"),
+        Ok(applied) => log::info!("✅ Applied migrations: {:?}", applied),
+        Err(e) => panic!("Failed to run database migrations: {}", e),
+    }
+
+    // Difficulty, reward, halving, supply cap and fee shared by mining and transfers
+    let chain_config = web::Data::new(config::ChainConfig::from_env().expect("Invalid chain configuration"));
+    if chain_config.mode == config::ChainMode::Regtest {
+        log::warn!("⚠️  CHAIN_MODE=regtest: mining difficulty is 0 and /blockchain/generate/{{n}} is enabled");
+    }
 
     // Initialize blockchain
-    blockchain::initialize_blockchain(db_pool.clone())
+    blockchain::initialize_blockchain(db_pool.clone(), &chain_config)
         .await
         .expect("Failed to initialize blockchain");
 
+    // Promote users configured in ADMIN_EMAILS
+    match services::auth_service::seed_admins(&db_pool).await {
+        Ok(0) => {}
+        Ok(n) => log::info!("👑 Promoted {} user(s) to admin", n),
+        Err(e) => log::error!("Failed to seed admins: {}", e),
+    }
+
+    // Broadcast channel for WebSocket subscribers
+    let event_bus = events::create_event_bus();
+
+    // Prometheus metrics registry
+    let metrics = web::Data::new(metrics::Metrics::new().expect("Failed to create metrics registry"));
+
+    // Failed login tracking shared across workers
+    let login_attempts = web::Data::new(services::login_attempt_service::LoginAttemptTracker::from_env());
+
+    // Signalled on SIGTERM/Ctrl+C so background work can wind down
+    let shutdown = shutdown::Shutdown::new();
+    let shutdown_data = web::Data::new(shutdown.clone());
+
     // Start Zakat scheduler
-    tokio::spawn(services::zakat_service::start_zakat_scheduler(db_pool.clone()));
+    let zakat_scheduler = tokio::spawn(services::zakat_service::start_zakat_scheduler(
+        db_pool.clone(),
+        chain_config.get_ref().clone(),
+        shutdown.clone(),
+    ));
+
+    // Notification emails queued by mining and zakat
+    let email_outbox = tokio::spawn(services::email_service::start_outbox_worker(
+        db_pool.clone(),
+        shutdown.clone(),
+    ));
 
-    HttpServer::new(move || {
+    // Webhook payloads queued by mining
+    let webhook_deliveries = tokio::spawn(services::webhook_service::start_delivery_worker(
+        db_pool.clone(),
+        shutdown.clone(),
+    ));
+
+    // Tokens of accounts deleted on any node stop working here
+    let revocation_sync = tokio::spawn(services::auth_service::start_revocation_sync(
+        db_pool.clone(),
+        shutdown.clone(),
+    ));
+
+    // CORS allowlist, parsed once and shared by every worker
+    let cors_origins = std::sync::Arc::new(config::CorsOrigins::from_env().expect("Invalid CORS configuration"));
+    log::info!("🌐 CORS allows: {}", cors_origins);
+
+    // Request body size caps
+    let body_limits = config::BodyLimits::from_env().expect("Invalid body limit configuration");
+
+    // Public wallet reads unless REQUIRE_AUTH_FOR_WALLET_READS is on
+    let wallet_reads = web::Data::new(config::WalletReadPolicy::from_env().expect("Invalid wallet read policy"));
+    if wallet_reads.require_auth {
+        log::info!("🔒 Wallet balances and history are readable only by their owner and admins");
+    }
+
+    let server = HttpServer::new(move || {
+        let cors_origins = cors_origins.clone();
         let cors = Cors::default()
-            .allowed_origin_fn(|origin, _req_head| {
-                let allowed_origins = env::var("ALLOWED_ORIGINS")
-                    .unwrap_or_else(|_| "http://localhost:5173".to_string());
-                let origins: Vec<&str> = allowed_origins.split(',').collect();
-                origins.iter().any(|&o| origin.as_bytes() == o.as_bytes())
+            .allowed_origin_fn(move |origin, _req_head| {
+                origin.to_str().is_ok_and(|o| cors_origins.allows(o))
             })
             .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
             .allowed_headers(vec![
                 actix_web::http::header::AUTHORIZATION,
                 actix_web::http::header::ACCEPT,
                 actix_web::http::header::CONTENT_TYPE,
+                // Browser clients retrying a transfer send one (see `create_transaction`)
+                actix_web::http::header::HeaderName::from_static("idempotency-key"),
             ])
             .max_age(3600);
 
         App::new()
             .app_data(web::Data::new(db_pool.clone()))
+            .app_data(web::Data::new(event_bus.clone()))
+            .app_data(metrics.clone())
+            .app_data(login_attempts.clone())
+            .app_data(shutdown_data.clone())
+            .app_data(chain_config.clone())
+            .app_data(wallet_reads.clone())
             .wrap(cors)
             .wrap(Logger::default())
-            .configure(handlers::configure_routes)
+            .configure(|cfg| handlers::configure_routes(cfg, &body_limits))
     })
     .bind(address)?
-    .run()
-    .await
+    // Signals are handled below so background tasks hear about shutdown before the server stops
+    .disable_signals()
+    .run();
+
+    let server_handle = server.handle();
+    let signal_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        shutdown::wait_for_signal().await;
+        log::info!("🛑 Shutdown signal received, draining background work...");
+        signal_shutdown.trigger();
+        // Graceful: in-flight requests (including a block being saved) are allowed to finish
+        server_handle.stop(true).await;
+    });
+
+    server.await?;
+
+    // The scheduler exits after its current run; make sure that run has completed
+    shutdown.trigger();
+    if let Err(e) = zakat_scheduler.await {
+        log::error!("Zakat scheduler terminated abnormally: {}", e);
+    }
+    if let Err(e) = email_outbox.await {
+        log::error!("Email outbox worker terminated abnormally: {}", e);
+    }
+    if let Err(e) = webhook_deliveries.await {
+        log::error!("Webhook delivery worker terminated abnormally: {}", e);
+    }
+    if let Err(e) = revocation_sync.await {
+        log::error!("Token revocation sync terminated abnormally: {}", e);
+    }
+
+    log::info!("👋 Server stopped");
+    Ok(())
 }
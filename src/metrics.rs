@@ -0,0 +1,86 @@
+use prometheus::{Encoder, Gauge, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+
+/// Prometheus registry and the metric handles updated by handlers and services
+pub struct Metrics {
+    pub registry: Registry,
+    pub total_blocks: IntGauge,
+    pub pending_transactions: IntGauge,
+    pub last_block_mining_seconds: Gauge,
+    pub otp_emails_sent: IntCounter,
+    pub otp_emails_failed: IntCounter,
+    pub login_attempts: IntCounterVec,
+    pub db_pool_in_use: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+
+        let total_blocks = IntGauge::new("blockchain_total_blocks", "Number of blocks in the chain")?;
+        let pending_transactions = IntGauge::new(
+            "blockchain_pending_transactions",
+            "Transactions waiting to be mined",
+        )?;
+        let last_block_mining_seconds = Gauge::new(
+            "blockchain_last_block_mining_seconds",
+            "Wall-clock time spent mining the most recent block",
+        )?;
+        let otp_emails_sent = IntCounter::new("otp_emails_sent_total", "OTP emails sent successfully")?;
+        let otp_emails_failed = IntCounter::new("otp_emails_failed_total", "OTP emails that failed to send")?;
+        let login_attempts = IntCounterVec::new(
+            Opts::new("login_attempts_total", "Login attempts by result"),
+            &["result"],
+        )?;
+        let db_pool_in_use = IntGauge::new("db_pool_connections_in_use", "Database connections checked out of the pool")?;
+
+        registry.register(Box::new(total_blocks.clone()))?;
+        registry.register(Box::new(pending_transactions.clone()))?;
+        registry.register(Box::new(last_block_mining_seconds.clone()))?;
+        registry.register(Box::new(otp_emails_sent.clone()))?;
+        registry.register(Box::new(otp_emails_failed.clone()))?;
+        registry.register(Box::new(login_attempts.clone()))?;
+        registry.register(Box::new(db_pool_in_use.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            total_blocks,
+            pending_transactions,
+            last_block_mining_seconds,
+            otp_emails_sent,
+            otp_emails_failed,
+            login_attempts,
+            db_pool_in_use,
+        })
+    }
+
+    /// Render all registered metrics in the Prometheus text exposition format
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_exposes_metric_names() {
+        let metrics = Metrics::new().unwrap();
+        metrics.login_attempts.with_label_values(&["success"]).inc();
+
+        let output = metrics.render().unwrap();
+        for name in [
+            "blockchain_total_blocks",
+            "blockchain_pending_transactions",
+            "blockchain_last_block_mining_seconds",
+            "otp_emails_sent_total",
+            "otp_emails_failed_total",
+            "login_attempts_total",
+            "db_pool_connections_in_use",
+        ] {
+            assert!(output.contains(name), "missing metric {}", name);
+        }
+    }
+}
@@ -1,3 +1,5 @@
 pub mod jwt_auth;
+pub mod require_admin;
 
 pub use jwt_auth::JwtAuth;
+pub use require_admin::RequireAdmin;
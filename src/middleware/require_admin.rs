@@ -0,0 +1,71 @@
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use crate::errors::ApiError;
+use crate::services::auth_service::{self, Claims};
+use std::future::{ready, Ready};
+
+/// Extractor that only succeeds for requests carrying an admin token.
+/// Missing or invalid tokens get `401`, valid non-admin tokens get `403`.
+pub struct RequireAdmin(pub Claims);
+
+impl FromRequest for RequireAdmin {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "));
+
+        let token = match token {
+            Some(t) => t,
+            None => return ready(Err(ApiError::Unauthorized("No authorization token provided".to_string()))),
+        };
+
+        match auth_service::verify_token(token) {
+            Ok(claims) if claims.is_admin() => ready(Ok(RequireAdmin(claims))),
+            Ok(_) => ready(Err(ApiError::Forbidden("Admin role required".to_string()))),
+            Err(_) => ready(Err(ApiError::Unauthorized("Invalid or expired token".to_string()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+
+    async fn admin_only(_admin: RequireAdmin) -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    async fn call_with(token: Option<String>) -> StatusCode {
+        let app = test::init_service(App::new().route("/admin", web::post().to(admin_only))).await;
+
+        let mut req = test::TestRequest::post().uri("/admin");
+        if let Some(token) = token {
+            req = req.insert_header(("Authorization", format!("Bearer {}", token)));
+        }
+
+        test::call_service(&app, req.to_request()).await.status()
+    }
+
+    #[actix_web::test]
+    async fn test_admin_token_passes() {
+        let token = auth_service::generate_token("1", "admin@example.com", "admin").unwrap();
+        assert_eq!(call_with(Some(token)).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_regular_token_rejected() {
+        let token = auth_service::generate_token("2", "user@example.com", "user").unwrap();
+        assert_eq!(call_with(Some(token)).await, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_missing_token_rejected() {
+        assert_eq!(call_with(None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(call_with(Some("garbage".to_string())).await, StatusCode::UNAUTHORIZED);
+    }
+}
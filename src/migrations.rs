@@ -0,0 +1,236 @@
+use crate::database::{AdvisoryLock, DbPool};
+use std::ops::DerefMut;
+
+/// A numbered SQL migration embedded from `migrations/`
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// All migrations in the order they are applied. New schema changes get a new entry;
+/// never edit a migration that has already shipped.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        sql: include_str!("../migrations/V1__initial_schema.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "roles_webhooks_zakat_distribution",
+        sql: include_str!("../migrations/V2__roles_webhooks_zakat_distribution.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "unique_normalized_cnic",
+        sql: include_str!("../migrations/V3__unique_normalized_cnic.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "idempotency_keys",
+        sql: include_str!("../migrations/V4__idempotency_keys.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "block_mining_stats",
+        sql: include_str!("../migrations/V5__block_mining_stats.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "utxo_is_coinbase",
+        sql: include_str!("../migrations/V6__utxo_is_coinbase.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "block_coinbase_and_fees",
+        sql: include_str!("../migrations/V7__block_coinbase_and_fees.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "payment_requests",
+        sql: include_str!("../migrations/V8__payment_requests.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "encrypted_notes",
+        sql: include_str!("../migrations/V9__encrypted_notes.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "wallet_status",
+        sql: include_str!("../migrations/V10__wallet_status.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "wallet_zakat_exempt",
+        sql: include_str!("../migrations/V11__wallet_zakat_exempt.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "zakat_calculation_basis",
+        sql: include_str!("../migrations/V12__zakat_calculation_basis.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "block_version",
+        sql: include_str!("../migrations/V13__block_version.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "transaction_ordinal",
+        sql: include_str!("../migrations/V14__transaction_ordinal.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "notifications",
+        sql: include_str!("../migrations/V15__notifications.sql"),
+    },
+    Migration {
+        version: 16,
+        name: "coinbase_transactions",
+        sql: include_str!("../migrations/V16__coinbase_transactions.sql"),
+    },
+    Migration {
+        version: 17,
+        name: "account_deletion",
+        sql: include_str!("../migrations/V17__account_deletion.sql"),
+    },
+    Migration {
+        version: 18,
+        name: "utxo_spent_by",
+        sql: include_str!("../migrations/V18__utxo_spent_by.sql"),
+    },
+    Migration {
+        version: 19,
+        name: "wallet_zakat_rate",
+        sql: include_str!("../migrations/V19__wallet_zakat_rate.sql"),
+    },
+    Migration {
+        version: 20,
+        name: "webhook_deliveries",
+        sql: include_str!("../migrations/V20__webhook_deliveries.sql"),
+    },
+    Migration {
+        version: 21,
+        name: "block_burned_fees",
+        sql: include_str!("../migrations/V21__block_burned_fees.sql"),
+    },
+    Migration {
+        version: 22,
+        name: "wallet_public_key",
+        sql: include_str!("../migrations/V22__wallet_public_key.sql"),
+    },
+];
+
+/// Arbitrary key for the advisory lock that serializes concurrent runners
+const MIGRATION_LOCK_KEY: i64 = 0x006d_6967_7261_7465;
+
+/// Apply pending migrations, returning the versions that were applied.
+/// Each migration runs in its own transaction together with its `schema_migrations` row.
+pub async fn run_migrations(pool: &DbPool) -> Result<Vec<i32>, anyhow::Error> {
+    // Another instance starting at the same time waits here instead of racing us
+    let mut lock = AdvisoryLock::acquire(pool, MIGRATION_LOCK_KEY).await?;
+
+    lock.client()
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                name VARCHAR(255) NOT NULL,
+                applied_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+            )",
+        )
+        .await?;
+
+    let result = apply_pending(lock.client_mut().deref_mut()).await;
+
+    lock.unlock().await?;
+
+    result
+}
+
+async fn apply_pending(client: &mut tokio_postgres::Client) -> Result<Vec<i32>, anyhow::Error> {
+    let applied: Vec<i32> = client
+        .query("SELECT version FROM schema_migrations", &[])
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    let mut newly_applied = Vec::new();
+
+    for migration in MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+        log::info!("Applying migration V{}__{}", migration.version, migration.name);
+
+        let transaction = client.transaction().await?;
+        transaction.batch_execute(migration.sql).await.map_err(|e| {
+            anyhow::anyhow!("Migration V{}__{} failed: {}", migration.version, migration.name, e)
+        })?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)",
+                &[&migration.version, &migration.name],
+            )
+            .await?;
+        transaction.commit().await?;
+
+        newly_applied.push(migration.version);
+    }
+
+    Ok(newly_applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_strictly_increasing() {
+        assert!(MIGRATIONS.windows(2).all(|w| w[0].version < w[1].version));
+        assert_eq!(MIGRATIONS.first().map(|m| m.version), Some(1));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_migrations_idempotent() {
+        let pool = match crate::database::create_test_pool("migrations").await {
+            Some(pool) => pool,
+            None => return,
+        };
+
+        let first = run_migrations(&pool).await.unwrap();
+        assert_eq!(first, MIGRATIONS.iter().map(|m| m.version).collect::<Vec<_>>());
+
+        let second = run_migrations(&pool).await.unwrap();
+        assert!(second.is_empty());
+
+        let client = pool.get().await.unwrap();
+        let tables: Vec<String> = client
+            .query(
+                "SELECT table_name::text FROM information_schema.tables WHERE table_schema = 'public'",
+                &[],
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        for table in [
+            "users", "wallets", "utxos", "blocks", "transactions", "pending_transactions",
+            "zakat_records", "system_logs", "transaction_logs", "beneficiaries", "email_otps",
+            "webhooks", "idempotency_keys", "notification_preferences", "email_outbox", "schema_migrations",
+        ] {
+            assert!(tables.iter().any(|t| t == table), "missing table {}", table);
+        }
+
+        let role_column = client
+            .query_opt(
+                "SELECT 1 FROM information_schema.columns WHERE table_name = 'users' AND column_name = 'role'",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert!(role_column.is_some());
+    }
+}
@@ -14,6 +14,7 @@ pub struct User {
     pub is_verified: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub role: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,15 +23,63 @@ pub struct Wallet {
     pub user_id: Option<Uuid>,
     pub balance: f64,
     pub last_zakat_date: Option<DateTime<Utc>>,
+    pub status: WalletStatus,
+    /// Left out of zakat deduction runs
+    pub zakat_exempt: bool,
+    /// Percentage charged instead of `ZAKAT_PERCENTAGE`, set by admins
+    pub zakat_rate: Option<f64>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Frozen wallets can neither send nor receive and are left out of zakat runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletStatus {
+    Active,
+    Frozen,
+}
+
+impl WalletStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WalletStatus::Active => "active",
+            WalletStatus::Frozen => "frozen",
+        }
+    }
+
+    /// Unknown values read as active; the column's CHECK constraint rules them out anyway
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "frozen" => WalletStatus::Frozen,
+            _ => WalletStatus::Active,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWalletStatusRequest {
+    pub status: WalletStatus,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ZakatExemptionRequest {
+    pub exempt: bool,
+}
+
+/// `None` clears the override so the wallet pays the global rate again
+#[derive(Debug, Deserialize)]
+pub struct ZakatRateRequest {
+    pub zakat_rate: Option<f64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UTXO {
     pub id: Uuid,
     pub wallet_id: String,
-    pub amount: f64,
+    /// Smallest units (see `utils::UNITS_PER_COIN`); decimal coins in JSON
+    #[serde(with = "crate::utils::coin_amount")]
+    pub amount: i64,
     pub transaction_hash: String,
     pub output_index: i32,
     pub is_spent: bool,
@@ -39,6 +88,15 @@ pub struct UTXO {
     pub reserved_by: Option<Uuid>,  // Pending transaction ID that reserved this UTXO
 }
 
+/// Consumed output together with the transaction that consumed it
+#[derive(Debug, Clone, Serialize)]
+pub struct SpentUTXO {
+    #[serde(flatten)]
+    pub utxo: UTXO,
+    /// `None` for outputs spent before spenders were recorded
+    pub spent_by_transaction: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
     pub index: i64,
@@ -48,6 +106,36 @@ pub struct Block {
     pub hash: String,
     pub nonce: i64,
     pub merkle_root: Option<String>,
+    /// Proof-of-work rule the hash was mined under (see `blockchain::block_meets_difficulty`);
+    /// exports from before block versions carry none and are legacy blocks
+    #[serde(default = "legacy_block_version")]
+    pub version: i32,
+}
+
+/// A block's fields without its transactions, for clients that page through those separately
+#[derive(Debug, Serialize)]
+pub struct BlockHeader {
+    pub index: i64,
+    pub timestamp: i64,
+    pub previous_hash: String,
+    pub hash: String,
+    pub nonce: i64,
+    pub merkle_root: Option<String>,
+    pub version: i32,
+    /// Transfers in the block (the coinbase isn't one of its transactions)
+    pub transaction_count: i64,
+}
+
+/// Where the chain currently ends, for clients that poll for new blocks
+#[derive(Debug, Serialize)]
+pub struct ChainTip {
+    pub index: i64,
+    pub hash: String,
+    pub timestamp: i64,
+}
+
+fn legacy_block_version() -> i32 {
+    crate::blockchain::LEGACY_BLOCK_VERSION
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,8 +144,15 @@ pub struct Transaction {
     pub transaction_hash: String,
     pub sender_wallet_id: String,
     pub receiver_wallet_id: String,
-    pub amount: f64,
+    #[serde(with = "crate::utils::coin_amount")]
+    pub amount: i64,
     pub note: Option<String>,
+    /// `note` is ciphertext for the receiver (see `crypto::encrypt_note`)
+    #[serde(default)]
+    pub note_encrypted: bool,
+    /// Fee paid to the miner; `None` for rows imported without one
+    #[serde(default, with = "crate::utils::coin_amount_opt")]
+    pub fee: Option<i64>,
     pub signature: String,
     pub block_index: Option<i64>,
     pub transaction_type: String,
@@ -71,14 +166,68 @@ pub struct PendingTransaction {
     pub transaction_hash: String,
     pub sender_wallet_id: String,
     pub receiver_wallet_id: String,
-    pub amount: f64,
-    pub fee: f64,
+    #[serde(with = "crate::utils::coin_amount")]
+    pub amount: i64,
+    #[serde(with = "crate::utils::coin_amount")]
+    pub fee: i64,
     pub note: Option<String>,
+    #[serde(default)]
+    pub note_encrypted: bool,
     pub signature: String,
     pub timestamp: i64,
     pub created_at: DateTime<Utc>,
 }
 
+/// Mining reward paid by a block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoinbaseOutput {
+    pub wallet_id: String,
+    #[serde(with = "crate::utils::coin_amount")]
+    pub amount: i64,
+    pub transaction_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionFee {
+    pub transaction_hash: String,
+    #[serde(with = "crate::utils::coin_amount")]
+    pub fee: i64,
+}
+
+/// Public key a transfer sender signs with, carried in exports since user accounts aren't
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SenderKey {
+    pub wallet_id: String,
+    /// PEM-encoded RSA public key
+    pub public_key: String,
+}
+
+/// One line of a chain export: a block plus what an import needs to rebuild its UTXOs and check its signatures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedBlock {
+    #[serde(flatten)]
+    pub block: Block,
+    /// At most one for a mined block; genesis has one per allocation
+    #[serde(default)]
+    pub coinbase: Vec<CoinbaseOutput>,
+    /// Transactions confirmed before fees were recorded have no entry
+    #[serde(default)]
+    pub fees: Vec<TransactionFee>,
+    /// Units of `fees` burned when the block was mined
+    #[serde(default, with = "crate::utils::coin_amount")]
+    pub burned_fees: i64,
+    /// Keys of the block's transfer senders; a wallet must keep the same key across the export
+    #[serde(default)]
+    pub sender_keys: Vec<SenderKey>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainImportSummary {
+    pub blocks_imported: usize,
+    pub tip_index: i64,
+    pub tip_hash: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Beneficiary {
     pub id: Uuid,
@@ -95,6 +244,11 @@ pub struct ZakatRecord {
     pub amount: f64,
     pub transaction_hash: Option<String>,
     pub deduction_date: DateTime<Utc>,
+    pub record_type: String,
+    /// Balance the deduction was computed against; `None` for distributions
+    pub balance_at_deduction: Option<f64>,
+    /// Percentage applied to `balance_at_deduction`
+    pub rate: Option<f64>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -123,6 +277,82 @@ pub struct SystemLog {
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub wallet_id: String,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Which wallet events email the user; users who never saved any get `Default`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationPreferences {
+    pub payment_received: bool,
+    pub zakat_deducted: bool,
+    /// Email when a mined send leaves the balance below this; `None` disables
+    #[serde(with = "crate::utils::coin_amount_opt")]
+    pub low_balance_threshold: Option<i64>,
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        NotificationPreferences {
+            payment_received: true,
+            zakat_deducted: false,
+            low_balance_threshold: None,
+        }
+    }
+}
+
+/// Owner of a wallet with what they want to hear about it
+#[derive(Debug, Clone)]
+pub struct NotificationRecipient {
+    pub wallet_id: String,
+    pub email: String,
+    pub preferences: NotificationPreferences,
+    /// Cached available balance, in units
+    pub balance: i64,
+}
+
+/// Email waiting in the outbox
+#[derive(Debug, Clone)]
+pub struct OutboxEmail {
+    pub id: Uuid,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub attempts: i32,
+}
+
+/// Webhook payload due for delivery, with where it goes and the secret it is signed with
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub body: String,
+    pub attempts: i32,
+}
+
+/// Invoice a receiver shares as a signed `blockwallet:` URI; paid at most once, before `expires_at`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub id: Uuid,
+    pub wallet_id: String,
+    #[serde(with = "crate::utils::coin_amount")]
+    pub amount: i64,
+    pub note: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    /// Hash of the transaction that paid it
+    pub fulfilled_by: Option<String>,
+    pub fulfilled_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
 // Request/Response DTOs
 #[derive(Debug, Deserialize)]
 pub struct RegisterRequest {
@@ -165,6 +395,46 @@ pub struct CreateTransactionRequest {
     pub receiver_wallet_id: String,
     pub amount: f64,
     pub note: Option<String>,
+    /// Encrypt `note` to the receiver's public key so only they can read it
+    #[serde(default)]
+    pub private_note: bool,
+    /// Payment request this transfer pays; marked fulfilled once the transaction is created
+    pub payment_request_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewPaymentRequest {
+    pub amount: f64,
+    pub note: Option<String>,
+    pub expires_in_seconds: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ParsePaymentUriRequest {
+    pub uri: String,
+}
+
+/// Fields of a transfer as they are signed; `note` is the stored note (ciphertext for private notes)
+#[derive(Debug, Deserialize)]
+pub struct TransactionPayloadRequest {
+    pub sender_wallet_id: String,
+    pub receiver_wallet_id: String,
+    pub amount: f64,
+    pub timestamp: i64,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifySignatureRequest {
+    pub public_key_pem: String,
+    pub payload: String,
+    /// Hex-encoded, as produced by `crypto::sign_data`
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WalletBalancesRequest {
+    pub wallet_ids: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -173,10 +443,48 @@ pub struct AddBeneficiaryRequest {
     pub nickname: Option<String>,
 }
 
+/// Outcome of one CSV row in a beneficiary import
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BeneficiaryImportStatus {
+    Added,
+    SkippedDuplicate,
+    InvalidWallet,
+    InvalidRow,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BeneficiaryImportResult {
+    /// 1-based line in the uploaded CSV
+    pub line: u64,
+    pub wallet_id: String,
+    pub status: BeneficiaryImportStatus,
+    pub beneficiary_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub wallet_id: String,
+    pub url: String,
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ZakatRecipient {
+    pub wallet_id: String,
+    pub weight: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DistributeZakatRequest {
+    pub recipients: Option<Vec<ZakatRecipient>>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct UpdateProfileRequest {
     pub full_name: Option<String>,
     pub email: Option<String>,
+    pub cnic: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -186,11 +494,70 @@ pub struct ApiResponse<T> {
     pub message: Option<String>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OffsetPage<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// Wallet as listed for admins, with its owner's email and unspent balance
+#[derive(Debug, Serialize)]
+pub struct AdminWalletEntry {
+    pub wallet_id: String,
+    pub user_id: Option<Uuid>,
+    pub email: Option<String>,
+    pub balance: f64,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct WalletBalance {
     pub wallet_id: String,
     pub balance: f64,
     pub utxo_count: i32,
+    pub created_at: DateTime<Utc>,
+    /// Whole days since `created_at`
+    pub age_days: i64,
+}
+
+/// Aggregate activity for a wallet's profile page; mining rewards aren't transactions
+/// and only show up in `unspent_utxo_count`
+#[derive(Debug, Serialize)]
+pub struct WalletSummary {
+    pub wallet_id: String,
+    pub transaction_count: i64,
+    #[serde(with = "crate::utils::coin_amount")]
+    pub total_sent: i64,
+    #[serde(with = "crate::utils::coin_amount")]
+    pub total_received: i64,
+    pub first_transaction_at: Option<DateTime<Utc>>,
+    pub last_activity_at: Option<DateTime<Utc>>,
+    pub unspent_utxo_count: i64,
+}
+
+/// Aggregate view of the pending pool; fee and age fields are `None` when it's empty
+#[derive(Debug, Serialize)]
+pub struct MempoolStats {
+    pub transaction_count: i64,
+    #[serde(with = "crate::utils::coin_amount")]
+    pub total_amount: i64,
+    #[serde(with = "crate::utils::coin_amount")]
+    pub total_fees: i64,
+    #[serde(with = "crate::utils::coin_amount_opt")]
+    pub min_fee: Option<i64>,
+    #[serde(with = "crate::utils::coin_amount_opt")]
+    pub median_fee: Option<i64>,
+    #[serde(with = "crate::utils::coin_amount_opt")]
+    pub max_fee: Option<i64>,
+    pub oldest_pending_seconds: Option<f64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -216,6 +583,167 @@ pub struct MiningStats {
     pub remaining_coins: f64,
     pub halving_interval: i32,
     pub percentage_mined: f64,
+    /// `None` until a block is mined with its work recorded
+    pub last_block_mining_seconds: Option<f64>,
+    /// Hashes per second over recent blocks
+    pub estimated_hashrate: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SupplyInfo {
+    pub max_supply: f64,
+    /// Sum of all coinbase rewards
+    pub total_mined: f64,
+    /// Fees destroyed under `FEE_BURN_PERCENTAGE`; they were never paid out, so they are
+    /// already absent from `total_mined` and `circulating`
+    pub total_burned: f64,
+    pub zakat_pool_balance: f64,
+    /// Unspent rewards with fewer than `COINBASE_MATURITY` blocks on top of them
+    pub immature: f64,
+    /// Mined coins outside the zakat pool, less immature rewards
+    pub circulating: f64,
+    pub percentage_mined: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NegativeBalance {
+    pub wallet_id: String,
+    pub available: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SupplyAudit {
+    pub unspent_supply: f64,
+    pub total_mined_coins: f64,
+    pub max_coin_supply: f64,
+    pub within_max_supply: bool,
+    pub negative_balances: Vec<NegativeBalance>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BlockValidationFailure {
+    pub index: i64,
+    pub reason: String,
+}
+
+/// Outcome of validating the canonical chain. `first_invalid_index` and `reason` describe
+/// the lowest failing block; `failures` lists every one found (just that one unless run in full).
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainValidationReport {
+    pub valid: bool,
+    /// Blocks stored, including any off the canonical chain
+    pub total_blocks: i64,
+    pub first_invalid_index: Option<i64>,
+    pub reason: Option<String>,
+    pub failures: Vec<BlockValidationFailure>,
+}
+
+impl ChainValidationReport {
+    pub fn record_failure(&mut self, index: i64, reason: String) {
+        if self.valid {
+            self.valid = false;
+            self.first_invalid_index = Some(index);
+            self.reason = Some(reason.clone());
+        }
+        self.failures.push(BlockValidationFailure { index, reason });
+    }
+}
+
+/// A transaction looked up by hash, tagged with whether it has been mined yet
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TransactionLookup {
+    Confirmed(Transaction),
+    Pending(PendingTransaction),
+}
+
+/// Whether a transaction is still in the pending pool or has been mined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionStatus {
+    Pending,
+    Confirmed,
+}
+
+/// The one shape clients get for a transaction, pending or confirmed. `block_index` and
+/// `transaction_type` are set once mined. Unset fields serialize as `null`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionView {
+    pub status: TransactionStatus,
+    pub id: Uuid,
+    pub transaction_hash: String,
+    pub sender_wallet_id: String,
+    pub receiver_wallet_id: String,
+    #[serde(with = "crate::utils::coin_amount")]
+    pub amount: i64,
+    #[serde(with = "crate::utils::coin_amount_opt")]
+    pub fee: Option<i64>,
+    pub note: Option<String>,
+    pub note_encrypted: bool,
+    pub signature: String,
+    pub block_index: Option<i64>,
+    pub transaction_type: Option<String>,
+    pub timestamp: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Transaction> for TransactionView {
+    fn from(tx: Transaction) -> Self {
+        TransactionView {
+            status: TransactionStatus::Confirmed,
+            id: tx.id,
+            transaction_hash: tx.transaction_hash,
+            sender_wallet_id: tx.sender_wallet_id,
+            receiver_wallet_id: tx.receiver_wallet_id,
+            amount: tx.amount,
+            fee: tx.fee,
+            note: tx.note,
+            note_encrypted: tx.note_encrypted,
+            signature: tx.signature,
+            block_index: tx.block_index,
+            transaction_type: Some(tx.transaction_type),
+            timestamp: tx.timestamp,
+            created_at: tx.created_at,
+        }
+    }
+}
+
+impl From<PendingTransaction> for TransactionView {
+    fn from(tx: PendingTransaction) -> Self {
+        TransactionView {
+            status: TransactionStatus::Pending,
+            id: tx.id,
+            transaction_hash: tx.transaction_hash,
+            sender_wallet_id: tx.sender_wallet_id,
+            receiver_wallet_id: tx.receiver_wallet_id,
+            amount: tx.amount,
+            fee: Some(tx.fee),
+            note: tx.note,
+            note_encrypted: tx.note_encrypted,
+            signature: tx.signature,
+            block_index: None,
+            transaction_type: None,
+            timestamp: tx.timestamp,
+            created_at: tx.created_at,
+        }
+    }
+}
+
+impl From<TransactionLookup> for TransactionView {
+    fn from(lookup: TransactionLookup) -> Self {
+        match lookup {
+            TransactionLookup::Confirmed(tx) => tx.into(),
+            TransactionLookup::Pending(tx) => tx.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RichlistEntry {
+    pub rank: i64,
+    pub wallet_id: String,
+    pub balance: f64,
+    pub percentage: f64,
 }
 
 #[derive(Debug, Serialize)]
@@ -224,3 +752,68 @@ pub struct KeyPair {
     pub private_key: String,
     pub wallet_id: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending() -> PendingTransaction {
+        PendingTransaction {
+            id: Uuid::new_v4(),
+            transaction_hash: "p".repeat(64),
+            sender_wallet_id: "alice".to_string(),
+            receiver_wallet_id: "bob".to_string(),
+            amount: 250_000_000,
+            fee: 10_000_000,
+            note: Some("rent".to_string()),
+            note_encrypted: false,
+            signature: "sig".to_string(),
+            timestamp: 1_700_000_000,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_pending_view_has_fee_and_no_block() {
+        let json = serde_json::to_value(TransactionView::from(pending())).unwrap();
+
+        assert_eq!(json["status"], "pending");
+        assert_eq!(json["amount"], 2.5);
+        assert_eq!(json["fee"], 0.1);
+        assert!(json["block_index"].is_null());
+        assert!(json["transaction_type"].is_null());
+        assert_eq!(json["note"], "rent");
+    }
+
+    #[test]
+    fn test_confirmed_view_has_block_and_same_shape() {
+        let p = pending();
+        let confirmed = Transaction {
+            id: p.id,
+            transaction_hash: p.transaction_hash.clone(),
+            sender_wallet_id: p.sender_wallet_id.clone(),
+            receiver_wallet_id: p.receiver_wallet_id.clone(),
+            amount: p.amount,
+            note: p.note.clone(),
+            note_encrypted: false,
+            fee: Some(p.fee),
+            signature: p.signature.clone(),
+            block_index: Some(7),
+            transaction_type: "transfer".to_string(),
+            timestamp: p.timestamp,
+            created_at: p.created_at,
+        };
+        let json = serde_json::to_value(TransactionView::from(TransactionLookup::Confirmed(confirmed))).unwrap();
+
+        assert_eq!(json["status"], "confirmed");
+        assert_eq!(json["amount"], 2.5);
+        assert_eq!(json["block_index"], 7);
+        assert_eq!(json["transaction_type"], "transfer");
+        assert_eq!(json["fee"], 0.1);
+
+        // Pending and confirmed serialize with exactly the same keys
+        let pending_json = serde_json::to_value(TransactionView::from(p)).unwrap();
+        let keys = |v: &serde_json::Value| v.as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+        assert_eq!(keys(&json), keys(&pending_json));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::database::DbPool;
+use chrono::{DateTime, Duration, DurationRound, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Upper bound on buckets in a single time-series response
+pub const MAX_BUCKETS: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeseriesMetric {
+    Volume,
+    TxCount,
+    Blocks,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeseriesInterval {
+    Day,
+    Hour,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimeseriesPoint {
+    pub timestamp: DateTime<Utc>,
+    pub value: f64,
+}
+
+impl TimeseriesMetric {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "volume" => Some(TimeseriesMetric::Volume),
+            "tx_count" => Some(TimeseriesMetric::TxCount),
+            "blocks" => Some(TimeseriesMetric::Blocks),
+            _ => None,
+        }
+    }
+
+    fn sql(&self) -> &'static str {
+        match self {
+            TimeseriesMetric::Volume => {
+                "SELECT date_trunc($1, created_at AT TIME ZONE 'UTC') AT TIME ZONE 'UTC' AS bucket,
+                        COALESCE(SUM(amount), 0)::float8
+                 FROM transactions
+                 WHERE created_at >= $2 AND created_at < $3
+                 GROUP BY bucket ORDER BY bucket"
+            }
+            TimeseriesMetric::TxCount => {
+                "SELECT date_trunc($1, created_at AT TIME ZONE 'UTC') AT TIME ZONE 'UTC' AS bucket,
+                        COUNT(*)::float8
+                 FROM transactions
+                 WHERE created_at >= $2 AND created_at < $3
+                 GROUP BY bucket ORDER BY bucket"
+            }
+            TimeseriesMetric::Blocks => {
+                "SELECT date_trunc($1, created_at AT TIME ZONE 'UTC') AT TIME ZONE 'UTC' AS bucket,
+                        COUNT(*)::float8
+                 FROM blocks
+                 WHERE created_at >= $2 AND created_at < $3
+                 GROUP BY bucket ORDER BY bucket"
+            }
+        }
+    }
+}
+
+impl TimeseriesInterval {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "day" => Some(TimeseriesInterval::Day),
+            "hour" => Some(TimeseriesInterval::Hour),
+            _ => None,
+        }
+    }
+
+    /// Unit name as understood by SQL `date_trunc`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeseriesInterval::Day => "day",
+            TimeseriesInterval::Hour => "hour",
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            TimeseriesInterval::Day => Duration::days(1),
+            TimeseriesInterval::Hour => Duration::hours(1),
+        }
+    }
+
+    /// Start of the bucket containing `ts` (matches `date_trunc` in UTC)
+    pub fn bucket_start(&self, ts: DateTime<Utc>) -> DateTime<Utc> {
+        ts.duration_trunc(self.duration()).unwrap_or(ts)
+    }
+}
+
+/// Parse an RFC 3339 timestamp or a plain `YYYY-MM-DD` date (midnight UTC)
+pub fn parse_range_bound(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|t| t.and_utc())
+        })
+}
+
+/// Resolve a report's `[start, end)` range from optional `from`/`to` params,
+/// defaulting to the 30 days before `now`. A date-only `to` covers that whole day.
+pub fn report_range(
+    from: Option<&str>,
+    to: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+    let to_bound = match to {
+        Some(t) => parse_range_bound(t).ok_or_else(|| "Invalid 'to' date".to_string())?,
+        None => now,
+    };
+
+    let start = match from {
+        Some(f) => parse_range_bound(f).ok_or_else(|| "Invalid 'from' date".to_string())?,
+        None => to_bound - Duration::days(30),
+    };
+
+    if start > to_bound {
+        return Err("'from' must not be after 'to'".to_string());
+    }
+
+    let end = match to {
+        Some(t) if NaiveDate::parse_from_str(t, "%Y-%m-%d").is_ok() => to_bound + Duration::days(1),
+        _ => to_bound,
+    };
+
+    Ok((start, end))
+}
+
+/// Check the range is ordered and small enough, returning the number of buckets it spans
+pub fn validate_range(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    interval: TimeseriesInterval,
+) -> Result<i64, String> {
+    if from >= to {
+        return Err("'from' must be before 'to'".to_string());
+    }
+
+    let first = interval.bucket_start(from);
+    let step = interval.duration().num_seconds();
+    let buckets = ((to - first).num_seconds() + step - 1) / step;
+
+    if buckets > MAX_BUCKETS {
+        return Err(format!(
+            "Range spans {} buckets, maximum is {}",
+            buckets, MAX_BUCKETS
+        ));
+    }
+
+    Ok(buckets)
+}
+
+/// Expand sparse `(bucket, value)` rows into a contiguous series, zero-filling empty buckets
+pub fn fill_buckets(
+    rows: Vec<(DateTime<Utc>, f64)>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    interval: TimeseriesInterval,
+) -> Vec<TimeseriesPoint> {
+    let values: HashMap<DateTime<Utc>, f64> = rows.into_iter().collect();
+
+    let mut points = Vec::new();
+    let mut bucket = interval.bucket_start(from);
+    while bucket < to {
+        points.push(TimeseriesPoint {
+            timestamp: bucket,
+            value: values.get(&bucket).copied().unwrap_or(0.0),
+        });
+        bucket += interval.duration();
+    }
+
+    points
+}
+
+pub async fn get_timeseries(
+    pool: &DbPool,
+    metric: TimeseriesMetric,
+    interval: TimeseriesInterval,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<TimeseriesPoint>, anyhow::Error> {
+    let client = pool.get().await?;
+
+    let rows = client
+        .query(metric.sql(), &[&interval.as_str(), &from, &to])
+        .await?;
+
+    let rows = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+
+    Ok(fill_buckets(rows, from, to, interval))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        parse_range_bound(s).unwrap()
+    }
+
+    #[test]
+    fn test_daily_tx_count_bucketing() {
+        let seeded = [
+            "2024-05-01T00:00:00Z",
+            "2024-05-01T13:45:00Z",
+            "2024-05-01T23:59:59Z",
+            "2024-05-03T08:00:00Z",
+        ];
+
+        // Group the way `date_trunc('day', ...)` does
+        let mut counts: HashMap<DateTime<Utc>, f64> = HashMap::new();
+        for ts in seeded {
+            *counts.entry(TimeseriesInterval::Day.bucket_start(at(ts))).or_default() += 1.0;
+        }
+
+        let series = fill_buckets(
+            counts.into_iter().collect(),
+            at("2024-05-01"),
+            at("2024-05-04"),
+            TimeseriesInterval::Day,
+        );
+
+        let values: Vec<(DateTime<Utc>, f64)> = series.iter().map(|p| (p.timestamp, p.value)).collect();
+        assert_eq!(
+            values,
+            vec![
+                (at("2024-05-01"), 3.0),
+                (at("2024-05-02"), 0.0),
+                (at("2024-05-03"), 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_range() {
+        assert_eq!(validate_range(at("2024-05-01"), at("2024-05-04"), TimeseriesInterval::Day), Ok(3));
+        assert!(validate_range(at("2024-05-04"), at("2024-05-01"), TimeseriesInterval::Day).is_err());
+        assert!(validate_range(at("2024-01-01"), at("2024-03-01"), TimeseriesInterval::Hour).is_err());
+    }
+
+    #[test]
+    fn test_report_range_custom() {
+        let now = at("2024-06-15T12:00:00Z");
+
+        let (start, end) = report_range(Some("2024-01-01"), Some("2024-03-31"), now).unwrap();
+        assert_eq!(start, at("2024-01-01"));
+        assert_eq!(end, at("2024-04-01"));
+
+        assert!(report_range(Some("2024-04-01"), Some("2024-03-31"), now).is_err());
+        assert!(report_range(Some("last month"), None, now).is_err());
+    }
+
+    #[test]
+    fn test_report_range_default() {
+        let now = at("2024-06-15T12:00:00Z");
+
+        let (start, end) = report_range(None, None, now).unwrap();
+        assert_eq!(end, now);
+        assert_eq!(start, at("2024-05-16T12:00:00Z"));
+    }
+
+    #[test]
+    fn test_parse_params() {
+        assert_eq!(TimeseriesMetric::parse("tx_count"), Some(TimeseriesMetric::TxCount));
+        assert_eq!(TimeseriesMetric::parse("users"), None);
+        assert_eq!(TimeseriesInterval::parse("week"), None);
+        assert_eq!(at("2024-05-01T10:30:00+02:00"), at("2024-05-01T08:30:00Z"));
+    }
+}
@@ -1,23 +1,51 @@
-use crate::models::{RegisterRequest, User};
+use crate::config::{JwtConfig, PasswordPolicy};
+use crate::models::{RegisterRequest, UpdateProfileRequest, User};
 use crate::database::{DbPool, queries};
 use crate::services::wallet_service::generate_wallet_keypair;
-use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey};
+use crate::shutdown::{run_until_shutdown, Shutdown};
+use jsonwebtoken::{encode, decode, Header, Validation};
 use serde::{Deserialize, Serialize};
 use chrono::{Utc, Duration};
+use std::collections::HashSet;
 use std::env;
 use std::ops::DerefMut;
+use std::sync::{OnceLock, RwLock};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // user_id
     pub email: String,
+    #[serde(default = "default_role")]
+    pub role: String,
     pub exp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+}
+
+fn default_role() -> String {
+    "user".to_string()
+}
+
+impl Claims {
+    pub fn is_admin(&self) -> bool {
+        self.role == "admin"
+    }
 }
 
 #[derive(Debug)]
 pub enum AuthError {
     UserAlreadyExists,
+    CnicAlreadyExists,
+    EmailInUse,
+    UserNotFound,
+    NoFieldsToUpdate,
     InvalidCredentials,
+    /// The account still holds coins or has transfers in flight
+    AccountNotEmpty(String),
+    /// Unmet password policy requirements, in human-readable form
+    WeakPassword(Vec<String>),
     TokenError(String),
     DatabaseError(String),
     WalletError(String),
@@ -27,7 +55,13 @@ impl std::fmt::Display for AuthError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             AuthError::UserAlreadyExists => write!(f, "User already exists"),
+            AuthError::CnicAlreadyExists => write!(f, "An account with this CNIC already exists"),
+            AuthError::EmailInUse => write!(f, "Email already in use"),
+            AuthError::UserNotFound => write!(f, "User not found"),
+            AuthError::NoFieldsToUpdate => write!(f, "No fields to update"),
             AuthError::InvalidCredentials => write!(f, "Invalid credentials"),
+            AuthError::AccountNotEmpty(reason) => write!(f, "Account can't be deleted: {}", reason),
+            AuthError::WeakPassword(unmet) => write!(f, "Password must contain {}", unmet.join(", ")),
             AuthError::TokenError(msg) => write!(f, "Token error: {}", msg),
             AuthError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
             AuthError::WalletError(msg) => write!(f, "Wallet error: {}", msg),
@@ -37,6 +71,30 @@ impl std::fmt::Display for AuthError {
 
 impl std::error::Error for AuthError {}
 
+/// Check `password` against `policy`. Used by every endpoint that sets a password.
+pub fn validate_password(password: &str, policy: &PasswordPolicy) -> Result<(), AuthError> {
+    let mut unmet = Vec::new();
+
+    if password.chars().count() < policy.min_length {
+        unmet.push(format!("at least {} characters", policy.min_length));
+    }
+    if policy.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+        unmet.push("a digit".to_string());
+    }
+    if policy.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+        unmet.push("an uppercase letter".to_string());
+    }
+    if policy.require_symbol && !password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace()) {
+        unmet.push("a symbol".to_string());
+    }
+
+    if unmet.is_empty() {
+        Ok(())
+    } else {
+        Err(AuthError::WeakPassword(unmet))
+    }
+}
+
 /// Register a new user
 pub async fn register_user(
     pool: &DbPool,
@@ -63,20 +121,44 @@ pub async fn register_user(
         return Err(AuthError::UserAlreadyExists);
     }
 
+    // One account per CNIC, whatever the formatting (matches idx_users_cnic_normalized)
+    let existing_cnic_check = transaction
+        .query_opt(
+            "SELECT id FROM users WHERE regexp_replace(cnic, '[-\\s]', '', 'g') = $1",
+            &[&normalize_cnic(&req.cnic)],
+        )
+        .await
+        .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+    if existing_cnic_check.is_some() {
+        return Err(AuthError::CnicAlreadyExists);
+    }
+
     // Generate wallet keypair
     let keypair = generate_wallet_keypair(aes_key)
         .map_err(|e| AuthError::WalletError(e.to_string()))?;
 
+    // Emails listed in ADMIN_EMAILS are registered as admins
+    let role = if admin_emails().contains(&req.email.to_lowercase()) { "admin" } else { "user" };
+
     // Create user
     let user_row = transaction
         .query_one(
-            "INSERT INTO users (email, full_name, cnic, wallet_id, public_key, encrypted_private_key) 
-             VALUES ($1, $2, $3, $4, $5, $6) 
-             RETURNING id, email, full_name, cnic, wallet_id, public_key, encrypted_private_key, is_verified, created_at, updated_at",
-            &[&req.email, &req.full_name, &req.cnic, &keypair.wallet_id, &keypair.public_key, &keypair.private_key],
+            "INSERT INTO users (email, full_name, cnic, wallet_id, public_key, encrypted_private_key, role) 
+             VALUES ($1, $2, $3, $4, $5, $6, $7) 
+             RETURNING id, email, full_name, cnic, wallet_id, public_key, encrypted_private_key, is_verified, created_at, updated_at, role",
+            &[&req.email, &req.full_name, &req.cnic, &keypair.wallet_id, &keypair.public_key, &keypair.private_key, &role],
         )
         .await
-        .map_err(|e| AuthError::DatabaseError(format!("Failed to create user: {}", e)))?;
+        .map_err(|e| {
+            // A concurrent registration can slip past the check above; the unique index still catches it
+            let constraint = e.as_db_error().and_then(|db| db.constraint());
+            if constraint == Some("idx_users_cnic_normalized") {
+                AuthError::CnicAlreadyExists
+            } else {
+                AuthError::DatabaseError(format!("Failed to create user: {}", e))
+            }
+        })?;
 
     let user = User {
         id: user_row.get(0),
@@ -89,6 +171,7 @@ pub async fn register_user(
         is_verified: user_row.get(7),
         created_at: user_row.get(8),
         updated_at: user_row.get(9),
+        role: user_row.get(10),
     };
 
     // Create wallet
@@ -118,40 +201,639 @@ pub async fn register_user(
     Ok(user)
 }
 
+/// Update the caller's name, email and/or CNIC. Fields equal to the stored value are skipped,
+/// so repeating the same request returns the user unchanged.
+pub async fn update_profile(
+    pool: &DbPool,
+    user_id: uuid::Uuid,
+    req: &UpdateProfileRequest,
+) -> Result<User, AuthError> {
+    if req.full_name.is_none() && req.email.is_none() && req.cnic.is_none() {
+        return Err(AuthError::NoFieldsToUpdate);
+    }
+
+    let client = pool.get().await
+        .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+    let current = queries::find_user_by_id(&client, user_id)
+        .await
+        .map_err(|e| AuthError::DatabaseError(e.to_string()))?
+        .ok_or(AuthError::UserNotFound)?;
+
+    let mut set = queries::UpdateSet::default();
+
+    if let Some(full_name) = req.full_name.as_ref().filter(|n| **n != current.full_name) {
+        set.push("full_name", full_name.clone());
+    }
+
+    if let Some(email) = req.email.as_ref().filter(|e| **e != current.email) {
+        let taken = client
+            .query_opt("SELECT id FROM users WHERE email = $1 AND id != $2", &[email, &user_id])
+            .await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        if taken.is_some() {
+            return Err(AuthError::EmailInUse);
+        }
+
+        // A new address has to be verified again
+        set.push("email", email.clone());
+        set.push("is_verified", false);
+    }
+
+    if let Some(cnic) = req.cnic.as_ref().filter(|c| normalize_cnic(c) != normalize_cnic(&current.cnic)) {
+        let taken = client
+            .query_opt(
+                "SELECT id FROM users WHERE regexp_replace(cnic, '[-\\s]', '', 'g') = $1 AND id != $2",
+                &[&normalize_cnic(cnic), &user_id],
+            )
+            .await
+            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+        if taken.is_some() {
+            return Err(AuthError::CnicAlreadyExists);
+        }
+
+        set.push("cnic", cnic.clone());
+    }
+
+    if set.is_empty() {
+        return Ok(current);
+    }
+
+    set.push("updated_at", Utc::now());
+
+    queries::update_user(&client, user_id, &set)
+        .await
+        .map_err(|e| {
+            // Concurrent updates can slip past the checks above; the unique indexes still catch them
+            match e.as_db_error().and_then(|db| db.constraint()) {
+                Some("idx_users_cnic_normalized") => AuthError::CnicAlreadyExists,
+                Some("users_email_key") => AuthError::EmailInUse,
+                _ => AuthError::DatabaseError(format!("Failed to update profile: {}", e)),
+            }
+        })?
+        .ok_or(AuthError::UserNotFound)
+}
+
+/// Delete the caller's account. The user row and wallet stay, since the chain references the
+/// wallet, but email, name and CNIC are replaced, the account's beneficiaries, webhooks, OTPs
+/// and queued emails are removed, and its tokens stop working. Refused while the wallet holds
+/// coins or has pending transactions.
+pub async fn delete_account(pool: &DbPool, user_id: uuid::Uuid) -> Result<(), AuthError> {
+    let mut client = pool.get().await
+        .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+    let db = |e: tokio_postgres::Error| AuthError::DatabaseError(e.to_string());
+
+    let transaction = client.deref_mut().transaction().await.map_err(db)?;
+
+    let row = transaction
+        .query_opt(
+            "SELECT email, wallet_id FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+            &[&user_id],
+        )
+        .await
+        .map_err(db)?
+        .ok_or(AuthError::UserNotFound)?;
+    let email: String = row.get(0);
+    let wallet_id: String = row.get(1);
+
+    let balance: i64 = transaction
+        .query_one(
+            "SELECT (COALESCE(SUM(amount), 0) * 100000000)::int8 FROM utxos WHERE wallet_id = $1 AND is_spent = false",
+            &[&wallet_id],
+        )
+        .await
+        .map_err(db)?
+        .get(0);
+    if balance > 0 {
+        return Err(AuthError::AccountNotEmpty(format!(
+            "wallet still holds {}; transfer it out first",
+            crate::utils::format_currency(crate::utils::from_units(balance))
+        )));
+    }
+    let pending: i64 = transaction
+        .query_one(
+            "SELECT COUNT(*) FROM pending_transactions WHERE sender_wallet_id = $1 OR receiver_wallet_id = $1",
+            &[&wallet_id],
+        )
+        .await
+        .map_err(db)?
+        .get(0);
+    if pending > 0 {
+        return Err(AuthError::AccountNotEmpty(format!(
+            "{} pending transaction(s) must be mined first",
+            pending
+        )));
+    }
+
+    // Unique placeholders: email and CNIC are unique columns
+    let tag = user_id.simple().to_string();
+    transaction
+        .execute(
+            "UPDATE users SET email = $2, full_name = 'Deleted user', cnic = $3, is_verified = FALSE,
+                role = 'user', deleted_at = NOW(), updated_at = NOW()
+             WHERE id = $1",
+            &[&user_id, &format!("deleted-{}@deleted.invalid", tag), &format!("deleted-{}", &tag[..12])],
+        )
+        .await
+        .map_err(db)?;
+    for sql in [
+        "DELETE FROM beneficiaries WHERE user_id = $1",
+        "DELETE FROM notification_preferences WHERE user_id = $1",
+    ] {
+        transaction.execute(sql, &[&user_id]).await.map_err(db)?;
+    }
+    for sql in [
+        "DELETE FROM email_otps WHERE email = $1",
+        "DELETE FROM email_outbox WHERE recipient = $1 AND status = 'queued'",
+    ] {
+        transaction.execute(sql, &[&email]).await.map_err(db)?;
+    }
+    transaction
+        .execute("DELETE FROM webhooks WHERE wallet_id = $1", &[&wallet_id])
+        .await
+        .map_err(db)?;
+    // Earlier log lines (registration) quote the address
+    transaction
+        .execute(
+            "UPDATE system_logs SET message = replace(message, $2, '[deleted]') WHERE user_id = $1",
+            &[&user_id, &email],
+        )
+        .await
+        .map_err(db)?;
+    transaction
+        .execute(
+            "INSERT INTO system_logs (log_type, user_id, message) VALUES ($1, $2, $3)",
+            &[&"account_deleted", &user_id, &format!("Account deleted; wallet {} kept", wallet_id)],
+        )
+        .await
+        .map_err(db)?;
+
+    transaction.commit().await
+        .map_err(|e| AuthError::DatabaseError(format!("Failed to commit transaction: {}", e)))?;
+
+    revoke_user_tokens(&user_id.to_string());
+    log::info!("🗑️ Account {} deleted (wallet {} kept)", user_id, wallet_id);
+
+    Ok(())
+}
+
+/// Users whose tokens are refused, i.e. deleted accounts. Filled by [`delete_account`] on this
+/// node and by [`start_revocation_sync`] for deletions made on other nodes.
+fn revoked_users() -> &'static RwLock<HashSet<String>> {
+    static REVOKED: OnceLock<RwLock<HashSet<String>>> = OnceLock::new();
+    REVOKED.get_or_init(Default::default)
+}
+
+fn revoke_user_tokens(user_id: &str) {
+    revoked_users().write().unwrap_or_else(|e| e.into_inner()).insert(user_id.to_string());
+}
+
+fn is_revoked(user_id: &str) -> bool {
+    revoked_users().read().unwrap_or_else(|e| e.into_inner()).contains(user_id)
+}
+
+/// Load every deleted account into the revocation set; returns how many were new
+pub async fn sync_revoked_users(pool: &DbPool) -> Result<usize, AuthError> {
+    let client = pool.get().await
+        .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+    let rows = client
+        .query("SELECT id::text FROM users WHERE deleted_at IS NOT NULL", &[])
+        .await
+        .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+    let mut revoked = revoked_users().write().unwrap_or_else(|e| e.into_inner());
+    Ok(rows.iter().filter(|row| revoked.insert(row.get(0))).count())
+}
+
+/// Refresh the revocation set every minute, so an account deleted through another node
+/// loses its tokens here too
+pub async fn start_revocation_sync(pool: DbPool, shutdown: Shutdown) {
+    run_until_shutdown(std::time::Duration::from_secs(60), shutdown, || async {
+        match sync_revoked_users(&pool).await {
+            Ok(0) => {}
+            Ok(n) => log::info!("🔒 Revoked tokens of {} deleted account(s)", n),
+            Err(e) => log::error!("Error syncing revoked accounts: {}", e),
+        }
+    })
+    .await;
+}
+
+/// CNIC with dashes and whitespace removed, so "35202-1234567-1" and "3520212345671" compare equal
+pub fn normalize_cnic(cnic: &str) -> String {
+    cnic.chars().filter(|c| *c != '-' && !c.is_whitespace()).collect()
+}
+
+/// Emails configured as admins via the comma-separated `ADMIN_EMAILS` variable
+pub fn admin_emails() -> Vec<String> {
+    env::var("ADMIN_EMAILS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|e| e.trim().to_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+/// Promote existing users listed in `ADMIN_EMAILS` to admin
+pub async fn seed_admins(pool: &DbPool) -> Result<u64, AuthError> {
+    let emails = admin_emails();
+    if emails.is_empty() {
+        return Ok(0);
+    }
+
+    let client = pool.get().await
+        .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+
+    client
+        .execute(
+            "UPDATE users SET role = 'admin' WHERE LOWER(email) = ANY($1) AND role <> 'admin'",
+            &[&emails],
+        )
+        .await
+        .map_err(|e| AuthError::DatabaseError(e.to_string()))
+}
+
+/// The token configuration, loaded once. main refuses to start when it is invalid; should
+/// one get this far anyway, every token operation fails instead of using a weak key.
+fn jwt_config() -> Result<&'static JwtConfig, AuthError> {
+    static JWT: OnceLock<Result<JwtConfig, String>> = OnceLock::new();
+    JWT.get_or_init(|| JwtConfig::from_env().map_err(|e| e.to_string()))
+        .as_ref()
+        .map_err(|e| AuthError::TokenError(e.clone()))
+}
+
 /// Generate JWT token
-pub fn generate_token(user_id: &str, email: &str) -> Result<String, AuthError> {
-    let jwt_secret = env::var("JWT_SECRET")
-        .unwrap_or_else(|_| "default-secret-change-in-production".to_string());
+pub fn generate_token(user_id: &str, email: &str, role: &str) -> Result<String, AuthError> {
+    generate_token_with(jwt_config()?, user_id, email, role)
+}
 
+/// Generate a token signed as `config` describes
+pub fn generate_token_with(config: &JwtConfig, user_id: &str, email: &str, role: &str) -> Result<String, AuthError> {
     let expiration = Utc::now()
-        .checked_add_signed(Duration::hours(24))
+        .checked_add_signed(Duration::hours(config.expiry_hours))
         .expect("valid timestamp")
         .timestamp();
 
     let claims = Claims {
         sub: user_id.to_string(),
         email: email.to_string(),
+        role: role.to_string(),
         exp: expiration,
+        iss: config.issuer.clone(),
+        aud: config.audience.clone(),
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(jwt_secret.as_ref()),
-    )
-    .map_err(|e| AuthError::TokenError(e.to_string()))
+    encode(&Header::new(config.algorithm), &claims, &config.encoding_key)
+        .map_err(|e| AuthError::TokenError(e.to_string()))
 }
 
-/// Verify JWT token
+/// Verify JWT token, refusing tokens of deleted accounts
 pub fn verify_token(token: &str) -> Result<Claims, AuthError> {
-    let jwt_secret = env::var("JWT_SECRET")
-        .unwrap_or_else(|_| "default-secret-change-in-production".to_string());
-
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(jwt_secret.as_ref()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
-    .map_err(|e| AuthError::TokenError(e.to_string()))
+    let claims = verify_token_with(jwt_config()?, token)?;
+    if is_revoked(&claims.sub) {
+        return Err(AuthError::TokenError("Token revoked".to_string()));
+    }
+    Ok(claims)
+}
+
+/// Verify a token against `config`: its algorithm and key, and its issuer and audience when set
+pub fn verify_token_with(config: &JwtConfig, token: &str) -> Result<Claims, AuthError> {
+    let mut validation = Validation::new(config.algorithm);
+    // A configured claim must be present, not merely correct when present
+    let mut required = vec!["exp"];
+    if let Some(issuer) = &config.issuer {
+        validation.set_issuer(&[issuer]);
+        required.push("iss");
+    }
+    match &config.audience {
+        Some(audience) => {
+            validation.set_audience(&[audience]);
+            required.push("aud");
+        }
+        None => validation.validate_aud = false,
+    }
+    validation.set_required_spec_claims(&required);
+
+    decode::<Claims>(token, &config.decoding_key, &validation)
+        .map(|data| data.claims)
+        .map_err(|e| AuthError::TokenError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register_request(email: &str, cnic: &str) -> RegisterRequest {
+        RegisterRequest {
+            email: email.to_string(),
+            full_name: "Test User".to_string(),
+            cnic: cnic.to_string(),
+            password: "password123".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_normalize_cnic() {
+        assert_eq!(normalize_cnic("35202-1234567-1"), "3520212345671");
+        assert_eq!(normalize_cnic(" 3520212345671 "), "3520212345671");
+    }
+
+    #[test]
+    fn test_weak_password_lists_unmet_requirements() {
+        let policy = PasswordPolicy {
+            min_length: 10,
+            require_digit: true,
+            require_uppercase: true,
+            require_symbol: true,
+        };
+
+        match validate_password("abc", &policy) {
+            Err(AuthError::WeakPassword(unmet)) => assert_eq!(
+                unmet,
+                vec!["at least 10 characters", "a digit", "an uppercase letter", "a symbol"]
+            ),
+            other => panic!("expected WeakPassword, got {:?}", other),
+        }
+        match validate_password("Abcdefghij1", &policy) {
+            Err(AuthError::WeakPassword(unmet)) => assert_eq!(unmet, vec!["a symbol"]),
+            other => panic!("expected WeakPassword, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_strong_password_accepted() {
+        let policy = PasswordPolicy {
+            min_length: 10,
+            require_digit: true,
+            require_uppercase: true,
+            require_symbol: true,
+        };
+        assert!(validate_password("Corr3ct-Horse", &policy).is_ok());
+        assert!(validate_password("password123", &PasswordPolicy::default()).is_ok());
+    }
+
+    #[test]
+    fn test_token_uses_configured_expiry() {
+        let config = JwtConfig { expiry_hours: 2, ..JwtConfig::hs256("secret") };
+        let token = generate_token_with(&config, "u1", "a@example.com", "user").unwrap();
+
+        let claims = verify_token_with(&config, &token).unwrap();
+        let expected = (Utc::now() + Duration::hours(2)).timestamp();
+        assert!((claims.exp - expected).abs() <= 5, "exp {} vs {}", claims.exp, expected);
+        assert_eq!(claims.sub, "u1");
+
+        assert!(verify_token_with(&JwtConfig::hs256("other"), &token).is_err());
+    }
+
+    #[test]
+    fn test_rs256_token_verifies_with_matching_public_key() {
+        let pem = || {
+            let (private_key, public_key) = crate::crypto::generate_keypair().unwrap();
+            (
+                crate::crypto::export_private_key_pem(&private_key).unwrap(),
+                crate::crypto::export_public_key_pem(&public_key).unwrap(),
+            )
+        };
+        let (private_pem, public_pem) = pem();
+        let (_, other_public_pem) = pem();
+
+        let config = JwtConfig::rs256(&private_pem, &public_pem).unwrap();
+        let token = generate_token_with(&config, "u1", "a@example.com", "admin").unwrap();
+        assert!(verify_token_with(&config, &token).unwrap().is_admin());
+
+        let other = JwtConfig::rs256(&private_pem, &other_public_pem).unwrap();
+        assert!(verify_token_with(&other, &token).is_err());
+        // An HS256 node never accepts it, whatever its secret
+        assert!(verify_token_with(&JwtConfig::hs256("secret"), &token).is_err());
+    }
+
+    #[test]
+    fn test_production_refuses_default_jwt_secret() {
+        use crate::config::{AppEnv, DEFAULT_JWT_SECRET};
+        let vars = |secret: Option<&'static str>| {
+            move |name: &str| match name {
+                "JWT_SECRET" => secret.map(String::from),
+                _ => None,
+            }
+        };
+
+        // No usable secret in production: no config, so nothing can be issued or verified
+        for secret in [None, Some(""), Some(DEFAULT_JWT_SECRET)] {
+            let err = JwtConfig::load(AppEnv::Production, &vars(secret)).err().unwrap();
+            assert!(err.contains("APP_ENV=production"), "{}", err);
+        }
+
+        // Development still runs on the fallback, whose tokens a real secret rejects
+        let dev = JwtConfig::load(AppEnv::Development, &vars(None)).unwrap();
+        let token = generate_token_with(&dev, "u1", "a@example.com", "admin").unwrap();
+        assert!(verify_token_with(&JwtConfig::hs256(DEFAULT_JWT_SECRET), &token).is_ok());
+
+        let prod = JwtConfig::load(AppEnv::Production, &vars(Some("a-real-secret"))).unwrap();
+        assert!(verify_token_with(&prod, &token).is_err());
+        let token = generate_token_with(&prod, "u1", "a@example.com", "admin").unwrap();
+        assert!(verify_token_with(&prod, &token).is_ok());
+    }
+
+    #[test]
+    fn test_issuer_and_audience_checked_when_configured() {
+        let plain = JwtConfig::hs256("secret");
+        let scoped = JwtConfig {
+            issuer: Some("blockwallet".to_string()),
+            audience: Some("api".to_string()),
+            ..JwtConfig::hs256("secret")
+        };
+
+        let token = generate_token_with(&scoped, "u1", "a@example.com", "user").unwrap();
+        let claims = verify_token_with(&scoped, &token).unwrap();
+        assert_eq!(claims.iss.as_deref(), Some("blockwallet"));
+        assert_eq!(claims.aud.as_deref(), Some("api"));
+
+        let unscoped = generate_token_with(&plain, "u1", "a@example.com", "user").unwrap();
+        assert!(verify_token_with(&scoped, &unscoped).is_err());
+        let wrong_audience = JwtConfig { audience: Some("admin".to_string()), ..scoped.clone() };
+        assert!(verify_token_with(&wrong_audience, &token).is_err());
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_register_rejects_duplicate_cnic() {
+        let pool = match crate::database::create_test_pool("auth_cnic").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        register_user(&pool, register_request("first@example.com", "35202-1234567-1"), &aes_key)
+            .await
+            .unwrap();
+
+        // Same CNIC without dashes, different email
+        let duplicate = register_user(&pool, register_request("second@example.com", "3520212345671"), &aes_key).await;
+        assert!(matches!(duplicate, Err(AuthError::CnicAlreadyExists)));
+
+        // A fresh CNIC registers fine
+        let fresh = register_user(&pool, register_request("third@example.com", "35202-7654321-9"), &aes_key).await;
+        assert!(fresh.is_ok());
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_update_profile() {
+        let pool = match crate::database::create_test_pool("auth_profile").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let user = register_user(&pool, register_request("profile@example.com", "35202-1111111-1"), &aes_key)
+            .await
+            .unwrap();
+        register_user(&pool, register_request("taken@example.com", "35202-2222222-2"), &aes_key)
+            .await
+            .unwrap();
+        pool.get().await.unwrap()
+            .execute("UPDATE users SET is_verified = TRUE WHERE id = $1", &[&user.id])
+            .await
+            .unwrap();
+
+        let update = |full_name: Option<&str>, email: Option<&str>, cnic: Option<&str>| UpdateProfileRequest {
+            full_name: full_name.map(str::to_string),
+            email: email.map(str::to_string),
+            cnic: cnic.map(str::to_string),
+        };
+
+        // Name only keeps the email verified
+        let updated = update_profile(&pool, user.id, &update(Some("Renamed"), None, None)).await.unwrap();
+        assert_eq!(updated.full_name, "Renamed");
+        assert_eq!(updated.email, "profile@example.com");
+        assert!(updated.is_verified);
+
+        // Email only resets verification
+        let updated = update_profile(&pool, user.id, &update(None, Some("new@example.com"), None)).await.unwrap();
+        assert_eq!(updated.email, "new@example.com");
+        assert_eq!(updated.full_name, "Renamed");
+        assert!(!updated.is_verified);
+
+        // Both at once
+        let updated = update_profile(&pool, user.id, &update(Some("Both"), Some("both@example.com"), None))
+            .await
+            .unwrap();
+        assert_eq!((updated.full_name.as_str(), updated.email.as_str()), ("Both", "both@example.com"));
+
+        // Repeating the same request is a no-op
+        let repeated = update_profile(&pool, user.id, &update(Some("Both"), Some("both@example.com"), None))
+            .await
+            .unwrap();
+        assert_eq!(repeated.updated_at, updated.updated_at);
+
+        // No fields
+        let empty = update_profile(&pool, user.id, &update(None, None, None)).await;
+        assert!(matches!(empty, Err(AuthError::NoFieldsToUpdate)));
+
+        // CNIC is applied and checked against other accounts
+        let updated = update_profile(&pool, user.id, &update(None, None, Some("35202-3333333-3"))).await.unwrap();
+        assert_eq!(updated.cnic, "35202-3333333-3");
+        let taken_cnic = update_profile(&pool, user.id, &update(None, None, Some("3520222222222"))).await;
+        assert!(matches!(taken_cnic, Err(AuthError::CnicAlreadyExists)));
+        let taken_email = update_profile(&pool, user.id, &update(None, Some("taken@example.com"), None)).await;
+        assert!(matches!(taken_email, Err(AuthError::EmailInUse)));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_delete_account_anonymizes_and_revokes() {
+        let Some(pool) = crate::database::create_test_pool("auth_delete").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let user = register_user(&pool, register_request("leaving@example.com", "35202-4444444-4"), &aes_key)
+            .await
+            .unwrap();
+        let client = pool.get().await.unwrap();
+        client
+            .execute(
+                "INSERT INTO beneficiaries (user_id, beneficiary_wallet_id, nickname) VALUES ($1, 'friend', 'Friend')",
+                &[&user.id],
+            )
+            .await
+            .unwrap();
+        let token = generate_token(&user.id.to_string(), &user.email, &user.role).unwrap();
+        assert!(verify_token(&token).is_ok());
+
+        delete_account(&pool, user.id).await.unwrap();
+
+        let deleted = queries::find_user_by_id(&client, user.id).await.unwrap().unwrap();
+        assert_eq!(deleted.full_name, "Deleted user");
+        assert!(!deleted.email.contains("leaving"));
+        assert!(!deleted.cnic.contains("4444444"));
+        assert_eq!(deleted.wallet_id, user.wallet_id);
+        assert!(queries::get_wallet(&client, &user.wallet_id).await.unwrap().is_some());
+        assert!(queries::find_user_by_email(&client, "leaving@example.com").await.unwrap().is_none());
+
+        let leftovers: i64 = client
+            .query_one(
+                "SELECT (SELECT COUNT(*) FROM beneficiaries WHERE user_id = $1)
+                      + (SELECT COUNT(*) FROM system_logs WHERE user_id = $1 AND message LIKE '%leaving@%')",
+                &[&user.id],
+            )
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(leftovers, 0);
+        let logged: i64 = client
+            .query_one("SELECT COUNT(*) FROM system_logs WHERE user_id = $1 AND log_type = 'account_deleted'", &[&user.id])
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(logged, 1);
+
+        assert!(matches!(verify_token(&token), Err(AuthError::TokenError(_))));
+        assert!(matches!(delete_account(&pool, user.id).await, Err(AuthError::UserNotFound)));
+
+        // The CNIC and email are free to register again
+        register_user(&pool, register_request("leaving@example.com", "35202-4444444-4"), &aes_key)
+            .await
+            .unwrap();
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_delete_account_refused_while_funds_remain() {
+        let Some(pool) = crate::database::create_test_pool("auth_delete_funded").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let user = register_user(&pool, register_request("funded@example.com", "35202-5555555-5"), &aes_key)
+            .await
+            .unwrap();
+        let other = register_user(&pool, register_request("payer@example.com", "35202-6666666-6"), &aes_key)
+            .await
+            .unwrap();
+        let client = pool.get().await.unwrap();
+
+        let utxo = queries::create_utxo(&client, &user.wallet_id, crate::utils::to_units(5.0), "funding", 0)
+            .await
+            .unwrap();
+        let refused = delete_account(&pool, user.id).await;
+        assert!(matches!(&refused, Err(AuthError::AccountNotEmpty(reason)) if reason.contains("5.00000000")), "{:?}", refused);
+
+        // Spent out, but an incoming transfer is still pending
+        client.execute("UPDATE utxos SET is_spent = true WHERE id = $1", &[&utxo.id]).await.unwrap();
+        client
+            .execute(
+                "INSERT INTO pending_transactions (transaction_hash, sender_wallet_id, receiver_wallet_id, amount, signature, timestamp)
+                 VALUES ('incoming', $1, $2, 1, 'sig', 0)",
+                &[&other.wallet_id, &user.wallet_id],
+            )
+            .await
+            .unwrap();
+        assert!(matches!(delete_account(&pool, user.id).await, Err(AuthError::AccountNotEmpty(_))));
+
+        let kept = queries::find_user_by_id(&client, user.id).await.unwrap().unwrap();
+        assert_eq!(kept.email, "funded@example.com");
+
+        client.execute("DELETE FROM pending_transactions", &[]).await.unwrap();
+        delete_account(&pool, user.id).await.unwrap();
+    }
 }
@@ -0,0 +1,515 @@
+use crate::blockchain::{change_after_spend, check_transaction_signatures, expected_coinbase_reward, validate_block, LEGACY_BLOCK_VERSION};
+use crate::config::ChainConfig;
+use crate::database::{DbPool, queries};
+use crate::crypto::import_public_key_pem;
+use crate::models::{ChainImportSummary, ExportedBlock, SenderKey, Transaction, UTXO};
+use crate::utils::from_units;
+use std::collections::HashMap;
+use std::ops::DerefMut;
+
+/// Blocks fetched per round trip while streaming an export
+pub const EXPORT_BATCH_SIZE: i64 = 100;
+
+#[derive(Debug)]
+pub enum ChainImportError {
+    Empty,
+    ChainNotEmpty,
+    InvalidLine { line: usize, message: String },
+    InvalidBlock { index: i64, message: String },
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for ChainImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ChainImportError::Empty => write!(f, "Export contains no blocks"),
+            ChainImportError::ChainNotEmpty => {
+                write!(f, "Chain already has blocks beyond genesis; import needs an empty database")
+            }
+            ChainImportError::InvalidLine { line, message } => write!(f, "Line {}: {}", line, message),
+            ChainImportError::InvalidBlock { index, message } => write!(f, "Block {}: {}", index, message),
+            ChainImportError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ChainImportError {}
+
+impl From<tokio_postgres::Error> for ChainImportError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        ChainImportError::DatabaseError(e.to_string())
+    }
+}
+
+/// Up to `limit` blocks from `from` upwards, oldest first, each with its coinbase, fees and sender keys
+pub async fn export_blocks(
+    client: &deadpool_postgres::Client,
+    from: i64,
+    limit: i64,
+) -> Result<Vec<ExportedBlock>, tokio_postgres::Error> {
+    let blocks = queries::get_blocks_from(client, from, limit).await?;
+
+    let mut exported = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        let coinbase = queries::get_block_coinbase_outputs(client, block.index).await?;
+        let fees = queries::get_block_transaction_fees(client, block.index).await?;
+        let burned_fees = queries::get_block_burned_fees(client, block.index).await?;
+        let mut senders: Vec<String> = block
+            .transactions
+            .iter()
+            .map(|tx| tx.sender_wallet_id.clone())
+            .collect();
+        senders.sort();
+        senders.dedup();
+        let sender_keys = queries::get_wallet_public_keys(client, &senders)
+            .await?
+            .into_iter()
+            .map(|(wallet_id, public_key)| SenderKey { wallet_id, public_key })
+            .collect();
+        exported.push(ExportedBlock { block, coinbase, fees, burned_fees, sender_keys });
+    }
+    Ok(exported)
+}
+
+/// Parse newline-delimited `ExportedBlock`s, checking each block against its predecessor,
+/// its coinbase against the reward schedule and its transaction signatures, as a deep
+/// `validate_blockchain` does
+pub fn parse_export(ndjson: &str, chain: &ChainConfig) -> Result<Vec<ExportedBlock>, ChainImportError> {
+    let mut blocks: Vec<ExportedBlock> = Vec::new();
+    let mut mined_before: i64 = 0;
+    // Sender keys seen so far; a wallet's key can't change partway through the export
+    let mut keys: HashMap<String, Option<rsa::RsaPublicKey>> = HashMap::new();
+
+    for (i, line) in ndjson.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let exported: ExportedBlock = serde_json::from_str(line)
+            .map_err(|e| ChainImportError::InvalidLine { line: i + 1, message: e.to_string() })?;
+        let block = &exported.block;
+        let invalid = |message: &str| ChainImportError::InvalidBlock { index: block.index, message: message.to_string() };
+
+        let previous = blocks.last().map(|b| &b.block);
+        if previous.is_none() && block.index != 0 {
+            return Err(invalid("export must start at the genesis block"));
+        }
+        if !validate_block(block, previous, chain) {
+            return Err(invalid("failed validation against its predecessor"));
+        }
+
+        let paid: i64 = exported.coinbase.iter().map(|c| c.amount).sum();
+        if block.index > 0 && exported.coinbase.len() > 1 {
+            return Err(invalid("only the genesis block may have several coinbase outputs"));
+        }
+
+        // Every transaction needs a fee entry, unless it is in a legacy block and was confirmed
+        // before fees were recorded. Then the coinbase can't be checked, as in `validate_blockchain`.
+        let recorded: HashMap<&str, i64> = exported
+            .fees
+            .iter()
+            .map(|f| (f.transaction_hash.as_str(), f.fee))
+            .collect();
+        let mut fees: Option<i64> = Some(0);
+        for tx in &block.transactions {
+            match recorded.get(tx.transaction_hash.as_str()) {
+                Some(fee) if tx.fee.is_some_and(|own| own != *fee) => {
+                    return Err(invalid(&format!("fee entry for transaction {} doesn't match it", tx.transaction_hash)));
+                }
+                Some(fee) => fees = fees.map(|total| total + fee),
+                None if block.version == LEGACY_BLOCK_VERSION && tx.fee.is_none() => fees = None,
+                None => return Err(invalid(&format!("no fee entry for transaction {}", tx.transaction_hash))),
+            }
+        }
+        // Genesis outputs are allocations rather than rewards
+        if let Some(fees) = fees.filter(|_| block.index > 0) {
+            if !(0..=fees).contains(&exported.burned_fees) {
+                return Err(invalid("burns more than the block's fees"));
+            }
+            let expected = expected_coinbase_reward(block.index, fees, exported.burned_fees, from_units(mined_before), chain);
+            if paid != expected {
+                return Err(invalid(&format!(
+                    "coinbase pays {}, expected {}",
+                    from_units(paid),
+                    from_units(expected)
+                )));
+            }
+        }
+        mined_before += paid;
+
+        for sender in &exported.sender_keys {
+            let key = import_public_key_pem(&sender.public_key)
+                .map_err(|_| invalid(&format!("invalid public key for wallet {}", sender.wallet_id)))?;
+            if matches!(keys.get(&sender.wallet_id), Some(Some(known)) if *known != key) {
+                return Err(invalid(&format!("signing key for wallet {} changed", sender.wallet_id)));
+            }
+            keys.insert(sender.wallet_id.clone(), Some(key));
+        }
+        if let Some(reason) = check_transaction_signatures(block, chain, &keys) {
+            return Err(invalid(&reason));
+        }
+
+        blocks.push(exported);
+    }
+
+    if blocks.is_empty() {
+        return Err(ChainImportError::Empty);
+    }
+    Ok(blocks)
+}
+
+/// Load an export into a node with no chain history, replaying every block's transactions
+/// to rebuild UTXOs and balances. The node's own genesis (and its allocations) is replaced by the imported one.
+/// Wallets are created without owners, keeping the sender keys the export carries; user accounts are not part of the export.
+pub async fn import_chain(
+    pool: &DbPool,
+    chain: &ChainConfig,
+    ndjson: &str,
+) -> Result<ChainImportSummary, ChainImportError> {
+    let blocks = parse_export(ndjson, chain)?;
+
+    let mut client = pool.get().await
+        .map_err(|e| ChainImportError::DatabaseError(e.to_string()))?;
+    let transaction = client.deref_mut().transaction().await?;
+
+    // Keep miners out until the import commits
+    transaction.batch_execute("LOCK TABLE blocks IN EXCLUSIVE MODE").await?;
+    let row = transaction
+        .query_one(
+            "SELECT (SELECT COUNT(*) FROM blocks WHERE \"index\" > 0),
+                    (SELECT COUNT(*) FROM transactions),
+                    (SELECT COUNT(*) FROM utxos WHERE transaction_hash IS DISTINCT FROM
+                        (SELECT coinbase_hash FROM blocks WHERE \"index\" = 0))",
+            &[],
+        )
+        .await?;
+    let (later_blocks, transactions, utxos): (i64, i64, i64) = (row.get(0), row.get(1), row.get(2));
+    if later_blocks + transactions + utxos > 0 {
+        return Err(ChainImportError::ChainNotEmpty);
+    }
+    // Drops the local genesis along with any allocations it made
+    transaction.batch_execute("DELETE FROM utxos; DELETE FROM blocks").await?;
+
+    for exported in &blocks {
+        insert_block(&transaction, exported).await?;
+    }
+
+    transaction
+        .execute(
+            "UPDATE wallets SET balance = COALESCE(
+                 (SELECT SUM(amount) FROM utxos WHERE utxos.wallet_id = wallets.wallet_id AND is_spent = false), 0)",
+            &[],
+        )
+        .await?;
+    transaction.commit().await?;
+
+    let tip = &blocks[blocks.len() - 1].block;
+    log::info!("✅ Imported {} blocks, tip {} at height {}", blocks.len(), tip.hash, tip.index);
+
+    Ok(ChainImportSummary {
+        blocks_imported: blocks.len(),
+        tip_index: tip.index,
+        tip_hash: tip.hash.clone(),
+    })
+}
+
+async fn insert_block(
+    db: &tokio_postgres::Transaction<'_>,
+    exported: &ExportedBlock,
+) -> Result<(), ChainImportError> {
+    let block = &exported.block;
+    let coinbase_hash = exported.coinbase.first().map(|c| c.transaction_hash.as_str());
+
+    db.execute(
+        "INSERT INTO blocks (\"index\", timestamp, previous_hash, hash, nonce, merkle_root, version, coinbase_hash, burned_fees)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::int8 * 0.00000001)",
+        &[
+            &block.index,
+            &block.timestamp,
+            &block.previous_hash,
+            &block.hash,
+            &block.nonce,
+            &block.merkle_root,
+            &block.version,
+            &coinbase_hash,
+            &exported.burned_fees,
+        ],
+    )
+    .await?;
+
+    let fees: HashMap<&str, i64> = exported
+        .fees
+        .iter()
+        .map(|f| (f.transaction_hash.as_str(), f.fee))
+        .collect();
+
+    // Same order mining used: transactions first, then the coinbase
+    for (ordinal, tx) in block.transactions.iter().enumerate() {
+        ensure_wallet(db, &tx.sender_wallet_id).await?;
+        ensure_wallet(db, &tx.receiver_wallet_id).await?;
+
+        let fee = fees.get(tx.transaction_hash.as_str()).copied();
+        db.execute(
+            "INSERT INTO transactions (id, transaction_hash, sender_wallet_id, receiver_wallet_id, amount, note, signature,
+                 block_index, transaction_type, timestamp, fee, note_encrypted, created_at, ordinal)
+             VALUES ($1, $2, $3, $4, $5::int8 * 0.00000001, $6, $7, $8, $9, $10, $11::int8 * 0.00000001, $12, $13, $14)",
+            &[
+                &tx.id,
+                &tx.transaction_hash,
+                &tx.sender_wallet_id,
+                &tx.receiver_wallet_id,
+                &tx.amount,
+                &tx.note,
+                &tx.signature,
+                &block.index,
+                &tx.transaction_type,
+                &tx.timestamp,
+                &fee,
+                &tx.note_encrypted,
+                &tx.created_at,
+                &(ordinal as i32),
+            ],
+        )
+        .await?;
+
+        spend_for_transaction(db, block.index, tx, fee.unwrap_or(0)).await?;
+    }
+
+    // Kept on ownerless wallets so the chain can still be checked and exported again
+    for sender in &exported.sender_keys {
+        ensure_wallet(db, &sender.wallet_id).await?;
+        db.execute(
+            "UPDATE wallets SET public_key = $2 WHERE wallet_id = $1 AND user_id IS NULL",
+            &[&sender.wallet_id, &sender.public_key],
+        )
+        .await?;
+    }
+
+    for (output_index, coinbase) in exported.coinbase.iter().enumerate() {
+        ensure_wallet(db, &coinbase.wallet_id).await?;
+        db.execute(
+            "INSERT INTO utxos (wallet_id, amount, transaction_hash, output_index, is_coinbase)
+             VALUES ($1, $2::int8 * 0.00000001, $3, $4, TRUE)",
+            &[&coinbase.wallet_id, &coinbase.amount, &coinbase.transaction_hash, &(output_index as i32)],
+        )
+        .await?;
+
+        // Mined rewards also appear in the miner's history, as mining records them
+        if block.index > 0 {
+            db.execute(
+                "INSERT INTO transactions (transaction_hash, sender_wallet_id, receiver_wallet_id, amount, fee, signature,
+                     block_index, transaction_type, timestamp, ordinal)
+                 VALUES ($1, $2, $3, $4::int8 * 0.00000001, 0, '', $5, 'coinbase', $6, $7)",
+                &[
+                    &coinbase.transaction_hash,
+                    &crate::blockchain::COINBASE_SENDER,
+                    &coinbase.wallet_id,
+                    &coinbase.amount,
+                    &block.index,
+                    &block.timestamp,
+                    &(block.transactions.len() as i32),
+                ],
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn ensure_wallet(db: &tokio_postgres::Transaction<'_>, wallet_id: &str) -> Result<(), tokio_postgres::Error> {
+    db.execute(
+        "INSERT INTO wallets (wallet_id) VALUES ($1) ON CONFLICT (wallet_id) DO NOTHING",
+        &[&wallet_id],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Spend the sender's outputs and create the receiver and change outputs, as mining does
+async fn spend_for_transaction(
+    db: &tokio_postgres::Transaction<'_>,
+    block_index: i64,
+    tx: &Transaction,
+    fee: i64,
+) -> Result<(), ChainImportError> {
+    let rows = db
+        .query(
+            "SELECT id, wallet_id, (amount * 100000000)::int8, transaction_hash, output_index, is_spent, created_at, spent_at, reserved_by
+             FROM utxos WHERE wallet_id = $1 AND is_spent = false
+             ORDER BY created_at ASC",
+            &[&tx.sender_wallet_id],
+        )
+        .await?;
+    let unspent: Vec<UTXO> = rows
+        .into_iter()
+        .map(|row| UTXO {
+            id: row.get(0),
+            wallet_id: row.get(1),
+            amount: row.get(2),
+            transaction_hash: row.get(3),
+            output_index: row.get(4),
+            is_spent: row.get(5),
+            created_at: row.get(6),
+            spent_at: row.get(7),
+            reserved_by: row.get(8),
+        })
+        .collect();
+
+    let selected = crate::coin_selection::select_utxos(&unspent, tx.amount + fee);
+    let total: i64 = selected.iter().map(|u| u.amount).sum();
+    let change = match change_after_spend(total, tx.amount, fee) {
+        Some(change) if !selected.is_empty() => change,
+        _ => {
+            return Err(ChainImportError::InvalidBlock {
+                index: block_index,
+                message: format!("transaction {} spends more than its sender holds", tx.transaction_hash),
+            })
+        }
+    };
+
+    let ids: Vec<uuid::Uuid> = selected.iter().map(|u| u.id).collect();
+    db.execute(
+        "UPDATE utxos SET is_spent = true, spent_at = NOW(), spent_by_transaction = $2 WHERE id = ANY($1)",
+        &[&ids, &tx.transaction_hash],
+    )
+    .await?;
+
+    db.execute(
+        "INSERT INTO utxos (wallet_id, amount, transaction_hash, output_index) VALUES ($1, $2::int8 * 0.00000001, $3, 0)",
+        &[&tx.receiver_wallet_id, &tx.amount, &tx.transaction_hash],
+    )
+    .await?;
+    if change > 0 {
+        db.execute(
+            "INSERT INTO utxos (wallet_id, amount, transaction_hash, output_index) VALUES ($1, $2::int8 * 0.00000001, $3, 1)",
+            &[&tx.sender_wallet_id, &change, &tx.transaction_hash],
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shutdown::Shutdown;
+
+    async fn export_all(pool: &DbPool) -> String {
+        let client = pool.get().await.unwrap();
+        export_blocks(&client, 0, i64::MAX)
+            .await
+            .unwrap()
+            .iter()
+            .map(|b| serde_json::to_string(b).unwrap() + "\n")
+            .collect()
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_export_import_reproduces_tip() {
+        let chain = ChainConfig { mining_difficulty: 1, fee_burn_percentage: 50.0, ..ChainConfig::default() };
+        let Some((source, miner, receiver)) = crate::database::create_funded_test_pair("chain_export_source", &chain, 0.0).await else { return };
+        let Some(target) = crate::database::create_test_pool("chain_export_target").await else { return };
+        crate::migrations::run_migrations(&target).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        // Block 1 pays the miner, block 2 carries a transfer out of that reward
+        let shutdown = Shutdown::new();
+        crate::blockchain::mine_block(&source, &miner.wallet_id, &chain, &shutdown).await.unwrap();
+        let request = crate::models::CreateTransactionRequest {
+            sender_wallet_id: miner.wallet_id.clone(),
+            receiver_wallet_id: receiver.wallet_id.clone(),
+            amount: 12.5,
+            note: Some("export".to_string()),
+            private_note: false,
+            payment_request_id: None,
+        };
+        crate::services::transaction_service::create_transaction(&source, request, &chain, &aes_key)
+            .await
+            .unwrap();
+        let tip = crate::blockchain::mine_block(&source, &miner.wallet_id, &chain, &shutdown).await.unwrap().block;
+
+        let export = export_all(&source).await;
+        assert_eq!(export.lines().count(), 3);
+
+        // The transfer's fee entry and signature are checked before anything is written
+        let tamper = |change: &dyn Fn(&mut ExportedBlock)| {
+            export
+                .lines()
+                .map(|line| {
+                    let mut exported: ExportedBlock = serde_json::from_str(line).unwrap();
+                    if exported.block.index == tip.index {
+                        change(&mut exported);
+                    }
+                    serde_json::to_string(&exported).unwrap() + "\n"
+                })
+                .collect::<String>()
+        };
+        let rejected = |result| matches!(result, Err(ChainImportError::InvalidBlock { index: 2, .. }));
+        assert!(parse_export(&tamper(&|_| ()), &chain).is_ok());
+        assert!(rejected(parse_export(&tamper(&|b| b.fees.clear()), &chain)));
+        assert!(rejected(parse_export(&tamper(&|b| b.sender_keys.clear()), &chain)));
+        let (_, other_key) = crate::crypto::generate_keypair().unwrap();
+        let other_pem = crate::crypto::export_public_key_pem(&other_key).unwrap();
+        assert!(rejected(parse_export(&tamper(&|b| b.sender_keys[0].public_key = other_pem.clone()), &chain)));
+
+        // The target's own genesis is replaced. Each block keeps the burn recorded when it was
+        // mined, even though the target burns a different share of fees.
+        let target_chain = ChainConfig { fee_burn_percentage: 0.0, ..chain.clone() };
+        crate::blockchain::initialize_blockchain(target.clone(), &target_chain).await.unwrap();
+        let summary = import_chain(&target, &target_chain, &export).await.unwrap();
+        assert_eq!(summary.blocks_imported, 3);
+        assert_eq!(summary.tip_hash, tip.hash);
+        assert_eq!(export_all(&target).await, export);
+        assert!(crate::blockchain::validate_blockchain(&target, &target_chain, false, true).await.unwrap().valid);
+        let burned = |pool: DbPool| async move {
+            queries::get_total_burned(&pool.get().await.unwrap()).await.unwrap()
+        };
+        assert!(burned(source.clone()).await > 0);
+        assert_eq!(burned(target.clone()).await, burned(source.clone()).await);
+
+        let balance = |wallet_id: String| {
+            let target = target.clone();
+            async move {
+                crate::services::wallet_service::get_wallet_balance(&target, &wallet_id)
+                    .await
+                    .unwrap()
+                    .balance
+            }
+        };
+        assert_eq!(balance(receiver.wallet_id.clone()).await, 12.5);
+        assert_eq!(
+            balance(miner.wallet_id.clone()).await,
+            crate::services::wallet_service::get_wallet_balance(&source, &miner.wallet_id).await.unwrap().balance
+        );
+
+        assert!(matches!(import_chain(&target, &target_chain, &export).await, Err(ChainImportError::ChainNotEmpty)));
+    }
+
+    #[test]
+    fn test_parse_export_rejects_broken_chain() {
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        let genesis = crate::blockchain::create_genesis_block(&chain).unwrap();
+        let line = |block: &crate::models::Block| {
+            serde_json::to_string(&ExportedBlock {
+                block: block.clone(),
+                coinbase: vec![],
+                fees: vec![],
+                burned_fees: 0,
+                sender_keys: vec![],
+            }).unwrap()
+        };
+
+        assert_eq!(parse_export(&line(&genesis), &chain).unwrap().len(), 1);
+        assert!(matches!(parse_export("", &chain), Err(ChainImportError::Empty)));
+        assert!(matches!(parse_export("not json", &chain), Err(ChainImportError::InvalidLine { line: 1, .. })));
+
+        let mut tampered = genesis.clone();
+        tampered.nonce += 1;
+        assert!(matches!(
+            parse_export(&line(&tampered), &chain),
+            Err(ChainImportError::InvalidBlock { index: 0, .. })
+        ));
+    }
+}
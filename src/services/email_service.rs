@@ -0,0 +1,112 @@
+use crate::database::{AdvisoryLock, DbPool, queries};
+use crate::shutdown::{run_until_shutdown, Shutdown};
+use lettre::{
+    Message, SmtpTransport, Transport,
+    message::header::ContentType,
+    transport::smtp::authentication::Credentials,
+};
+use std::env;
+use std::time::Duration;
+
+/// Delivery attempts before a queued email is marked failed
+pub const MAX_SEND_ATTEMPTS: i32 = 5;
+
+/// Emails sent per outbox run
+const OUTBOX_BATCH_SIZE: i64 = 50;
+
+/// Arbitrary key for the advisory lock that keeps two nodes from sending the same email
+const OUTBOX_LOCK_KEY: i64 = 0x6f75_7462_6f78;
+
+/// Send an HTML email through the SMTP server configured by `SMTP_*`
+pub async fn send_html(to_email: &str, subject: &str, html_body: String) -> Result<(), String> {
+    let smtp_host = env::var("SMTP_HOST").unwrap_or_else(|_| "smtp.gmail.com".to_string());
+    let smtp_port: u16 = env::var("SMTP_PORT")
+        .unwrap_or_else(|_| "587".to_string())
+        .parse()
+        .unwrap_or(587);
+    let smtp_username = env::var("SMTP_USERNAME").map_err(|_| "SMTP_USERNAME not set in .env")?;
+    let smtp_password = env::var("SMTP_PASSWORD").map_err(|_| "SMTP_PASSWORD not set in .env")?;
+    let from_email = env::var("SMTP_FROM_EMAIL").unwrap_or_else(|_| smtp_username.clone());
+    let from_name = env::var("SMTP_FROM_NAME").unwrap_or_else(|_| "BlockWallet".to_string());
+
+    // Build email
+    let email = Message::builder()
+        .from(format!("{} <{}>", from_name, from_email).parse().map_err(|e| format!("Invalid from address: {}", e))?)
+        .to(to_email.parse().map_err(|e| format!("Invalid to address: {}", e))?)
+        .subject(subject)
+        .header(ContentType::TEXT_HTML)
+        .body(html_body)
+        .map_err(|e| format!("Failed to build email: {}", e))?;
+
+    // Create SMTP credentials
+    let creds = Credentials::new(smtp_username.clone(), smtp_password.clone());
+
+    // Create SMTP transport with STARTTLS
+    let mailer = SmtpTransport::starttls_relay(&smtp_host)
+        .map_err(|e| format!("Failed to create SMTP transport: {}", e))?
+        .credentials(creds)
+        .port(smtp_port)
+        .timeout(Some(std::time::Duration::from_secs(10)))
+        .build();
+
+    // Send email
+    mailer.send(&email)
+        .map_err(|e| format!("Failed to send email: {}", e))?;
+
+    log::info!("✅ Email sent successfully to {}", to_email);
+    
+    Ok(())
+}
+
+/// Send queued outbox emails, returning how many went out. Skips the run if another
+/// node is already draining the outbox.
+pub async fn deliver_queued(pool: &DbPool) -> Result<usize, anyhow::Error> {
+    let Some(lock) = AdvisoryLock::try_acquire(pool, OUTBOX_LOCK_KEY).await? else {
+        return Ok(0);
+    };
+    let client = lock.client();
+
+    let result = async {
+        let mut sent = 0;
+        for email in queries::get_queued_emails(client, OUTBOX_BATCH_SIZE).await? {
+            match send_html(&email.recipient, &email.subject, email.body).await {
+                Ok(()) => {
+                    queries::mark_email_sent(client, email.id).await?;
+                    sent += 1;
+                }
+                Err(e) => {
+                    log::warn!("Email {} to {} failed (attempt {}): {}", email.id, email.recipient, email.attempts + 1, e);
+                    queries::record_email_failure(client, email.id, &e, MAX_SEND_ATTEMPTS).await?;
+                }
+            }
+        }
+        Ok::<_, anyhow::Error>(sent)
+    }
+    .await;
+
+    lock.unlock().await?;
+
+    result
+}
+
+/// Drain the outbox every `EMAIL_OUTBOX_INTERVAL` seconds (default 30) until `shutdown`
+pub async fn start_outbox_worker(pool: DbPool, shutdown: Shutdown) {
+    let interval_seconds = env::var("EMAIL_OUTBOX_INTERVAL")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(30);
+
+    log::info!("📧 Email outbox worker started (every {} seconds)", interval_seconds);
+
+    run_until_shutdown(Duration::from_secs(interval_seconds), shutdown, || async {
+        match deliver_queued(&pool).await {
+            Ok(0) => {}
+            Ok(n) => log::info!("📧 Sent {} queued email(s)", n),
+            Err(e) => log::error!("Error draining email outbox: {}", e),
+        }
+    })
+    .await;
+
+    log::info!("📧 Email outbox worker stopped");
+}
@@ -0,0 +1,190 @@
+use crate::database::queries;
+use crate::models::{Block, Transaction, Wallet};
+use serde::Serialize;
+
+/// Entity matched by an explorer search, tagged with its `type`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum SearchResult {
+    Block(Block),
+    Transaction(Transaction),
+    Wallet(Wallet),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchQuery {
+    /// Numeric queries are block heights
+    BlockIndex(i64),
+    /// Anything else may be a block hash, transaction hash or wallet id
+    Identifier(String),
+}
+
+pub fn classify(query: &str) -> Option<SearchQuery> {
+    let query = query.trim();
+    if query.is_empty() {
+        return None;
+    }
+
+    match query.parse::<i64>() {
+        Ok(index) if index >= 0 => Some(SearchQuery::BlockIndex(index)),
+        _ => Some(SearchQuery::Identifier(query.to_string())),
+    }
+}
+
+/// Lookups the explorer search needs, so it can run against the database or a fixture
+pub trait ExplorerLookup {
+    type Error;
+
+    async fn block_by_index(&self, index: i64) -> Result<Option<Block>, Self::Error>;
+    async fn block_by_hash(&self, hash: &str) -> Result<Option<Block>, Self::Error>;
+    async fn transaction_by_hash(&self, hash: &str) -> Result<Option<Transaction>, Self::Error>;
+    async fn wallet(&self, wallet_id: &str) -> Result<Option<Wallet>, Self::Error>;
+}
+
+impl ExplorerLookup for deadpool_postgres::Client {
+    type Error = tokio_postgres::Error;
+
+    async fn block_by_index(&self, index: i64) -> Result<Option<Block>, Self::Error> {
+        queries::get_block_by_index(self, index).await
+    }
+
+    async fn block_by_hash(&self, hash: &str) -> Result<Option<Block>, Self::Error> {
+        queries::get_block_by_hash(self, hash).await
+    }
+
+    async fn transaction_by_hash(&self, hash: &str) -> Result<Option<Transaction>, Self::Error> {
+        queries::get_transaction_by_hash(self, hash).await
+    }
+
+    async fn wallet(&self, wallet_id: &str) -> Result<Option<Wallet>, Self::Error> {
+        queries::get_wallet(self, wallet_id).await
+    }
+}
+
+/// Resolve `query` to a block, transaction or wallet (checked in that order)
+pub async fn search<L: ExplorerLookup>(lookup: &L, query: &str) -> Result<Option<SearchResult>, L::Error> {
+    let identifier = match classify(query) {
+        None => return Ok(None),
+        Some(SearchQuery::BlockIndex(index)) => {
+            return Ok(lookup.block_by_index(index).await?.map(SearchResult::Block));
+        }
+        Some(SearchQuery::Identifier(identifier)) => identifier,
+    };
+
+    if let Some(block) = lookup.block_by_hash(&identifier).await? {
+        return Ok(Some(SearchResult::Block(block)));
+    }
+
+    if let Some(transaction) = lookup.transaction_by_hash(&identifier).await? {
+        return Ok(Some(SearchResult::Transaction(transaction)));
+    }
+
+    Ok(lookup.wallet(&identifier).await?.map(SearchResult::Wallet))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::convert::Infallible;
+    use uuid::Uuid;
+
+    struct Fixture {
+        block: Block,
+        transaction: Transaction,
+        wallet: Wallet,
+    }
+
+    impl ExplorerLookup for Fixture {
+        type Error = Infallible;
+
+        async fn block_by_index(&self, index: i64) -> Result<Option<Block>, Infallible> {
+            Ok((self.block.index == index).then(|| self.block.clone()))
+        }
+
+        async fn block_by_hash(&self, hash: &str) -> Result<Option<Block>, Infallible> {
+            Ok((self.block.hash == hash).then(|| self.block.clone()))
+        }
+
+        async fn transaction_by_hash(&self, hash: &str) -> Result<Option<Transaction>, Infallible> {
+            Ok((self.transaction.transaction_hash == hash).then(|| self.transaction.clone()))
+        }
+
+        async fn wallet(&self, wallet_id: &str) -> Result<Option<Wallet>, Infallible> {
+            Ok((self.wallet.wallet_id == wallet_id).then(|| self.wallet.clone()))
+        }
+    }
+
+    fn fixture() -> Fixture {
+        Fixture {
+            block: Block {
+                index: 7,
+                timestamp: 0,
+                transactions: vec![],
+                previous_hash: "0".to_string(),
+                hash: "b".repeat(64),
+                nonce: 0,
+                merkle_root: None,
+                version: crate::blockchain::BLOCK_VERSION,
+            },
+            transaction: Transaction {
+                id: Uuid::new_v4(),
+                transaction_hash: "t".repeat(64),
+                sender_wallet_id: "sender".to_string(),
+                receiver_wallet_id: "receiver".to_string(),
+                amount: crate::utils::UNITS_PER_COIN,
+                note: None,
+                note_encrypted: false,
+                fee: Some(0),
+                signature: "sig".to_string(),
+                block_index: Some(7),
+                transaction_type: "transfer".to_string(),
+                timestamp: 0,
+                created_at: Utc::now(),
+            },
+            wallet: Wallet {
+                wallet_id: "w".repeat(64),
+                user_id: None,
+                balance: 0.0,
+                last_zakat_date: None,
+                status: crate::models::WalletStatus::Active,
+                zakat_exempt: false,
+                zakat_rate: None,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            },
+        }
+    }
+
+    async fn result_type(query: &str) -> Option<String> {
+        let result = search(&fixture(), query).await.unwrap();
+        result.map(|r| serde_json::to_value(r).unwrap()["type"].as_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_search_block_index() {
+        assert_eq!(result_type("7").await.as_deref(), Some("block"));
+    }
+
+    #[tokio::test]
+    async fn test_search_block_hash() {
+        assert_eq!(result_type(&"b".repeat(64)).await.as_deref(), Some("block"));
+    }
+
+    #[tokio::test]
+    async fn test_search_transaction_hash() {
+        assert_eq!(result_type(&"t".repeat(64)).await.as_deref(), Some("transaction"));
+    }
+
+    #[tokio::test]
+    async fn test_search_wallet() {
+        assert_eq!(result_type(&format!(" {} ", "w".repeat(64))).await.as_deref(), Some("wallet"));
+    }
+
+    #[tokio::test]
+    async fn test_search_miss() {
+        assert_eq!(result_type("8").await, None);
+        assert_eq!(result_type("unknown").await, None);
+        assert_eq!(result_type("  ").await, None);
+    }
+}
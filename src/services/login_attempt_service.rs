@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct AttemptState {
+    failures: u32,
+    window_start: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Counts failed logins per key (email or IP) and locks the key out after too many
+pub struct LoginAttemptTracker {
+    max_attempts: u32,
+    window: Duration,
+    lockout: Duration,
+    trust_proxy_headers: bool,
+    entries: Mutex<HashMap<String, AttemptState>>,
+}
+
+impl LoginAttemptTracker {
+    pub fn new(max_attempts: u32, window: Duration, lockout: Duration) -> Self {
+        LoginAttemptTracker {
+            max_attempts,
+            window,
+            lockout,
+            trust_proxy_headers: false,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Key IP lockouts by `Forwarded`/`X-Forwarded-For` instead of the socket peer.
+    /// Only safe behind a proxy that overwrites those headers.
+    pub fn with_trusted_proxy(mut self, trust_proxy_headers: bool) -> Self {
+        self.trust_proxy_headers = trust_proxy_headers;
+        self
+    }
+
+    pub fn trusts_proxy_headers(&self) -> bool {
+        self.trust_proxy_headers
+    }
+
+    pub fn from_env() -> Self {
+        let max_attempts = env::var("MAX_LOGIN_ATTEMPTS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u32>()
+            .unwrap_or(5);
+
+        let window_seconds = env::var("LOGIN_ATTEMPT_WINDOW")
+            .unwrap_or_else(|_| "900".to_string())
+            .parse::<u64>()
+            .unwrap_or(900);
+
+        let lockout_seconds = env::var("LOGIN_LOCKOUT_SECONDS")
+            .unwrap_or_else(|_| "900".to_string())
+            .parse::<u64>()
+            .unwrap_or(900);
+
+        let trust_proxy_headers = env::var("TRUST_PROXY_HEADERS").is_ok_and(|v| v == "true" || v == "1");
+
+        LoginAttemptTracker::new(
+            max_attempts,
+            Duration::from_secs(window_seconds),
+            Duration::from_secs(lockout_seconds),
+        )
+        .with_trusted_proxy(trust_proxy_headers)
+    }
+
+    /// Remaining lockout for `key`, if it is currently locked out
+    pub fn retry_after(&self, key: &str, now: Instant) -> Option<Duration> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .and_then(|state| state.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    /// Record a failed attempt; returns the lockout duration if this failure triggered one
+    pub fn record_failure(&self, key: &str, now: Instant) -> Option<Duration> {
+        let mut entries = self.entries.lock().unwrap();
+        let state = entries.entry(key.to_string()).or_insert(AttemptState {
+            failures: 0,
+            window_start: now,
+            locked_until: None,
+        });
+
+        // Start a fresh window once the previous one (or lockout) has expired
+        let lock_expired = state.locked_until.is_some_and(|until| until <= now);
+        if lock_expired || now.duration_since(state.window_start) > self.window {
+            state.failures = 0;
+            state.window_start = now;
+            state.locked_until = None;
+        }
+
+        state.failures += 1;
+        if state.failures >= self.max_attempts && state.locked_until.is_none() {
+            state.locked_until = Some(now + self.lockout);
+            return Some(self.lockout);
+        }
+
+        None
+    }
+
+    /// Clear the failure count for `key` after a successful login
+    pub fn record_success(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> LoginAttemptTracker {
+        LoginAttemptTracker::new(3, Duration::from_secs(60), Duration::from_secs(300))
+    }
+
+    #[test]
+    fn test_nth_failure_triggers_lockout() {
+        let tracker = tracker();
+        let now = Instant::now();
+
+        assert_eq!(tracker.record_failure("a@example.com", now), None);
+        assert_eq!(tracker.record_failure("a@example.com", now), None);
+        assert!(tracker.retry_after("a@example.com", now).is_none());
+
+        assert_eq!(tracker.record_failure("a@example.com", now), Some(Duration::from_secs(300)));
+        assert_eq!(
+            tracker.retry_after("a@example.com", now + Duration::from_secs(100)),
+            Some(Duration::from_secs(200))
+        );
+
+        // Lockout expires after the cooldown
+        assert!(tracker.retry_after("a@example.com", now + Duration::from_secs(300)).is_none());
+        assert!(tracker.retry_after("b@example.com", now).is_none());
+    }
+
+    #[test]
+    fn test_success_resets_counter() {
+        let tracker = tracker();
+        let now = Instant::now();
+
+        tracker.record_failure("a@example.com", now);
+        tracker.record_failure("a@example.com", now);
+        tracker.record_success("a@example.com");
+
+        assert_eq!(tracker.record_failure("a@example.com", now), None);
+        assert_eq!(tracker.record_failure("a@example.com", now), None);
+        assert!(tracker.retry_after("a@example.com", now).is_none());
+    }
+
+    #[test]
+    fn test_failures_outside_window_do_not_accumulate() {
+        let tracker = tracker();
+        let now = Instant::now();
+
+        tracker.record_failure("a@example.com", now);
+        tracker.record_failure("a@example.com", now);
+
+        let later = now + Duration::from_secs(61);
+        assert_eq!(tracker.record_failure("a@example.com", later), None);
+    }
+}
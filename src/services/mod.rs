@@ -3,3 +3,12 @@ pub mod wallet_service;
 pub mod zakat_service;
 pub mod auth_service;
 pub mod otp_service;
+pub mod webhook_service;
+pub mod nisab_service;
+pub mod login_attempt_service;
+pub mod analytics_service;
+pub mod explorer_service;
+pub mod payment_request_service;
+pub mod chain_export_service;
+pub mod email_service;
+pub mod notification_service;
@@ -0,0 +1,165 @@
+use std::env;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Nisab threshold derived from the gold price, cached for a configurable TTL
+pub struct NisabProvider {
+    ttl: Duration,
+    nisab_grams: f64,
+    coin_price: f64,
+    cache: Mutex<Option<(f64, Instant)>>,
+}
+
+/// Nisab expressed in coins: value of `nisab_grams` of gold divided by the coin's price
+pub fn nisab_in_coins(gold_price_per_gram: f64, nisab_grams: f64, coin_price: f64) -> f64 {
+    gold_price_per_gram * nisab_grams / coin_price
+}
+
+impl NisabProvider {
+    pub fn new(ttl: Duration, nisab_grams: f64, coin_price: f64) -> Self {
+        NisabProvider {
+            ttl,
+            nisab_grams,
+            coin_price,
+            cache: Mutex::new(None),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let ttl_seconds = env::var("NISAB_CACHE_TTL")
+            .unwrap_or_else(|_| "3600".to_string())
+            .parse::<u64>()
+            .unwrap_or(3600);
+
+        // 85 grams of gold is the commonly used nisab
+        let nisab_grams = env::var("NISAB_GOLD_GRAMS")
+            .unwrap_or_else(|_| "85.0".to_string())
+            .parse::<f64>()
+            .unwrap_or(85.0);
+
+        let coin_price = env::var("COIN_PRICE")
+            .unwrap_or_else(|_| "1.0".to_string())
+            .parse::<f64>()
+            .unwrap_or(1.0);
+
+        NisabProvider::new(Duration::from_secs(ttl_seconds), nisab_grams, coin_price)
+    }
+
+    /// Current nisab in coins, refreshing via `fetch_price` when the cache is stale.
+    /// Falls back to `fallback` if the price can't be fetched.
+    pub async fn threshold<F, Fut>(&self, fetch_price: F, fallback: f64) -> f64
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<f64, String>>,
+    {
+        if let Some((value, fetched_at)) = *self.cache.lock().unwrap() {
+            if fetched_at.elapsed() < self.ttl {
+                return value;
+            }
+        }
+
+        match fetch_price().await {
+            Ok(price) if price > 0.0 && self.coin_price > 0.0 => {
+                let value = nisab_in_coins(price, self.nisab_grams, self.coin_price);
+                *self.cache.lock().unwrap() = Some((value, Instant::now()));
+                value
+            }
+            Ok(price) => {
+                log::warn!("Ignoring invalid gold price {} (coin price {}), using static nisab {}", price, self.coin_price, fallback);
+                fallback
+            }
+            Err(e) => {
+                log::warn!("Failed to fetch gold price: {}, using static nisab {}", e, fallback);
+                fallback
+            }
+        }
+    }
+}
+
+/// Fetch the gold price per gram from `url`, reading the numeric JSON `field`
+pub async fn fetch_gold_price(url: &str, field: &str) -> Result<f64, String> {
+    let mut request = reqwest::Client::new()
+        .get(url)
+        .timeout(Duration::from_secs(10));
+
+    if let Ok(api_key) = env::var("GOLD_PRICE_API_KEY") {
+        request = request.header("x-access-token", api_key);
+    }
+
+    let body: serde_json::Value = request
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .error_for_status()
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    body.get(field)
+        .and_then(|v| v.as_f64())
+        .ok_or_else(|| format!("Field '{}' missing from gold price response", field))
+}
+
+fn provider() -> &'static NisabProvider {
+    static PROVIDER: OnceLock<NisabProvider> = OnceLock::new();
+    PROVIDER.get_or_init(NisabProvider::from_env)
+}
+
+/// Zakat threshold in coins: gold-pegged when `GOLD_PRICE_API_URL` is set, else `ZAKAT_THRESHOLD`
+pub async fn current_threshold() -> f64 {
+    let static_threshold = env::var("ZAKAT_THRESHOLD")
+        .unwrap_or_else(|_| "100.0".to_string())
+        .parse::<f64>()
+        .unwrap_or(100.0);
+
+    let url = match env::var("GOLD_PRICE_API_URL") {
+        Ok(url) if !url.is_empty() => url,
+        _ => return static_threshold,
+    };
+
+    let field = env::var("GOLD_PRICE_FIELD").unwrap_or_else(|_| "price_gram_24k".to_string());
+
+    provider()
+        .threshold(|| async move { fetch_gold_price(&url, &field).await }, static_threshold)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nisab_in_coins() {
+        // 85g at 80/g with a coin worth 2 => 3400 coins
+        assert_eq!(nisab_in_coins(80.0, 85.0, 2.0), 3400.0);
+    }
+
+    #[tokio::test]
+    async fn test_threshold_uses_price_and_caches() {
+        let provider = NisabProvider::new(Duration::from_secs(60), 85.0, 1.0);
+
+        let first = provider.threshold(|| async { Ok(10.0) }, 100.0).await;
+        assert_eq!(first, 850.0);
+
+        // Cached value is returned without hitting the price source again
+        let second = provider
+            .threshold(|| async { Err::<f64, String>("should not be called".to_string()) }, 100.0)
+            .await;
+        assert_eq!(second, 850.0);
+    }
+
+    #[tokio::test]
+    async fn test_threshold_falls_back_on_fetch_failure() {
+        let provider = NisabProvider::new(Duration::from_secs(60), 85.0, 1.0);
+
+        let value = provider
+            .threshold(|| async { Err::<f64, String>("timeout".to_string()) }, 100.0)
+            .await;
+        assert_eq!(value, 100.0);
+
+        let value = provider.threshold(|| async { Ok(-1.0) }, 100.0).await;
+        assert_eq!(value, 100.0);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::database::queries;
+use crate::models::{NotificationPreferences, PendingTransaction};
+use crate::utils::from_units;
+use deadpool_postgres::Client;
+use uuid::Uuid;
+
+/// The user's saved preferences, or the defaults if they never saved any
+pub async fn get_preferences(client: &Client, user_id: Uuid) -> Result<NotificationPreferences, tokio_postgres::Error> {
+    Ok(queries::get_notification_preferences(client, user_id)
+        .await?
+        .unwrap_or_default())
+}
+
+fn email_html(heading: &str, message: &str) -> String {
+    format!(
+        "<h2>{}</h2><p>{}</p><p style=\"color: #6B7280; font-size: 14px;\">Change which emails you get under notification preferences.</p>",
+        heading, message
+    )
+}
+
+/// Queue emails for the transfers mined into `block_index`: receivers who want payment
+/// notices, and senders left below their low-balance threshold
+pub async fn notify_block_mined(
+    client: &Client,
+    block_index: i64,
+    mined: &[&PendingTransaction],
+) -> Result<(), tokio_postgres::Error> {
+    let mut wallet_ids: Vec<String> = mined
+        .iter()
+        .flat_map(|tx| [tx.sender_wallet_id.clone(), tx.receiver_wallet_id.clone()])
+        .collect();
+    wallet_ids.sort();
+    wallet_ids.dedup();
+    if wallet_ids.is_empty() {
+        return Ok(());
+    }
+
+    let recipients = queries::get_notification_recipients(client, &wallet_ids).await?;
+    let mut emails = Vec::new();
+
+    // Consolidations and other self-transfers aren't payments
+    for tx in mined.iter().filter(|tx| tx.sender_wallet_id != tx.receiver_wallet_id) {
+        let Some(receiver) = recipients.iter().find(|r| r.wallet_id == tx.receiver_wallet_id) else { continue };
+        if !receiver.preferences.payment_received {
+            continue;
+        }
+        let message = format!(
+            "You received {} coins from wallet {}. Transaction {} was confirmed in block {}.",
+            from_units(tx.amount),
+            tx.sender_wallet_id,
+            tx.transaction_hash,
+            block_index
+        );
+        emails.push((
+            receiver.email.clone(),
+            "payment_received",
+            "BlockWallet - Payment received",
+            email_html("Payment received", &message),
+        ));
+    }
+
+    let mut senders: Vec<&str> = mined.iter().map(|tx| tx.sender_wallet_id.as_str()).collect();
+    senders.sort();
+    senders.dedup();
+    for sender in recipients.iter().filter(|r| senders.contains(&r.wallet_id.as_str())) {
+        let Some(threshold) = sender.preferences.low_balance_threshold else { continue };
+        if sender.balance >= threshold {
+            continue;
+        }
+        let message = format!(
+            "After block {} your wallet {} has {} coins, below your alert threshold of {}.",
+            block_index,
+            sender.wallet_id,
+            from_units(sender.balance),
+            from_units(threshold)
+        );
+        emails.push((
+            sender.email.clone(),
+            "low_balance",
+            "BlockWallet - Low balance",
+            email_html("Low balance", &message),
+        ));
+    }
+
+    queries::enqueue_emails(client, &emails).await
+}
+
+/// Queue a zakat notice if the wallet's owner asked for one
+pub async fn notify_zakat_deducted(
+    client: &Client,
+    wallet_id: &str,
+    amount: i64,
+    transaction_hash: &str,
+) -> Result<(), tokio_postgres::Error> {
+    let recipients = queries::get_notification_recipients(client, &[wallet_id.to_string()]).await?;
+    let Some(owner) = recipients.first().filter(|r| r.preferences.zakat_deducted) else { return Ok(()) };
+
+    let message = format!(
+        "Zakat of {} coins is being deducted from wallet {} in transaction {}.",
+        from_units(amount),
+        wallet_id,
+        transaction_hash
+    );
+    queries::enqueue_email(
+        client,
+        &owner.email,
+        "zakat_deducted",
+        "BlockWallet - Zakat deducted",
+        &email_html("Zakat deducted", &message),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::{initialize_blockchain, mine_block};
+    use crate::config::ChainConfig;
+    use crate::shutdown::Shutdown;
+    use crate::utils::to_units;
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_mined_payment_queues_email_per_preferences() {
+        let Some(pool) = crate::database::create_test_pool("payment_notifications").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        initialize_blockchain(pool.clone(), &chain).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let sender = crate::database::register_test_user(&pool, "sender@example.com", "1").await;
+        let enabled = crate::database::register_test_user(&pool, "enabled@example.com", "2").await;
+        let disabled = crate::database::register_test_user(&pool, "disabled@example.com", "3").await;
+
+        let client = pool.get().await.unwrap();
+        queries::create_utxo(&client, &sender.wallet_id, to_units(100.0), "funding", 0).await.unwrap();
+
+        // Received payments are opt-in by default; one receiver turns them off
+        assert!(get_preferences(&client, enabled.id).await.unwrap().payment_received);
+        let opted_out = NotificationPreferences { payment_received: false, ..NotificationPreferences::default() };
+        queries::upsert_notification_preferences(&client, disabled.id, &opted_out).await.unwrap();
+        assert_eq!(get_preferences(&client, disabled.id).await.unwrap(), opted_out);
+        drop(client);
+
+        for receiver in [&enabled, &disabled] {
+            let request = crate::models::CreateTransactionRequest {
+                sender_wallet_id: sender.wallet_id.clone(),
+                receiver_wallet_id: receiver.wallet_id.clone(),
+                amount: 5.0,
+                note: None,
+                private_note: false,
+                payment_request_id: None,
+            };
+            crate::services::transaction_service::create_transaction(&pool, request, &chain, &aes_key)
+                .await
+                .unwrap();
+        }
+
+        // Nothing is queued until the transfers are mined
+        let client = pool.get().await.unwrap();
+        assert!(queries::get_queued_emails(&client, 10).await.unwrap().is_empty());
+        drop(client);
+        mine_block(&pool, &sender.wallet_id, &chain, &Shutdown::new()).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        let queued: Vec<(String, String)> = client
+            .query("SELECT recipient, event FROM email_outbox WHERE status = 'queued'", &[])
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+        assert_eq!(queued, vec![("enabled@example.com".to_string(), "payment_received".to_string())]);
+    }
+}
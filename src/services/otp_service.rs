@@ -1,12 +1,10 @@
 use crate::database::{DbPool, queries};
+use crate::metrics::Metrics;
 use chrono::{Utc, Duration};
 use rand::Rng;
-use lettre::{
-    Message, SmtpTransport, Transport,
-    message::header::ContentType,
-    transport::smtp::authentication::Credentials,
-};
-use std::env;
+
+/// Minimum gap between two codes for the same email
+pub const RESEND_COOLDOWN_SECONDS: i64 = 60;
 
 #[derive(Debug)]
 pub enum OtpError {
@@ -14,6 +12,8 @@ pub enum OtpError {
     InvalidOtp,
     ExpiredOtp,
     SendError(String),
+    /// Seconds until another code may be requested
+    ResendTooSoon(u64),
 }
 
 impl std::fmt::Display for OtpError {
@@ -23,6 +23,9 @@ impl std::fmt::Display for OtpError {
             OtpError::InvalidOtp => write!(f, "Invalid or already used OTP"),
             OtpError::ExpiredOtp => write!(f, "OTP has expired"),
             OtpError::SendError(msg) => write!(f, "Failed to send OTP: {}", msg),
+            OtpError::ResendTooSoon(secs) => {
+                write!(f, "Please wait {} seconds before requesting another OTP", secs)
+            }
         }
     }
 }
@@ -37,16 +40,6 @@ pub fn generate_otp() -> String {
 
 /// Send email with OTP
 async fn send_email(to_email: &str, otp: &str) -> Result<(), String> {
-    let smtp_host = env::var("SMTP_HOST").unwrap_or_else(|_| "smtp.gmail.com".to_string());
-    let smtp_port: u16 = env::var("SMTP_PORT")
-        .unwrap_or_else(|_| "587".to_string())
-        .parse()
-        .unwrap_or(587);
-    let smtp_username = env::var("SMTP_USERNAME").map_err(|_| "SMTP_USERNAME not set in .env")?;
-    let smtp_password = env::var("SMTP_PASSWORD").map_err(|_| "SMTP_PASSWORD not set in .env")?;
-    let from_email = env::var("SMTP_FROM_EMAIL").unwrap_or_else(|_| smtp_username.clone());
-    let from_name = env::var("SMTP_FROM_NAME").unwrap_or_else(|_| "BlockWallet".to_string());
-
     // Create email body
     let html_body = format!(
         r#"
@@ -87,68 +80,79 @@ async fn send_email(to_email: &str, otp: &str) -> Result<(), String> {
         otp
     );
 
-    // Build email
-    let email = Message::builder()
-        .from(format!("{} <{}>", from_name, from_email).parse().map_err(|e| format!("Invalid from address: {}", e))?)
-        .to(to_email.parse().map_err(|e| format!("Invalid to address: {}", e))?)
-        .subject("BlockWallet - Email Verification Code")
-        .header(ContentType::TEXT_HTML)
-        .body(html_body)
-        .map_err(|e| format!("Failed to build email: {}", e))?;
-
-    // Create SMTP credentials
-    let creds = Credentials::new(smtp_username.clone(), smtp_password.clone());
-
-    // Create SMTP transport with STARTTLS
-    let mailer = SmtpTransport::starttls_relay(&smtp_host)
-        .map_err(|e| format!("Failed to create SMTP transport: {}", e))?
-        .credentials(creds)
-        .port(smtp_port)
-        .timeout(Some(std::time::Duration::from_secs(10)))
-        .build();
-
-    // Send email
-    mailer.send(&email)
-        .map_err(|e| format!("Failed to send email: {}", e))?;
-
-    log::info!("✅ Email sent successfully to {}", to_email);
-    
-    Ok(())
+    crate::services::email_service::send_html(to_email, "BlockWallet - Email Verification Code", html_body).await
 }
 
-/// Send OTP to email and store in database
-pub async fn send_otp(pool: &DbPool, email: &str) -> Result<String, OtpError> {
-    let client = pool.get().await
+/// Expire any outstanding codes for `email` and store a fresh one
+async fn reissue_otp(client: &deadpool_postgres::Client, email: &str) -> Result<String, OtpError> {
+    queries::invalidate_otps(client, email)
+        .await
         .map_err(|e| OtpError::DatabaseError(e.to_string()))?;
 
-    // Generate OTP
     let otp = generate_otp();
-    let expires_at = Utc::now() + Duration::minutes(10);
-
-    // Store OTP in database
-    queries::create_otp(&client, email, &otp, expires_at)
+    queries::create_otp(client, email, &otp, Utc::now() + Duration::minutes(10))
         .await
         .map_err(|e| OtpError::DatabaseError(e.to_string()))?;
 
-    // Send email with OTP
-    send_email(email, &otp)
+    Ok(otp)
+}
+
+/// Send a new OTP to `email`, invalidating every earlier one, at most once per cooldown
+pub async fn send_otp(pool: &DbPool, email: &str, metrics: &Metrics) -> Result<String, OtpError> {
+    let client = pool.get().await
+        .map_err(|e| OtpError::DatabaseError(e.to_string()))?;
+
+    let last_sent = queries::latest_otp_created_at(&client, email)
         .await
-        .map_err(|e| OtpError::SendError(e))?;
+        .map_err(|e| OtpError::DatabaseError(e.to_string()))?;
+    if let Some(last_sent) = last_sent {
+        let wait = (last_sent + Duration::seconds(RESEND_COOLDOWN_SECONDS) - Utc::now()).num_seconds();
+        if wait > 0 {
+            return Err(OtpError::ResendTooSoon(wait as u64));
+        }
+    }
+
+    let otp = reissue_otp(&client, email).await?;
+
+    if let Err(e) = send_email(email, &otp).await {
+        metrics.otp_emails_failed.inc();
+        return Err(OtpError::SendError(e));
+    }
+    metrics.otp_emails_sent.inc();
 
     log::info!("📧 OTP sent to {}", email);
 
     Ok(otp) // In production, consider not returning OTP for security
 }
 
+/// Send a new OTP; the same as [`send_otp`], which already invalidates earlier codes
+pub async fn resend_otp(pool: &DbPool, email: &str, metrics: &Metrics) -> Result<String, OtpError> {
+    send_otp(pool, email, metrics).await
+}
+
 /// Verify OTP and mark user as verified
 pub async fn verify_otp(pool: &DbPool, email: &str, otp: &str) -> Result<(), OtpError> {
     let client = pool.get().await
         .map_err(|e| OtpError::DatabaseError(e.to_string()))?;
 
-    // Verify OTP
-    let is_valid = queries::verify_otp(&client, email, otp)
+    // Compare in Rust rather than in SQL so timing doesn't reveal how much of the code matched;
+    // every active code is checked so the number of candidates doesn't either
+    let candidates = queries::get_active_otps(&client, email)
         .await
         .map_err(|e| OtpError::DatabaseError(e.to_string()))?;
+    let mut matched = None;
+    for (id, code) in &candidates {
+        if crate::crypto::constant_time_eq(code.as_bytes(), otp.as_bytes()) {
+            matched = Some(*id);
+        }
+    }
+
+    let is_valid = match matched {
+        Some(id) => queries::mark_otp_verified(&client, id)
+            .await
+            .map_err(|e| OtpError::DatabaseError(e.to_string()))?,
+        None => false,
+    };
 
     if !is_valid {
         return Err(OtpError::InvalidOtp);
@@ -163,3 +167,102 @@ pub async fn verify_otp(pool: &DbPool, email: &str, otp: &str) -> Result<(), Otp
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_resend_invalidates_previous_code() {
+        let Some(pool) = crate::database::create_test_pool("otp_resend").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let email = "resend@example.com";
+
+        let client = pool.get().await.unwrap();
+        let old_otp = reissue_otp(&client, email).await.unwrap();
+        let mut new_otp = reissue_otp(&client, email).await.unwrap();
+        while new_otp == old_otp {
+            new_otp = reissue_otp(&client, email).await.unwrap();
+        }
+        drop(client);
+
+        assert!(matches!(verify_otp(&pool, email, &old_otp).await, Err(OtpError::InvalidOtp)));
+        verify_otp(&pool, email, &new_otp).await.unwrap();
+        // A code works once
+        assert!(matches!(verify_otp(&pool, email, &new_otp).await, Err(OtpError::InvalidOtp)));
+
+        let metrics = Metrics::new().unwrap();
+        assert!(matches!(
+            resend_otp(&pool, email, &metrics).await,
+            Err(OtpError::ResendTooSoon(secs)) if secs > 0 && secs <= RESEND_COOLDOWN_SECONDS as u64
+        ));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_send_otp_invalidates_previous_code() {
+        let Some(pool) = crate::database::create_test_pool("otp_send").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let email = "send@example.com";
+        let metrics = Metrics::new().unwrap();
+
+        // The code is stored before the email goes out, so a test without SMTP still issues one
+        let send = || async {
+            match send_otp(&pool, email, &metrics).await {
+                Ok(_) | Err(OtpError::SendError(_)) => {}
+                Err(e) => panic!("{}", e),
+            }
+        };
+        let active = || async {
+            let client = pool.get().await.unwrap();
+            queries::get_active_otps(&client, email).await.unwrap()
+        };
+
+        send().await;
+        let first = active().await;
+        assert_eq!(first.len(), 1);
+        assert!(matches!(send_otp(&pool, email, &metrics).await, Err(OtpError::ResendTooSoon(_))));
+
+        // Once the cooldown has passed, a second send replaces the first code
+        let client = pool.get().await.unwrap();
+        client
+            .execute(
+                "UPDATE email_otps SET created_at = created_at - INTERVAL '2 minutes' WHERE email = $1",
+                &[&email],
+            )
+            .await
+            .unwrap();
+        drop(client);
+        send().await;
+        let second = active().await;
+        assert_eq!(second.len(), 1);
+        assert_ne!(second[0].0, first[0].0);
+        if second[0].1 != first[0].1 {
+            assert!(matches!(verify_otp(&pool, email, &first[0].1).await, Err(OtpError::InvalidOtp)));
+        }
+        verify_otp(&pool, email, &second[0].1).await.unwrap();
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_verify_otp_accepts_only_the_exact_code() {
+        let Some(pool) = crate::database::create_test_pool("otp_verify").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let email = "verify@example.com";
+
+        let client = pool.get().await.unwrap();
+        let otp = reissue_otp(&client, email).await.unwrap();
+        drop(client);
+
+        let last = otp.chars().last().unwrap().to_digit(10).unwrap();
+        let off_by_one = format!("{}{}", &otp[..otp.len() - 1], (last + 1) % 10);
+        for wrong in [off_by_one.as_str(), &otp[..otp.len() - 1], "", "not-a-code"] {
+            assert!(matches!(verify_otp(&pool, email, wrong).await, Err(OtpError::InvalidOtp)), "{:?}", wrong);
+        }
+        // Someone else's email doesn't match either
+        assert!(matches!(verify_otp(&pool, "other@example.com", &otp).await, Err(OtpError::InvalidOtp)));
+
+        verify_otp(&pool, email, &otp).await.unwrap();
+    }
+}
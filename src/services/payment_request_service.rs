@@ -0,0 +1,286 @@
+use crate::database::{DbPool, queries};
+use crate::models::PaymentRequest;
+use crate::services::webhook_service::sign_payload;
+use crate::utils::{format_units, to_units};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+pub const URI_SCHEME: &str = "blockwallet";
+
+/// How long a payment request stays payable when the receiver doesn't say
+pub const DEFAULT_EXPIRY_SECONDS: i64 = 24 * 60 * 60;
+pub const MAX_EXPIRY_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+#[derive(Debug)]
+pub enum PaymentRequestError {
+    InvalidAmount,
+    InvalidExpiry,
+    InvalidUri(String),
+    WalletNotFound,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for PaymentRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PaymentRequestError::InvalidAmount => write!(f, "Amount must be positive"),
+            PaymentRequestError::InvalidExpiry => {
+                write!(f, "expires_in_seconds must be between 1 and {}", MAX_EXPIRY_SECONDS)
+            }
+            PaymentRequestError::InvalidUri(msg) => write!(f, "Invalid payment URI: {}", msg),
+            PaymentRequestError::WalletNotFound => write!(f, "Wallet not found"),
+            PaymentRequestError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PaymentRequestError {}
+
+/// Decoded `blockwallet:<wallet_id>?amount=..&note=..&expires=..&id=..&sig=..` URI.
+/// Every query field is optional; a bare `blockwallet:<wallet_id>` is just an address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaymentUri {
+    pub wallet_id: String,
+    pub amount: Option<i64>,
+    pub note: Option<String>,
+    /// Unix timestamp after which the request can no longer be paid
+    pub expires: Option<i64>,
+    pub request_id: Option<Uuid>,
+    pub signature: Option<String>,
+}
+
+impl PaymentUri {
+    pub fn new(wallet_id: &str, amount: Option<i64>, note: Option<&str>) -> Self {
+        PaymentUri {
+            wallet_id: wallet_id.to_string(),
+            amount,
+            note: note.filter(|n| !n.is_empty()).map(str::to_string),
+            expires: None,
+            request_id: None,
+            signature: None,
+        }
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires.is_some_and(|expires| now >= expires)
+    }
+
+    /// The URI without `sig`; this is what the signature covers
+    fn unsigned(&self) -> String {
+        let mut params = Vec::new();
+        if let Some(amount) = self.amount {
+            params.push(format!("amount={}", format_units(amount)));
+        }
+        if let Some(note) = &self.note {
+            params.push(format!("note={}", percent_encode(note)));
+        }
+        if let Some(expires) = self.expires {
+            params.push(format!("expires={}", expires));
+        }
+        if let Some(id) = self.request_id {
+            params.push(format!("id={}", id));
+        }
+
+        if params.is_empty() {
+            format!("{}:{}", URI_SCHEME, self.wallet_id)
+        } else {
+            format!("{}:{}?{}", URI_SCHEME, self.wallet_id, params.join("&"))
+        }
+    }
+
+    pub fn to_uri(&self) -> String {
+        match &self.signature {
+            Some(sig) if self.unsigned().contains('?') => format!("{}&sig={}", self.unsigned(), sig),
+            Some(sig) => format!("{}?sig={}", self.unsigned(), sig),
+            None => self.unsigned(),
+        }
+    }
+
+    pub fn sign(mut self, secret: &str) -> Self {
+        self.signature = Some(sign_payload(secret, self.unsigned().as_bytes()));
+        self
+    }
+
+    /// Whether `sig` was issued by this server for exactly these fields
+    pub fn verify(&self, secret: &str) -> bool {
+        let expected = sign_payload(secret, self.unsigned().as_bytes());
+        self.signature
+            .as_deref()
+            .is_some_and(|sig| crate::crypto::constant_time_eq(sig.as_bytes(), expected.as_bytes()))
+    }
+}
+
+/// Decode a `blockwallet:` URI. Unknown query fields are ignored so newer URIs still parse.
+pub fn parse_uri(uri: &str) -> Result<PaymentUri, PaymentRequestError> {
+    let invalid = |msg: &str| PaymentRequestError::InvalidUri(msg.to_string());
+
+    let rest = uri
+        .strip_prefix(URI_SCHEME)
+        .and_then(|r| r.strip_prefix(':'))
+        .ok_or_else(|| invalid("expected blockwallet: scheme"))?;
+    let (wallet_id, query) = rest.split_once('?').unwrap_or((rest, ""));
+    if wallet_id.is_empty() {
+        return Err(invalid("missing wallet id"));
+    }
+
+    let mut parsed = PaymentUri::new(wallet_id, None, None);
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value).ok_or_else(|| invalid("bad percent-encoding"))?;
+        match key {
+            "amount" => {
+                let amount = value.parse::<f64>().ok().filter(|a| a.is_finite() && *a > 0.0);
+                parsed.amount = Some(to_units(amount.ok_or_else(|| invalid("bad amount"))?));
+            }
+            "note" => parsed.note = Some(value),
+            "expires" => parsed.expires = Some(value.parse().map_err(|_| invalid("bad expires"))?),
+            "id" => parsed.request_id = Some(value.parse().map_err(|_| invalid("bad id"))?),
+            "sig" => parsed.signature = Some(value),
+            _ => {}
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Signed URI for a stored payment request
+pub fn request_uri(request: &PaymentRequest, secret: &str) -> PaymentUri {
+    PaymentUri {
+        expires: Some(request.expires_at.timestamp()),
+        request_id: Some(request.id),
+        ..PaymentUri::new(&request.wallet_id, Some(request.amount), request.note.as_deref())
+    }
+    .sign(secret)
+}
+
+pub async fn create_payment_request(
+    pool: &DbPool,
+    wallet_id: &str,
+    amount: i64,
+    note: Option<String>,
+    expires_in_seconds: Option<i64>,
+) -> Result<PaymentRequest, PaymentRequestError> {
+    if amount <= 0 {
+        return Err(PaymentRequestError::InvalidAmount);
+    }
+    let expires_in = expires_in_seconds.unwrap_or(DEFAULT_EXPIRY_SECONDS);
+    if !(1..=MAX_EXPIRY_SECONDS).contains(&expires_in) {
+        return Err(PaymentRequestError::InvalidExpiry);
+    }
+
+    let client = pool.get().await
+        .map_err(|e| PaymentRequestError::DatabaseError(e.to_string()))?;
+
+    queries::get_wallet(&client, wallet_id)
+        .await
+        .map_err(|e| PaymentRequestError::DatabaseError(e.to_string()))?
+        .ok_or(PaymentRequestError::WalletNotFound)?;
+
+    let now = Utc::now();
+    let request = PaymentRequest {
+        id: Uuid::new_v4(),
+        wallet_id: wallet_id.to_string(),
+        amount,
+        note: note.filter(|n| !n.is_empty()),
+        expires_at: now + Duration::seconds(expires_in),
+        fulfilled_by: None,
+        fulfilled_at: None,
+        created_at: now,
+    };
+
+    queries::create_payment_request(&client, &request)
+        .await
+        .map_err(|e| PaymentRequestError::DatabaseError(e.to_string()))?;
+
+    Ok(request)
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+
+    fn sample_request(expires_at: chrono::DateTime<Utc>) -> PaymentRequest {
+        PaymentRequest {
+            id: Uuid::new_v4(),
+            wallet_id: "abc".to_string(),
+            amount: to_units(2.5),
+            note: Some("rent & food".to_string()),
+            expires_at,
+            fulfilled_by: None,
+            fulfilled_at: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_uri_generation() {
+        assert_eq!(PaymentUri::new("abc", None, None).to_uri(), "blockwallet:abc");
+
+        let request = sample_request(chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap());
+        let uri = request_uri(&request, SECRET).to_uri();
+        let expected_prefix = format!(
+            "blockwallet:abc?amount=2.50000000&note=rent%20%26%20food&expires=1700000000&id={}&sig=",
+            request.id
+        );
+        assert!(uri.starts_with(&expected_prefix), "{}", uri);
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        let request = sample_request(Utc::now() + Duration::hours(1));
+        let signed = request_uri(&request, SECRET);
+
+        let parsed = parse_uri(&signed.to_uri()).unwrap();
+        assert_eq!(parsed, signed);
+        assert_eq!(parsed.request_id, Some(request.id));
+        assert_eq!(parsed.note.as_deref(), Some("rent & food"));
+        assert!(parsed.verify(SECRET));
+        assert!(!parsed.verify("other-secret"));
+
+        let tampered = PaymentUri { amount: Some(to_units(25.0)), ..parsed };
+        assert!(!tampered.verify(SECRET));
+
+        assert!(parse_uri("bitcoin:abc").is_err());
+        assert!(parse_uri("blockwallet:abc?amount=-1").is_err());
+    }
+
+    #[test]
+    fn test_expiry() {
+        let now = Utc::now();
+        let live = request_uri(&sample_request(now + Duration::minutes(5)), SECRET);
+        let stale = request_uri(&sample_request(now - Duration::minutes(5)), SECRET);
+
+        assert!(!live.is_expired(now.timestamp()));
+        assert!(stale.is_expired(now.timestamp()));
+        assert!(!PaymentUri::new("abc", None, None).is_expired(now.timestamp()));
+    }
+}
@@ -1,7 +1,9 @@
-use crate::models::{PendingTransaction, CreateTransactionRequest};
-use crate::crypto::{create_transaction_payload, verify_signature, import_public_key_pem, sha256_hash, decrypt_private_key, import_private_key_pem, sign_data};
+use crate::models::{PendingTransaction, CreateTransactionRequest, TransactionLookup, User, Wallet, WalletStatus};
+use crate::crypto::{create_transaction_payload, verify_signature, import_public_key_pem, sha256_hash, decrypt_private_key, import_private_key_pem, sign_data, encrypt_note, decrypt_note};
+use crate::config::ChainConfig;
 use crate::database::{DbPool, queries};
 use crate::blockchain::calculate_wallet_balance;
+use crate::utils::{from_units, to_units};
 use uuid::Uuid;
 use chrono::Utc;
 use std::env;
@@ -9,9 +11,18 @@ use std::env;
 #[derive(Debug)]
 pub enum TransactionError {
     InvalidWallet(String),
+    WalletFrozen(String),
     InsufficientBalance,
     InvalidSignature,
-    InvalidAmount,
+    InvalidAmount(String),
+    NothingToConsolidate,
+    SelfTransfer,
+    IdempotencyConflict,
+    IdempotencyKeyReused,
+    PaymentRequest(String),
+    InvalidNote(String),
+    NotFound,
+    NotReceiver,
     DatabaseError(String),
     CryptoError(String),
 }
@@ -20,9 +31,18 @@ impl std::fmt::Display for TransactionError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             TransactionError::InvalidWallet(msg) => write!(f, "Invalid wallet: {}", msg),
+            TransactionError::WalletFrozen(msg) => write!(f, "Wallet frozen: {}", msg),
             TransactionError::InsufficientBalance => write!(f, "Insufficient balance"),
             TransactionError::InvalidSignature => write!(f, "Invalid signature"),
-            TransactionError::InvalidAmount => write!(f, "Invalid amount"),
+            TransactionError::InvalidAmount(msg) => write!(f, "Invalid amount: {}", msg),
+            TransactionError::NothingToConsolidate => write!(f, "Nothing to consolidate"),
+            TransactionError::SelfTransfer => write!(f, "Sender and receiver wallets must differ"),
+            TransactionError::IdempotencyConflict => write!(f, "A request with this Idempotency-Key is still being processed"),
+            TransactionError::IdempotencyKeyReused => write!(f, "Idempotency-Key was already used for a different request"),
+            TransactionError::PaymentRequest(msg) => write!(f, "Payment request: {}", msg),
+            TransactionError::InvalidNote(msg) => write!(f, "Invalid note: {}", msg),
+            TransactionError::NotFound => write!(f, "Transaction not found"),
+            TransactionError::NotReceiver => write!(f, "Only the receiver can read this note"),
             TransactionError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
             TransactionError::CryptoError(msg) => write!(f, "Crypto error: {}", msg),
         }
@@ -31,86 +51,238 @@ impl std::fmt::Display for TransactionError {
 
 impl std::error::Error for TransactionError {}
 
+/// Which side of a transfer a wallet is on, so a missing wallet can be reported precisely
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferSide {
+    Sender,
+    Receiver,
+}
+
+impl std::fmt::Display for TransferSide {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TransferSide::Sender => write!(f, "Sender"),
+            TransferSide::Receiver => write!(f, "Receiver"),
+        }
+    }
+}
+
+/// Load a wallet taking part in a transfer; a missing wallet becomes `InvalidWallet` and a
+/// frozen one `WalletFrozen`, both naming its side
+pub async fn require_wallet(
+    client: &deadpool_postgres::Client,
+    wallet_id: &str,
+    side: TransferSide,
+) -> Result<Wallet, TransactionError> {
+    let wallet = queries::get_wallet(client, wallet_id)
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| TransactionError::InvalidWallet(format!("{} wallet {} not found", side, wallet_id)))?;
+
+    if wallet.status == WalletStatus::Frozen {
+        return Err(TransactionError::WalletFrozen(format!("{} wallet {}", side, wallet_id)));
+    }
+    Ok(wallet)
+}
+
+/// Load both wallets of a transfer, checking the sender first
+pub async fn require_transfer_wallets(
+    client: &deadpool_postgres::Client,
+    sender_wallet_id: &str,
+    receiver_wallet_id: &str,
+) -> Result<(Wallet, Wallet), TransactionError> {
+    let sender = require_wallet(client, sender_wallet_id, TransferSide::Sender).await?;
+    let receiver = require_wallet(client, receiver_wallet_id, TransferSide::Receiver).await?;
+    Ok((sender, receiver))
+}
+
+/// Basic checks shared by every transfer path. Self-transfers are only allowed when
+/// the caller opts in (e.g. UTXO consolidation).
+pub fn validate_transfer(
+    sender_wallet_id: &str,
+    receiver_wallet_id: &str,
+    amount: i64,
+    allow_self_transfer: bool,
+) -> Result<(), TransactionError> {
+    // Amounts below one unit round to zero
+    if amount <= 0 {
+        return Err(TransactionError::InvalidAmount("amount must be positive".to_string()));
+    }
+
+    if sender_wallet_id == receiver_wallet_id && !allow_self_transfer {
+        return Err(TransactionError::SelfTransfer);
+    }
+
+    Ok(())
+}
+
+/// User transfers must send at least `chain.min_transaction_amount`. System transfers
+/// (zakat, consolidation) are exempt.
+pub fn check_minimum_amount(amount: i64, chain: &ChainConfig) -> Result<(), TransactionError> {
+    if amount < to_units(chain.min_transaction_amount) {
+        return Err(TransactionError::InvalidAmount(format!(
+            "{} is below the minimum transaction amount of {}",
+            from_units(amount),
+            chain.min_transaction_amount
+        )));
+    }
+    Ok(())
+}
+
+/// No transfer can send more than could ever exist; also keeps `amount + fee` far from overflowing
+pub fn check_maximum_amount(amount: i64, chain: &ChainConfig) -> Result<(), TransactionError> {
+    if amount > to_units(chain.max_coin_supply) {
+        return Err(TransactionError::InvalidAmount(format!(
+            "{} exceeds the maximum coin supply of {}",
+            from_units(amount),
+            chain.max_coin_supply
+        )));
+    }
+    Ok(())
+}
+
+/// User transfer notes may be at most `chain.max_note_length` characters, checked before
+/// any encryption
+pub fn check_note_length(note: Option<&str>, chain: &ChainConfig) -> Result<(), TransactionError> {
+    match note.map(|note| note.chars().count()) {
+        Some(length) if length > chain.max_note_length => Err(TransactionError::InvalidNote(format!(
+            "{} characters exceeds the maximum of {}",
+            length, chain.max_note_length
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Sign `payload` with the user's decrypted private key
+fn sign_as_user(user: &User, payload: &str, aes_key: &[u8]) -> Result<String, TransactionError> {
+    // Decrypt and import private key from the user record
+    let decrypted_private_key = decrypt_private_key(&user.encrypted_private_key, aes_key)
+        .map_err(|e| TransactionError::CryptoError(e.to_string()))?;
+
+    let private_key = import_private_key_pem(&decrypted_private_key)
+        .map_err(|e| TransactionError::CryptoError(e.to_string()))?;
+
+    sign_data(&private_key, payload).map_err(|e| TransactionError::CryptoError(e.to_string()))
+}
+
+/// Verify `signature` over `payload` against the public key of the user owning
+/// `sender_wallet_id`. The key always comes from that record, never from the caller, so a
+/// signature made by another wallet's key is `InvalidSignature`.
+pub async fn verify_sender_signature(
+    client: &deadpool_postgres::Client,
+    sender_wallet_id: &str,
+    payload: &str,
+    signature: &str,
+) -> Result<(), TransactionError> {
+    let wallet = queries::get_wallet(client, sender_wallet_id)
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| TransactionError::InvalidWallet(format!("Sender wallet {} not found", sender_wallet_id)))?;
+    let user_id = wallet.user_id.ok_or_else(|| {
+        TransactionError::InvalidWallet("Sender wallet has no associated signing key".to_string())
+    })?;
+    let user = queries::find_user_by_id(client, user_id)
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| TransactionError::InvalidWallet("Sender user not found".to_string()))?;
+
+    let public_key = import_public_key_pem(&user.public_key)
+        .map_err(|e| TransactionError::CryptoError(e.to_string()))?;
+
+    // A signature that can't even be decoded is as wrong as one that doesn't match
+    match verify_signature(&public_key, payload, signature) {
+        Ok(true) => Ok(()),
+        _ => Err(TransactionError::InvalidSignature),
+    }
+}
+
+/// Pending transaction already stored under `transaction_hash`, if any
+async fn find_duplicate(
+    client: &deadpool_postgres::Client,
+    transaction_hash: &str,
+) -> Result<Option<PendingTransaction>, TransactionError> {
+    let existing = queries::get_pending_transaction_by_hash(client, transaction_hash)
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+    if existing.is_some() {
+        log::info!("↩️ Transaction {} is already pending; returning it", transaction_hash);
+    }
+    Ok(existing)
+}
+
 /// Validate and create a new transaction
 pub async fn create_transaction(
     pool: &DbPool,
     req: CreateTransactionRequest,
+    chain: &ChainConfig,
     aes_key: &[u8],
 ) -> Result<PendingTransaction, TransactionError> {
     let client = pool.get().await
         .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
 
-    // Validate amount
-    if req.amount <= 0.0 {
-        return Err(TransactionError::InvalidAmount);
-    }
+    // Validate amount and reject transfers to the sending wallet
+    let amount = to_units(req.amount);
+    validate_transfer(&req.sender_wallet_id, &req.receiver_wallet_id, amount, false)?;
+    check_minimum_amount(amount, chain)?;
+    check_maximum_amount(amount, chain)?;
+    check_note_length(req.note.as_deref(), chain)?;
 
-    // Check sender wallet exists
-    let sender_wallet = queries::get_wallet(&client, &req.sender_wallet_id)
-        .await
-        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?
-        .ok_or_else(|| TransactionError::InvalidWallet("Sender wallet not found".to_string()))?;
+    // Both wallets must exist; nothing is created for an unknown receiver
+    let (sender_wallet, receiver_wallet) =
+        require_transfer_wallets(&client, &req.sender_wallet_id, &req.receiver_wallet_id).await?;
 
-    // Check receiver wallet exists
-    let _receiver_wallet = queries::get_wallet(&client, &req.receiver_wallet_id)
-        .await
-        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?
-        .ok_or_else(|| TransactionError::InvalidWallet("Receiver wallet not found".to_string()))?;
+    if let Some(request_id) = req.payment_request_id {
+        check_payment_request(&client, request_id, &req.receiver_wallet_id, amount).await?;
+    }
 
-    // Get transaction fee from environment
-    let transaction_fee = env::var("TRANSACTION_FEE")
-        .unwrap_or_else(|_| "0.1".to_string())
-        .parse::<f64>()
-        .unwrap_or(0.1);
+    let transaction_fee = to_units(chain.transaction_fee);
 
-    // Calculate sender's balance from UTXOs
+    // Calculate sender's balance from UTXOs, leaving out mining rewards that haven't matured
     let sender_balance = calculate_wallet_balance(&client, &req.sender_wallet_id)
         .await
         .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+    let immature = queries::get_immature_coinbase_total(&client, Some(&req.sender_wallet_id), chain.coinbase_maturity)
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
 
     // Check if sender has enough balance for amount + fee
-    let total_required = req.amount + transaction_fee;
-    if sender_balance < total_required {
+    let total_required = amount
+        .checked_add(transaction_fee)
+        .ok_or_else(|| TransactionError::InvalidAmount("amount plus fee is too large".to_string()))?;
+    if to_units(sender_balance) - immature < total_required {
         return Err(TransactionError::InsufficientBalance);
     }
 
-    // Get sender's user info for public key and encrypted private key
-    let sender_user = queries::find_user_by_id(&client, sender_wallet.user_id.unwrap())
+    // Get sender's user info for public key and encrypted private key; system wallets such as
+    // the zakat pool have no owner and therefore nothing to sign with
+    let sender_user_id = sender_wallet.user_id.ok_or_else(|| {
+        TransactionError::InvalidWallet("Sender wallet has no associated signing key".to_string())
+    })?;
+    let sender_user = queries::find_user_by_id(&client, sender_user_id)
         .await
         .map_err(|e| TransactionError::DatabaseError(e.to_string()))?
         .ok_or_else(|| TransactionError::InvalidWallet("Sender user not found".to_string()))?;
 
-    // Decrypt and import private key from sender's user record
-    let decrypted_private_key = decrypt_private_key(&sender_user.encrypted_private_key, aes_key)
-        .map_err(|e| TransactionError::CryptoError(e.to_string()))?;
-    
-    let private_key = import_private_key_pem(&decrypted_private_key)
-        .map_err(|e| TransactionError::CryptoError(e.to_string()))?;
+    // Private notes are stored (and signed) as ciphertext only the receiver can decrypt
+    let note_encrypted = req.private_note && req.note.is_some();
+    let note = match &req.note {
+        Some(note) if note_encrypted => Some(encrypt_note_for(&client, &receiver_wallet, note).await?),
+        _ => req.note.clone(),
+    };
 
     // Create transaction payload
     let timestamp = Utc::now().timestamp();
     let payload = create_transaction_payload(
         &req.sender_wallet_id,
         &req.receiver_wallet_id,
-        req.amount,
+        from_units(amount),
         timestamp,
-        &req.note,
+        &note,
     );
 
     // Sign transaction
-    let signature = sign_data(&private_key, &payload)
-        .map_err(|e| TransactionError::CryptoError(e.to_string()))?;
-
-    // Verify signature with public key
-    let public_key = import_public_key_pem(&sender_user.public_key)
-        .map_err(|e| TransactionError::CryptoError(e.to_string()))?;
-
-    let is_valid = verify_signature(&public_key, &payload, &signature)
-        .map_err(|e| TransactionError::CryptoError(e.to_string()))?;
-
-    if !is_valid {
-        return Err(TransactionError::InvalidSignature);
-    }
+    let signature = sign_as_user(&sender_user, &payload, aes_key)?;
+    verify_sender_signature(&client, &req.sender_wallet_id, &payload, &signature).await?;
 
     // Create transaction hash
     let transaction_hash = sha256_hash(format!("{}{}", payload, signature).as_bytes());
@@ -121,23 +293,49 @@ pub async fn create_transaction(
         transaction_hash: transaction_hash.clone(),
         sender_wallet_id: req.sender_wallet_id.clone(),
         receiver_wallet_id: req.receiver_wallet_id.clone(),
-        amount: req.amount,
+        amount,
         fee: transaction_fee,
-        note: req.note.clone(),
+        note: note.clone(),
+        note_encrypted,
         signature,
         timestamp,
         created_at: Utc::now(),
     };
 
-    // Save to database
-    queries::create_pending_transaction(&client, &pending_tx)
-        .await
-        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+    // The same fields signed in the same second hash the same; hand back the transaction
+    // already pending instead of spending the inputs twice
+    if let Some(existing) = find_duplicate(&client, &transaction_hash).await? {
+        return Ok(existing);
+    }
+
+    // Claim the payment request first so two transfers can't both pay it
+    if let Some(request_id) = req.payment_request_id {
+        let claimed = queries::fulfill_payment_request(&client, request_id, &transaction_hash)
+            .await
+            .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+        if !claimed {
+            return Err(TransactionError::PaymentRequest("already paid or expired".to_string()));
+        }
+    }
+
+    // Save to database; losing a race with an identical submission returns the winner's row
+    let saved = queries::create_pending_transaction(&client, &pending_tx).await;
+    if !matches!(saved, Ok(true)) {
+        if let Some(request_id) = req.payment_request_id {
+            if let Err(release_err) = queries::release_payment_request(&client, request_id, &transaction_hash).await {
+                log::error!("Failed to release payment request {}: {}", request_id, release_err);
+            }
+        }
+        return match saved {
+            Err(e) => Err(TransactionError::DatabaseError(e.to_string())),
+            _ => find_duplicate(&client, &transaction_hash)
+                .await?
+                .ok_or_else(|| TransactionError::DatabaseError(format!("Pending transaction {} vanished", transaction_hash))),
+        };
+    }
 
     // Update sender's balance (will now reflect pending transaction deduction)
-    let updated_sender_balance = calculate_wallet_balance(&client, &req.sender_wallet_id).await
-        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
-    queries::update_wallet_balance(&client, &req.sender_wallet_id, updated_sender_balance).await
+    let updated_sender_balance = queries::refresh_wallet_balance(&client, &req.sender_wallet_id).await
         .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
 
     log::info!("✅ Created pending transaction {} for {} coins (new available balance: {})", 
@@ -153,7 +351,7 @@ pub async fn create_transaction(
         "pending",
         None,
         None,
-        req.note.clone(),
+        note.clone(),
     )
     .await
     .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
@@ -167,7 +365,7 @@ pub async fn create_transaction(
         "pending",
         None,
         None,
-        req.note,
+        note,
     )
     .await
     .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
@@ -177,6 +375,271 @@ pub async fn create_transaction(
     Ok(pending_tx)
 }
 
+/// Encrypt `note` to the public key of the user owning `receiver`
+async fn encrypt_note_for(
+    client: &deadpool_postgres::Client,
+    receiver: &Wallet,
+    note: &str,
+) -> Result<String, TransactionError> {
+    let receiver_user = match receiver.user_id {
+        Some(user_id) => queries::find_user_by_id(client, user_id)
+            .await
+            .map_err(|e| TransactionError::DatabaseError(e.to_string()))?,
+        None => None,
+    }
+    .ok_or_else(|| TransactionError::InvalidWallet("Receiver has no key to encrypt a private note to".to_string()))?;
+
+    let public_key = import_public_key_pem(&receiver_user.public_key)
+        .map_err(|e| TransactionError::CryptoError(e.to_string()))?;
+    encrypt_note(&public_key, note).map_err(|e| TransactionError::InvalidNote(e.to_string()))
+}
+
+/// The plaintext note of a transaction, for its receiver only. Private notes are decrypted
+/// with the receiver's key; `None` when the transaction has no note.
+pub async fn read_note(
+    client: &deadpool_postgres::Client,
+    tx_hash: &str,
+    reader: &User,
+    aes_key: &[u8],
+) -> Result<Option<String>, TransactionError> {
+    let lookup = find_transaction(client, tx_hash)
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+    let (receiver, note, note_encrypted) = match lookup {
+        Some(TransactionLookup::Confirmed(tx)) => (tx.receiver_wallet_id, tx.note, tx.note_encrypted),
+        Some(TransactionLookup::Pending(tx)) => (tx.receiver_wallet_id, tx.note, tx.note_encrypted),
+        None => return Err(TransactionError::NotFound),
+    };
+
+    if receiver != reader.wallet_id {
+        return Err(TransactionError::NotReceiver);
+    }
+
+    match note {
+        Some(ciphertext) if note_encrypted => {
+            let private_key_pem = decrypt_private_key(&reader.encrypted_private_key, aes_key)
+                .map_err(|e| TransactionError::CryptoError(e.to_string()))?;
+            let private_key = import_private_key_pem(&private_key_pem)
+                .map_err(|e| TransactionError::CryptoError(e.to_string()))?;
+            decrypt_note(&private_key, &ciphertext)
+                .map(Some)
+                .map_err(|e| TransactionError::CryptoError(e.to_string()))
+        }
+        note => Ok(note),
+    }
+}
+
+/// A transfer may pay a payment request only if it sends exactly the requested amount to the
+/// requesting wallet while the request is unpaid and unexpired
+async fn check_payment_request(
+    client: &deadpool_postgres::Client,
+    request_id: Uuid,
+    receiver_wallet_id: &str,
+    amount: i64,
+) -> Result<(), TransactionError> {
+    let request = queries::get_payment_request(client, request_id)
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| TransactionError::PaymentRequest("not found".to_string()))?;
+
+    if request.wallet_id != receiver_wallet_id {
+        return Err(TransactionError::PaymentRequest("receiver does not match the request".to_string()));
+    }
+    if request.amount != amount {
+        return Err(TransactionError::PaymentRequest("amount does not match the request".to_string()));
+    }
+    if request.fulfilled_by.is_some() {
+        return Err(TransactionError::PaymentRequest("already paid".to_string()));
+    }
+    if request.expires_at <= Utc::now() {
+        return Err(TransactionError::PaymentRequest("expired".to_string()));
+    }
+    Ok(())
+}
+
+/// Fingerprint of the request fields a replayed idempotency key must match
+fn idempotency_request_hash(req: &CreateTransactionRequest) -> String {
+    let mut data = format!(
+        "{}|{}|{}|{}",
+        req.sender_wallet_id,
+        req.receiver_wallet_id,
+        req.amount,
+        req.note.as_deref().unwrap_or("")
+    );
+    if req.private_note {
+        data.push_str("|private");
+    }
+    if let Some(request_id) = req.payment_request_id {
+        data.push_str(&format!("|{}", request_id));
+    }
+    sha256_hash(data.as_bytes())
+}
+
+/// `create_transaction` guarded by a client-supplied `Idempotency-Key`. Repeating the same
+/// request with the same key within `IDEMPOTENCY_KEY_TTL` seconds returns the original
+/// pending transaction instead of creating another one.
+pub async fn create_transaction_idempotent(
+    pool: &DbPool,
+    req: CreateTransactionRequest,
+    chain: &ChainConfig,
+    aes_key: &[u8],
+    idempotency_key: &str,
+) -> Result<PendingTransaction, TransactionError> {
+    let client = pool.get().await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+
+    let ttl_seconds = env::var("IDEMPOTENCY_KEY_TTL")
+        .unwrap_or_else(|_| "86400".to_string())
+        .parse::<i64>()
+        .unwrap_or(86400);
+
+    queries::delete_expired_idempotency_keys(&client, ttl_seconds)
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+
+    let sender_wallet_id = req.sender_wallet_id.clone();
+    let request_hash = idempotency_request_hash(&req);
+
+    let claimed = queries::claim_idempotency_key(&client, &sender_wallet_id, idempotency_key, &request_hash)
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+
+    if !claimed {
+        let existing = queries::get_idempotency_key(&client, &sender_wallet_id, idempotency_key)
+            .await
+            .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+
+        return match existing {
+            Some((stored_hash, _)) if stored_hash != request_hash => Err(TransactionError::IdempotencyKeyReused),
+            Some((_, Some(response))) => {
+                log::info!("↩️ Replaying transaction for Idempotency-Key {} from {}", idempotency_key, sender_wallet_id);
+                serde_json::from_value(response).map_err(|e| TransactionError::DatabaseError(e.to_string()))
+            }
+            // Still in flight (or expired between the claim and the read)
+            _ => Err(TransactionError::IdempotencyConflict),
+        };
+    }
+
+    // create_transaction takes its own connection; don't hold two at once
+    drop(client);
+    let result = create_transaction(pool, req, chain, aes_key).await;
+    let client = pool.get().await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+
+    match result {
+        Ok(pending_tx) => {
+            let response = serde_json::to_value(&pending_tx)
+                .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+            queries::complete_idempotency_key(&client, &sender_wallet_id, idempotency_key, &pending_tx.transaction_hash, &response)
+                .await
+                .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+            Ok(pending_tx)
+        }
+        Err(e) => {
+            // Failed requests don't consume the key, so the client can retry with it
+            if let Err(release_err) = queries::release_idempotency_key(&client, &sender_wallet_id, idempotency_key).await {
+                log::error!("Failed to release Idempotency-Key {}: {}", idempotency_key, release_err);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Merge a wallet's dust UTXOs (below `DUST_THRESHOLD`) into a single output via a
+/// self-transfer. The dust is reserved for the pending transaction so mining spends exactly it.
+pub async fn consolidate_dust(
+    pool: &DbPool,
+    wallet_id: &str,
+    owner: &User,
+    chain: &ChainConfig,
+    aes_key: &[u8],
+) -> Result<PendingTransaction, TransactionError> {
+    let client = pool.get().await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+
+    let dust_threshold = env::var("DUST_THRESHOLD")
+        .unwrap_or_else(|_| "1.0".to_string())
+        .parse::<f64>()
+        .unwrap_or(1.0);
+
+    require_wallet(&client, wallet_id, TransferSide::Sender).await?;
+
+    let transaction_fee = to_units(chain.transaction_fee);
+
+    let utxos = queries::get_unspent_utxos(&client, wallet_id)
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+
+    // Merging fewer than two outputs, or dust that can't cover the fee, achieves nothing
+    let (dust, amount) = crate::coin_selection::plan_consolidation(&utxos, to_units(dust_threshold), transaction_fee)
+        .ok_or(TransactionError::NothingToConsolidate)?;
+
+    validate_transfer(wallet_id, wallet_id, amount, true)?;
+
+    let timestamp = Utc::now().timestamp();
+    let note = Some(format!("Consolidated {} dust UTXOs", dust.len()));
+    let payload = create_transaction_payload(wallet_id, wallet_id, from_units(amount), timestamp, &note);
+    let signature = sign_as_user(owner, &payload, aes_key)?;
+    verify_sender_signature(&client, wallet_id, &payload, &signature).await?;
+    let transaction_hash = sha256_hash(format!("{}{}", payload, signature).as_bytes());
+
+    let pending_tx = PendingTransaction {
+        id: Uuid::new_v4(),
+        transaction_hash: transaction_hash.clone(),
+        sender_wallet_id: wallet_id.to_string(),
+        receiver_wallet_id: wallet_id.to_string(),
+        amount,
+        fee: transaction_fee,
+        note: note.clone(),
+        note_encrypted: false,
+        signature,
+        timestamp,
+        created_at: Utc::now(),
+    };
+
+    let inserted = queries::create_pending_transaction(&client, &pending_tx)
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+    if !inserted {
+        // An identical consolidation from the same second already reserved the dust
+        return find_duplicate(&client, &transaction_hash)
+            .await?
+            .ok_or_else(|| TransactionError::DatabaseError(format!("Pending transaction {} vanished", transaction_hash)));
+    }
+
+    // Reserve the dust so mining spends exactly these outputs
+    let dust_ids: Vec<Uuid> = dust.iter().map(|u| u.id).collect();
+    client
+        .execute(
+            "UPDATE utxos SET reserved_by = $1 WHERE id = ANY($2) AND is_spent = false",
+            &[&pending_tx.id, &dust_ids],
+        )
+        .await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+
+    queries::refresh_wallet_balance(&client, wallet_id).await
+        .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+
+    queries::create_transaction_log(
+        &client,
+        wallet_id,
+        "consolidated",
+        Some(transaction_hash.clone()),
+        None,
+        "pending",
+        None,
+        None,
+        note,
+    )
+    .await
+    .map_err(|e| TransactionError::DatabaseError(e.to_string()))?;
+
+    log::info!("✅ Consolidating {} dust UTXOs into {} coins for wallet {} in {}",
+        dust.len(), amount, wallet_id, transaction_hash);
+
+    Ok(pending_tx)
+}
+
 /* DEPRECATED: No longer using UTXO reservation - balance calculation now uses pending transaction amounts directly
 /// Reserve UTXOs for a pending transaction (lock coins until mined or failed)
 async fn reserve_utxos_for_pending_transaction(
@@ -254,8 +717,7 @@ pub async fn release_reserved_utxos(
         .await?;
     
     // Update wallet balance (coins are now available again)
-    let updated_balance = calculate_wallet_balance(&client, &wallet_id).await?;
-    queries::update_wallet_balance(&client, &wallet_id, updated_balance).await?;
+    let updated_balance = queries::refresh_wallet_balance(&client, &wallet_id).await?;
     
     log::info!("✅ Released reserved UTXOs for pending transaction {} (new balance: {})", 
         pending_tx_id, updated_balance);
@@ -264,6 +726,21 @@ pub async fn release_reserved_utxos(
 }
 */
 
+/// Find a transaction by hash, falling back to the mempool when it isn't mined yet.
+/// Confirmed is checked first since mining inserts the confirmed row before dropping the pending one.
+pub async fn find_transaction(
+    client: &deadpool_postgres::Client,
+    tx_hash: &str,
+) -> Result<Option<TransactionLookup>, tokio_postgres::Error> {
+    if let Some(transaction) = queries::get_transaction_by_hash(client, tx_hash).await? {
+        return Ok(Some(TransactionLookup::Confirmed(transaction)));
+    }
+
+    Ok(queries::get_pending_transaction_by_hash(client, tx_hash)
+        .await?
+        .map(TransactionLookup::Pending))
+}
+
 /// Get pending transactions count
 pub async fn get_pending_count(pool: &DbPool) -> Result<i32, Box<dyn std::error::Error>> {
     let client = pool.get().await?;
@@ -273,3 +750,450 @@ pub async fn get_pending_count(pool: &DbPool) -> Result<i32, Box<dyn std::error:
     let count: i64 = row.get(0);
     Ok(count as i32)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_transfer_rejected() {
+        assert!(matches!(
+            validate_transfer("alice", "alice", to_units(10.0), false),
+            Err(TransactionError::SelfTransfer)
+        ));
+        assert!(validate_transfer("alice", "alice", to_units(10.0), true).is_ok());
+    }
+
+    #[test]
+    fn test_normal_transfer_allowed() {
+        assert!(validate_transfer("alice", "bob", to_units(10.0), false).is_ok());
+        assert!(matches!(
+            validate_transfer("alice", "bob", 0, false),
+            Err(TransactionError::InvalidAmount(_))
+        ));
+    }
+
+    #[test]
+    fn test_minimum_transaction_amount() {
+        let chain = ChainConfig { min_transaction_amount: 0.001, ..ChainConfig::default() };
+
+        match check_minimum_amount(to_units(0.001) - 1, &chain) {
+            Err(TransactionError::InvalidAmount(msg)) => {
+                assert_eq!(msg, "0.00099999 is below the minimum transaction amount of 0.001")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(check_minimum_amount(to_units(0.001), &chain).is_ok());
+        assert!(check_minimum_amount(to_units(0.001) + 1, &chain).is_ok());
+
+        // The default floor is a single unit
+        assert!(check_minimum_amount(1, &ChainConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_maximum_transaction_amount() {
+        let chain = ChainConfig { max_coin_supply: 1000.0, ..ChainConfig::default() };
+
+        assert!(check_maximum_amount(to_units(1000.0), &chain).is_ok());
+        match check_maximum_amount(to_units(1000.0) + 1, &chain) {
+            Err(TransactionError::InvalidAmount(msg)) => {
+                assert_eq!(msg, "1000.00000001 exceeds the maximum coin supply of 1000")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // An absurd amount saturates to i64::MAX units, where adding the fee used to overflow
+        assert!(check_maximum_amount(to_units(1e300), &ChainConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_note_length_limit() {
+        let chain = ChainConfig { max_note_length: 4, ..ChainConfig::default() };
+
+        assert!(check_note_length(None, &chain).is_ok());
+        assert!(check_note_length(Some("rent"), &chain).is_ok());
+        // Counted in characters, not bytes
+        assert!(check_note_length(Some("زکوٰ"), &chain).is_ok());
+        match check_note_length(Some("rents"), &chain) {
+            Err(TransactionError::InvalidNote(msg)) => assert_eq!(msg, "5 characters exceeds the maximum of 4"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let default = ChainConfig::default();
+        assert!(check_note_length(Some(&"a".repeat(256)), &default).is_ok());
+        assert!(check_note_length(Some(&"a".repeat(257)), &default).is_err());
+    }
+
+    fn transfer(sender: &str, receiver: &str, amount: f64) -> CreateTransactionRequest {
+        CreateTransactionRequest {
+            sender_wallet_id: sender.to_string(),
+            receiver_wallet_id: receiver.to_string(),
+            amount,
+            note: None,
+            private_note: false,
+            payment_request_id: None,
+        }
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_transfer_wallets_report_missing_side() {
+        let pool = match crate::database::create_test_pool("tx_wallet_checks").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let sender = crate::database::register_test_user(&pool, "sender@example.com", "1").await;
+        let receiver = crate::database::register_test_user(&pool, "receiver@example.com", "2").await;
+
+        let client = pool.get().await.unwrap();
+
+        // Both present
+        let (s, r) = require_transfer_wallets(&client, &sender.wallet_id, &receiver.wallet_id).await.unwrap();
+        assert_eq!((s.wallet_id, r.wallet_id), (sender.wallet_id.clone(), receiver.wallet_id.clone()));
+
+        // Missing sender
+        match require_transfer_wallets(&client, "missing", &receiver.wallet_id).await {
+            Err(TransactionError::InvalidWallet(msg)) => assert_eq!(msg, "Sender wallet missing not found"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Missing receiver
+        match require_transfer_wallets(&client, &sender.wallet_id, "missing").await {
+            Err(TransactionError::InvalidWallet(msg)) => assert_eq!(msg, "Receiver wallet missing not found"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Creating a transfer to an unknown receiver creates neither a wallet nor a pending transaction
+        queries::create_utxo(&client, &sender.wallet_id, to_units(100.0), "funding", 0).await.unwrap();
+        drop(client);
+        let result = create_transaction(&pool, transfer(&sender.wallet_id, "missing", 5.0), &ChainConfig::default(), &aes_key).await;
+        assert!(matches!(result, Err(TransactionError::InvalidWallet(_))));
+
+        let client = pool.get().await.unwrap();
+        assert!(queries::get_wallet(&client, "missing").await.unwrap().is_none());
+        let pending: i64 = client
+            .query_one("SELECT COUNT(*) FROM pending_transactions", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(pending, 0);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_ownerless_sender_is_rejected() {
+        let Some(pool) = crate::database::create_test_pool("tx_ownerless_sender").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let receiver = crate::database::register_test_user(&pool, "receiver@example.com", "1").await;
+
+        let client = pool.get().await.unwrap();
+        queries::ensure_wallet(&client, "SYSTEM_WALLET").await.unwrap();
+        queries::create_utxo(&client, "SYSTEM_WALLET", to_units(100.0), "funding", 0).await.unwrap();
+        drop(client);
+
+        let result = create_transaction(&pool, transfer("SYSTEM_WALLET", &receiver.wallet_id, 5.0), &ChainConfig::default(), &aes_key).await;
+        match result {
+            Err(TransactionError::InvalidWallet(msg)) => assert_eq!(msg, "Sender wallet has no associated signing key"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let client = pool.get().await.unwrap();
+        let pending: i64 = client
+            .query_one("SELECT COUNT(*) FROM pending_transactions", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(pending, 0);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_signature_checked_against_claimed_sender() {
+        let Some(pool) = crate::database::create_test_pool("tx_sender_signature").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let a = crate::database::register_test_user(&pool, "a@example.com", "1").await;
+        let b = crate::database::register_test_user(&pool, "b@example.com", "2").await;
+
+        let client = pool.get().await.unwrap();
+
+        // Signed with A's key, so only valid when A is the sender
+        let payload = create_transaction_payload(&b.wallet_id, &a.wallet_id, 5.0, 1733571000, &None);
+        let signature = sign_as_user(&a, &payload, &aes_key).unwrap();
+        assert!(matches!(
+            verify_sender_signature(&client, &b.wallet_id, &payload, &signature).await,
+            Err(TransactionError::InvalidSignature)
+        ));
+        assert!(verify_sender_signature(&client, &a.wallet_id, &payload, &signature).await.is_ok());
+
+        assert!(matches!(
+            verify_sender_signature(&client, &a.wallet_id, &payload, "not-hex").await,
+            Err(TransactionError::InvalidSignature)
+        ));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_frozen_wallet_cannot_send_or_receive() {
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        let Some((pool, sender, receiver)) = crate::database::create_funded_test_pair("tx_frozen_wallet", &chain, 100.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+        let miner = crate::database::register_test_user(&pool, "miner@example.com", "3").await;
+
+        // Queued while both wallets were active
+        let queued = create_transaction(&pool, transfer(&sender.wallet_id, &receiver.wallet_id, 5.0), &chain, &aes_key)
+            .await
+            .unwrap();
+
+        // A frozen receiver can't be paid, and the queued transfer is held back by mining
+        let client = pool.get().await.unwrap();
+        queries::set_wallet_status(&client, &receiver.wallet_id, WalletStatus::Frozen).await.unwrap();
+        drop(client);
+        match create_transaction(&pool, transfer(&sender.wallet_id, &receiver.wallet_id, 6.0), &chain, &aes_key).await {
+            Err(TransactionError::WalletFrozen(msg)) => assert_eq!(msg, format!("Receiver wallet {}", receiver.wallet_id)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let block = crate::blockchain::mine_block(&pool, &miner.wallet_id, &chain, &crate::shutdown::Shutdown::new())
+            .await
+            .unwrap()
+            .block;
+        assert!(block.transactions.is_empty());
+        let client = pool.get().await.unwrap();
+        assert!(queries::get_pending_transaction_by_hash(&client, &queued.transaction_hash).await.unwrap().is_some());
+
+        // A frozen sender can't pay anyone
+        queries::set_wallet_status(&client, &receiver.wallet_id, WalletStatus::Active).await.unwrap();
+        queries::set_wallet_status(&client, &sender.wallet_id, WalletStatus::Frozen).await.unwrap();
+        drop(client);
+        match create_transaction(&pool, transfer(&sender.wallet_id, &receiver.wallet_id, 6.0), &chain, &aes_key).await {
+            Err(TransactionError::WalletFrozen(msg)) => assert_eq!(msg, format!("Sender wallet {}", sender.wallet_id)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Once unfrozen, the held-back transfer is mined
+        let client = pool.get().await.unwrap();
+        queries::set_wallet_status(&client, &sender.wallet_id, WalletStatus::Active).await.unwrap();
+        drop(client);
+        let block = crate::blockchain::mine_block(&pool, &miner.wallet_id, &chain, &crate::shutdown::Shutdown::new())
+            .await
+            .unwrap()
+            .block;
+        assert_eq!(block.transactions.len(), 1);
+        assert_eq!(block.transactions[0].transaction_hash, queued.transaction_hash);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_idempotency_key_deduplicates() {
+        let Some((pool, sender, receiver)) = crate::database::create_funded_test_pair("tx_idempotency", &ChainConfig::default(), 100.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let pending_count = || async {
+            let row = pool.get().await.unwrap()
+                .query_one("SELECT COUNT(*) FROM pending_transactions", &[])
+                .await
+                .unwrap();
+            row.get::<_, i64>(0)
+        };
+
+        // Same key twice: one pending transaction, same result both times
+        let first = create_transaction_idempotent(&pool, transfer(&sender.wallet_id, &receiver.wallet_id, 5.0), &ChainConfig::default(), &aes_key, "key-1")
+            .await
+            .unwrap();
+        let retry = create_transaction_idempotent(&pool, transfer(&sender.wallet_id, &receiver.wallet_id, 5.0), &ChainConfig::default(), &aes_key, "key-1")
+            .await
+            .unwrap();
+        assert_eq!(first.id, retry.id);
+        assert_eq!(first.transaction_hash, retry.transaction_hash);
+        assert_eq!(pending_count().await, 1);
+
+        // Reusing the key for a different request is rejected
+        let reused = create_transaction_idempotent(&pool, transfer(&sender.wallet_id, &receiver.wallet_id, 6.0), &ChainConfig::default(), &aes_key, "key-1").await;
+        assert!(matches!(reused, Err(TransactionError::IdempotencyKeyReused)));
+
+        // A different key creates a second transaction (different amount so the hash differs within the same second)
+        let second = create_transaction_idempotent(&pool, transfer(&sender.wallet_id, &receiver.wallet_id, 4.0), &ChainConfig::default(), &aes_key, "key-2")
+            .await
+            .unwrap();
+        assert_ne!(first.id, second.id);
+        assert_eq!(pending_count().await, 2);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_identical_resubmission_returns_pending_transaction() {
+        let Some((pool, sender, receiver)) = crate::database::create_funded_test_pair("tx_duplicate_hash", &ChainConfig::default(), 100.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+        let client = pool.get().await.unwrap();
+
+        // The hash covers the timestamp, so retry with a fresh amount if a second boundary split the pair
+        for amount in [5.0, 4.0, 3.0] {
+            let first = create_transaction(&pool, transfer(&sender.wallet_id, &receiver.wallet_id, amount), &ChainConfig::default(), &aes_key)
+                .await
+                .unwrap();
+            let again = create_transaction(&pool, transfer(&sender.wallet_id, &receiver.wallet_id, amount), &ChainConfig::default(), &aes_key)
+                .await
+                .unwrap();
+            if first.timestamp != again.timestamp {
+                continue;
+            }
+
+            assert_eq!(first.id, again.id);
+            let rows = client
+                .query_one("SELECT COUNT(*) FROM pending_transactions WHERE transaction_hash = $1", &[&first.transaction_hash])
+                .await
+                .unwrap()
+                .get::<_, i64>(0);
+            assert_eq!(rows, 1);
+            return;
+        }
+        panic!("never submitted twice within one second");
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_find_transaction_pending_then_confirmed() {
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        let Some((pool, sender, receiver)) = crate::database::create_funded_test_pair("tx_lookup", &chain, 100.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let pending = create_transaction(&pool, transfer(&sender.wallet_id, &receiver.wallet_id, 5.0), &chain, &aes_key)
+            .await
+            .unwrap();
+
+        // Pending hash
+        let client = pool.get().await.unwrap();
+        match find_transaction(&client, &pending.transaction_hash).await.unwrap() {
+            Some(TransactionLookup::Pending(tx)) => assert_eq!(tx.id, pending.id),
+            other => panic!("expected pending, got {:?}", other),
+        }
+        let json = serde_json::to_value(find_transaction(&client, &pending.transaction_hash).await.unwrap()).unwrap();
+        assert_eq!(json["status"], "pending");
+        assert_eq!(json["transaction_hash"], serde_json::json!(pending.transaction_hash));
+        drop(client);
+
+        // Confirmed hash
+        let block = crate::blockchain::mine_block(&pool, &sender.wallet_id, &chain, &crate::shutdown::Shutdown::new())
+            .await
+            .unwrap()
+            .block;
+        let client = pool.get().await.unwrap();
+        match find_transaction(&client, &pending.transaction_hash).await.unwrap() {
+            Some(TransactionLookup::Confirmed(tx)) => assert_eq!(tx.block_index, Some(block.index)),
+            other => panic!("expected confirmed, got {:?}", other),
+        }
+
+        // Unknown hash
+        assert!(find_transaction(&client, "no-such-hash").await.unwrap().is_none());
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_confirmed_transaction_reports_fee_paid() {
+        let chain = ChainConfig { mining_difficulty: 1, transaction_fee: 0.25, ..ChainConfig::default() };
+        let Some((pool, sender, receiver)) = crate::database::create_funded_test_pair("tx_confirmed_fee", &chain, 100.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+        let client = pool.get().await.unwrap();
+
+        let pending = create_transaction(&pool, transfer(&sender.wallet_id, &receiver.wallet_id, 5.0), &chain, &aes_key)
+            .await
+            .unwrap();
+        assert_eq!(pending.fee, to_units(0.25));
+
+        let block = crate::blockchain::mine_block(&pool, &sender.wallet_id, &chain, &crate::shutdown::Shutdown::new())
+            .await
+            .unwrap()
+            .block;
+        assert_eq!(block.transactions[0].fee, Some(pending.fee));
+
+        let confirmed = queries::get_transaction_by_hash(&client, &pending.transaction_hash).await.unwrap().unwrap();
+        assert_eq!(confirmed.fee, Some(pending.fee));
+        let history = queries::get_wallet_transactions(&client, &receiver.wallet_id, 10, 0).await.unwrap();
+        assert_eq!(history[0].fee, Some(pending.fee));
+        let json = serde_json::to_value(crate::models::TransactionView::from(confirmed)).unwrap();
+        assert_eq!(json["fee"], 0.25);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_private_note_readable_only_by_receiver() {
+        let chain = ChainConfig { mining_difficulty: 1, ..ChainConfig::default() };
+        let Some((pool, sender, receiver)) = crate::database::create_funded_test_pair("tx_private_note", &chain, 100.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let request = CreateTransactionRequest {
+            note: Some("medical bills".to_string()),
+            private_note: true,
+            ..transfer(&sender.wallet_id, &receiver.wallet_id, 5.0)
+        };
+        let pending = create_transaction(&pool, request, &chain, &aes_key).await.unwrap();
+        assert!(pending.note_encrypted);
+
+        let block = crate::blockchain::mine_block(&pool, &sender.wallet_id, &chain, &crate::shutdown::Shutdown::new())
+            .await
+            .unwrap()
+            .block;
+        assert!(crate::blockchain::validate_blockchain(&pool, &chain, false, false).await.unwrap().valid);
+
+        // The block listing only carries ciphertext
+        let client = pool.get().await.unwrap();
+        let listed = queries::get_block_by_index(&client, block.index).await.unwrap().unwrap();
+        let tx = listed
+            .transactions
+            .iter()
+            .find(|t| t.transaction_hash == pending.transaction_hash)
+            .unwrap();
+        assert!(tx.note_encrypted);
+        assert!(!tx.note.as_deref().unwrap().contains("medical"));
+
+        let note = read_note(&client, &pending.transaction_hash, &receiver, &aes_key).await.unwrap();
+        assert_eq!(note.as_deref(), Some("medical bills"));
+
+        assert!(matches!(
+            read_note(&client, &pending.transaction_hash, &sender, &aes_key).await,
+            Err(TransactionError::NotReceiver)
+        ));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_payment_request_fulfilled_once() {
+        let Some((pool, sender, receiver)) = crate::database::create_funded_test_pair("tx_payment_request", &ChainConfig::default(), 100.0).await else { return };
+        let aes_key = crate::database::TEST_AES_KEY;
+
+        let request = crate::services::payment_request_service::create_payment_request(
+            &pool,
+            &receiver.wallet_id,
+            to_units(5.0),
+            Some("invoice".to_string()),
+            None,
+        )
+        .await
+        .unwrap();
+        let pay = |amount: f64| CreateTransactionRequest {
+            payment_request_id: Some(request.id),
+            ..transfer(&sender.wallet_id, &receiver.wallet_id, amount)
+        };
+
+        // Wrong amount is rejected without claiming the request
+        let wrong = create_transaction(&pool, pay(4.0), &ChainConfig::default(), &aes_key).await;
+        assert!(matches!(wrong, Err(TransactionError::PaymentRequest(_))));
+
+        let paid = create_transaction(&pool, pay(5.0), &ChainConfig::default(), &aes_key).await.unwrap();
+        let client = pool.get().await.unwrap();
+        let stored = queries::get_payment_request(&client, request.id).await.unwrap().unwrap();
+        assert_eq!(stored.fulfilled_by, Some(paid.transaction_hash));
+        assert!(stored.fulfilled_at.is_some());
+        drop(client);
+
+        // A second payment of the same request is refused
+        let again = create_transaction(&pool, pay(5.0), &ChainConfig::default(), &aes_key).await;
+        assert!(matches!(again, Err(TransactionError::PaymentRequest(_))));
+    }
+}
@@ -1,13 +1,24 @@
-use crate::models::{KeyPair, WalletBalance};
+use crate::models::{BeneficiaryImportResult, BeneficiaryImportStatus, KeyPair, RichlistEntry, Transaction, WalletBalance};
 use crate::crypto::{generate_keypair, export_public_key_pem, export_private_key_pem, generate_wallet_id, encrypt_private_key};
-use crate::database::{DbPool, queries};
+use crate::database::{with_retry, DbError, DbPool, queries};
 use crate::blockchain::calculate_wallet_balance;
+use crate::services::payment_request_service::PaymentUri;
+use crate::utils::{format_units, from_units};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+/// Most wallets a single batch balance request may ask for
+pub const MAX_BALANCE_BATCH: usize = 100;
+
+/// Most rows a single beneficiary CSV import may contain
+pub const MAX_BENEFICIARY_IMPORT: usize = 500;
 
 #[derive(Debug)]
 pub enum WalletError {
     KeyGenerationError(String),
     EncryptionError(String),
     DatabaseError(String),
+    QrCodeError(String),
     WalletNotFound,
 }
 
@@ -17,6 +28,7 @@ impl std::fmt::Display for WalletError {
             WalletError::KeyGenerationError(msg) => write!(f, "Key generation error: {}", msg),
             WalletError::EncryptionError(msg) => write!(f, "Encryption error: {}", msg),
             WalletError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+            WalletError::QrCodeError(msg) => write!(f, "QR code error: {}", msg),
             WalletError::WalletNotFound => write!(f, "Wallet not found"),
         }
     }
@@ -52,52 +64,107 @@ pub fn generate_wallet_keypair(aes_key: &[u8]) -> Result<KeyPair, WalletError> {
     })
 }
 
-/// Get wallet balance with UTXO count
+/// Get wallet balance with UTXO count, computed from UTXOs on every call.
+/// Read-only: the cached `wallets.balance` is refreshed by whoever changes the UTXOs.
 pub async fn get_wallet_balance(pool: &DbPool, wallet_id: &str) -> Result<WalletBalance, WalletError> {
-    let client = pool.get().await
-        .map_err(|e| WalletError::DatabaseError(e.to_string()))?;
+    // Connection-level failures are retried; the reads are repeated from scratch
+    let (wallet, utxos, pending_amount) = with_retry(|| async move {
+        let client = pool.get().await?;
 
-    // Check if wallet exists
-    let wallet = queries::get_wallet(&client, wallet_id)
-        .await
-        .map_err(|e| WalletError::DatabaseError(e.to_string()))?
-        .ok_or(WalletError::WalletNotFound)?;
+        // Check if wallet exists
+        let Some(wallet) = queries::get_wallet(&client, wallet_id).await? else {
+            return Ok(None);
+        };
 
-    // Get all unspent UTXOs
-    let utxos = queries::get_unspent_utxos(&client, wallet_id)
-        .await
-        .map_err(|e| WalletError::DatabaseError(e.to_string()))?;
+        // Get all unspent UTXOs
+        let utxos = queries::get_unspent_utxos(&client, wallet_id).await?;
+
+        // Calculate amount locked in pending outgoing transactions
+        let pending_amount: i64 = client
+            .query_one(
+                "SELECT (COALESCE(SUM(amount), 0) * 100000000)::int8 
+                 FROM pending_transactions 
+                 WHERE sender_wallet_id = $1",
+                &[&wallet_id],
+            )
+            .await?
+            .get(0);
+
+        Ok::<_, DbError>(Some((wallet, utxos, pending_amount)))
+    })
+    .await
+    .map_err(|e| WalletError::DatabaseError(e.to_string()))?
+    .ok_or(WalletError::WalletNotFound)?;
 
     // Calculate total balance from all unspent UTXOs
-    let total_balance: f64 = utxos.iter().map(|u| u.amount).sum();
-    
-    // Calculate amount locked in pending outgoing transactions
-    let pending_amount_result = client.query_one(
-        "SELECT COALESCE(SUM(amount)::float8, 0) 
-         FROM pending_transactions 
-         WHERE sender_wallet_id = $1",
-        &[&wallet_id],
-    ).await;
-    
-    let pending_amount: f64 = match pending_amount_result {
-        Ok(row) => row.get(0),
-        Err(_) => 0.0,
-    };
+    let total_balance: i64 = utxos.iter().map(|u| u.amount).sum();
     
     // Available balance = total balance - pending sends
-    let balance = total_balance - pending_amount;
+    let balance = from_units(total_balance - pending_amount);
     let utxo_count = utxos.len() as i32;
 
-    // Update cached balance in wallet table
-    queries::update_wallet_balance(&client, wallet_id, balance)
-        .await
-        .map_err(|e| WalletError::DatabaseError(e.to_string()))?;
-
     Ok(WalletBalance {
         wallet_id: wallet_id.to_string(),
         balance,
         utxo_count,
+        created_at: wallet.created_at,
+        age_days: age_days(wallet.created_at, Utc::now()),
+    })
+}
+
+/// Whole days between a wallet's creation and `now`
+pub fn age_days(created_at: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+    (now - created_at).num_days().max(0)
+}
+
+/// Balances for several wallets using one grouped UTXO query and one pending-amounts query.
+/// Unknown wallet ids are left out of the result.
+pub async fn get_wallet_balances(
+    pool: &DbPool,
+    wallet_ids: &[String],
+) -> Result<HashMap<String, WalletBalance>, WalletError> {
+    let (existing, unspent, pending) = with_retry(|| async move {
+        let client = pool.get().await?;
+        let existing = queries::get_wallet_creation_times(&client, wallet_ids).await?;
+        let ids: Vec<String> = existing.iter().map(|(wallet_id, _)| wallet_id.clone()).collect();
+        let unspent = queries::get_unspent_totals(&client, &ids).await?;
+        let pending = queries::get_pending_send_totals(&client, &ids).await?;
+        Ok::<_, DbError>((existing, unspent, pending))
     })
+    .await
+    .map_err(|e| WalletError::DatabaseError(e.to_string()))?;
+
+    Ok(combine_balances(existing, unspent, pending, Utc::now()))
+}
+
+/// Available balance per wallet: unspent total minus pending sends, as in `get_wallet_balance`
+pub fn combine_balances(
+    wallets: Vec<(String, DateTime<Utc>)>,
+    unspent: Vec<(String, f64, i64)>,
+    pending: Vec<(String, f64)>,
+    now: DateTime<Utc>,
+) -> HashMap<String, WalletBalance> {
+    let unspent: HashMap<String, (f64, i64)> = unspent
+        .into_iter()
+        .map(|(wallet_id, total, count)| (wallet_id, (total, count)))
+        .collect();
+    let pending: HashMap<String, f64> = pending.into_iter().collect();
+
+    wallets
+        .into_iter()
+        .map(|(wallet_id, created_at)| {
+            let (total, count) = unspent.get(&wallet_id).copied().unwrap_or((0.0, 0));
+            let locked = pending.get(&wallet_id).copied().unwrap_or(0.0);
+            let balance = WalletBalance {
+                wallet_id: wallet_id.clone(),
+                balance: total - locked,
+                utxo_count: count as i32,
+                created_at,
+                age_days: age_days(created_at, now),
+            };
+            (wallet_id, balance)
+        })
+        .collect()
 }
 
 /// Check if wallet exists
@@ -111,3 +178,332 @@ pub async fn wallet_exists(pool: &DbPool, wallet_id: &str) -> Result<bool, Walle
 
     Ok(wallet.is_some())
 }
+
+/// Rank holders by balance (descending) with their share of `total_supply` in percent
+pub fn rank_holders(mut holders: Vec<(String, f64)>, total_supply: f64) -> Vec<RichlistEntry> {
+    holders.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    holders
+        .into_iter()
+        .enumerate()
+        .map(|(i, (wallet_id, balance))| RichlistEntry {
+            rank: i as i64 + 1,
+            wallet_id,
+            balance,
+            percentage: if total_supply > 0.0 { balance / total_supply * 100.0 } else { 0.0 },
+        })
+        .collect()
+}
+
+/// Render a wallet's transaction history as CSV from that wallet's point of view
+pub fn transactions_to_csv(wallet_id: &str, transactions: &[Transaction]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["hash", "direction", "counterparty", "amount", "fee", "type", "block_index", "timestamp"])?;
+
+    for tx in transactions {
+        let (direction, counterparty) = if tx.sender_wallet_id == wallet_id {
+            ("sent", &tx.receiver_wallet_id)
+        } else {
+            ("received", &tx.sender_wallet_id)
+        };
+
+        let timestamp = chrono::DateTime::from_timestamp(tx.timestamp, 0)
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_else(|| tx.timestamp.to_string());
+
+        writer.write_record([
+            tx.transaction_hash.as_str(),
+            direction,
+            counterparty.as_str(),
+            &format_units(tx.amount),
+            &tx.fee.map(format_units).unwrap_or_default(),
+            tx.transaction_type.as_str(),
+            &tx.block_index.map(|i| i.to_string()).unwrap_or_default(),
+            &timestamp,
+        ])?;
+    }
+
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+/// Add beneficiaries from `wallet_id,nickname` CSV rows (header row optional). Each row is
+/// reported on its own, so one bad row doesn't stop the rest of the import.
+pub async fn import_beneficiaries(
+    pool: &DbPool,
+    user_id: uuid::Uuid,
+    csv_body: &str,
+) -> Result<Vec<BeneficiaryImportResult>, WalletError> {
+    let client = pool.get().await
+        .map_err(|e| WalletError::DatabaseError(e.to_string()))?;
+
+    let mut known: std::collections::HashSet<String> = queries::get_user_beneficiaries(&client, user_id)
+        .await
+        .map_err(|e| WalletError::DatabaseError(e.to_string()))?
+        .into_iter()
+        .map(|b| b.beneficiary_wallet_id)
+        .collect();
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(csv_body.as_bytes());
+
+    let mut results = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or(i as u64 + 1);
+                results.push(BeneficiaryImportResult {
+                    line,
+                    wallet_id: String::new(),
+                    status: BeneficiaryImportStatus::InvalidRow,
+                    beneficiary_id: None,
+                });
+                continue;
+            }
+        };
+
+        let line = record.position().map(|p| p.line()).unwrap_or(i as u64 + 1);
+        let wallet_id = record.get(0).unwrap_or_default().to_string();
+        if i == 0 && wallet_id.eq_ignore_ascii_case("wallet_id") {
+            continue;
+        }
+        let nickname = record.get(1).filter(|n| !n.is_empty()).map(str::to_string);
+        let mut result = BeneficiaryImportResult {
+            line,
+            wallet_id: wallet_id.clone(),
+            status: BeneficiaryImportStatus::InvalidRow,
+            beneficiary_id: None,
+        };
+
+        if wallet_id.is_empty() || record.len() > 2 {
+            results.push(result);
+            continue;
+        }
+        if known.contains(&wallet_id) {
+            result.status = BeneficiaryImportStatus::SkippedDuplicate;
+            results.push(result);
+            continue;
+        }
+
+        let exists = queries::get_wallet(&client, &wallet_id)
+            .await
+            .map_err(|e| WalletError::DatabaseError(e.to_string()))?
+            .is_some();
+        if !exists {
+            result.status = BeneficiaryImportStatus::InvalidWallet;
+            results.push(result);
+            continue;
+        }
+
+        let beneficiary = queries::add_beneficiary(&client, user_id, &wallet_id, nickname)
+            .await
+            .map_err(|e| WalletError::DatabaseError(e.to_string()))?;
+        known.insert(wallet_id);
+        result.status = BeneficiaryImportStatus::Added;
+        result.beneficiary_id = Some(beneficiary.id);
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// Text encoded in a wallet's QR code: the bare wallet id, or a `blockwallet:` URI
+/// carrying the requested amount and note
+pub fn payment_uri(wallet_id: &str, amount: Option<i64>, note: Option<&str>) -> String {
+    let uri = PaymentUri::new(wallet_id, amount, note);
+    if uri.amount.is_none() && uri.note.is_none() {
+        wallet_id.to_string()
+    } else {
+        uri.to_uri()
+    }
+}
+
+pub fn render_qr_png(payload: &str) -> Result<Vec<u8>, WalletError> {
+    let code = qrcode::QrCode::new(payload.as_bytes())
+        .map_err(|e| WalletError::QrCodeError(e.to_string()))?;
+    let image = code.render::<image::Luma<u8>>().min_dimensions(256, 256).build();
+
+    let mut png = std::io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, image::ImageFormat::Png)
+        .map_err(|e| WalletError::QrCodeError(e.to_string()))?;
+    Ok(png.into_inner())
+}
+
+pub fn render_qr_svg(payload: &str) -> Result<String, WalletError> {
+    let code = qrcode::QrCode::new(payload.as_bytes())
+        .map_err(|e| WalletError::QrCodeError(e.to_string()))?;
+    Ok(code
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(256, 256)
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::to_units;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_rank_holders() {
+        let holders = vec![
+            ("carol".to_string(), 25.0),
+            ("alice".to_string(), 50.0),
+            ("bob".to_string(), 25.0),
+        ];
+
+        let ranked = rank_holders(holders, 200.0);
+
+        let order: Vec<(i64, &str, f64)> = ranked
+            .iter()
+            .map(|e| (e.rank, e.wallet_id.as_str(), e.percentage))
+            .collect();
+        assert_eq!(order, vec![(1, "alice", 25.0), (2, "bob", 12.5), (3, "carol", 12.5)]);
+    }
+
+    #[test]
+    fn test_transactions_to_csv() {
+        let tx = Transaction {
+            id: Uuid::new_v4(),
+            transaction_hash: "abc123".to_string(),
+            sender_wallet_id: "alice".to_string(),
+            receiver_wallet_id: "bob".to_string(),
+            amount: to_units(12.5),
+            note: None,
+            note_encrypted: false,
+            fee: Some(to_units(0.1)),
+            signature: "sig".to_string(),
+            block_index: Some(3),
+            transaction_type: "transfer".to_string(),
+            timestamp: 0,
+            created_at: Utc::now(),
+        };
+
+        let csv = String::from_utf8(transactions_to_csv("alice", &[tx]).unwrap()).unwrap();
+        let mut lines = csv.lines();
+
+        assert_eq!(lines.next(), Some("hash,direction,counterparty,amount,fee,type,block_index,timestamp"));
+        assert_eq!(lines.next(), Some("abc123,sent,bob,12.50000000,0.10000000,transfer,3,1970-01-01T00:00:00+00:00"));
+    }
+
+    #[test]
+    fn test_payment_uri() {
+        assert_eq!(payment_uri("abc", None, None), "abc");
+        assert_eq!(
+            payment_uri("abc", Some(to_units(1.5)), Some("rent & food")),
+            "blockwallet:abc?amount=1.50000000&note=rent%20%26%20food"
+        );
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_batch_balances_match_individual_lookups() {
+        let pool = match crate::database::create_test_pool("wallet_balances").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        client
+            .batch_execute(
+                "INSERT INTO wallets (wallet_id) VALUES ('alice'), ('bob'), ('carol');
+                 INSERT INTO utxos (wallet_id, amount, transaction_hash, output_index) VALUES
+                     ('alice', 10.5, 'fund1', 0), ('alice', 2.25, 'fund2', 0), ('bob', 7.0, 'fund3', 0);
+                 INSERT INTO pending_transactions (transaction_hash, sender_wallet_id, receiver_wallet_id, amount, signature, timestamp)
+                     VALUES ('pending1', 'alice', 'bob', 3.0, 'sig', 0);",
+            )
+            .await
+            .unwrap();
+        drop(client);
+
+        let ids: Vec<String> = ["alice", "bob", "carol", "unknown"].iter().map(|s| s.to_string()).collect();
+        let batch = get_wallet_balances(&pool, &ids).await.unwrap();
+
+        assert_eq!(batch.len(), 3);
+        assert!(!batch.contains_key("unknown"));
+        assert_eq!(batch["alice"].balance, 9.75);
+        assert_eq!(batch["alice"].utxo_count, 2);
+        assert_eq!(batch["carol"].balance, 0.0);
+
+        for wallet_id in ["alice", "bob", "carol"] {
+            let single = get_wallet_balance(&pool, wallet_id).await.unwrap();
+            assert_eq!(batch[wallet_id].balance, single.balance, "{}", wallet_id);
+            assert_eq!(batch[wallet_id].utxo_count, single.utxo_count, "{}", wallet_id);
+        }
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_balance_reports_wallet_age() {
+        let Some(pool) = crate::database::create_test_pool("wallet_age").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let created_at = Utc::now() - chrono::Duration::days(10) - chrono::Duration::hours(3);
+        let client = pool.get().await.unwrap();
+        client
+            .execute("INSERT INTO wallets (wallet_id, created_at) VALUES ('aged', $1)", &[&created_at])
+            .await
+            .unwrap();
+        drop(client);
+
+        let single = get_wallet_balance(&pool, "aged").await.unwrap();
+        assert_eq!(single.age_days, 10);
+        assert_eq!(single.created_at.timestamp_micros(), created_at.timestamp_micros());
+
+        let batch = get_wallet_balances(&pool, &["aged".to_string()]).await.unwrap();
+        assert_eq!(batch["aged"].age_days, 10);
+        assert_eq!(batch["aged"].created_at, single.created_at);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_import_beneficiaries_reports_each_row() {
+        let pool = match crate::database::create_test_pool("beneficiary_import").await {
+            Some(pool) => pool,
+            None => return,
+        };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let user = crate::database::register_test_user(&pool, "importer@example.com", "1").await;
+
+        let client = pool.get().await.unwrap();
+        client
+            .batch_execute("INSERT INTO wallets (wallet_id) VALUES ('alice'), ('bob'), ('carol')")
+            .await
+            .unwrap();
+        queries::add_beneficiary(&client, user.id, "carol", None).await.unwrap();
+        drop(client);
+
+        let csv_body = "wallet_id,nickname\nalice,Mom\nghost,Nobody\nbob\nalice,Again\ncarol,Known\n,empty\n";
+        let results = import_beneficiaries(&pool, user.id, csv_body).await.unwrap();
+
+        let summary: Vec<(u64, &str, BeneficiaryImportStatus)> = results
+            .iter()
+            .map(|r| (r.line, r.wallet_id.as_str(), r.status))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (2, "alice", BeneficiaryImportStatus::Added),
+                (3, "ghost", BeneficiaryImportStatus::InvalidWallet),
+                (4, "bob", BeneficiaryImportStatus::Added),
+                (5, "alice", BeneficiaryImportStatus::SkippedDuplicate),
+                (6, "carol", BeneficiaryImportStatus::SkippedDuplicate),
+                (7, "", BeneficiaryImportStatus::InvalidRow),
+            ]
+        );
+        assert!(results[0].beneficiary_id.is_some());
+
+        let client = pool.get().await.unwrap();
+        let saved = queries::get_user_beneficiaries(&client, user.id).await.unwrap();
+        assert_eq!(saved.len(), 3);
+        let mom = saved.iter().find(|b| b.beneficiary_wallet_id == "alice").unwrap();
+        assert_eq!(mom.nickname.as_deref(), Some("Mom"));
+    }
+}
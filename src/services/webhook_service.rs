@@ -0,0 +1,320 @@
+use crate::database::{AdvisoryLock, DbPool, queries};
+use crate::models::Block;
+use crate::shutdown::{run_until_shutdown, Shutdown};
+use deadpool_postgres::Client;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Delivery attempts before a queued webhook payload is marked failed
+pub const MAX_DELIVERY_ATTEMPTS: i32 = 5;
+
+/// Wait before the first retry; doubled after each further failure
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Payloads delivered per worker run
+const DELIVERY_BATCH_SIZE: i64 = 50;
+
+/// Arbitrary key for the advisory lock that keeps two nodes from delivering the same payload
+const DELIVERY_LOCK_KEY: i64 = 0x0077_6562_686f_6f6b;
+
+/// Hex-encoded HMAC-SHA256 of the request body, sent as `X-Signature`
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// `WEBHOOK_ALLOW_PRIVATE_TARGETS=true` lets webhooks reach private and local addresses,
+/// for development against a receiver on the same machine
+pub fn allow_private_targets() -> bool {
+    env::var("WEBHOOK_ALLOW_PRIVATE_TARGETS").is_ok_and(|v| v == "true" || v == "1")
+}
+
+/// Addresses a webhook must never reach: loopback, private and link-local ranges (including
+/// the cloud metadata service at 169.254.169.254), and other non-public space
+pub fn is_forbidden_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                || v4.is_documentation()
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64)
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_forbidden_address(IpAddr::V4(v4)),
+            None => {
+                let first = v6.segments()[0];
+                v6.is_loopback()
+                    || v6.is_unspecified()
+                    || v6.is_multicast()
+                    // Unique local fc00::/7 and link-local fe80::/10
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+/// Resolve a webhook URL's host, refusing anything but http(s) and, unless `allow_private`,
+/// hosts with any address [`is_forbidden_address`] rejects. Returns the host and its addresses
+pub async fn resolve_target(url: &str, allow_private: bool) -> Result<(String, Vec<SocketAddr>), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid webhook URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err("Webhook URL must be http(s)".to_string());
+    }
+    let host = parsed.host_str().ok_or("Webhook URL has no host")?.to_string();
+    let port = parsed.port_or_known_default().unwrap_or(80);
+
+    // IPv6 literals keep their brackets in the URL but not in a lookup
+    let lookup = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((lookup, port))
+        .await
+        .map_err(|e| format!("Can't resolve webhook host {}: {}", host, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("Webhook host {} has no addresses", host));
+    }
+    if !allow_private && addrs.iter().any(|addr| is_forbidden_address(addr.ip())) {
+        return Err(format!("Webhook host {} resolves to a private or local address", host));
+    }
+    Ok((host, addrs))
+}
+
+/// POST a signed payload once. The host is checked by [`resolve_target`] and the request is
+/// pinned to the addresses it approved, so a DNS change can't redirect it; nor can an HTTP redirect
+pub async fn deliver(url: &str, secret: &str, body: &str, allow_private: bool) -> Result<(), String> {
+    let (host, addrs) = resolve_target(url, allow_private).await?;
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve_to_addrs(&host, &addrs)
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+    let resp = http
+        .post(url)
+        .header("Content-Type", "application/json")
+        .header("X-Signature", sign_payload(secret, body.as_bytes()))
+        .body(body.to_string())
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("Returned {}", resp.status()))
+    }
+}
+
+/// Queue a `transaction_confirmed` payload for each webhook of a wallet `block` touches;
+/// the delivery worker sends them
+pub async fn queue_block_deliveries(client: &Client, block: &Block) -> Result<(), tokio_postgres::Error> {
+    if block.transactions.is_empty() {
+        return Ok(());
+    }
+
+    let mut wallet_ids: Vec<String> = block
+        .transactions
+        .iter()
+        .flat_map(|tx| [tx.sender_wallet_id.clone(), tx.receiver_wallet_id.clone()])
+        .collect();
+    wallet_ids.sort();
+    wallet_ids.dedup();
+
+    let webhooks = queries::get_webhooks_for_wallets(client, &wallet_ids).await?;
+    let mut deliveries = Vec::new();
+
+    for webhook in webhooks {
+        for tx in block.transactions.iter().filter(|tx| {
+            tx.sender_wallet_id == webhook.wallet_id || tx.receiver_wallet_id == webhook.wallet_id
+        }) {
+            let body = serde_json::json!({
+                "event": "transaction_confirmed",
+                "wallet_id": webhook.wallet_id,
+                "transaction": tx,
+                "block": {
+                    "index": block.index,
+                    "hash": block.hash,
+                    "timestamp": block.timestamp,
+                },
+            })
+            .to_string();
+            deliveries.push((webhook.id, body));
+        }
+    }
+
+    queries::enqueue_webhook_deliveries(client, &deliveries).await
+}
+
+/// Deliver queued webhook payloads that are due, returning how many succeeded. Skips the run
+/// if another node is already delivering.
+pub async fn deliver_queued(pool: &DbPool, allow_private: bool) -> Result<usize, anyhow::Error> {
+    let Some(lock) = AdvisoryLock::try_acquire(pool, DELIVERY_LOCK_KEY).await? else {
+        return Ok(0);
+    };
+    let client = lock.client();
+
+    let result = async {
+        let mut delivered = 0;
+        for delivery in queries::get_due_webhook_deliveries(client, DELIVERY_BATCH_SIZE).await? {
+            match deliver(&delivery.url, &delivery.secret, &delivery.body, allow_private).await {
+                Ok(()) => {
+                    queries::mark_webhook_delivered(client, delivery.id).await?;
+                    delivered += 1;
+                }
+                Err(e) => {
+                    log::warn!("Webhook {} to {} failed (attempt {}): {}", delivery.id, delivery.url, delivery.attempts + 1, e);
+                    queries::record_webhook_failure(
+                        client,
+                        delivery.id,
+                        &e,
+                        MAX_DELIVERY_ATTEMPTS,
+                        INITIAL_RETRY_DELAY.as_secs_f64(),
+                    )
+                    .await?;
+                }
+            }
+        }
+        Ok::<_, anyhow::Error>(delivered)
+    }
+    .await;
+
+    lock.unlock().await?;
+
+    result
+}
+
+/// Deliver queued webhooks every `WEBHOOK_DELIVERY_INTERVAL` seconds (default 5) until `shutdown`
+pub async fn start_delivery_worker(pool: DbPool, shutdown: Shutdown) {
+    let interval_seconds = env::var("WEBHOOK_DELIVERY_INTERVAL")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(5);
+    let allow_private = allow_private_targets();
+
+    log::info!("🔔 Webhook delivery worker started (every {} seconds)", interval_seconds);
+
+    run_until_shutdown(Duration::from_secs(interval_seconds), shutdown, || async {
+        match deliver_queued(&pool, allow_private).await {
+            Ok(0) => {}
+            Ok(n) => log::info!("🔔 Delivered {} webhook(s)", n),
+            Err(e) => log::error!("Error delivering webhooks: {}", e),
+        }
+    })
+    .await;
+
+    log::info!("🔔 Webhook delivery worker stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_signed_payload_arrives() {
+        let server = MockServer::start().await;
+        let body = r#"{"event":"transaction_confirmed"}"#.to_string();
+        let expected_signature = sign_payload("s3cret", body.as_bytes());
+
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .and(header("X-Signature", expected_signature.as_str()))
+            .and(body_string(body.clone()))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/hook", server.uri());
+        // The mock server listens on loopback
+        let result = deliver(&url, "s3cret", &body, true).await;
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_forbidden_addresses() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(is_forbidden_address(ip.parse().unwrap()), "{} should be forbidden", ip);
+        }
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(!is_forbidden_address(ip.parse().unwrap()), "{} should be allowed", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_private_targets_rejected() {
+        for url in ["http://169.254.169.254/latest/meta-data/", "http://127.0.0.1:8080/hook", "http://localhost/hook", "http://[::1]/hook"] {
+            assert!(resolve_target(url, false).await.is_err(), "{} should be rejected", url);
+        }
+        assert!(resolve_target("ftp://example.com/hook", true).await.is_err());
+        assert!(resolve_target("http://127.0.0.1:8080/hook", true).await.is_ok());
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_queued_delivery_retried_until_it_succeeds() {
+        let Some(pool) = crate::database::create_test_pool("webhook_deliveries").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = pool.get().await.unwrap();
+        queries::ensure_wallet(&client, "hooked").await.unwrap();
+        let webhook = queries::create_webhook(&client, "hooked", &server.uri(), "s3cret").await.unwrap();
+        queries::enqueue_webhook_deliveries(&client, &[(webhook.id, "{}".to_string())]).await.unwrap();
+        drop(client);
+
+        // Without the override the loopback receiver is never contacted
+        assert_eq!(deliver_queued(&pool, false).await.unwrap(), 0);
+        let status = || async {
+            let client = pool.get().await.unwrap();
+            let row = client
+                .query_one("SELECT status, attempts, last_error FROM webhook_deliveries WHERE webhook_id = $1", &[&webhook.id])
+                .await
+                .unwrap();
+            (row.get::<_, String>(0), row.get::<_, i32>(1), row.get::<_, Option<String>>(2))
+        };
+        let (_, attempts, error) = status().await;
+        assert_eq!(attempts, 1);
+        assert!(error.unwrap().contains("private or local address"));
+
+        // The retry waits out its backoff; make it due now
+        let retry_now = || async {
+            let client = pool.get().await.unwrap();
+            client.execute("UPDATE webhook_deliveries SET next_attempt_at = NOW()", &[]).await.unwrap();
+        };
+        retry_now().await;
+        assert_eq!(deliver_queued(&pool, true).await.unwrap(), 0, "receiver returns 500");
+        retry_now().await;
+        assert_eq!(deliver_queued(&pool, true).await.unwrap(), 1);
+
+        let (status, attempts, error) = status().await;
+        assert_eq!((status.as_str(), attempts, error), ("delivered", 3, None));
+    }
+}
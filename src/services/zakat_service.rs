@@ -1,44 +1,331 @@
+use crate::config::ChainConfig;
 use crate::database::{DbPool, queries};
 use crate::models::PendingTransaction;
-use crate::crypto::{create_transaction_payload, sha256_hash};
-use chrono::Utc;
+use crate::services::{nisab_service, notification_service, transaction_service};
+use crate::services::transaction_service::{TransactionError, TransferSide};
+use crate::crypto::{constant_time_eq, create_transaction_payload, sha256_hash};
+use crate::utils::{from_units, to_units};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use uuid::Uuid;
 use std::env;
-use tokio::time::{interval, Duration as TokioDuration};
+use std::sync::OnceLock;
+use crate::shutdown::{run_until_shutdown, Shutdown};
+use tokio::time::Duration as TokioDuration;
+
+#[derive(Debug)]
+pub enum DistributionError {
+    EmptyPool,
+    NoRecipients,
+    InvalidWeight(String),
+    InvalidRecipient(String),
+}
+
+impl std::fmt::Display for DistributionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DistributionError::EmptyPool => write!(f, "Zakat pool has no available balance to distribute"),
+            DistributionError::NoRecipients => write!(f, "No zakat recipients configured"),
+            DistributionError::InvalidWeight(wallet_id) => write!(f, "Invalid weight for recipient {}", wallet_id),
+            DistributionError::InvalidRecipient(wallet_id) => write!(f, "Invalid recipient wallet: {}", wallet_id),
+        }
+    }
+}
+
+impl std::error::Error for DistributionError {}
+
+/// Calendar used to decide when zakat is due again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZakatCalendar {
+    /// Fixed `ZAKAT_PERIOD` in seconds
+    Solar,
+    /// One full Hijri (lunar) year
+    Hijri,
+}
+
+impl ZakatCalendar {
+    pub fn from_env() -> Self {
+        match env::var("ZAKAT_CALENDAR").unwrap_or_default().to_lowercase().as_str() {
+            "hijri" => ZakatCalendar::Hijri,
+            _ => ZakatCalendar::Solar,
+        }
+    }
+}
 
-/// Calculate zakat amount (2.5% of balance)
-fn calculate_zakat(balance: f64) -> f64 {
-    let zakat_percentage = env::var("ZAKAT_PERCENTAGE")
+/// Whether zakat is due again given when it was last paid
+pub fn zakat_period_elapsed(
+    calendar: ZakatCalendar,
+    last_zakat_date: DateTime<Utc>,
+    now: DateTime<Utc>,
+    period_seconds: i64,
+) -> bool {
+    match calendar {
+        ZakatCalendar::Solar => (now - last_zakat_date).num_seconds() >= period_seconds,
+        ZakatCalendar::Hijri => crate::hijri::full_year_elapsed(last_zakat_date.date_naive(), now.date_naive()),
+    }
+}
+
+/// Zakat percentage from `ZAKAT_PERCENTAGE` (default 2.5)
+fn zakat_rate() -> f64 {
+    env::var("ZAKAT_PERCENTAGE")
         .unwrap_or_else(|_| "2.5".to_string())
         .parse::<f64>()
-        .unwrap_or(2.5);
-    
-    balance * (zakat_percentage / 100.0)
+        .unwrap_or(2.5)
+}
+
+/// Calculate zakat amount (`rate` percent of balance)
+fn calculate_zakat(balance: f64, rate: f64) -> f64 {
+    balance * (rate / 100.0)
+}
+
+/// Signature tag of a wallet's zakat deduction into the pool
+pub const DEDUCTION_SIGNATURE_TAG: &str = "SYSTEM_ZAKAT";
+/// Signature tag of a payout from the zakat pool
+pub const DISTRIBUTION_SIGNATURE_TAG: &str = "SYSTEM_ZAKAT_DISTRIBUTION";
+
+/// `SYSTEM_SIGNING_SECRET`, loaded once. main refuses to start when it is invalid.
+fn system_signing_secret() -> &'static str {
+    static SECRET: OnceLock<String> = OnceLock::new();
+    SECRET.get_or_init(|| {
+        crate::config::parse_system_signing_secret(
+            env::var("SYSTEM_SIGNING_SECRET").ok().as_deref(),
+            crate::config::AppEnv::from_env(),
+        )
+        .expect("Invalid SYSTEM_SIGNING_SECRET")
+    })
+}
+
+/// The signature system transactions carry in place of a user's RSA signature: a hex HMAC-SHA256
+/// of the tag and payload under `SYSTEM_SIGNING_SECRET`, so only the server can produce it
+pub fn system_signature(signature_tag: &str, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(system_signing_secret().as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(format!("{}_{}", signature_tag, payload).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Tag of the system signature `signature` is over `payload`, if it is one
+pub fn system_signature_tag(payload: &str, signature: &str) -> Option<&'static str> {
+    [DEDUCTION_SIGNATURE_TAG, DISTRIBUTION_SIGNATURE_TAG]
+        .into_iter()
+        .find(|tag| constant_time_eq(system_signature(tag, payload).as_bytes(), signature.as_bytes()))
+}
+
+/// Build a system-signed, fee-free transaction (zakat deductions and distributions)
+fn build_system_transaction(
+    signature_tag: &str,
+    sender_wallet_id: &str,
+    receiver_wallet_id: &str,
+    amount: i64,
+    note: &str,
+) -> PendingTransaction {
+    let timestamp = Utc::now().timestamp();
+    let note = Some(note.to_string());
+    let payload = create_transaction_payload(
+        sender_wallet_id,
+        receiver_wallet_id,
+        from_units(amount),
+        timestamp,
+        &note,
+    );
+
+    // For system transactions, we use a system signature
+    let signature = system_signature(signature_tag, &payload);
+    let transaction_hash = sha256_hash(format!("{}{}", payload, signature).as_bytes());
+
+    PendingTransaction {
+        id: Uuid::new_v4(),
+        transaction_hash,
+        sender_wallet_id: sender_wallet_id.to_string(),
+        receiver_wallet_id: receiver_wallet_id.to_string(),
+        amount,
+        fee: 0, // System transactions have no fee
+        note,
+        note_encrypted: false,
+        signature,
+        timestamp,
+        created_at: Utc::now(),
+    }
+}
+
+/// Split `total` units between recipients proportionally to their weights.
+/// Shares are rounded down to whole units so the sum never exceeds `total`.
+pub fn split_by_weight(total: i64, recipients: &[(String, f64)]) -> Result<Vec<(String, i64)>, DistributionError> {
+    if recipients.is_empty() {
+        return Err(DistributionError::NoRecipients);
+    }
+
+    if total <= 0 {
+        return Err(DistributionError::EmptyPool);
+    }
+
+    for (wallet_id, weight) in recipients {
+        if !weight.is_finite() || *weight <= 0.0 {
+            return Err(DistributionError::InvalidWeight(wallet_id.clone()));
+        }
+    }
+
+    let total_weight: f64 = recipients.iter().map(|(_, w)| w).sum();
+
+    Ok(recipients
+        .iter()
+        .map(|(wallet_id, weight)| {
+            let share = (total as f64 * weight / total_weight).floor() as i64;
+            (wallet_id.clone(), share)
+        })
+        .collect())
+}
+
+/// Parse `ZAKAT_RECIPIENTS` (`wallet_id:weight,wallet_id:weight`; weight defaults to 1)
+pub fn configured_recipients() -> Vec<(String, f64)> {
+    env::var("ZAKAT_RECIPIENTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once(':') {
+            Some((wallet_id, weight)) => (wallet_id.trim().to_string(), weight.trim().parse().unwrap_or(0.0)),
+            None => (entry.to_string(), 1.0),
+        })
+        .collect()
+}
+
+/// Distribute the zakat pool's available balance among recipients by weight
+pub async fn distribute_zakat_pool(
+    pool: &DbPool,
+    chain: &ChainConfig,
+    recipients: &[(String, f64)],
+) -> Result<Vec<PendingTransaction>, anyhow::Error> {
+    let client = pool.get().await?;
+
+    let zakat_pool_wallet_id = &chain.zakat_pool_wallet_id;
+
+    // Frozen recipients are skipped and their share goes to the others
+    let mut eligible = Vec::with_capacity(recipients.len());
+    for (wallet_id, weight) in recipients {
+        if wallet_id == zakat_pool_wallet_id {
+            return Err(DistributionError::InvalidRecipient(wallet_id.clone()).into());
+        }
+        match transaction_service::require_wallet(&client, wallet_id, TransferSide::Receiver).await {
+            Ok(_) => eligible.push((wallet_id.clone(), *weight)),
+            Err(TransactionError::WalletFrozen(_)) => {
+                log::warn!("Skipping frozen zakat recipient {}", wallet_id);
+            }
+            Err(TransactionError::InvalidWallet(_)) => {
+                return Err(DistributionError::InvalidRecipient(wallet_id.clone()).into());
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    match transaction_service::require_wallet(&client, zakat_pool_wallet_id, TransferSide::Sender).await {
+        Ok(_) => {}
+        Err(TransactionError::InvalidWallet(_)) => return Err(DistributionError::EmptyPool.into()),
+        Err(e) => return Err(e.into()),
+    }
+
+    // Available balance already excludes amounts locked in pending transactions
+    let available = crate::blockchain::calculate_wallet_balance(&client, zakat_pool_wallet_id).await?;
+    let shares = split_by_weight(to_units(available), &eligible)?;
+
+    let mut created = Vec::new();
+
+    for (wallet_id, amount) in shares {
+        if amount <= 0 {
+            continue;
+        }
+
+        let pending_tx = build_system_transaction(
+            DISTRIBUTION_SIGNATURE_TAG,
+            zakat_pool_wallet_id,
+            &wallet_id,
+            amount,
+            "Zakat Distribution",
+        );
+
+        if !queries::create_pending_transaction(&client, &pending_tx).await? {
+            return Err(anyhow::anyhow!("Pending transaction {} already exists", pending_tx.transaction_hash));
+        }
+
+        client
+            .execute(
+                "INSERT INTO zakat_records (wallet_id, amount, transaction_hash, deduction_date, record_type) VALUES ($1, $2::int8 * 0.00000001, $3, $4, 'distribution')",
+                &[&wallet_id, &amount, &pending_tx.transaction_hash, &Utc::now()],
+            )
+            .await?;
+
+        queries::create_transaction_log(
+            &client,
+            &wallet_id,
+            "zakat_received",
+            Some(pending_tx.transaction_hash.clone()),
+            None,
+            "pending",
+            None,
+            None,
+            pending_tx.note.clone(),
+        )
+        .await?;
+
+        created.push(pending_tx);
+    }
+
+    queries::refresh_wallet_balance(&client, zakat_pool_wallet_id).await?;
+
+    let distributed = from_units(created.iter().map(|tx| tx.amount).sum());
+
+    queries::create_system_log(
+        &client,
+        "zakat_distribution",
+        None,
+        &format!("Zakat pool distributed {} to {} recipient(s)", distributed, created.len()),
+        None,
+        Some(serde_json::json!({
+            "pool_wallet_id": zakat_pool_wallet_id,
+            "distributed": distributed,
+            "recipients": created
+                .iter()
+                .map(|tx| serde_json::json!({
+                    "wallet_id": tx.receiver_wallet_id,
+                    "amount": from_units(tx.amount),
+                    "transaction_hash": tx.transaction_hash,
+                }))
+                .collect::<Vec<_>>(),
+        })),
+    )
+    .await?;
+
+    log::info!("✅ Zakat pool distributed {} to {} recipient(s)", distributed, created.len());
+
+    Ok(created)
 }
 
 /// Process zakat deduction for a single wallet
 async fn process_wallet_zakat(
-    client: &deadpool_postgres::Client,
+    client: &mut deadpool_postgres::Client,
     wallet_id: &str,
     zakat_pool_wallet_id: &str,
+    zakat_threshold: f64,
 ) -> Result<(), anyhow::Error> {
-    // Get wallet
-    let wallet = match queries::get_wallet(client, wallet_id).await? {
-        Some(w) => w,
-        None => return Ok(()), // Skip if wallet doesn't exist
+    // Get wallet, skipping it if it was removed or frozen since the run listed it
+    let wallet = match transaction_service::require_wallet(client, wallet_id, TransferSide::Sender).await {
+        Ok(w) => w,
+        Err(TransactionError::InvalidWallet(_)) | Err(TransactionError::WalletFrozen(_)) => return Ok(()),
+        Err(e) => return Err(e.into()),
     };
 
+    // Exempted since the run listed it
+    if wallet.zakat_exempt {
+        return Ok(());
+    }
+
     // Skip if balance is 0 or negative
     if wallet.balance <= 0.0 {
         return Ok(());
     }
 
     // Check if balance meets the zakat threshold (nisab)
-    let zakat_threshold = env::var("ZAKAT_THRESHOLD")
-        .unwrap_or_else(|_| "100.0".to_string())
-        .parse::<f64>()
-        .unwrap_or(100.0);
-    
     if wallet.balance < zakat_threshold {
         log::info!(
             "Wallet {} balance ({}) is below zakat threshold ({}), skipping zakat deduction",
@@ -55,88 +342,86 @@ async fn process_wallet_zakat(
         .parse::<i64>()
         .unwrap_or(2592000);
 
+    let calendar = ZakatCalendar::from_env();
+
     if let Some(last_zakat_date) = wallet.last_zakat_date {
-        let now = Utc::now();
-        let time_since_last_zakat = (now - last_zakat_date).num_seconds();
-        
         // If zakat was paid within the zakat period, skip
-        if time_since_last_zakat < zakat_period_seconds {
+        if !zakat_period_elapsed(calendar, last_zakat_date, Utc::now(), zakat_period_seconds) {
             log::info!(
-                "Zakat already paid for wallet {} (last paid {} seconds ago, period is {} seconds)", 
+                "Zakat already paid for wallet {} (last paid {}, {:?} calendar)", 
                 wallet_id, 
-                time_since_last_zakat, 
-                zakat_period_seconds
+                last_zakat_date, 
+                calendar
             );
             return Ok(());
         }
     }
 
-    // Calculate zakat
-    let zakat_amount = calculate_zakat(wallet.balance);
+    // Calculate zakat at the wallet's own rate if an admin set one, keeping the basis for the record
+    let rate = wallet.zakat_rate.unwrap_or_else(zakat_rate);
+    let note = format!("Monthly Zakat Deduction ({}%)", rate);
+    let zakat_amount = to_units(calculate_zakat(wallet.balance, rate));
     
-    if zakat_amount < 0.01 {
+    if zakat_amount < to_units(0.01) {
         return Ok(()); // Skip if zakat is too small
     }
 
-    log::info!("Processing zakat for wallet {}: {} (balance: {})", wallet_id, zakat_amount, wallet.balance);
+    // The pool never pays zakat to itself
+    transaction_service::validate_transfer(wallet_id, zakat_pool_wallet_id, zakat_amount, false)?;
+
+    // The claim, pending transaction, record, balance and logs are written all-or-nothing:
+    // any error drops `transaction` and rolls back, leaving the period unclaimed
+    let transaction = client.transaction().await?;
+
+    // Claim the period before creating anything. Overlapping runs (scheduler and manual trigger)
+    // both read the same last_zakat_date; only the one that swaps it out goes on to deduct.
+    let claimed_at = Utc::now();
+    let claimed = transaction
+        .execute(
+            "UPDATE wallets SET last_zakat_date = $2 
+             WHERE wallet_id = $1 AND last_zakat_date IS NOT DISTINCT FROM $3",
+            &[&wallet_id, &claimed_at, &wallet.last_zakat_date],
+        )
+        .await?;
+    if claimed == 0 {
+        log::info!("Zakat for wallet {} was already taken by a concurrent run", wallet_id);
+        return Ok(());
+    }
+
+    log::info!("Processing zakat for wallet {}: {} (balance: {})", wallet_id, from_units(zakat_amount), wallet.balance);
 
     // Create zakat transaction
-    let timestamp = Utc::now().timestamp();
-    let payload = create_transaction_payload(
+    let pending_tx = build_system_transaction(
+        DEDUCTION_SIGNATURE_TAG,
         wallet_id,
         zakat_pool_wallet_id,
         zakat_amount,
-        timestamp,
-        &Some("Monthly Zakat Deduction (2.5%)".to_string()),
+        &note,
     );
+    let transaction_hash = pending_tx.transaction_hash.clone();
 
-    // For system transactions, we use a system signature
-    let signature = sha256_hash(format!("SYSTEM_ZAKAT_{}", payload).as_bytes());
-    let transaction_hash = sha256_hash(format!("{}{}", payload, signature).as_bytes());
-
-    // Create pending transaction
-    let pending_tx = PendingTransaction {
-        id: Uuid::new_v4(),
-        transaction_hash: transaction_hash.clone(),
-        sender_wallet_id: wallet_id.to_string(),
-        receiver_wallet_id: zakat_pool_wallet_id.to_string(),
-        amount: zakat_amount,
-        fee: 0.0, // Zakat transactions have no fee
-        note: Some("Monthly Zakat Deduction (2.5%)".to_string()),
-        signature: signature.clone(),
-        timestamp,
-        created_at: Utc::now(),
-    };
-
-    // Save pending transaction
-    queries::create_pending_transaction(client, &pending_tx).await?;
+    if !queries::create_pending_transaction(&transaction, &pending_tx).await? {
+        return Err(anyhow::anyhow!("Pending transaction {} already exists", transaction_hash));
+    }
 
     // Update sender's balance (will now reflect pending zakat deduction)
-    let updated_balance = crate::blockchain::calculate_wallet_balance(client, wallet_id).await?;
-    queries::update_wallet_balance(client, wallet_id, updated_balance).await?;
+    let updated_balance = queries::refresh_wallet_balance(&transaction, wallet_id).await?;
 
     log::info!("✅ Created zakat pending transaction {} for {} coins (new available balance: {})", 
-        transaction_hash, zakat_amount, updated_balance);
+        transaction_hash, from_units(zakat_amount), updated_balance);
 
     // Record zakat deduction
-    client
-        .execute(
-            "INSERT INTO zakat_records (wallet_id, amount, transaction_hash, deduction_date) VALUES ($1, $2::float8, $3, $4)",
-            &[&wallet_id, &zakat_amount, &transaction_hash, &Utc::now()],
-        )
-        .await?;
-
-    // Update last zakat date
-    client
+    transaction
         .execute(
-            "UPDATE wallets SET last_zakat_date = $1 WHERE wallet_id = $2",
-            &[&Utc::now(), &wallet_id],
+            "INSERT INTO zakat_records (wallet_id, amount, transaction_hash, deduction_date, balance_at_deduction, rate) 
+             VALUES ($1, $2::int8 * 0.00000001, $3, $4, $5::int8 * 0.00000001, $6::float8)",
+            &[&wallet_id, &zakat_amount, &transaction_hash, &Utc::now(), &to_units(wallet.balance), &rate],
         )
         .await?;
 
     // Log zakat deduction
     queries::create_system_log(
-        client,
+        &transaction,
         "zakat_deduction",
         None,
         &format!("Zakat deducted from wallet {}: {}", wallet_id, zakat_amount),
@@ -150,7 +435,7 @@ async fn process_wallet_zakat(
     .await?;
 
     queries::create_transaction_log(
-        client,
+        &transaction,
         wallet_id,
         "zakat_deducted",
         Some(transaction_hash.clone()),
@@ -158,33 +443,45 @@ async fn process_wallet_zakat(
         "pending",
         None,
         None,
-        Some("Monthly Zakat Deduction (2.5%)".to_string()),
+        Some(note),
     )
     .await?;
 
+    transaction.commit().await?;
+
+    // Queued only once the deduction is committed
+    if let Err(e) = notification_service::notify_zakat_deducted(client, wallet_id, zakat_amount, &transaction_hash).await {
+        log::error!("Failed to queue zakat notification for wallet {}: {}", wallet_id, e);
+    }
+
     log::info!("✅ Zakat deduction created for wallet {}: {}", wallet_id, zakat_amount);
 
     Ok(())
 }
 
 /// Process zakat for all wallets
-pub async fn process_monthly_zakat(pool: &DbPool) -> Result<(), anyhow::Error> {
+pub async fn process_monthly_zakat(pool: &DbPool, chain: &ChainConfig) -> Result<(), anyhow::Error> {
     log::info!("🕌 Starting monthly zakat deduction process...");
 
-    let client = pool.get().await?;
+    let mut client = pool.get().await?;
 
-    let zakat_pool_wallet_id = env::var("ZAKAT_POOL_WALLET_ID")
-        .unwrap_or_else(|_| "ZAKAT_POOL".to_string());
+    let zakat_pool_wallet_id = &chain.zakat_pool_wallet_id;
 
-    // Ensure zakat pool wallet exists
-    if queries::get_wallet(&client, &zakat_pool_wallet_id).await?.is_none() {
-        log::info!("Creating zakat pool wallet...");
-        queries::create_wallet(&client, &zakat_pool_wallet_id, None).await?;
+    // Ensure zakat pool wallet exists; an upsert, so an overlapping run creating it too is harmless
+    if queries::ensure_wallet(&client, zakat_pool_wallet_id).await? {
+        log::info!("Created zakat pool wallet {}", zakat_pool_wallet_id);
     }
 
-    // Get all wallets
+    // Nisab is resolved once per run (gold-pegged when configured)
+    let zakat_threshold = nisab_service::current_threshold().await;
+    log::info!("Zakat threshold (nisab) for this run: {}", zakat_threshold);
+
+    // Get all active, non-exempt wallets; frozen ones are left alone until unfrozen
     let rows = client
-        .query("SELECT wallet_id FROM wallets WHERE wallet_id != $1", &[&zakat_pool_wallet_id])
+        .query(
+            "SELECT wallet_id FROM wallets WHERE wallet_id != $1 AND status = 'active' AND NOT zakat_exempt",
+            &[&zakat_pool_wallet_id],
+        )
         .await?;
 
     let mut processed_count = 0;
@@ -193,7 +490,7 @@ pub async fn process_monthly_zakat(pool: &DbPool) -> Result<(), anyhow::Error> {
     for row in rows {
         let wallet_id: String = row.get(0);
 
-        match process_wallet_zakat(&client, &wallet_id, &zakat_pool_wallet_id).await {
+        match process_wallet_zakat(&mut client, &wallet_id, zakat_pool_wallet_id, zakat_threshold).await {
             Ok(_) => processed_count += 1,
             Err(e) => {
                 error_count += 1;
@@ -207,8 +504,8 @@ pub async fn process_monthly_zakat(pool: &DbPool) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-/// Start zakat scheduler (configurable intervals)
-pub async fn start_zakat_scheduler(pool: DbPool) {
+/// Start zakat scheduler (configurable intervals), exiting once `shutdown` is triggered
+pub async fn start_zakat_scheduler(pool: DbPool, chain: ChainConfig, shutdown: Shutdown) {
     log::info!("🕌 Starting Zakat scheduler...");
 
     // Get configuration from environment
@@ -232,23 +529,22 @@ pub async fn start_zakat_scheduler(pool: DbPool) {
         zakat_period_seconds / 86400
     );
 
-    // Run checks at the check interval
-    let mut interval = interval(TokioDuration::from_secs(check_interval_seconds));
-    
-    loop {
-        interval.tick().await;
-        
+    // Run checks at the check interval; a run in progress finishes before the loop exits
+    run_until_shutdown(TokioDuration::from_secs(check_interval_seconds), shutdown, || async {
         log::info!("🕌 Running scheduled zakat check");
-        
-        if let Err(e) = process_monthly_zakat(&pool).await {
+
+        if let Err(e) = process_monthly_zakat(&pool, &chain).await {
             log::error!("Error processing zakat: {}", e);
         }
-    }
+    })
+    .await;
+
+    log::info!("🕌 Zakat scheduler stopped");
 }
 
 /// Manually trigger zakat deduction (for testing or admin purposes)
-pub async fn trigger_zakat_deduction(pool: &DbPool) -> Result<(), anyhow::Error> {
-    process_monthly_zakat(pool).await
+pub async fn trigger_zakat_deduction(pool: &DbPool, chain: &ChainConfig) -> Result<(), anyhow::Error> {
+    process_monthly_zakat(pool, chain).await
 }
 
 /* DEPRECATED: No longer using UTXO reservation - balance calculation now uses pending transaction amounts directly
@@ -297,3 +593,314 @@ async fn reserve_utxos_for_zakat(
     Ok(())
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solar_period_boundary() {
+        let last = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let period = 2_592_000;
+
+        let just_before = last + chrono::Duration::seconds(period - 1);
+        let at_boundary = last + chrono::Duration::seconds(period);
+
+        assert!(!zakat_period_elapsed(ZakatCalendar::Solar, last, just_before, period));
+        assert!(zakat_period_elapsed(ZakatCalendar::Solar, last, at_boundary, period));
+    }
+
+    #[test]
+    fn test_hijri_period_boundary() {
+        // 1 Ramadan 1445 -> 1 Ramadan 1446 is 354 days; the solar period is ignored
+        let last = "2024-03-11T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let day_before = "2025-02-28T23:59:59Z".parse::<DateTime<Utc>>().unwrap();
+        let anniversary = "2025-03-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        assert!(!zakat_period_elapsed(ZakatCalendar::Hijri, last, day_before, 0));
+        assert!(zakat_period_elapsed(ZakatCalendar::Hijri, last, anniversary, i64::MAX));
+    }
+
+    #[test]
+    fn test_system_signature_needs_the_secret() {
+        let payload = create_transaction_payload("saver", "ZAKAT_POOL", 2.5, 1733571000, &None);
+        let signature = system_signature(DEDUCTION_SIGNATURE_TAG, &payload);
+        assert_eq!(system_signature_tag(&payload, &signature), Some(DEDUCTION_SIGNATURE_TAG));
+        assert_eq!(system_signature_tag(&format!("{}0", payload), &signature), None);
+
+        // The old unkeyed hash anyone could compute is no longer a system signature
+        let unkeyed = sha256_hash(format!("{}_{}", DEDUCTION_SIGNATURE_TAG, payload).as_bytes());
+        assert_eq!(system_signature_tag(&payload, &unkeyed), None);
+    }
+
+    #[test]
+    fn test_split_even() {
+        let recipients = vec![("a".to_string(), 1.0), ("b".to_string(), 1.0)];
+        let shares = split_by_weight(to_units(10.0), &recipients).unwrap();
+        assert_eq!(shares, vec![("a".to_string(), to_units(5.0)), ("b".to_string(), to_units(5.0))]);
+    }
+
+    #[test]
+    fn test_split_weighted_never_overdraws() {
+        let recipients = vec![("a".to_string(), 3.0), ("b".to_string(), 1.0), ("c".to_string(), 2.0)];
+        let shares = split_by_weight(to_units(1.0), &recipients).unwrap();
+
+        assert_eq!(shares[0].1, 50_000_000);
+        assert_eq!(shares[1].1, 16_666_666);
+        assert_eq!(shares[2].1, 33_333_333);
+
+        let total: i64 = shares.iter().map(|(_, amount)| amount).sum();
+        assert!(total <= to_units(1.0));
+    }
+
+    #[test]
+    fn test_split_rejects_empty_pool() {
+        let recipients = vec![("a".to_string(), 1.0)];
+        assert!(matches!(split_by_weight(0, &recipients), Err(DistributionError::EmptyPool)));
+    }
+
+    #[test]
+    fn test_split_rejects_bad_input() {
+        assert!(matches!(split_by_weight(to_units(10.0), &[]), Err(DistributionError::NoRecipients)));
+
+        let recipients = vec![("a".to_string(), 0.0)];
+        assert!(matches!(split_by_weight(to_units(10.0), &recipients), Err(DistributionError::InvalidWeight(_))));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_zakat_skips_frozen_wallets() {
+        let Some(pool) = crate::database::create_test_pool("zakat_frozen").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        for wallet_id in ["active", "frozen"] {
+            queries::ensure_wallet(&client, wallet_id).await.unwrap();
+            queries::create_utxo(&client, wallet_id, to_units(1000.0), &format!("funding_{}", wallet_id), 0).await.unwrap();
+            queries::refresh_wallet_balance(&client, wallet_id).await.unwrap();
+        }
+        queries::set_wallet_status(&client, "frozen", crate::models::WalletStatus::Frozen).await.unwrap();
+        drop(client);
+
+        process_monthly_zakat(&pool, &ChainConfig::default()).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        let rows = client.query("SELECT wallet_id FROM zakat_records", &[]).await.unwrap();
+        let charged: Vec<String> = rows.iter().map(|row| row.get(0)).collect();
+        assert_eq!(charged, vec!["active".to_string()]);
+
+        let frozen = queries::get_wallet(&client, "frozen").await.unwrap().unwrap();
+        assert!(frozen.last_zakat_date.is_none());
+        assert_eq!(frozen.balance, 1000.0);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_zakat_skips_exempt_wallets() {
+        let Some(pool) = crate::database::create_test_pool("zakat_exempt").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        for wallet_id in ["liable", "exempt"] {
+            queries::ensure_wallet(&client, wallet_id).await.unwrap();
+            queries::create_utxo(&client, wallet_id, to_units(1000.0), &format!("funding_{}", wallet_id), 0).await.unwrap();
+            queries::refresh_wallet_balance(&client, wallet_id).await.unwrap();
+        }
+        queries::set_zakat_exempt(&client, "exempt", true).await.unwrap();
+        drop(client);
+
+        process_monthly_zakat(&pool, &ChainConfig::default()).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        let rows = client.query("SELECT wallet_id FROM zakat_records", &[]).await.unwrap();
+        let charged: Vec<String> = rows.iter().map(|row| row.get(0)).collect();
+        assert_eq!(charged, vec!["liable".to_string()]);
+
+        let liable = queries::get_wallet(&client, "liable").await.unwrap().unwrap();
+        assert!(liable.last_zakat_date.is_some());
+        let exempt = queries::get_wallet(&client, "exempt").await.unwrap().unwrap();
+        assert!(exempt.last_zakat_date.is_none());
+        assert_eq!(exempt.balance, 1000.0);
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_zakat_record_keeps_calculation_basis() {
+        let Some(pool) = crate::database::create_test_pool("zakat_basis").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        queries::ensure_wallet(&client, "payer").await.unwrap();
+        queries::create_utxo(&client, "payer", to_units(1234.5), "funding", 0).await.unwrap();
+        let balance = queries::refresh_wallet_balance(&client, "payer").await.unwrap();
+        drop(client);
+
+        process_monthly_zakat(&pool, &ChainConfig::default()).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        let row = client
+            .query_one(
+                "SELECT (amount * 100000000)::int8, balance_at_deduction::float8, rate::float8 
+                 FROM zakat_records WHERE wallet_id = 'payer'",
+                &[],
+            )
+            .await
+            .unwrap();
+        let (amount, basis, rate): (i64, f64, f64) = (row.get(0), row.get(1), row.get(2));
+
+        assert_eq!(basis, balance);
+        assert_eq!(rate, zakat_rate());
+        assert_eq!(amount, to_units(basis * rate / 100.0));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_zakat_rate_override() {
+        let Some(pool) = crate::database::create_test_pool("zakat_rate_override").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        for wallet_id in ["standard", "business"] {
+            queries::ensure_wallet(&client, wallet_id).await.unwrap();
+            queries::create_utxo(&client, wallet_id, to_units(1000.0), &format!("funding_{}", wallet_id), 0).await.unwrap();
+            queries::refresh_wallet_balance(&client, wallet_id).await.unwrap();
+        }
+        let business = queries::set_zakat_rate(&client, "business", Some(1.25)).await.unwrap().unwrap();
+        assert_eq!(business.zakat_rate, Some(1.25));
+        drop(client);
+
+        process_monthly_zakat(&pool, &ChainConfig::default()).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        let charged = |wallet_id: &'static str| {
+            let client = &client;
+            async move {
+                let row = client
+                    .query_one(
+                        "SELECT (amount * 100000000)::int8, rate::float8 FROM zakat_records WHERE wallet_id = $1",
+                        &[&wallet_id],
+                    )
+                    .await
+                    .unwrap();
+                (row.get::<_, i64>(0), row.get::<_, f64>(1))
+            }
+        };
+        assert_eq!(charged("business").await, (to_units(12.5), 1.25));
+        assert_eq!(charged("standard").await, (to_units(1000.0 * zakat_rate() / 100.0), zakat_rate()));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_overlapping_runs_deduct_once() {
+        let Some(pool) = crate::database::create_test_pool("zakat_overlap").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        queries::ensure_wallet(&client, "ZAKAT_POOL").await.unwrap();
+        queries::ensure_wallet(&client, "payer").await.unwrap();
+        queries::create_utxo(&client, "payer", to_units(1000.0), "funding", 0).await.unwrap();
+        queries::refresh_wallet_balance(&client, "payer").await.unwrap();
+        drop(client);
+
+        // Scheduled run and manual trigger at the same time
+        let chain = ChainConfig::default();
+        let (scheduled, manual) = tokio::join!(process_monthly_zakat(&pool, &chain), trigger_zakat_deduction(&pool, &chain));
+        scheduled.unwrap();
+        manual.unwrap();
+
+        let client = pool.get().await.unwrap();
+        let pending: i64 = client
+            .query_one("SELECT COUNT(*) FROM pending_transactions WHERE sender_wallet_id = 'payer'", &[])
+            .await
+            .unwrap()
+            .get(0);
+        let records: i64 = client
+            .query_one("SELECT COUNT(*) FROM zakat_records WHERE wallet_id = 'payer'", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!((pending, records), (1, 1));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_overlapping_runs_create_pool_once() {
+        let Some(pool) = crate::database::create_test_pool("zakat_pool_race").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        queries::ensure_wallet(&client, "payer").await.unwrap();
+        queries::create_utxo(&client, "payer", to_units(1000.0), "funding", 0).await.unwrap();
+        queries::refresh_wallet_balance(&client, "payer").await.unwrap();
+        drop(client);
+
+        // Neither run finds the pool wallet, so both try to create it
+        let chain = ChainConfig::default();
+        let (scheduled, manual) = tokio::join!(process_monthly_zakat(&pool, &chain), trigger_zakat_deduction(&pool, &chain));
+        scheduled.unwrap();
+        manual.unwrap();
+
+        let client = pool.get().await.unwrap();
+        let pools: i64 = client
+            .query_one("SELECT COUNT(*) FROM wallets WHERE wallet_id = 'ZAKAT_POOL'", &[])
+            .await
+            .unwrap()
+            .get(0);
+        let records: i64 = client
+            .query_one("SELECT COUNT(*) FROM zakat_records WHERE wallet_id = 'payer'", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!((pools, records), (1, 1));
+    }
+
+    /// Needs `TEST_DATABASE_URL`; skipped when unset
+    #[tokio::test]
+    async fn test_failed_deduction_rolls_back() {
+        let Some(pool) = crate::database::create_test_pool("zakat_rollback").await else { return };
+        crate::migrations::run_migrations(&pool).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        queries::ensure_wallet(&client, "payer").await.unwrap();
+        queries::create_utxo(&client, "payer", to_units(1000.0), "funding", 0).await.unwrap();
+        queries::refresh_wallet_balance(&client, "payer").await.unwrap();
+        // Fail the last write of the deduction, after the pending transaction and record are inserted
+        client
+            .batch_execute(
+                "CREATE FUNCTION fail_zakat_log() RETURNS trigger AS $$
+                 BEGIN RAISE EXCEPTION 'injected failure'; END $$ LANGUAGE plpgsql;
+                 CREATE TRIGGER fail_zakat_log BEFORE INSERT ON transaction_logs
+                     FOR EACH ROW WHEN (NEW.action = 'zakat_deducted') EXECUTE FUNCTION fail_zakat_log();",
+            )
+            .await
+            .unwrap();
+        drop(client);
+
+        process_monthly_zakat(&pool, &ChainConfig::default()).await.unwrap();
+
+        let client = pool.get().await.unwrap();
+        let deductions = || {
+            let client = &client;
+            async move {
+                client
+                    .query_one(
+                        "SELECT (SELECT COUNT(*) FROM pending_transactions WHERE sender_wallet_id = 'payer'), 
+                                (SELECT COUNT(*) FROM zakat_records WHERE wallet_id = 'payer')",
+                        &[],
+                    )
+                    .await
+                    .map(|row| (row.get::<_, i64>(0), row.get::<_, i64>(1)))
+                    .unwrap()
+            }
+        };
+        assert_eq!(deductions().await, (0, 0));
+        let payer = queries::get_wallet(&client, "payer").await.unwrap().unwrap();
+        assert!(payer.last_zakat_date.is_none());
+        assert_eq!(payer.balance, 1000.0);
+
+        // The period was never claimed, so the next run deducts
+        client.batch_execute("DROP TRIGGER fail_zakat_log ON transaction_logs").await.unwrap();
+        process_monthly_zakat(&pool, &ChainConfig::default()).await.unwrap();
+        assert_eq!(deductions().await, (1, 1));
+    }
+}
@@ -0,0 +1,136 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::time::{interval, Duration};
+
+/// Process-wide shutdown signal observed by background tasks and mining
+#[derive(Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+    /// Mirrors the watch value so mining threads can poll it without locking
+    flag: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Shutdown {
+            sender: Arc::new(sender),
+            flag: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+
+    /// Flag for synchronous code (proof-of-work threads) to poll
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.flag)
+    }
+
+    /// Resolves once shutdown has been triggered
+    pub async fn wait(&self) {
+        let mut receiver = self.sender.subscribe();
+        // Only errors if the sender is dropped, which can't happen while `self` holds it
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wait for SIGTERM or Ctrl+C
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Run `job` every `period` until shutdown. A job already in progress is
+/// allowed to finish; the loop exits before the next tick.
+pub async fn run_until_shutdown<F, Fut>(period: Duration, shutdown: Shutdown, mut job: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut ticker = interval(period);
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.wait() => break,
+            _ = ticker.tick() => job().await,
+        }
+
+        if shutdown.is_triggered() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[tokio::test]
+    async fn test_scheduler_stops_on_shutdown() {
+        let shutdown = Shutdown::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let counter = Arc::clone(&runs);
+        let handle = tokio::spawn(run_until_shutdown(Duration::from_millis(10), shutdown.clone(), move || {
+            let counter = Arc::clone(&counter);
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        }));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown.trigger();
+
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("scheduler did not stop after shutdown")
+            .unwrap();
+
+        let runs_at_shutdown = runs.load(Ordering::SeqCst);
+        assert!(runs_at_shutdown > 0);
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), runs_at_shutdown);
+    }
+
+    #[tokio::test]
+    async fn test_wait_after_trigger_returns_immediately() {
+        let shutdown = Shutdown::new();
+        shutdown.trigger();
+
+        assert!(shutdown.is_triggered());
+        tokio::time::timeout(Duration::from_millis(100), shutdown.wait())
+            .await
+            .expect("wait should resolve once triggered");
+    }
+}
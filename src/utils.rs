@@ -1,3 +1,56 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Amounts are held as integers in the smallest unit, 1e-8 of a coin
+pub const UNITS_PER_COIN: i64 = 100_000_000;
+
+/// Decimal coin amount from the API in smallest units, rounded to the nearest unit
+pub fn to_units(amount: f64) -> i64 {
+    (amount * UNITS_PER_COIN as f64).round() as i64
+}
+
+/// Smallest units as the API's decimal coin amount
+pub fn from_units(units: i64) -> f64 {
+    units as f64 / UNITS_PER_COIN as f64
+}
+
+/// Exact 8-decimal rendering of an amount in smallest units
+pub fn format_units(units: i64) -> String {
+    let sign = if units < 0 { "-" } else { "" };
+    let units = units.unsigned_abs();
+    let per_coin = UNITS_PER_COIN as u64;
+    format!("{}{}.{:08}", sign, units / per_coin, units % per_coin)
+}
+
+/// Serde adapter for amounts held in smallest units but exchanged as decimal coins
+pub mod coin_amount {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(units: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(super::from_units(*units))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        f64::deserialize(deserializer).map(super::to_units)
+    }
+}
+
+/// `coin_amount` for optional amounts; `None` serializes as `null`
+pub mod coin_amount_opt {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(units: &Option<i64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match units {
+            Some(units) => serializer.serialize_some(&super::from_units(*units)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+        Option::<f64>::deserialize(deserializer).map(|amount| amount.map(super::to_units))
+    }
+}
+
 pub fn format_currency(amount: f64) -> String {
     format!("{:.8}", amount)
 }
@@ -10,6 +63,31 @@ pub fn truncate_hash(hash: &str, length: usize) -> String {
     }
 }
 
+/// Escape `%`, `_` and `\` so user input matches literally inside an `ILIKE` pattern
+pub fn escape_like(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Encode a transaction pagination cursor from the last row's `created_at` and `id`
+pub fn encode_tx_cursor(created_at: DateTime<Utc>, id: Uuid) -> String {
+    format!("{}_{}", created_at.timestamp_micros(), id)
+}
+
+/// Decode a cursor produced by `encode_tx_cursor`
+pub fn decode_tx_cursor(cursor: &str) -> Option<(DateTime<Utc>, Uuid)> {
+    let (micros, id) = cursor.split_once('_')?;
+    let created_at = DateTime::from_timestamp_micros(micros.parse().ok()?)?;
+    let id = Uuid::parse_str(id).ok()?;
+    Some((created_at, id))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -20,9 +98,56 @@ mod tests {
         assert_eq!(format_currency(0.1), "0.10000000");
     }
 
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("rent"), "rent");
+        assert_eq!(escape_like("100%_off\\"), "100\\%\\_off\\\\");
+    }
+
+    #[test]
+    fn test_unit_conversion() {
+        assert_eq!(to_units(1.0), UNITS_PER_COIN);
+        assert_eq!(to_units(0.1), 10_000_000);
+        assert_eq!(to_units(0.00000001), 1);
+        assert_eq!(from_units(30_000_000), 0.3);
+        assert_eq!(format_units(123_456_789), "1.23456789");
+        assert_eq!(format_units(-5), "-0.00000005");
+    }
+
+    #[test]
+    fn test_summing_small_amounts_is_exact() {
+        // Ten thousand 1-unit outputs: the float sum drifts, the unit sum doesn't
+        let float_total: f64 = (0..10_000).map(|_| 0.00000001).sum();
+        let unit_total: i64 = (0..10_000).map(|_| to_units(0.00000001)).sum();
+        assert_ne!(float_total, 0.0001);
+        assert_eq!(unit_total, to_units(0.0001));
+        assert_eq!(from_units(unit_total), 0.0001);
+
+        let float_total: f64 = (0..10).map(|_| 0.1).sum();
+        let unit_total: i64 = (0..10).map(|_| to_units(0.1)).sum();
+        assert_ne!(float_total, 1.0);
+        assert_eq!(unit_total, UNITS_PER_COIN);
+    }
+
     #[test]
     fn test_truncate_hash() {
         let hash = "abcdef1234567890";
         assert_eq!(truncate_hash(hash, 8), "abcd...7890");
     }
+
+    #[test]
+    fn test_tx_cursor_roundtrip() {
+        let created_at = DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap();
+        let id = Uuid::new_v4();
+
+        let cursor = encode_tx_cursor(created_at, id);
+        assert_eq!(decode_tx_cursor(&cursor), Some((created_at, id)));
+    }
+
+    #[test]
+    fn test_tx_cursor_rejects_garbage() {
+        assert_eq!(decode_tx_cursor(""), None);
+        assert_eq!(decode_tx_cursor("123"), None);
+        assert_eq!(decode_tx_cursor("abc_not-a-uuid"), None);
+    }
 }